@@ -0,0 +1,98 @@
+use std::hint::black_box;
+
+use bad_editor::{ByteOffset, Cursor, EditBatch, MoveTarget, MultiCursor, RopeBuffer, RopeExt, SearchQuery};
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+
+/// Roughly 1 MB of realistic looking source code
+fn large_source() -> String {
+    include_str!("../src/render.rs").repeat(32)
+}
+
+/// One cursor at the start of each of the first `n` lines
+fn cursors_at_line_starts(content: &RopeBuffer, n: usize) -> MultiCursor {
+    let mut cursors = MultiCursor::new();
+    let new_cursors = (0..n).map(|lineno| Cursor::new_with_offset(content.line_to_byte(lineno))).collect();
+    cursors.set_cursors(0, new_cursors);
+    cursors
+}
+
+fn multicursor_edit(c: &mut Criterion) {
+    let text = large_source();
+    c.bench_function("insert with 1000 cursors", |b| {
+        b.iter_batched(
+            || {
+                let content = RopeBuffer::from_str(&text);
+                let cursors = cursors_at_line_starts(&content, 1000);
+                (content, cursors)
+            },
+            |(mut content, mut cursors)| {
+                let edits = EditBatch::insert_with_cursors(&cursors, "// ");
+                content.do_edits(&mut cursors, edits);
+                (content, cursors)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn search(c: &mut Criterion) {
+    let content = RopeBuffer::from_str(&large_source());
+    let query = SearchQuery::new("fn ".into(), false);
+    c.bench_function("search from 1000 cursors", |b| {
+        b.iter_batched(
+            || cursors_at_line_starts(&content, 1000),
+            |mut cursors| {
+                content.search_with_cursors(&mut cursors, black_box(&query));
+                cursors
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    let query = SearchQuery::new("not in the buffer".into(), false);
+    c.bench_function("search without match", |b| {
+        b.iter_batched(
+            MultiCursor::new,
+            |mut cursors| {
+                content.search_with_cursors(&mut cursors, black_box(&query));
+                cursors
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    // every indented line starts with a partial match which makes naively
+    // comparing the needle at each occurrence of its first byte slow
+    let needle = "        let this_variable_name_does_not_occur = ";
+    c.bench_function("search long needle starting with whitespace", |b| {
+        b.iter(|| content.find_next(ByteOffset(0), black_box(needle)))
+    });
+    c.bench_function("search backward long needle starting with whitespace", |b| {
+        b.iter(|| content.find_prev(ByteOffset(content.len_bytes()), black_box(needle)))
+    });
+}
+
+fn count_graphemes(c: &mut Criterion) {
+    let line = ropey::Rope::from_str(&"naïve 👩‍👩‍👧 text ".repeat(10_000));
+    c.bench_function("count grapheme clusters in a long line", |b| {
+        b.iter(|| black_box(&line).count_grapheme_clusters())
+    });
+}
+
+fn word_movement(c: &mut Criterion) {
+    let n_words = 10_000;
+    let content = RopeBuffer::from_str(&"lorem_ipsum_dolor_sit_amet, ".repeat(n_words));
+    c.bench_function("move word by word across a long line", |b| {
+        b.iter_batched(
+            MultiCursor::new,
+            |mut cursors| {
+                for _ in 0..2 * n_words {
+                    cursors.move_to(&content, MoveTarget::NextWordBoundaryRight);
+                }
+                cursors
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, multicursor_edit, search, count_graphemes, word_movement);
+criterion_main!(benches);
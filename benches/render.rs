@@ -0,0 +1,43 @@
+use bad_editor::{Action, App, MoveTarget, PaneAction};
+use criterion::{Criterion, criterion_group, criterion_main};
+use crossterm::terminal::WindowSize;
+
+/// Sets up an app with a single pane of highlighted Rust code as if it was
+/// shown in a terminal of the given size
+fn app_with_content(text: &str, wsize: &WindowSize) -> App {
+    let mut app = App::new();
+    app.handle_action(Action::NewPane);
+    app.set("ft", "rust");
+    let pane = app.current_pane_mut();
+    pane.set_text(text);
+    pane.update_viewport_size(wsize.columns, wsize.rows - 2);
+    app
+}
+
+fn render(c: &mut Criterion) {
+    let wsize = WindowSize { rows: 50, columns: 160, width: 0, height: 0 };
+    let mut app = app_with_content(include_str!("../src/render.rs"), &wsize);
+    c.bench_function("render top of file", |b| {
+        b.iter(|| app.render(&mut std::io::sink(), &wsize).unwrap())
+    });
+
+    app.handle_action(Action::HandledByPane(PaneAction::MoveTo(MoveTarget::EndOfFile)));
+    c.bench_function("render end of file", |b| {
+        b.iter(|| app.render(&mut std::io::sink(), &wsize).unwrap())
+    });
+
+    let large_file = include_str!("../src/render.rs").repeat(32);
+    let mut app = app_with_content(&large_file, &wsize);
+    app.handle_action(Action::HandledByPane(PaneAction::MoveTo(MoveTarget::ByteOffset(large_file.len() / 2))));
+    c.bench_function("render middle of large file", |b| {
+        b.iter(|| app.render(&mut std::io::sink(), &wsize).unwrap())
+    });
+
+    let mut app = app_with_content(&"x".repeat(1 << 20), &wsize);
+    c.bench_function("render long line", |b| {
+        b.iter(|| app.render(&mut std::io::sink(), &wsize).unwrap())
+    });
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
@@ -18,4 +18,7 @@ fn main() {
     let syntax_set = syntax_set_builder.build();
     dump_to_uncompressed_file(&syntax_set, format!("{out_dir}/syntaxes.packdump"))
         .expect("dumping syntaxes should work");
+    // the metadata (from .tmPreferences files) is not part of the syntax set dump
+    dump_to_uncompressed_file(syntax_set.metadata(), format!("{out_dir}/metadata.packdump"))
+        .expect("dumping syntax metadata should work");
 }
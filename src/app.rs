@@ -2,10 +2,18 @@ use std::collections::VecDeque;
 use std::sync::Arc;
 
 use crate::cli::FilePathWithOptionalLocation;
-use crate::clipboard::InternalClipboard;
-use crate::highlighter::BadHighlighterManager;
+use crate::clipboard::{ClipboardBackend, Clips, InternalClipboard};
+use crate::editing::Transform;
+use crate::highlighter::{BadHighlighterManager, ThemeVariant};
 use crate::prompt_completer::CmdCompleter;
-use crate::{Action, Pane};
+use crate::ropebuffer::RopeBuffer;
+use crate::{Action, CharSearch, CustomCommand, MoveTarget, Pane, PaneAction};
+
+/// The settings that apply to the whole editor rather than a single pane,
+/// these are the ones that can be set in the config file
+const CONFIG_SETTINGS: &[&str] = &[
+    "clipboard", "clipboard_history", "clipboard_joiner", "highlight_max_line_length", "max_file_size", "theme",
+];
 
 pub(crate) enum AppState {
     Idle,
@@ -19,15 +27,27 @@ pub struct App {
     pub(crate) action_queue: VecDeque<Action>,
     pub(crate) highlighting: Arc<BadHighlighterManager>,
     pub(crate) prompt_completer: CmdCompleter,
+    /// Commands defined by the user in addition to the built-in ones
+    pub(crate) custom_commands: std::collections::HashMap<String, CustomCommand>,
     pub(crate) clipboard: InternalClipboard,
     pub(crate) dirs: Option<directories::ProjectDirs>,
+    /// Running language servers by filetype
+    #[cfg(feature = "lsp")]
+    pub(crate) language_servers: std::collections::HashMap<String, crate::lsp::LspClient>,
     info: Option<String>,
+    /// Set when the next key press is a character argument for the action
+    pub(crate) awaiting_char: Option<fn(char) -> Action>,
+    last_char_search: Option<CharSearch>,
+    /// The transformation done by the last `to` command
+    last_transform: Option<Transform>,
+    /// Opening a file larger than this (in bytes) asks for confirmation, 0 means no limit
+    pub(crate) max_file_size: u64,
 }
 
 impl App {
     pub fn new() -> Self {
         let highlighting = BadHighlighterManager::new();
-        let prompt_completer = CmdCompleter::make_completer(highlighting.filetypes().as_slice());
+        let prompt_completer = CmdCompleter::make_completer(highlighting.filetypes().as_slice(), &highlighting.theme_names());
         Self {
             panes: vec![],
             current_pane_index: 0,
@@ -35,9 +55,16 @@ impl App {
             action_queue: VecDeque::new(),
             highlighting: Arc::new(highlighting),
             prompt_completer,
+            custom_commands: std::collections::HashMap::new(),
             clipboard: InternalClipboard::new(),
             dirs: None,
+            #[cfg(feature = "lsp")]
+            language_servers: std::collections::HashMap::new(),
             info: None,
+            awaiting_char: None,
+            last_char_search: None,
+            last_transform: None,
+            max_file_size: 256 * 1024 * 1024,
         }
     }
 
@@ -55,53 +82,332 @@ impl App {
         Pane::new_from_file(file_loc, highlighting)
     }
 
-    fn confirm_saved(&mut self) -> bool {
-        if self.current_pane().modified && self.current_pane().path.is_some() {
-            if let Ok(wsize) = crossterm::terminal::window_size() {
-                let _ = crossterm::execute!(
-                    std::io::stdout(),
-                    crossterm::cursor::MoveTo(0, wsize.height - 1)
-                );
-            }
+    /// Shows `question` on the bottom line and blocks until the user presses one
+    /// of the `choices` (returned in lowercase) or Esc (returns `None`).
+    fn ask(&self, question: &str, choices: &[char]) -> Option<char> {
+        if let Ok(wsize) = crossterm::terminal::window_size() {
             let _ = crossterm::execute!(
                 std::io::stdout(),
-                crossterm::style::Print("save changes to file before closing? (y)es / (n)o / (a)bort")
+                crossterm::cursor::MoveTo(0, wsize.rows - 1),
+                crossterm::terminal::Clear(crossterm::terminal::ClearType::UntilNewLine)
             );
-            use crossterm::event::{Event, KeyEvent, KeyCode};
-            loop {
-                let event = crossterm::event::read();
-                if let Ok(Event::Key(KeyEvent { code, .. })) = event {
-                    match code {
-                        KeyCode::Char('Y' | 'y') => {
-                            self.current_pane_mut().save();
-                            return true
-                        }
-                        KeyCode::Char('N' | 'n') => return true,
-                        KeyCode::Char('A' | 'a') => return false,
-                        KeyCode::Esc => return false,
-                        _ => {}
+        }
+        let _ = crossterm::execute!(std::io::stdout(), crossterm::style::Print(question));
+        use crossterm::event::{Event, KeyEvent, KeyCode};
+        loop {
+            let event = crossterm::event::read();
+            if let Ok(Event::Key(KeyEvent { code, .. })) = event {
+                match code {
+                    KeyCode::Char(c) if choices.contains(&c.to_ascii_lowercase()) => {
+                        return Some(c.to_ascii_lowercase())
                     }
+                    KeyCode::Esc => return None,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn confirm_saved(&mut self) -> bool {
+        if self.current_pane().modified && self.current_pane().path.is_some() {
+            match self.ask("save changes to file before closing? (y)es / (n)o / (a)bort", &['y', 'n', 'a']) {
+                Some('y') => {
+                    self.current_pane_mut().save();
+                    // keep the pane open if saving failed
+                    !self.current_pane().modified
                 }
+                Some('n') => true,
+                _ => false,
             }
         } else {
             true
         }
     }
 
-    pub fn open_file_in_new_pane(&mut self, file_loc: &FilePathWithOptionalLocation) -> &mut Pane {
+    /// Size of the file in megabytes if it is larger than `max_file_size`
+    /// (reading a huge file into memory can freeze the editor for a while)
+    fn oversized_file_mb(&self, path: &std::path::Path) -> Option<u64> {
+        let metadata = std::fs::metadata(path).ok()?;
+        if self.max_file_size == 0 || !metadata.is_file() || metadata.len() <= self.max_file_size {
+            return None
+        }
+        Some(metadata.len() / (1024 * 1024))
+    }
+
+    /// Asks whether to open a file that is larger than `max_file_size`
+    fn confirm_file_size(&self, path: &std::path::Path) -> bool {
+        match self.oversized_file_mb(path) {
+            Some(size_mb) => self.ask(&format!("file is {size_mb}MB, open anyway? (y)es / (n)o"), &['y', 'n']) == Some('y'),
+            None => true,
+        }
+    }
+
+    pub(crate) fn revert_current_pane(&mut self) {
+        if self.current_pane().path.is_none() {
+            self.inform("revert error: buffer is not associated with a file".into());
+            return
+        }
+        if self.current_pane().modified {
+            let answer = self.ask("discard unsaved changes and reload file from disk? (y)es / (n)o", &['y', 'n']);
+            if answer != Some('y') {
+                return
+            }
+        }
+        self.current_pane_mut().revert();
+    }
+
+    /// Opens a new pane with a unified diff from the current pane to `other`,
+    /// which is either the number of an open pane or a path to a file
+    pub(crate) fn show_diff(&mut self, other: &str) {
+        let (other_name, other_content) = match other.parse::<usize>() {
+            Ok(n) if (1..=self.panes.len()).contains(&n) => {
+                let pane = &self.panes[n - 1];
+                (pane.title.clone(), pane.content.to_string())
+            }
+            Ok(n) => {
+                self.inform(format!("diff error: there is no pane {n}"));
+                return
+            }
+            Err(_) if other.is_empty() => {
+                self.inform("Usage: diff PANE_NUMBER | diff FILE".into());
+                return
+            }
+            Err(_) => {
+                match std::fs::read_to_string(crate::expand_path(other)) {
+                    Ok(content) => (crate::quote_path(other), content),
+                    Err(err) => {
+                        self.inform(format!("diff error: {err}: {}", crate::quote_path(other)));
+                        return
+                    }
+                }
+            }
+        };
+        let current = self.current_pane();
+        let Some(diff) = crate::diff::unified_diff(&current.title, &current.content.to_string(), &other_name, &other_content) else {
+            self.inform("No differences".into());
+            return
+        };
+        let mut pane = Pane::empty();
+        pane.title = format!("diff {} {other_name}", current.title);
+        pane.content = RopeBuffer::from_str(&diff);
+        let _ = pane.set_filetype("diff", self.highlighting.clone());
+        self.switch_to_new_pane(pane);
+    }
+
+    /// Shows the lints of all panes in a pane of their own, pressing Enter on
+    /// one of them jumps to its location
+    pub(crate) fn show_lint_list(&mut self) {
+        match self.panes.iter().position(|pane| pane.lint_list.is_some()) {
+            Some(idx) => self.current_pane_index = idx,
+            None => {
+                let mut pane = Pane::empty();
+                pane.title = "lints".into();
+                pane.lint_list = Some(vec![]);
+                self.switch_to_new_pane(pane);
+            }
+        }
+        self.update_lint_lists();
+        if self.current_pane().lint_list.as_ref().is_some_and(|locations| locations.is_empty()) {
+            self.inform("No lints".into());
+        }
+    }
+
+    /// Opens a pane that lists the entries of `dir`, Enter opens the entry on
+    /// the line with the cursor and Backspace goes up to the parent directory
+    pub(crate) fn browse_directory(&mut self, dir: &std::path::Path) {
+        let mut pane = Pane::empty();
+        match pane.list_directory(dir) {
+            Ok(()) => self.switch_to_new_pane(pane),
+            Err(err) => self.inform(format!("browse error: {err}: {}", crate::quote_path(&dir.to_string_lossy()))),
+        }
+    }
+
+    /// Refreshes the lint list panes after the lints have changed
+    pub(crate) fn update_lint_lists(&mut self) {
+        if !self.panes.iter().any(|pane| pane.lint_list.is_some()) {
+            return
+        }
+        let mut text = String::new();
+        let mut locations = vec![];
+        for pane in self.panes.iter() {
+            let Some(path) = &pane.path else { continue };
+            for lint in pane.lints.iter() {
+                let Some(target) = lint.location() else { continue };
+                text.push_str(&format!(
+                    "{}:{}:{} {} {}\n",
+                    path.display(), lint.lineno(), lint.column(), lint.severity(), lint.message
+                ));
+                locations.push((path.clone(), target));
+            }
+        }
+        for pane in self.panes.iter_mut().filter(|pane| pane.lint_list.is_some()) {
+            pane.replace_generated_content(&text);
+            pane.lint_list = Some(locations.clone());
+        }
+    }
+
+    /// Jumps to the lint on the line with the cursor in a lint list pane
+    fn go_to_listed_lint(&mut self) {
+        let pane = self.current_pane();
+        let line = pane.cursors.primary().current_line_number(&pane.content);
+        let Some((path, target)) = pane.lint_list.as_ref().and_then(|locations| locations.get(line)).cloned() else {
+            return
+        };
+        match self.panes.iter().position(|pane| pane.path.as_ref() == Some(&path)) {
+            Some(idx) => self.current_pane_index = idx,
+            None => {
+                if self.open_file_in_new_pane(&FilePathWithOptionalLocation::from(path)).is_none() {
+                    return
+                }
+            }
+        }
+        let pane = self.current_pane_mut();
+        pane.cursors.esc();
+        pane.cursors.primary_mut().move_to(&pane.content, target);
+        pane.adjust_viewport();
+    }
+
+    /// Copies the path of the current file to the clipboard, remote files are
+    /// copied in the `HOST:PATH` form
+    pub(crate) fn copy_current_path(&mut self, absolute: bool) {
+        let pane = self.current_pane();
+        let path = match (&pane.remote, &pane.path) {
+            (Some(remote), _) => remote.to_string(),
+            (None, Some(path)) if absolute => match std::path::absolute(path) {
+                Ok(path) => path.to_string_lossy().to_string(),
+                Err(err) => {
+                    self.inform(format!("copypath error: {err}"));
+                    return
+                }
+            },
+            (None, Some(path)) => path.to_string_lossy().to_string(),
+            (None, None) => {
+                self.inform("copypath error: file has not been saved".into());
+                return
+            }
+        };
+        self.inform(format!("Copied {path}"));
+        self.copy_to_clipboard(Clips::new(vec![path]));
+    }
+
+    /// Shows the beginnings of the copies in the clipboard history with their
+    /// numbers for the `paste N` command
+    pub(crate) fn list_clipboard_history(&mut self) {
+        if self.clipboard.history_len() == 0 {
+            self.inform("Clipboard history is empty".into());
+            return
+        }
+        let entries: Vec<String> = self.clipboard
+            .history()
+            .enumerate()
+            .map(|(index, clips)| {
+                let preview: String = clips.texts.join(" ").chars().take(20).map(|c| if c.is_control() { ' ' } else { c }).collect();
+                format!("{index}: {}", preview.trim())
+            })
+            .collect();
+        self.inform(entries.join(" | "));
+    }
+
+    fn copy_to_clipboard(&mut self, clips: Clips) {
+        if let Err(err) = self.clipboard.copy(clips) {
+            self.inform(format!("clipboard error: {err}"));
+        }
+    }
+
+    /// Opens the file whose path is under the cursor in a new pane. Relative paths
+    /// are resolved relative to the directory of the current file first.
+    pub(crate) fn open_path_under_cursor(&mut self) {
+        let Some(token) = self.current_pane().path_under_cursor() else {
+            self.inform("gotofile error: no path under cursor".into());
+            return
+        };
+        let expanded = crate::expand_env_vars(&token);
+        let current_dir = self.current_pane().path.as_ref()
+            .and_then(|path| path.parent())
+            .map(|dir| dir.join(&expanded));
+        for candidate in [current_dir, Some(std::path::PathBuf::from(&expanded))].into_iter().flatten() {
+            let file_loc = FilePathWithOptionalLocation::parse_from_str(&candidate.to_string_lossy(), true);
+            if file_loc.path.is_file() {
+                self.open_file_in_new_pane(&file_loc);
+                return
+            }
+        }
+        self.inform(format!("gotofile error: {} does not exist", crate::quote_path(&token)));
+    }
+
+    /// Opens a `[user@]host:path` file over scp in a new pane
+    pub(crate) fn open_remote_file(&mut self, arg: &str) {
+        let Some(remote) = crate::remote::RemotePath::parse(arg) else {
+            self.inform("Usage: remote [USER@]HOST:PATH".into());
+            return
+        };
+        match Pane::new_from_remote(remote, self.highlighting.clone()) {
+            Ok(pane) => self.switch_to_new_pane(pane),
+            Err(err) => self.inform(format!("remote error: {err}")),
+        }
+    }
+
+    /// Opens the file in a new pane and returns it. A file larger than
+    /// `max_file_size` is not opened (there is no way to ask for confirmation
+    /// when the files are opened from the command line).
+    pub fn open_file_in_new_pane(&mut self, file_loc: &FilePathWithOptionalLocation) -> Option<&mut Pane> {
+        if let Some(size_mb) = self.oversized_file_mb(&file_loc.path) {
+            let fpath = crate::quote_path(file_loc.path.to_string_lossy().as_ref());
+            self.inform(format!("{fpath} is {size_mb}MB, use 'open -f {fpath}' to open it anyway"));
+            return None
+        }
         let pane = self.create_pane_from_file(file_loc);
         self.switch_to_new_pane(pane);
-        let i = self.panes.len() - 1;
-        &mut self.panes[i]
+        self.panes.last_mut()
+    }
+
+    /// Opens the standard input in a new unsaved pane, highlighted as `filetype`
+    /// if one is given
+    pub fn open_stdin_in_new_pane(&mut self, filetype: Option<&str>) {
+        let mut pane = match Pane::new_from_reader(std::io::stdin().lock(), "stdin") {
+            Ok(pane) => pane,
+            Err(err) => {
+                self.inform(format!("stdin error: {err}"));
+                return
+            }
+        };
+        if let Some(ft) = filetype {
+            if pane.set_filetype(ft, self.highlighting.clone()).is_err() {
+                pane.inform(format!("unknown filetype {ft}"));
+            }
+        }
+        self.switch_to_new_pane(pane);
     }
 
     pub fn open_file_in_current_pane(&mut self, file_loc: &FilePathWithOptionalLocation) {
         if self.confirm_saved() {
+            self.current_pane_mut().remove_swap_file();
             let pane = self.create_pane_from_file(file_loc);
             self.panes[self.current_pane_index] = pane;
         }
     }
 
+    /// Returns `true` if any of the panes were autosaved
+    pub(crate) fn autosave_idle_panes(&mut self) -> bool {
+        let mut saved = false;
+        for pane in self.panes.iter_mut() {
+            saved |= pane.autosave_if_idle();
+        }
+        saved
+    }
+
+    pub(crate) fn update_swap_files(&mut self) {
+        for pane in self.panes.iter_mut() {
+            pane.update_swap_file();
+        }
+    }
+
+    pub(crate) fn remove_swap_files(&mut self) {
+        for pane in self.panes.iter_mut() {
+            pane.remove_swap_file();
+        }
+    }
+
     pub fn status_msg(&self) -> Option<&str> {
         match self.current_pane().status_msg() {
             Some(msg) => Some(msg),
@@ -139,6 +445,10 @@ impl App {
         self.dirs.as_ref().map(|dirs| dirs.config_dir().join("syntaxes"))
     }
 
+    pub fn theme_dir(&self) -> Option<std::path::PathBuf> {
+        self.dirs.as_ref().map(|dirs| dirs.config_dir().join("themes"))
+    }
+
     pub fn prompt_history_file(&self) -> Option<std::path::PathBuf> {
         self.dirs.as_ref().map(|dirs| dirs.state_dir().unwrap_or_else(|| dirs.cache_dir()).join("history"))
     }
@@ -151,16 +461,103 @@ impl App {
         let new_value = new_value.trim();
         // TODO: we should make it impossible to have these not match prompt_completer
         match setting {
+            "auto_close" => {
+                self.current_pane_mut().settings.auto_close_pairs = match new_value {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        self.inform("set error: auto_close must be one of: on, off".into());
+                        return
+                    }
+                }
+            }
             "autoindent" => {
                 self.current_pane_mut().settings.autoindent = match new_value {
                     "off" => crate::pane_settings::AutoIndent::None,
                     "keep" => crate::pane_settings::AutoIndent::Keep,
+                    "smart" => crate::pane_settings::AutoIndent::Smart,
                     _ => {
-                        self.inform("set error: autoindent must be one of: off, keep".into());
+                        self.inform("set error: autoindent must be one of: off, keep, smart".into());
                         return
                     }
                 }
             },
+            "autosave" => {
+                self.current_pane_mut().settings.autosave = match new_value {
+                    "off" | "0" => None,
+                    _ => match new_value.parse() {
+                        Ok(seconds) => Some(std::time::Duration::from_secs(seconds)),
+                        Err(_) => {
+                            self.inform("set error: autosave must be a number of seconds or off".into());
+                            return
+                        }
+                    }
+                }
+            },
+            "backspace_dedent" => {
+                self.current_pane_mut().settings.backspace_dedent = match new_value {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        self.inform("set error: backspace_dedent must be one of: on, off".into());
+                        return
+                    }
+                }
+            }
+            "backspace_join" => {
+                self.current_pane_mut().settings.backspace_join = match new_value {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        self.inform("set error: backspace_join must be one of: on, off".into());
+                        return
+                    }
+                }
+            }
+            "clipboard" => {
+                let backend = match new_value {
+                    "osc52" => ClipboardBackend::Osc52,
+                    "system" => ClipboardBackend::System,
+                    "both" => ClipboardBackend::Both,
+                    _ => {
+                        self.inform("set error: clipboard must be one of: osc52, system, both".into());
+                        return
+                    }
+                };
+                self.clipboard.set_backend(backend);
+            }
+            "clipboard_history" => {
+                match new_value.parse() {
+                    Ok(n) => self.clipboard.set_history_size(n),
+                    Err(_) => self.inform("set error: clipboard_history must be a positive number".into()),
+                }
+            }
+            "clipboard_joiner" => {
+                match crate::clipboard::unescape(new_value) {
+                    Ok(joiner) => self.clipboard.set_joiner(joiner),
+                    Err(err) => self.inform(format!("set error: clipboard_joiner has {err}")),
+                }
+            }
+            "cursor" => {
+                self.current_pane_mut().settings.cursor_shape = match new_value {
+                    "block" => crate::pane_settings::CursorShape::Block,
+                    "bar" => crate::pane_settings::CursorShape::Bar,
+                    _ => {
+                        self.inform("set error: cursor must be one of: block, bar".into());
+                        return
+                    }
+                }
+            }
+            "cursor_blink" => {
+                self.current_pane_mut().settings.cursor_blink = match new_value {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        self.inform("set error: cursor_blink must be one of: on, off".into());
+                        return
+                    }
+                }
+            }
             "debug" => {
                 match new_value {
                     "scopes" => self.current_pane_mut().settings.debug_scopes = true,
@@ -185,6 +582,45 @@ impl App {
                     self.inform(format!("set error: {setting} must be one of {}", &self.highlighting.filetypes().join(", ")));
                 }
             },
+            "highlight_max_line_length" => {
+                match new_value.parse() {
+                    Ok(n) => {
+                        Arc::make_mut(&mut self.highlighting).set_max_line_length(n);
+                        self.rehighlight_panes();
+                    }
+                    Err(_) => self.inform("set error: highlight_max_line_length must be a number (0 disables highlighting)".into()),
+                }
+            }
+            "highlight_occurrences" => {
+                self.current_pane_mut().settings.highlight_occurrences = match new_value {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        self.inform("set error: highlight_occurrences must be one of: on, off".into());
+                        return
+                    }
+                }
+            }
+            "highlight_word" => {
+                self.current_pane_mut().settings.highlight_word = match new_value {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        self.inform("set error: highlight_word must be one of: on, off".into());
+                        return
+                    }
+                }
+            }
+            "indent_guides" => {
+                self.current_pane_mut().settings.indent_guides = match new_value {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        self.inform("set error: indent_guides must be one of: on, off".into());
+                        return
+                    }
+                }
+            }
             "indent_size" => {
                 match new_value.parse() {
                     Ok(n) if n <= 32 => {
@@ -206,6 +642,16 @@ impl App {
                     }
                 }
             }
+            "inline_lints" => {
+                self.current_pane_mut().settings.inline_lints = match new_value {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        self.inform("set error: inline_lints must be one of: on, off".into());
+                        return
+                    }
+                }
+            }
             "insert_final_newline" => {
                 self.current_pane_mut().settings.insert_final_newline = match new_value {
                     "on" => true,
@@ -216,6 +662,23 @@ impl App {
                     }
                 }
             }
+            "lint_level" => {
+                self.current_pane_mut().settings.lint_level = match new_value {
+                    "error" => crate::linter::Severity::Error,
+                    "warning" => crate::linter::Severity::Warning,
+                    "info" => crate::linter::Severity::Info,
+                    _ => {
+                        self.inform("set error: lint_level must be one of: error, warning, info".into());
+                        return
+                    }
+                }
+            }
+            "max_file_size" => {
+                match new_value.parse::<u64>() {
+                    Ok(mb) => self.max_file_size = mb.saturating_mul(1024 * 1024),
+                    Err(_) => self.inform("set error: max_file_size must be a number of megabytes (0 for no limit)".into()),
+                }
+            }
             "normalize_end_of_line" => {
                 self.current_pane_mut().settings.normalize_end_of_line = match new_value {
                     "on" => true,
@@ -226,6 +689,78 @@ impl App {
                     }
                 }
             }
+            "paste_distribute" => {
+                self.current_pane_mut().settings.paste_distribute = match new_value {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        self.inform("set error: paste_distribute must be one of: on, off".into());
+                        return
+                    }
+                }
+            }
+            "ruler" => {
+                let rulers = match new_value {
+                    "off" => Ok(vec![]),
+                    columns => columns.split(',').map(|column| column.trim().parse()).collect(),
+                };
+                match rulers {
+                    Ok(rulers) => self.current_pane_mut().settings.rulers = rulers,
+                    Err(_) => self.inform("set error: ruler must be off or a comma-separated list of column numbers".into()),
+                }
+            }
+            "smartcase" => {
+                self.current_pane_mut().settings.smartcase = match new_value {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        self.inform("set error: smartcase must be one of: on, off".into());
+                        return
+                    }
+                }
+            }
+            "status_scope" => {
+                self.current_pane_mut().settings.status_scope = match new_value {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        self.inform("set error: status_scope must be one of: on, off".into());
+                        return
+                    }
+                }
+            }
+            "theme" => {
+                if new_value == "toggle" {
+                    self.set_theme(self.highlighting.theme_variant().toggled());
+                } else if !self.set_named_theme(new_value) {
+                    let mut choices = self.highlighting.theme_names();
+                    choices.push("toggle");
+                    self.inform(format!("set error: theme must be one of: {}", choices.join(", ")));
+                }
+            }
+            "title" => {
+                self.current_pane_mut().settings.title_style = match new_value {
+                    "path" => crate::pane_settings::TitleStyle::Path,
+                    "absolute" => crate::pane_settings::TitleStyle::Absolute,
+                    "relative" => crate::pane_settings::TitleStyle::Relative,
+                    "name" => crate::pane_settings::TitleStyle::Name,
+                    _ => {
+                        self.inform("set error: title must be one of: path, absolute, relative, name".into());
+                        return
+                    }
+                };
+                self.current_pane_mut().update_title();
+            }
+            "trim_modified_only" => {
+                self.current_pane_mut().settings.trim_modified_only = match new_value {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        self.inform("set error: trim_modified_only must be one of: on, off".into());
+                        return
+                    }
+                }
+            }
             "trim_trailing_whitespace" => {
                 self.current_pane_mut().settings.trim_trailing_whitespace = match new_value {
                     "on" => true,
@@ -236,6 +771,35 @@ impl App {
                     }
                 }
             }
+            "undo_levels" => {
+                match new_value.parse() {
+                    Ok(n) => {
+                        let pane = self.current_pane_mut();
+                        pane.settings.undo_levels = n;
+                        pane.content.limit_undo_history(n);
+                    }
+                    Err(_) => {
+                        self.inform("set error: undo_levels must be a non-negative number".into());
+                    }
+                }
+            }
+            "whitespace" => {
+                self.current_pane_mut().settings.show_trailing_whitespace = match new_value {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        self.inform("set error: whitespace must be one of: on, off".into());
+                        return
+                    }
+                }
+            }
+            "word_chars" => {
+                self.current_pane_mut().settings.extra_word_chars = match new_value {
+                    "default" => None,
+                    "none" => Some(String::new()),
+                    chars => Some(chars.to_string()),
+                }
+            }
             _ => {
                 self.info.replace(format!("set error: '{setting}' is not a valid setting"));
             },
@@ -247,17 +811,100 @@ impl App {
         if !syntax_dir.exists() {
             std::fs::DirBuilder::new().recursive(true).create(&syntax_dir).ok()?;
         }
-        let (hl, result) = BadHighlighterManager::new_with_syntaxes_from_dir(&syntax_dir);
+        let (mut hl, result) = BadHighlighterManager::new_with_syntaxes_from_dir(&syntax_dir);
         if let Err(err) = result {
             self.inform(format!("{err}"));
             None
         } else {
+            hl.copy_settings_from(&self.highlighting);
             self.highlighting = Arc::new(hl);
-            self.prompt_completer = CmdCompleter::make_completer(self.highlighting.filetypes().as_slice());
+            self.rebuild_prompt_completer();
             Some(())
         }
     }
 
+    /// Loads the `.tmTheme` files from the theme directory, `dark.tmTheme` and
+    /// `light.tmTheme` replace the built-in themes and the others can be
+    /// selected by name with `set theme`
+    pub fn load_themes(&mut self) {
+        let Some(theme_dir) = self.theme_dir().filter(|dir| dir.exists()) else { return };
+        self.load_themes_from(&theme_dir);
+    }
+
+    pub fn load_themes_from(&mut self, dir: &std::path::Path) {
+        match Arc::make_mut(&mut self.highlighting).load_themes_from_folder(dir) {
+            Ok(errors) => {
+                for (path, err) in errors {
+                    self.inform(format!("theme error: {}: {err}", path.display()));
+                }
+            }
+            Err(err) => self.inform(format!("theme error: {}: {err}", dir.display())),
+        }
+        self.rehighlight_panes();
+        self.rebuild_prompt_completer();
+    }
+
+    pub fn config_file(&self) -> Option<std::path::PathBuf> {
+        self.dirs.as_ref().map(|dirs| dirs.config_dir().join("config"))
+    }
+
+    /// Applies the settings from the config file. It is loaded before any files
+    /// are opened, so only the settings that are not per pane can be set there.
+    pub fn load_config(&mut self) {
+        let Some(path) = self.config_file() else { return };
+        match std::fs::read_to_string(&path) {
+            Ok(config) => self.apply_config(&config),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => self.inform(format!("config error: {err}")),
+        }
+    }
+
+    /// Runs the `set KEY VALUE` lines of `config`, empty lines and lines
+    /// starting with `#` are skipped
+    fn apply_config(&mut self, config: &str) {
+        for (lineno, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+            match line.strip_prefix("set ").and_then(|setting| setting.trim_start().split_once(' ')) {
+                Some((key, value)) if CONFIG_SETTINGS.contains(&key) => self.set(key, value),
+                Some((key, _)) => self.inform(format!("config error: line {}: '{key}' can not be set in the config file", lineno + 1)),
+                None => self.inform(format!("config error: line {}: expected 'set KEY VALUE'", lineno + 1)),
+            }
+        }
+    }
+
+    /// Switches to the `dark` or `light` theme and re-highlights all panes with it
+    pub fn set_theme(&mut self, variant: ThemeVariant) {
+        let name = match variant {
+            ThemeVariant::Dark => "dark",
+            ThemeVariant::Light => "light",
+        };
+        self.set_named_theme(name);
+    }
+
+    /// Switches to a built-in theme or one loaded from the theme directory,
+    /// returns false if there is no such theme
+    pub fn set_named_theme(&mut self, name: &str) -> bool {
+        if self.highlighting.theme_name() == name {
+            return true
+        }
+        if Arc::make_mut(&mut self.highlighting).set_theme(name).is_err() {
+            return false
+        }
+        self.rehighlight_panes();
+        true
+    }
+
+    fn rehighlight_panes(&mut self) {
+        for pane in self.panes.iter_mut() {
+            if let Some(hl) = pane.highlighter.as_mut() {
+                *hl = hl.with_manager(self.highlighting.clone());
+            }
+        }
+    }
+
     pub fn handle_action(&mut self, action: Action) {
         if matches!(self.state, AppState::InPrompt) {
             return
@@ -266,8 +913,12 @@ impl App {
             Action::None => (),
             Action::Quit => (),
             Action::Esc => {
+                let had_lints = !self.current_pane().lints.is_empty();
                 self.current_pane_mut().esc();
                 self.info.take();
+                if had_lints && self.current_pane().lints.is_empty() {
+                    self.update_lint_lists();
+                }
             }
             Action::Resize(_columns, _rows) => {
                 // this event is handled in App::run
@@ -284,21 +935,63 @@ impl App {
                 self.command_prompt_with(Some(stub), self.prompt_completer.clone());
             }
             Action::SetInfo(s) => self.inform(s),
+            Action::HandledByPane(PaneAction::InsertNewline) if self.current_pane().lint_list.is_some() => {
+                self.go_to_listed_lint();
+            }
+            Action::HandledByPane(PaneAction::InsertNewline) if self.current_pane().dir_listing.is_some() => {
+                if let Some(path) = self.current_pane().listed_path_under_cursor() {
+                    self.enqueue(Action::Open { path: FilePathWithOptionalLocation::from(path), force: false });
+                }
+            }
+            Action::HandledByPane(PaneAction::DeleteBackward) if self.current_pane().dir_listing.is_some() => {
+                if let Some(parent) = self.current_pane().listed_parent() {
+                    self.enqueue(Action::Open { path: FilePathWithOptionalLocation::from(parent), force: false });
+                }
+            }
             Action::HandledByPane(pa) => self.current_pane_mut().handle_event(pa),
-            Action::Copy => self.clipboard.copy(self.current_pane().selections()),
+            Action::Copy => self.copy_to_clipboard(self.current_pane().copy()),
+            Action::Cut if self.current_pane().is_read_only() => {}
             Action::Cut => {
                 let cuts = self.current_pane_mut().cut();
-                self.clipboard.copy(cuts);
+                self.copy_to_clipboard(cuts);
             }
-            Action::Paste => {
-                let clips = self.clipboard.content().to_vec();
-                if clips.is_empty() {
-                    self.inform("Internal clipboard is empty. Use Ctrl+Shift+v to paste from external clipboard.".into());
-                } else {
+            Action::Paste => match self.clipboard.latest().filter(|clips| !clips.texts.is_empty()).cloned() {
+                Some(clips) => {
                     self.info.take();
                     self.current_pane_mut().insert_from_clipboard(&clips);
                 }
+                None => self.inform("Nothing to paste (Ctrl+Shift+v pastes from the external clipboard)".into()),
+            },
+            Action::CopyToRegister(register) => {
+                let clips = self.current_pane().copy();
+                self.clipboard.copy_to_register(register, clips);
+                self.inform(format!("Copied to register {register}"));
             }
+            Action::PasteFromRegister(register) => match self.clipboard.content_of_register(register) {
+                Some(clips) => {
+                    let clips = clips.clone();
+                    self.info.take();
+                    self.current_pane_mut().insert_from_clipboard(&clips);
+                }
+                None => self.inform(format!("paste error: register {register} is empty")),
+            },
+            Action::PasteFromHistory(index) => match self.clipboard.history_entry(index) {
+                Some(clips) => {
+                    let clips = clips.clone();
+                    self.info.take();
+                    self.current_pane_mut().insert_from_clipboard(&clips);
+                }
+                None => self.inform(format!("paste error: there are only {} copies in the history", self.clipboard.history_len())),
+            },
+            Action::PastePrimary => match self.clipboard.primary_content() {
+                // nothing selected is not an error, there is just nothing to paste
+                Ok(content) if content.is_empty() => {}
+                Ok(content) => {
+                    self.info.take();
+                    self.current_pane_mut().insert_from_clipboard(&Clips::new(vec![content]));
+                }
+                Err(err) => self.inform(format!("pasteprimary error: {err}")),
+            },
             Action::Save => {
                 self.current_pane_mut().save();
             }
@@ -306,8 +999,10 @@ impl App {
                 let hl = self.highlighting.clone();
                 self.current_pane_mut().save_as(&path, hl);
             }
-            Action::Open(path) => {
-                self.open_file_in_current_pane(&path);
+            Action::Open { path, force } => {
+                if force || self.confirm_file_size(&path.path) {
+                    self.open_file_in_current_pane(&path);
+                }
             }
             Action::NewPane => {
                 self.panes.push(Pane::empty());
@@ -316,6 +1011,7 @@ impl App {
             Action::ClosePane => {
                 if self.panes.len() > 1 {
                     if self.confirm_saved() {
+                        self.current_pane_mut().remove_swap_file();
                         self.panes.remove(self.current_pane_index);
                         self.current_pane_index = self.current_pane_index.saturating_sub(1);
                     }
@@ -340,6 +1036,44 @@ impl App {
                     self.current_pane_index -= 1;
                 }
             }
+            Action::AwaitChar(f) => self.awaiting_char = Some(f),
+            Action::CharSearch(search) => {
+                self.last_char_search = Some(search);
+                self.char_search(search);
+            }
+            Action::RepeatCharSearch { reverse } => match self.last_char_search {
+                Some(search) if reverse => self.char_search(search.reversed()),
+                Some(search) => self.char_search(search),
+                None => self.inform("No previous character search".into()),
+            },
+        }
+    }
+
+    /// Transforms the text of every selection in the current pane and remembers
+    /// the transformation so that it can be repeated with `to again`
+    pub(crate) fn transform_selections(&mut self, transform: Transform) {
+        let pane = self.current_pane_mut();
+        let tab_width = pane.settings.tab_width;
+        pane.transform_selections(|s| Some(transform.apply(&s, tab_width)));
+        self.last_transform = Some(transform);
+    }
+
+    pub(crate) fn repeat_transform(&mut self) {
+        match self.last_transform.clone() {
+            Some(transform) => self.transform_selections(transform),
+            None => self.inform("to error: no previous transformation to repeat".into()),
+        }
+    }
+
+    /// Moves the cursors to the searched character, or extends the selections
+    /// if there are any
+    fn char_search(&mut self, search: CharSearch) {
+        let pane = self.current_pane_mut();
+        let target = MoveTarget::Char(search);
+        if pane.cursors.primary().has_selection() {
+            pane.handle_event(PaneAction::SelectTo(target));
+        } else {
+            pane.handle_event(PaneAction::MoveTo(target));
         }
     }
 }
@@ -349,3 +1083,299 @@ impl Default for App {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ByteOffset;
+
+    #[test]
+    fn lint_list_jumps_to_lints() {
+        let mut app = App::new();
+        for name in ["a.txt", "b.txt"] {
+            let mut pane = Pane::empty();
+            pane.path = Some(name.into());
+            pane.content = RopeBuffer::from_str("one\ntwo\nthree\n");
+            pane.lints = crate::linter::Lint::parse(&format!("{name}:3:2:error:bad {name}")).into_iter().collect();
+            app.switch_to_new_pane(pane);
+        }
+        app.handle_command("lints");
+        assert_eq!(app.current_pane().content.to_string(), "a.txt:3:2 error bad a.txt\nb.txt:3:2 error bad b.txt\n");
+
+        app.handle_action(Action::HandledByPane(PaneAction::MoveTo(MoveTarget::Down(1))));
+        app.handle_action(Action::HandledByPane(PaneAction::InsertNewline));
+        assert_eq!(app.current_pane().path, Some("b.txt".into()));
+        assert_eq!(app.current_pane().cursors.primary().offset, ByteOffset(9));
+
+        app.handle_action(Action::Esc);
+        app.handle_command("lints");
+        assert_eq!(app.current_pane().content.to_string(), "a.txt:3:2 error bad a.txt\n");
+    }
+
+    #[test]
+    fn lint_list_is_read_only() {
+        let mut app = App::new();
+        let mut pane = Pane::empty();
+        pane.path = Some("a.txt".into());
+        pane.lints = crate::linter::Lint::parse("a.txt:1:1:error:bad").into_iter().collect();
+        app.switch_to_new_pane(pane);
+        app.handle_command("lints");
+        app.handle_action(Action::HandledByPane(PaneAction::MoveTo(MoveTarget::Right(3))));
+        app.handle_action(Action::HandledByPane(PaneAction::Insert("x".into())));
+        app.handle_action(Action::HandledByPane(PaneAction::DeleteBackward));
+        app.handle_action(Action::Esc);
+        assert_eq!(app.current_pane().content.to_string(), "a.txt:1:1 error bad\n");
+        assert!(!app.current_pane().modified);
+        // the list is only rebuilt when the lints change
+        assert_eq!(app.current_pane().cursors.primary().offset, ByteOffset(3));
+    }
+
+    #[test]
+    fn repeat_transform() {
+        let mut app = App::new();
+        let mut pane = Pane::empty();
+        pane.content = RopeBuffer::from_str("foo bar baz");
+        app.switch_to_new_pane(pane);
+        app.handle_command("to again");
+        assert!(app.status_msg().is_some_and(|msg| msg.contains("no previous")));
+
+        app.current_pane_mut().cursors.primary_mut().selection_from = Some(ByteOffset(3));
+        app.handle_command("to *2");
+        assert_eq!(app.current_pane().content.to_string(), "foofoo bar baz");
+
+        let pane = app.current_pane_mut();
+        pane.cursors.primary_mut().offset = ByteOffset(7);
+        pane.cursors.primary_mut().selection_from = Some(ByteOffset(10));
+        pane.cursors.spawn_new(&pane.content, crate::Cursor::new_with_selection(ByteOffset(11), Some(ByteOffset(14))));
+        app.handle_command("to again");
+        assert_eq!(app.current_pane().content.to_string(), "foofoo barbar bazbaz");
+    }
+
+    #[test]
+    fn directory_listing_opens_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("file.txt"), "hello\n").unwrap();
+        let mut app = App::new();
+        app.open_file_in_new_pane(&FilePathWithOptionalLocation::from(dir.clone()));
+        assert_eq!(app.current_pane().content.to_string(), "../\nsub/\nfile.txt\n");
+        assert!(!app.current_pane().modified);
+
+        app.handle_action(Action::HandledByPane(PaneAction::MoveTo(MoveTarget::Down(1))));
+        app.handle_action(Action::HandledByPane(PaneAction::InsertNewline));
+        let action = app.action_queue.pop_front().unwrap();
+        app.handle_action(action);
+        assert_eq!(app.current_pane().dir_listing, Some(dir.join("sub")));
+        assert_eq!(app.current_pane().content.to_string(), "../\n");
+
+        app.handle_action(Action::HandledByPane(PaneAction::InsertNewline));
+        let action = app.action_queue.pop_front().unwrap();
+        app.handle_action(action);
+        assert_eq!(app.current_pane().dir_listing, Some(dir.clone()));
+        app.handle_action(Action::HandledByPane(PaneAction::MoveTo(MoveTarget::Down(2))));
+        app.handle_action(Action::HandledByPane(PaneAction::InsertNewline));
+        let action = app.action_queue.pop_front().unwrap();
+        app.handle_action(action);
+        assert_eq!(app.current_pane().path, Some(dir.join("file.txt")));
+        assert_eq!(app.current_pane().content.to_string(), "hello\n");
+    }
+
+    #[test]
+    fn directory_listing_is_read_only() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("file.txt"), "hello\n").unwrap();
+        let mut app = App::new();
+        app.open_file_in_new_pane(&FilePathWithOptionalLocation::from(tmp.path().to_path_buf()));
+        app.clipboard.copy(Clips::new(vec!["pasted".into()])).unwrap();
+        app.handle_action(Action::HandledByPane(PaneAction::Insert("x".into())));
+        app.handle_action(Action::HandledByPane(PaneAction::DeleteForward));
+        app.handle_action(Action::HandledByPane(PaneAction::DeleteWord));
+        app.handle_action(Action::Paste);
+        app.handle_action(Action::HandledByPane(PaneAction::SelectAll));
+        app.handle_action(Action::Cut);
+        assert_eq!(app.clipboard.content(), ["pasted"]);
+        assert_eq!(app.current_pane().content.to_string(), "../\nfile.txt\n");
+        assert!(!app.current_pane().modified);
+    }
+
+    #[test]
+    fn browse_and_go_up_with_backspace() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let mut app = App::new();
+        app.switch_to_new_pane(Pane::empty());
+        app.handle_command(&format!("browse {}", dir.join("sub").display()));
+        assert_eq!(app.panes.len(), 2);
+        assert_eq!(app.current_pane().dir_listing, Some(dir.join("sub")));
+        app.handle_action(Action::HandledByPane(PaneAction::DeleteBackward));
+        let action = app.action_queue.pop_front().unwrap();
+        app.handle_action(action);
+        assert_eq!(app.current_pane().dir_listing, Some(dir.clone()));
+        assert_eq!(app.current_pane().content.to_string(), "../\nsub/\n");
+
+        app.handle_command(&format!("browse {}", dir.join("missing").display()));
+        assert!(app.status_msg().is_some_and(|msg| msg.starts_with("browse error")));
+        assert_eq!(app.panes.len(), 2);
+    }
+
+    #[test]
+    fn copy_path_of_current_file() {
+        let mut app = App::new();
+        app.switch_to_new_pane(Pane::empty());
+        app.handle_command("copypath");
+        assert!(app.status_msg().is_some_and(|msg| msg.starts_with("copypath error")));
+        assert!(app.clipboard.content().is_empty());
+
+        app.current_pane_mut().path = Some("src/main.rs".into());
+        app.handle_command("copypath");
+        assert_eq!(app.clipboard.content(), ["src/main.rs"]);
+        app.handle_command("copypath abs");
+        let copied = std::path::PathBuf::from(&app.clipboard.content()[0]);
+        assert!(copied.is_absolute() && copied.ends_with("src/main.rs"));
+    }
+
+    #[test]
+    fn linewise_copy_and_paste() {
+        let mut app = App::new();
+        app.switch_to_new_pane(Pane::empty());
+        app.handle_action(Action::HandledByPane(PaneAction::Insert("ab\ncd".into())));
+        app.handle_action(Action::Copy);
+        assert_eq!(app.clipboard.content(), ["cd\n"]);
+        app.handle_action(Action::HandledByPane(PaneAction::MoveTo(MoveTarget::Left(1))));
+        app.handle_action(Action::Paste);
+        assert_eq!(app.current_pane().content.to_string(), "ab\ncd\ncd");
+        assert_eq!(app.current_pane().cursors.primary().offset, ByteOffset(7));
+
+        app.handle_action(Action::HandledByPane(PaneAction::MoveTo(MoveTarget::StartOfFile)));
+        let pane = app.current_pane_mut();
+        pane.cursors.spawn_new(&pane.content, crate::cursor::Cursor::new_with_offset(ByteOffset(4)));
+        app.handle_action(Action::Copy);
+        assert_eq!(app.clipboard.content(), ["ab\n", "cd\n"]);
+        app.handle_action(Action::Paste);
+        assert_eq!(app.current_pane().content.to_string(), "ab\nab\ncd\ncd\ncd");
+    }
+
+    #[test]
+    fn linewise_cut_and_paste() {
+        let mut app = App::new();
+        app.switch_to_new_pane(Pane::empty());
+        app.handle_action(Action::HandledByPane(PaneAction::Insert("ab\ncd\nef".into())));
+        app.handle_action(Action::HandledByPane(PaneAction::MoveTo(MoveTarget::Up(1))));
+        app.handle_action(Action::Cut);
+        assert_eq!(app.current_pane().content.to_string(), "ab\nef");
+        assert_eq!(app.clipboard.latest(), Some(&Clips::lines(vec!["cd\n".into()])));
+        app.handle_action(Action::Paste);
+        assert_eq!(app.current_pane().content.to_string(), "ab\ncd\nef");
+
+        app.handle_action(Action::HandledByPane(PaneAction::SelectTo(MoveTarget::Right(1))));
+        app.handle_action(Action::Cut);
+        assert_eq!(app.current_pane().content.to_string(), "ab\ncd\nf");
+        assert_eq!(app.clipboard.latest(), Some(&Clips::new(vec!["e".into()])));
+    }
+
+    #[test]
+    fn force_open_skips_file_size_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "hello\n").unwrap();
+        let mut app = App::new();
+        app.switch_to_new_pane(Pane::empty());
+        app.handle_command("set max_file_size 0");
+        assert_eq!(app.max_file_size, 0);
+        app.max_file_size = 1;
+        app.handle_command(&format!("open -f {}", path.display()));
+        let action = app.action_queue.pop_front().unwrap();
+        assert!(matches!(action, Action::Open { force: true, .. }));
+        app.handle_action(action);
+        assert_eq!(app.current_pane().content.to_string(), "hello\n");
+    }
+
+    #[test]
+    fn lossy_file_is_only_saved_when_forced() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, b"caf\xe9\n").unwrap();
+        let mut app = App::new();
+        app.open_file_in_new_pane(&FilePathWithOptionalLocation::from(path.clone()));
+        app.handle_action(Action::HandledByPane(PaneAction::Insert("x".into())));
+        app.handle_action(Action::Save);
+        assert!(app.status_msg().is_some_and(|msg| msg.contains("save -f")));
+        assert_eq!(std::fs::read(&path).unwrap(), b"caf\xe9\n");
+        app.current_pane_mut().settings.autosave = Some(std::time::Duration::ZERO);
+        assert!(!app.autosave_idle_panes());
+
+        app.handle_command("save -f");
+        let action = app.action_queue.pop_front().unwrap();
+        app.handle_action(action);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "xcaf\u{fffd}\n");
+    }
+
+    #[test]
+    fn oversized_file_is_not_read_into_new_pane() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "hello\n").unwrap();
+        let mut app = App::new();
+        app.max_file_size = 1;
+        assert!(app.open_file_in_new_pane(&FilePathWithOptionalLocation::from(path.clone())).is_none());
+        assert!(app.panes.is_empty());
+        assert!(app.info.as_ref().is_some_and(|msg| msg.contains("open -f")));
+        app.max_file_size = 0;
+        let pane = app.open_file_in_new_pane(&FilePathWithOptionalLocation::from(path)).unwrap();
+        assert_eq!(pane.content.to_string(), "hello\n");
+    }
+
+    #[test]
+    fn config_sets_editor_wide_settings() {
+        let mut app = App::new();
+        app.apply_config("# comment\n\nset max_file_size 1\nset clipboard_history 3\n");
+        assert_eq!(app.max_file_size, 1024 * 1024);
+        assert!(app.info.is_none());
+        app.apply_config("set indent_size 2\n");
+        assert!(app.info.as_ref().is_some_and(|msg| msg.contains("line 1: 'indent_size'")));
+        app.apply_config("\nmax_file_size 2\n");
+        assert!(app.info.as_ref().is_some_and(|msg| msg.contains("line 2: expected")));
+        assert_eq!(app.max_file_size, 1024 * 1024);
+    }
+
+    #[test]
+    fn scratch_opens_untitled_pane() {
+        let mut app = App::new();
+        app.switch_to_new_pane(Pane::empty());
+        app.handle_command("scratch python");
+        assert_eq!(app.panes.len(), 2);
+        assert_eq!(app.current_pane().filetype(), "python");
+        assert!(app.current_pane().path.is_none());
+        app.handle_command("scratch nosuchlanguage");
+        assert_eq!(app.panes.len(), 2);
+        app.handle_command("scratch");
+        assert_eq!(app.current_pane().filetype(), "plain");
+    }
+
+    #[test]
+    fn switching_theme_rehighlights_panes() {
+        let mut app = App::new();
+        let mut pane = Pane::empty();
+        pane.set_filetype("rust", app.highlighting.clone()).unwrap();
+        app.switch_to_new_pane(pane);
+        let background = |app: &mut App| {
+            let hl = app.current_pane_mut().highlighter.as_mut().unwrap();
+            hl.highlight_line("fn main() {}\n").next().unwrap().0.background
+        };
+        let dark = background(&mut app);
+
+        app.handle_command("set theme toggle");
+        assert_eq!(app.highlighting.theme_variant(), ThemeVariant::Light);
+        assert_ne!(background(&mut app), dark);
+        assert_eq!(app.current_pane().highlighter.as_ref().unwrap().ft(), "rust");
+
+        app.handle_command("set theme dark");
+        assert_eq!(background(&mut app), dark);
+        app.handle_command("set theme light");
+        assert_eq!(app.highlighting.theme_variant(), ThemeVariant::Light);
+        app.handle_command("set theme sepia");
+        assert!(app.status_msg().is_some_and(|msg| msg.starts_with("set error: theme")));
+    }
+}
@@ -9,9 +9,17 @@ pub struct FilePathWithOptionalLocation {
     pub path: PathBuf,
     pub line: Option<NonZeroUsize>,
     pub column: Option<NonZeroUsize>,
+    /// Text to search for after opening the file
+    pub search: Option<String>,
 }
 
 impl FilePathWithOptionalLocation {
+    /// Parses a path with an optional location suffix. In order of precedence:
+    ///
+    /// 1. `arg` is a path to an existing file (even if it contains colons)
+    /// 2. `file:/pattern` – everything after the first `:/` is the pattern
+    /// 3. `file:line:col` or `file:line` (`a:5:10` is line 10 of `a:5` if only `a:5` exists)
+    /// 4. anything else is a path to a (new) file
     pub fn parse_from_str(arg: &str, expand_path: bool) -> Self {
         let to_path = if expand_path {
             |s: &str| crate::expand_path(s)
@@ -20,41 +28,44 @@ impl FilePathWithOptionalLocation {
         };
 
         if to_path(arg).exists() {
-            return FilePathWithOptionalLocation {
-                path: to_path(arg),
-                line: None,
-                column: None,
+            return FilePathWithOptionalLocation::from(to_path(arg))
+        }
+        if let Some((path, pattern)) = arg.split_once(":/") {
+            if !pattern.is_empty() {
+                return FilePathWithOptionalLocation {
+                    search: Some(pattern.to_string()),
+                    ..FilePathWithOptionalLocation::from(to_path(path))
+                }
             }
         }
         if let Some((pre1, num)) = arg.rsplit_once(':') {
             if let Ok(num_last) = num.parse() {
                 if let Some((pre2, num)) = pre1.rsplit_once(':') {
-                    if let Ok(num_second_last) = num.parse() {
-                        return FilePathWithOptionalLocation {
-                            path: to_path(pre2),
-                            line: Some(num_second_last),
-                            column: Some(num_last),
+                    // `a:5:10` is line 10 of the file `a:5` if it exists but `a` doesn't
+                    let pre1_is_the_file = to_path(pre1).exists() && !to_path(pre2).exists();
+                    if !pre1_is_the_file {
+                        if let Ok(num_second_last) = num.parse() {
+                            return FilePathWithOptionalLocation {
+                                line: Some(num_second_last),
+                                column: Some(num_last),
+                                ..FilePathWithOptionalLocation::from(to_path(pre2))
+                            }
                         }
                     }
                 }
                 return FilePathWithOptionalLocation {
-                    path: to_path(pre1),
                     line: Some(num_last),
-                    column: None,
+                    ..FilePathWithOptionalLocation::from(to_path(pre1))
                 }
             }
         }
-        FilePathWithOptionalLocation {
-            path: to_path(arg),
-            line: None,
-            column: None,
-        }
+        FilePathWithOptionalLocation::from(to_path(arg))
     }
 }
 
 impl From<PathBuf> for FilePathWithOptionalLocation {
     fn from(value: PathBuf) -> Self {
-        Self { path: value, line: None, column: None }
+        Self { path: value, line: None, column: None, search: None }
     }
 }
 
@@ -74,7 +85,84 @@ pub fn parse_cli_args() -> clap::ArgMatches {
             Arg::new("file")
                 .value_parser(open_file_at_loc_parser)
                 .action(clap::ArgAction::Append)
-                .help("File to open, position can be specified via file[:row[:col]]"),
+                .help("File to open, position can be specified via file[:row[:col]] or file:/pattern, - reads standard input"),
+        )
+        .arg(
+            Arg::new("ft")
+                .long("ft")
+                .value_name("FILETYPE")
+                .help("Filetype of the buffer read from standard input")
         )
         .get_matches()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_line_and_column() {
+        let loc = FilePathWithOptionalLocation::parse_from_str("nonexistent.txt:12:3", false);
+        assert_eq!(loc.path, PathBuf::from("nonexistent.txt"));
+        assert_eq!(loc.line, NonZeroUsize::new(12));
+        assert_eq!(loc.column, NonZeroUsize::new(3));
+        assert_eq!(loc.search, None);
+    }
+
+    #[test]
+    fn parse_search_pattern() {
+        let loc = FilePathWithOptionalLocation::parse_from_str("nonexistent.txt:/fn main:/x", false);
+        assert_eq!(loc.path, PathBuf::from("nonexistent.txt"));
+        assert_eq!(loc.line, None);
+        assert_eq!(loc.search.as_deref(), Some("fn main:/x"));
+    }
+
+    /// Creates an empty file in a temporary directory of its own which is
+    /// removed when the returned guard is dropped
+    fn temp_file(name: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        std::fs::write(&path, "").unwrap();
+        (dir, path)
+    }
+
+    fn parse(arg: &str) -> (PathBuf, Option<usize>, Option<usize>) {
+        let loc = FilePathWithOptionalLocation::parse_from_str(arg, false);
+        (loc.path, loc.line.map(NonZeroUsize::get), loc.column.map(NonZeroUsize::get))
+    }
+
+    #[test]
+    fn parse_existing_file() {
+        let (_dir, path) = temp_file("plain.txt");
+        let s = path.to_str().unwrap();
+        assert_eq!(parse(s), (path.clone(), None, None));
+        assert_eq!(parse(&format!("{s}:10")), (path.clone(), Some(10), None));
+        assert_eq!(parse(&format!("{s}:10:5")), (path.clone(), Some(10), Some(5)));
+    }
+
+    #[test]
+    fn parse_existing_file_with_colons() {
+        let (_dir, path) = temp_file("a:b");
+        let s = path.to_str().unwrap();
+        assert_eq!(parse(s), (path.clone(), None, None));
+        assert_eq!(parse(&format!("{s}:10")), (path.clone(), Some(10), None));
+        assert_eq!(parse(&format!("{s}:10:5")), (path.clone(), Some(10), Some(5)));
+
+        let (_dir, path) = temp_file("a:5");
+        let s = path.to_str().unwrap();
+        assert_eq!(parse(s), (path.clone(), None, None));
+        assert_eq!(parse(&format!("{s}:10")), (path.clone(), Some(10), None));
+    }
+
+    #[test]
+    fn parse_windows_style_path() {
+        assert_eq!(parse(r"C:\src\main.rs"), (PathBuf::from(r"C:\src\main.rs"), None, None));
+        assert_eq!(parse(r"C:\src\main.rs:7"), (PathBuf::from(r"C:\src\main.rs"), Some(7), None));
+        assert_eq!(parse(r"C:\src\main.rs:7:2"), (PathBuf::from(r"C:\src\main.rs"), Some(7), Some(2)));
+    }
+
+    #[test]
+    fn zero_is_not_a_location() {
+        assert_eq!(parse("nonexistent.txt:0"), (PathBuf::from("nonexistent.txt:0"), None, None));
+    }
+}
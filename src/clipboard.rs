@@ -1,9 +1,63 @@
+use std::collections::{HashMap, VecDeque};
 use std::default::Default;
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+
 use crossterm::clipboard::CopyToClipboard;
 
-#[derive(Default)]
+/// Where copied text is sent in addition to the internal clipboard
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardBackend {
+    /// OSC52 escape sequences handled by the terminal (works over ssh)
+    #[default]
+    Osc52,
+    /// The `wl-copy`, `xclip` or `xsel` command
+    System,
+    /// Both of the above, not having any of the commands installed is fine
+    Both,
+}
+
+/// The text copied from each cursor at once
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Clips {
+    pub texts: Vec<String>,
+    /// Whole lines copied without a selection, these are pasted at the start
+    /// of the line instead of at the cursor
+    pub linewise: bool,
+}
+
+impl Clips {
+    pub fn new(texts: Vec<String>) -> Self {
+        Self { texts, linewise: false }
+    }
+
+    pub fn lines(texts: Vec<String>) -> Self {
+        Self { texts, linewise: true }
+    }
+}
+
 pub struct InternalClipboard {
-    clips: Vec<String>
+    /// The latest copies, newest first
+    history: VecDeque<Clips>,
+    /// Maximum number of copies kept in the history
+    history_size: usize,
+    /// Separator between the clips of multiple cursors in the external clipboard
+    joiner: String,
+    backend: ClipboardBackend,
+    /// Named storage (like vim registers) that never touches the external clipboard
+    registers: HashMap<char, Clips>,
+}
+
+impl Default for InternalClipboard {
+    fn default() -> Self {
+        Self {
+            history: VecDeque::new(),
+            history_size: 10,
+            joiner: "\n".to_string(),
+            backend: ClipboardBackend::default(),
+            registers: HashMap::new(),
+        }
+    }
 }
 
 impl InternalClipboard {
@@ -11,14 +65,261 @@ impl InternalClipboard {
         Self::default()
     }
 
-    pub fn copy(&mut self, content: Vec<String>) {
-        self.clips = content;
-        let content = self.content().join("\n");
-        let _ = crossterm::execute!(std::io::stdout(), CopyToClipboard::to_clipboard_from(&content));
-        let _ = crossterm::execute!(std::io::stdout(), CopyToClipboard::to_primary_from(&content));
+    /// Adds the clips to the history and copies them to the external clipboard
+    /// and the primary selection, only starting the system clipboard commands
+    /// can fail (they run in the background)
+    pub fn copy(&mut self, clips: Clips) -> std::io::Result<()> {
+        self.history.push_front(clips);
+        self.history.truncate(self.history_size);
+        if cfg!(test) {
+            // tests must not overwrite the clipboard of whoever runs them
+            return Ok(())
+        }
+        let content = self.joined();
+        if self.backend != ClipboardBackend::System {
+            let _ = crossterm::execute!(std::io::stdout(), CopyToClipboard::to_clipboard_from(&content));
+            let _ = crossterm::execute!(std::io::stdout(), CopyToClipboard::to_primary_from(&content));
+        }
+        if self.backend != ClipboardBackend::Osc52 {
+            let result = write_clipboard_tool(false, content.clone())
+                .and_then(|()| write_clipboard_tool(true, content));
+            match result {
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound && self.backend == ClipboardBackend::Both => {}
+                result => result?,
+            }
+        }
+        Ok(())
     }
 
     pub fn content(&self) -> &[String] {
-        &self.clips
+        self.latest().map_or(&[], |clips| &clips.texts)
+    }
+
+    pub fn latest(&self) -> Option<&Clips> {
+        self.history.front()
+    }
+
+    /// An earlier copy, 0 is the latest one
+    pub fn history_entry(&self, index: usize) -> Option<&Clips> {
+        self.history.get(index)
+    }
+
+    pub fn history(&self) -> impl Iterator<Item = &Clips> {
+        self.history.iter()
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Keeps at most `size` copies (but always at least the latest one)
+    pub fn set_history_size(&mut self, size: usize) {
+        self.history_size = size.max(1);
+        self.history.truncate(self.history_size);
+    }
+
+    pub fn copy_to_register(&mut self, register: char, clips: Clips) {
+        self.registers.insert(register, clips);
+    }
+
+    pub fn content_of_register(&self, register: char) -> Option<&Clips> {
+        self.registers.get(&register)
+    }
+
+    /// The content as it is sent to the external clipboard, whole lines
+    /// already end in line endings so they are not joined with anything
+    pub fn joined(&self) -> String {
+        match self.latest() {
+            Some(clips) if clips.linewise => clips.texts.concat(),
+            _ => self.content().join(&self.joiner),
+        }
+    }
+
+    pub fn set_joiner(&mut self, joiner: String) {
+        self.joiner = joiner;
+    }
+
+    pub fn set_backend(&mut self, backend: ClipboardBackend) {
+        self.backend = backend;
+    }
+
+    /// Reads the primary selection (the text last selected with the mouse on
+    /// X11 and Wayland). Terminals don't allow reading it with an escape
+    /// sequence so one of `wl-paste`, `xclip` or `xsel` has to be installed.
+    /// The error includes what the tool printed to explain the failure.
+    pub fn primary_content(&self) -> std::io::Result<String> {
+        read_clipboard_tool(true)
+    }
+}
+
+/// Commands for reading or writing the clipboard (or the primary selection)
+/// in the order they are tried
+fn clipboard_tools(primary: bool, write: bool) -> Vec<Vec<&'static str>> {
+    let mut tools = vec![
+        vec!["xclip", "-selection", if primary { "primary" } else { "clipboard" }, if write { "-i" } else { "-o" }],
+        vec!["xsel", if primary { "--primary" } else { "--clipboard" }, if write { "--input" } else { "--output" }],
+    ];
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        let mut wayland = vec![if write { "wl-copy" } else { "wl-paste" }];
+        if primary {
+            wayland.push("--primary");
+        }
+        if !write {
+            wayland.push("--no-newline");
+        }
+        tools.insert(0, wayland);
+    }
+    tools
+}
+
+/// Starts the first clipboard tool that is installed, the error is of kind
+/// `NotFound` if there are none. Returns the name of the tool too.
+fn spawn_clipboard_tool(primary: bool, write: bool) -> std::io::Result<(Child, &'static str)> {
+    for tool in clipboard_tools(primary, write) {
+        let spawned = Command::new(tool[0])
+            .args(&tool[1..])
+            .stdin(if write { Stdio::piped() } else { Stdio::null() })
+            // the writing tools stay in the background to serve the selection
+            // so their output can not be waited on
+            .stdout(if write { Stdio::null() } else { Stdio::piped() })
+            .stderr(if write { Stdio::null() } else { Stdio::piped() })
+            .spawn();
+        match spawned {
+            Ok(child) => return Ok((child, tool[0])),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "the system clipboard requires wl-clipboard, xclip or xsel",
+    ))
+}
+
+/// Writes `content` to the clipboard with the first tool that is installed.
+/// The input is written and the tool waited on in another thread so that a
+/// slow tool doesn't freeze the editor.
+fn write_clipboard_tool(primary: bool, content: String) -> std::io::Result<()> {
+    let (mut child, _) = spawn_clipboard_tool(primary, true)?;
+    std::thread::spawn(move || {
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(content.as_bytes());
+        }
+        let _ = child.wait();
+    });
+    Ok(())
+}
+
+/// Reads the clipboard with the first tool that is installed, an empty
+/// selection is not an error
+fn read_clipboard_tool(primary: bool) -> std::io::Result<String> {
+    let (child, name) = spawn_clipboard_tool(primary, false)?;
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if is_empty_selection_error(&stderr) {
+        return Ok(String::new())
+    }
+    // the last line of the error output usually says what went wrong
+    Err(match stderr.lines().map(str::trim).rfind(|line| !line.is_empty()) {
+        Some(reason) => std::io::Error::other(format!("{name} failed: {reason}")),
+        None => std::io::Error::other(format!("{name} failed ({})", output.status)),
+    })
+}
+
+/// Checks if the error output of a clipboard tool means that there simply
+/// is nothing selected, eg. `wl-paste` prints "Nothing is copied" and `xclip`
+/// prints "Error: target STRING not available"
+fn is_empty_selection_error(stderr: &str) -> bool {
+    stderr.lines().any(|line| {
+        let line = line.trim();
+        line.starts_with("Nothing is copied")
+            || line.starts_with("No selection")
+            || (line.contains("target ") && line.ends_with(" not available"))
+    })
+}
+
+/// Expands the escapes `\n`, `\t`, `\s` (space) and `\\` so that whitespace
+/// can be given as a setting value
+pub(crate) fn unescape(s: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('s') => result.push(' '),
+            Some('\\') => result.push('\\'),
+            Some(other) => return Err(format!("unknown escape '\\{other}'")),
+            None => return Err("trailing '\\'".to_string()),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_clips_with_unescaped_joiner() {
+        let mut clipboard = InternalClipboard::new();
+        clipboard.copy(Clips::new(vec!["a".into(), "b".into(), "c".into()])).unwrap();
+        assert_eq!(clipboard.joined(), "a\nb\nc");
+        clipboard.set_joiner(unescape(",\\s").unwrap());
+        assert_eq!(clipboard.joined(), "a, b, c");
+        assert_eq!(clipboard.content(), ["a", "b", "c"]);
+        clipboard.copy(Clips::lines(vec!["a\n".into(), "b\n".into()])).unwrap();
+        assert_eq!(clipboard.joined(), "a\nb\n");
+        assert_eq!(unescape("\\t|\\\\").unwrap(), "\t|\\");
+        assert!(unescape("\\x").is_err());
+        assert!(unescape("x\\").is_err());
+    }
+
+    #[test]
+    fn empty_selection_is_not_an_error() {
+        assert!(is_empty_selection_error("Nothing is copied\n"));
+        assert!(is_empty_selection_error("No selection\n"));
+        assert!(is_empty_selection_error("Error: target STRING not available\n"));
+        assert!(is_empty_selection_error("Error: target UTF8_STRING not available\n"));
+        assert!(!is_empty_selection_error("Error: Can't open display: (null)\n"));
+        assert!(!is_empty_selection_error("Failed to connect to a Wayland server\n"));
+        assert!(!is_empty_selection_error(""));
+    }
+
+    #[test]
+    fn registers_are_separate() {
+        let mut clipboard = InternalClipboard::new();
+        clipboard.copy(Clips::new(vec!["clipboard".into()])).unwrap();
+        clipboard.copy_to_register('a', Clips::new(vec!["a1".into(), "a2".into()]));
+        clipboard.copy_to_register('b', Clips::lines(vec!["b\n".into()]));
+        assert_eq!(clipboard.content(), ["clipboard"]);
+        assert_eq!(clipboard.history_len(), 1);
+        assert_eq!(clipboard.content_of_register('a'), Some(&Clips::new(vec!["a1".into(), "a2".into()])));
+        assert_eq!(clipboard.content_of_register('b'), Some(&Clips::lines(vec!["b\n".into()])));
+        assert_eq!(clipboard.content_of_register('c'), None);
+    }
+
+    #[test]
+    fn copies_are_kept_in_history() {
+        let mut clipboard = InternalClipboard::new();
+        clipboard.set_history_size(3);
+        for clip in ["a", "b", "b", "c"] {
+            clipboard.copy(Clips::new(vec![clip.into()])).unwrap();
+        }
+        assert_eq!(clipboard.history_len(), 3);
+        assert_eq!(clipboard.content(), ["c"]);
+        assert_eq!(clipboard.history_entry(1), Some(&Clips::new(vec!["b".into()])));
+        assert_eq!(clipboard.history_entry(2), Some(&Clips::new(vec!["b".into()])));
+        assert_eq!(clipboard.history_entry(3), None);
+        clipboard.set_history_size(0);
+        assert_eq!(clipboard.history_len(), 1);
+        assert_eq!(clipboard.content(), ["c"]);
     }
 }
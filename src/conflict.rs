@@ -0,0 +1,195 @@
+use ropey::RopeSlice;
+
+use crate::ByteOffset;
+use crate::ropebuffer::RopeBuffer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConflictMarker {
+    /// `<<<<<<< ours`
+    Start,
+    /// `||||||| base` (only in diff3-style conflicts)
+    Base,
+    /// `=======`
+    Separator,
+    /// `>>>>>>> theirs`
+    End,
+}
+
+fn conflict_marker(line: RopeSlice) -> Option<ConflictMarker> {
+    let prefix: String = line.chars().take(8).collect();
+    let (marker, rest) = prefix.split_at_checked(7)?;
+    let at_eol = matches!(rest, "" | "\n" | "\r");
+    let label_or_eol = at_eol || rest == " ";
+    match marker {
+        "<<<<<<<" if label_or_eol => Some(ConflictMarker::Start),
+        "|||||||" if label_or_eol => Some(ConflictMarker::Base),
+        "=======" if at_eol => Some(ConflictMarker::Separator),
+        ">>>>>>>" if label_or_eol => Some(ConflictMarker::End),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictRegion {
+    Marker,
+    Ours,
+    Base,
+    Theirs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictResolution {
+    Ours,
+    Theirs,
+    Both,
+}
+
+/// Line numbers (0-based) of the markers of a merge conflict
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conflict {
+    pub start: usize,
+    pub base: Option<usize>,
+    pub separator: usize,
+    pub end: usize,
+}
+
+impl Conflict {
+    fn parse_at(content: &RopeBuffer, start: usize) -> Option<Conflict> {
+        if conflict_marker(content.lines_at(start).next()?) != Some(ConflictMarker::Start) {
+            return None
+        }
+        let mut base = None;
+        let mut separator = None;
+        for (line, lineno) in content.lines_at(start + 1).zip(start + 1..) {
+            match conflict_marker(line) {
+                Some(ConflictMarker::Start) => return None,
+                Some(ConflictMarker::Base) if base.is_none() && separator.is_none() => base = Some(lineno),
+                Some(ConflictMarker::Separator) if separator.is_none() => separator = Some(lineno),
+                Some(ConflictMarker::End) => {
+                    if let Some(separator) = separator {
+                        return Some(Conflict { start, base, separator, end: lineno })
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    pub fn contains_line(&self, lineno: usize) -> bool {
+        (self.start..=self.end).contains(&lineno)
+    }
+
+    pub fn region_of_line(&self, lineno: usize) -> Option<ConflictRegion> {
+        if !self.contains_line(lineno) {
+            None
+        } else if lineno == self.start || lineno == self.separator || lineno == self.end || Some(lineno) == self.base {
+            Some(ConflictRegion::Marker)
+        } else if lineno > self.separator {
+            Some(ConflictRegion::Theirs)
+        } else if self.base.is_some_and(|base| lineno > base) {
+            Some(ConflictRegion::Base)
+        } else {
+            Some(ConflictRegion::Ours)
+        }
+    }
+
+    /// Byte range of the whole conflict including the line break after the end marker
+    pub fn byte_range(&self, content: &RopeBuffer) -> std::ops::Range<ByteOffset> {
+        content.line_to_byte(self.start)..content.line_to_byte(self.end + 1)
+    }
+
+    /// Text that replaces the conflict when it is resolved with `resolution`
+    pub fn resolved(&self, content: &RopeBuffer, resolution: ConflictResolution) -> String {
+        let lines = |from: usize, to: usize| content.slice(&(content.line_to_byte(from)..content.line_to_byte(to))).to_string();
+        let ours = || lines(self.start + 1, self.base.unwrap_or(self.separator));
+        let theirs = || lines(self.separator + 1, self.end);
+        match resolution {
+            ConflictResolution::Ours => ours(),
+            ConflictResolution::Theirs => theirs(),
+            ConflictResolution::Both => ours() + &theirs(),
+        }
+    }
+}
+
+/// Finds all well-formed merge conflicts in `content`
+pub fn find_conflicts(content: &RopeBuffer) -> Vec<Conflict> {
+    let mut conflicts = vec![];
+    let mut search_from = ByteOffset(0);
+    while let Some(offset) = content.find_next(search_from, "<<<<<<<") {
+        let lineno = content.byte_to_line(offset);
+        let next_lineno = match Conflict::parse_at(content, lineno) {
+            Some(conflict) => {
+                conflicts.push(conflict);
+                conflict.end + 1
+            }
+            None => lineno + 1,
+        };
+        match content.try_line_to_byte(next_lineno) {
+            Some(next) if next.0 < content.len_bytes() => search_from = next,
+            _ => break,
+        }
+    }
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFLICTED: &str = concat!(
+        "fn main() {\n",
+        "<<<<<<< HEAD\n",
+        "    println!(\"ours\");\n",
+        "=======\n",
+        "    println!(\"theirs\");\n",
+        ">>>>>>> feature\n",
+        "}\n",
+        "<<<<<<< HEAD\n",
+        "a\n",
+        "||||||| base\n",
+        "b\n",
+        "=======\n",
+        "c\n",
+        ">>>>>>> feature",
+    );
+
+    #[test]
+    fn finds_conflicts() {
+        let content = RopeBuffer::from_str(CONFLICTED);
+        assert_eq!(find_conflicts(&content), vec![
+            Conflict { start: 1, base: None, separator: 3, end: 5 },
+            Conflict { start: 7, base: Some(9), separator: 11, end: 13 },
+        ]);
+    }
+
+    #[test]
+    fn ignores_incomplete_conflicts() {
+        let content = RopeBuffer::from_str("<<<<<<< HEAD\na\n>>>>>>> x\n=======\n<<<<<<<<\n");
+        assert_eq!(find_conflicts(&content), vec![]);
+    }
+
+    #[test]
+    fn regions() {
+        let content = RopeBuffer::from_str(CONFLICTED);
+        let conflicts = find_conflicts(&content);
+        let region = |lineno| conflicts.iter().find_map(|c| c.region_of_line(lineno));
+        assert_eq!(region(0), None);
+        assert_eq!(region(1), Some(ConflictRegion::Marker));
+        assert_eq!(region(2), Some(ConflictRegion::Ours));
+        assert_eq!(region(4), Some(ConflictRegion::Theirs));
+        assert_eq!(region(6), None);
+        assert_eq!(region(10), Some(ConflictRegion::Base));
+    }
+
+    #[test]
+    fn resolve() {
+        let content = RopeBuffer::from_str(CONFLICTED);
+        let conflicts = find_conflicts(&content);
+        assert_eq!(conflicts[0].resolved(&content, ConflictResolution::Ours), "    println!(\"ours\");\n");
+        assert_eq!(conflicts[0].resolved(&content, ConflictResolution::Theirs), "    println!(\"theirs\");\n");
+        assert_eq!(conflicts[1].resolved(&content, ConflictResolution::Both), "a\nc\n");
+        let range = conflicts[1].byte_range(&content);
+        assert_eq!(range.end.0, content.len_bytes());
+    }
+}
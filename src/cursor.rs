@@ -1,7 +1,7 @@
 use std::ops::Range;
 
 use crate::ropebuffer::RopeBuffer;
-use crate::{ByteOffset, MoveTarget};
+use crate::{ByteOffset, CharSearch, MoveTarget};
 
 #[derive(Debug, Clone)]
 pub struct MultiCursor {
@@ -32,25 +32,65 @@ impl MultiCursor {
         self.cursors.len()
     }
 
-    /// Adds a new cursor and sets it as the primary cursor.
+    /// Adds a new cursor and sets it as the primary cursor, merging it with
+    /// the cursors it overlaps.
     /// Returns `false` and does nothing if equivalent cursor already exists.
-    pub fn spawn_new_primary(&mut self, new: Cursor) -> bool {
-        if self.spawn_new(new) {
+    pub fn spawn_new_primary(&mut self, content: &RopeBuffer, new: Cursor) -> bool {
+        self.push_and_normalize(content, new, true)
+    }
+
+    /// Adds a new cursor, merging it with the cursors it overlaps.
+    /// Returns `false` and does nothing if equivalent cursor already exists.
+    pub fn spawn_new(&mut self, content: &RopeBuffer, new: Cursor) -> bool {
+        self.push_and_normalize(content, new, false)
+    }
+
+    fn push_and_normalize(&mut self, content: &RopeBuffer, new: Cursor, primary: bool) -> bool {
+        if self.cursors.iter().any(|old| old.pos() == new.pos()) {
+            return false
+        }
+        self.cursors.push(new);
+        if primary {
             self.primary_index = self.cursors.len() - 1;
-            true
-        } else {
-            false
         }
+        self.normalize(content);
+        true
     }
 
-    /// Adds a new cursor.
-    /// Returns `false` and does nothing if equivalent cursor already exists.
-    pub fn spawn_new(&mut self, new: Cursor) -> bool {
-        if self.cursors.iter().all(|old| old.pos() != new.pos()) {
-            self.cursors.push(new);
-            true
-        } else {
-            false
+    /// Sorts the cursors by position and merges the ones that overlap. Cursors
+    /// without a selection are absorbed by a selection they are inside of (or at
+    /// either end of) and cursors at the same position are merged, but selections
+    /// that merely touch are kept apart so that they can still be edited
+    /// separately. The cursor that the primary cursor gets merged into becomes
+    /// the new primary cursor.
+    pub fn normalize(&mut self, content: &RopeBuffer) {
+        let end_of_content = ByteOffset(content.len_bytes());
+        let primary_index = self.primary_index;
+        let mut cursors: Vec<(bool, Cursor)> = self.cursors
+            .drain(..)
+            .enumerate()
+            .map(|(i, mut cursor)| {
+                cursor.offset = cursor.offset.min(end_of_content);
+                cursor.selection_from = cursor.selection_from.map(|sel| sel.min(end_of_content));
+                (i == primary_index, cursor)
+            })
+            .collect();
+        cursors.sort_by_key(|(_, cursor)| (cursor.pos(), cursor.end()));
+        for (is_primary, cursor) in cursors {
+            match self.cursors.last_mut() {
+                Some(last) if last.overlaps(&cursor) => {
+                    *last = last.merged_with(&cursor);
+                    if is_primary {
+                        self.primary_index = self.cursors.len() - 1;
+                    }
+                }
+                _ => {
+                    if is_primary {
+                        self.primary_index = self.cursors.len();
+                    }
+                    self.cursors.push(cursor);
+                }
+            }
         }
     }
 
@@ -60,6 +100,38 @@ impl MultiCursor {
         self.primary_index = new_primary;
     }
 
+    /// Replaces each selection with a cursor at the end of every line it covers,
+    /// a selection that ends at the start of a line doesn't cover that line.
+    /// Cursors without a selection are kept as they are.
+    pub fn split_to_line_ends(&mut self, content: &RopeBuffer) {
+        let mut cursors = vec![];
+        let mut primary = 0;
+        for (i, cursor) in self.cursors.iter().enumerate() {
+            let lines = match cursor.selection() {
+                Some(selection) => {
+                    let mut lines = cursor.line_span(content);
+                    if lines.len() > 1 && content.line_to_byte(lines.end - 1) == selection.end {
+                        lines.end -= 1;
+                    }
+                    lines
+                }
+                None => 0..0,
+            };
+            if i == self.primary_index {
+                primary = cursors.len() + lines.len().saturating_sub(1);
+            }
+            if lines.is_empty() {
+                cursors.push(*cursor);
+            }
+            for line in lines {
+                let line_start = Cursor::new_with_offset(content.line_to_byte(line));
+                cursors.push(Cursor::new_with_offset(line_start.line_end(content)));
+            }
+        }
+        self.set_cursors(primary, cursors);
+        self.normalize(content);
+    }
+
     /// Called when Esc is pressed, removes selections and extra cursors
     pub fn esc(&mut self) {
         for cursor in self.iter_mut() {
@@ -183,6 +255,7 @@ impl Cursor {
             MoveTarget::NextWordBoundaryLeft => Some(self.word_boundary_left(content)),
             MoveTarget::NextWordBoundaryRight => Some(self.word_boundary_right(content)),
             MoveTarget::MatchingPair => self.matching_pair(content),
+            MoveTarget::Char(search) => self.char_on_line(content, search),
             MoveTarget::ByteOffset(b) => {
                 // try to find a nearby grapheme cluster boundary to tolerate some imprecision
                 for d in 0..5 {
@@ -323,50 +396,119 @@ impl Cursor {
     }
 
     pub fn word_boundary_left(&self, content: &RopeBuffer) -> ByteOffset {
-        let mut p = self.offset;
-        while let Some(prev) = content.previous_boundary_from(p) {
-            if content.is_word_boundary(prev) {
-                return prev
-            }
-            p = prev;
-        }
-        ByteOffset(0)
+        content.word_boundaries_backward(self.offset).next().unwrap_or(ByteOffset(0))
     }
 
     pub fn word_boundary_right(&self, content: &RopeBuffer) -> ByteOffset {
-        let mut p = self.offset;
-        while let Some(next) = content.next_boundary_from(p) {
-            if content.is_word_boundary(next) {
-                return next
+        content.word_boundaries(self.offset).next().unwrap_or(ByteOffset(content.len_bytes()))
+    }
+
+    /// Finds the target of a character search, only the current line is searched
+    pub fn char_on_line(&self, content: &RopeBuffer, search: CharSearch) -> Option<ByteOffset> {
+        let mut buf = [0; 4];
+        let needle = search.target.encode_utf8(&mut buf).as_bytes();
+        let is_match = |pos: ByteOffset| content.bytes_at(pos).take(needle.len()).eq(needle.iter().copied());
+        if search.backward {
+            let line_start = self.line_start(content);
+            // the character right before the cursor is skipped when the cursor
+            // would stay in place (so that repeating the search works)
+            let end = if search.till { content.previous_boundary_from(self.offset)? } else { self.offset };
+            let pos = content.bytes_at(end)
+                .reversed()
+                .take(end.0.saturating_sub(line_start.0))
+                .zip(1..)
+                .filter(|(b, _)| *b == needle[0])
+                .map(|(_, i)| ByteOffset(end.0 - i))
+                .find(|pos| is_match(*pos))?;
+            Some(if search.till { ByteOffset(pos.0 + needle.len()) } else { pos })
+        } else {
+            let line_end = self.line_end(content);
+            let mut start = content.next_boundary_from(self.offset)?;
+            if search.till {
+                start = content.next_boundary_from(start)?;
             }
-            p = next;
+            let pos = content.bytes_at(start)
+                .take(line_end.0.saturating_sub(start.0))
+                .zip(start.0..)
+                .filter(|(b, _)| *b == needle[0])
+                .map(|(_, i)| ByteOffset(i))
+                .find(|pos| is_match(*pos))?;
+            if search.till { content.previous_boundary_from(pos) } else { Some(pos) }
         }
-        ByteOffset(content.len_bytes())
     }
 
     pub fn matching_pair(&self, content: &RopeBuffer) -> Option<ByteOffset> {
-        let find_pair = |close: u8, open: u8, backwards: bool| -> Option<ByteOffset> {
+        self.matching_pair_ignoring(content, |_| false)
+    }
+
+    /// The bracket under the cursor (or right before it if the cursor is just
+    /// after a closing bracket) and the offset of its matching pair. Only the
+    /// bytes `within` are searched so that the cost doesn't depend on the size
+    /// of the buffer, and `<>` only count as brackets if `angle_brackets` is set.
+    pub fn bracket_pair(
+        &self,
+        content: &RopeBuffer,
+        within: Range<ByteOffset>,
+        angle_brackets: bool,
+    ) -> Option<(ByteOffset, ByteOffset)> {
+        if let Some(pair) = self.find_pair(content, within.clone(), angle_brackets, |_| false) {
+            return Some((self.offset, pair))
+        }
+        let before = ByteOffset(self.offset.0.checked_sub(1)?);
+        match content.get_byte(before) {
+            Some(b')' | b']' | b'}') => {}
+            Some(b'>') if angle_brackets => {}
+            _ => return None,
+        }
+        let pair = Cursor::new_with_offset(before).find_pair(content, within, angle_brackets, |_| false)?;
+        Some((before, pair))
+    }
+
+    /// Like `matching_pair` but brackets at offsets for which `is_ignored`
+    /// returns true (eg. ones inside strings) are not counted
+    pub fn matching_pair_ignoring(
+        &self,
+        content: &RopeBuffer,
+        is_ignored: impl FnMut(ByteOffset) -> bool,
+    ) -> Option<ByteOffset> {
+        self.find_pair(content, ByteOffset(0)..ByteOffset(content.len_bytes()), true, is_ignored)
+    }
+
+    fn find_pair(
+        &self,
+        content: &RopeBuffer,
+        within: Range<ByteOffset>,
+        angle_brackets: bool,
+        mut is_ignored: impl FnMut(ByteOffset) -> bool,
+    ) -> Option<ByteOffset> {
+        if !within.contains(&self.offset) {
+            return None
+        }
+        let mut find_pair = |close: u8, open: u8, backwards: bool| -> Option<ByteOffset> {
             let mut bytes = content.bytes_at(self.offset);
-            if backwards {
+            let limit = if backwards {
                 bytes.reverse();
+                self.offset.0 - within.start.0
             } else {
                 bytes.next();
-            }
+                within.end.0 - self.offset.0 - 1
+            };
             let mut depth = 1;
-            for (b, i) in bytes.zip(1..) {
+            for (b, i) in bytes.take(limit).zip(1..) {
+                if b != open && b != close {
+                    continue
+                }
+                let pos = if backwards { ByteOffset(self.offset.0 - i) } else { ByteOffset(self.offset.0 + i) };
+                if is_ignored(pos) {
+                    continue
+                }
                 if b == open {
                     depth += 1;
                 }
                 if b == close {
                     depth -= 1;
                     if depth == 0 {
-                        return Some(
-                            if backwards {
-                                ByteOffset(self.offset.0 - i)
-                            } else {
-                                ByteOffset(self.offset.0 + i)
-                            }
-                        )
+                        return Some(pos)
                     }
                 }
             }
@@ -376,11 +518,11 @@ impl Cursor {
             Some(b'(') => find_pair(b')', b'(', false),
             Some(b'[') => find_pair(b']', b'[', false),
             Some(b'{') => find_pair(b'}', b'{', false),
-            Some(b'<') => find_pair(b'>', b'<', false),
+            Some(b'<') if angle_brackets => find_pair(b'>', b'<', false),
             Some(b')') => find_pair(b'(', b')', true),
             Some(b']') => find_pair(b'[', b']', true),
             Some(b'}') => find_pair(b'{', b'}', true),
-            Some(b'>') => find_pair(b'<', b'>', true),
+            Some(b'>') if angle_brackets => find_pair(b'<', b'>', true),
             _ => None,
         }
     }
@@ -404,6 +546,36 @@ impl Cursor {
         }
     }
 
+    /// The end of the selection or the offset if there is no selection
+    fn end(&self) -> ByteOffset {
+        self.selection_from.map_or(self.offset, |sel| sel.max(self.offset))
+    }
+
+    /// Whether `self` and `other` (which must not start before `self`) should
+    /// be merged, see [`MultiCursor::normalize`]
+    fn overlaps(&self, other: &Cursor) -> bool {
+        if !self.has_selection() || !other.has_selection() {
+            other.pos() <= self.end()
+        } else {
+            other.pos() < self.end()
+        }
+    }
+
+    fn merged_with(&self, other: &Cursor) -> Cursor {
+        match (self.has_selection(), other.has_selection()) {
+            (_, false) => *self,
+            (false, true) => *other,
+            (true, true) => {
+                let (start, end) = (self.pos(), self.end().max(other.end()));
+                if self.offset < self.selection_from.unwrap_or(self.offset) {
+                    Cursor::new_with_selection(start, Some(end))
+                } else {
+                    Cursor::new_with_selection(end, Some(start))
+                }
+            }
+        }
+    }
+
     fn pos(&self) -> ByteOffset {
         if let Some(sel) = self.selection_from {
             sel.min(self.offset)
@@ -471,6 +643,42 @@ mod tests {
         Cursor { offset, selection_from, ..Default::default() }
     }
 
+    #[rstest]
+    #[case::find(5, 'x', false, false, Some(7))]
+    #[case::skips_char_under_cursor(5, 'b', false, false, Some(8))]
+    #[case::till(5, 'x', true, false, Some(6))]
+    #[case::find_backward(5, 'ä', false, true, Some(3))]
+    #[case::till_backward(8, 'ä', true, true, Some(5))]
+    #[case::till_backward_adjacent(5, 'ä', true, true, None)]
+    #[case::only_current_line(5, 'z', false, false, None)]
+    #[case::only_current_line_backward(5, 'a', false, true, None)]
+    fn char_search(
+        #[case] offset: usize,
+        #[case] target: char,
+        #[case] till: bool,
+        #[case] backward: bool,
+        #[case] expected: Option<usize>,
+    ) {
+        let r = RopeBuffer::from_str("ab\näbcxbx\nz");
+        let search = CharSearch { target, till, backward };
+        assert_eq!(cursor(offset, None).char_on_line(&r, search), expected.map(ByteOffset));
+    }
+
+    #[test]
+    fn repeat_till_does_not_get_stuck() {
+        let r = RopeBuffer::from_str("a.b.c.");
+        let mut c = cursor(0, None);
+        let search = CharSearch { target: '.', till: true, backward: false };
+        c.move_to(&r, MoveTarget::Char(search));
+        assert_eq!(c.offset, ByteOffset(2));
+        c.move_to(&r, MoveTarget::Char(search));
+        assert_eq!(c.offset, ByteOffset(4));
+        c.move_to(&r, MoveTarget::Char(search.reversed()));
+        assert_eq!(c.offset, ByteOffset(2));
+        c.move_to(&r, MoveTarget::Char(search.reversed()));
+        assert_eq!(c.offset, ByteOffset(2));
+    }
+
     #[test]
     fn move_right() {
         let s = format!("a{SIMPLE_EMOJI}ä{THUMBS_UP_WITH_MODIFIER}b{FAMILY}");
@@ -700,6 +908,7 @@ mod tests {
     #[case("(]", 0, None)]
     #[case("[)", 1, None)]
     #[case("(a)", 1, None)]
+    #[case("(a)", 3, None)]
     #[case("", 0, None)]
     fn matching_pair(
         #[case] s: &'static str,
@@ -711,6 +920,63 @@ mod tests {
         assert_eq!(cursor.matching_pair(&r), expected)
     }
 
+    #[rstest]
+    #[case("f(x)", 1, Some((1, 3)))]
+    #[case("f(x)", 3, Some((3, 1)))]
+    // just after a closing bracket (where `matching_pair` finds nothing)
+    #[case("f(x)", 4, Some((3, 1)))]
+    #[case("[a](b)", 3, Some((3, 5)))]
+    // not after an opening bracket
+    #[case("f(x)", 2, None)]
+    #[case("(x", 2, None)]
+    #[case("", 0, None)]
+    // angle brackets are not matched unless asked for
+    #[case("a->b<c>", 4, None)]
+    #[case("a->b<c>", 7, None)]
+    fn bracket_pair(
+        #[case] s: &'static str,
+        #[case] start: usize,
+        #[case] expected: Option<(usize, usize)>,
+    ) {
+        let r = RopeBuffer::from_str(s);
+        let cursor = Cursor::new_with_offset(ByteOffset(start));
+        let everything = ByteOffset(0)..ByteOffset(r.len_bytes());
+        assert_eq!(cursor.bracket_pair(&r, everything, false), expected.map(|(a, b)| (ByteOffset(a), ByteOffset(b))));
+    }
+
+    #[rstest]
+    #[case("<b>", 0, Some((0, 2)))]
+    #[case("<b>", 3, Some((2, 0)))]
+    fn bracket_pair_angle_brackets(
+        #[case] s: &'static str,
+        #[case] start: usize,
+        #[case] expected: Option<(usize, usize)>,
+    ) {
+        let r = RopeBuffer::from_str(s);
+        let cursor = Cursor::new_with_offset(ByteOffset(start));
+        let everything = ByteOffset(0)..ByteOffset(r.len_bytes());
+        assert_eq!(cursor.bracket_pair(&r, everything, true), expected.map(|(a, b)| (ByteOffset(a), ByteOffset(b))));
+    }
+
+    #[rstest]
+    #[case("(a)(b)", 0, 0..3, Some((0, 2)))]
+    #[case("(a)(b)", 0, 0..2, None)]
+    #[case("(a)(b)", 5, 3..6, Some((5, 3)))]
+    #[case("(a)(b)", 5, 4..6, None)]
+    // the cursor itself is outside of the range
+    #[case("(a)(b)", 0, 1..6, None)]
+    fn bracket_pair_within(
+        #[case] s: &'static str,
+        #[case] start: usize,
+        #[case] within: Range<usize>,
+        #[case] expected: Option<(usize, usize)>,
+    ) {
+        let r = RopeBuffer::from_str(s);
+        let cursor = Cursor::new_with_offset(ByteOffset(start));
+        let within = ByteOffset(within.start)..ByteOffset(within.end);
+        assert_eq!(cursor.bracket_pair(&r, within, false), expected.map(|(a, b)| (ByteOffset(a), ByteOffset(b))));
+    }
+
     #[rstest]
     #[case(3, 2)]
     #[case(2, 0)]
@@ -730,15 +996,92 @@ mod tests {
         let r = RopeBuffer::from_str("abc");
         let mut m = MultiCursor::new();
         let cursor_with_same_position = Cursor::default();
-        assert!(!m.spawn_new_primary(cursor_with_same_position));
+        assert!(!m.spawn_new_primary(&r, cursor_with_same_position));
         assert_eq!(m.cursor_count(), 1);
 
         m.select_to(&r, MoveTarget::Right(1));
         let cursor_with_same_selection = Cursor::new_with_selection(ByteOffset(0), Some(ByteOffset(1)));
-        assert!(!m.spawn_new_primary(cursor_with_same_selection));
+        assert!(!m.spawn_new_primary(&r, cursor_with_same_selection));
         assert_eq!(m.cursor_count(), 1);
         let cursor_with_rev_selection = Cursor::new_with_selection(ByteOffset(1), Some(ByteOffset(0)));
-        assert!(!m.spawn_new_primary(cursor_with_rev_selection));
+        assert!(!m.spawn_new_primary(&r, cursor_with_rev_selection));
+        assert_eq!(m.cursor_count(), 1);
+    }
+
+    #[test]
+    fn spawned_cursors_are_merged_with_overlapping_ones() {
+        let r = RopeBuffer::from_str("0123456789");
+        let mut m = MultiCursor::new();
+        m.select_to(&r, MoveTarget::Right(3));
+        assert!(m.spawn_new_primary(&r, cursor(5, Some(2))));
+        assert_eq!(m.cursor_count(), 1);
+        assert_eq!(m.primary().selection(), Some(ByteOffset(0)..ByteOffset(5)));
+        assert!(m.spawn_new(&r, cursor(7, None)));
+        assert_eq!(m.cursor_count(), 2);
+        assert_eq!(m.primary().selection(), Some(ByteOffset(0)..ByteOffset(5)));
+    }
+
+    fn normalized(cursors: Vec<Cursor>, primary: usize) -> (Vec<Option<Range<ByteOffset>>>, usize) {
+        let r = RopeBuffer::from_str("0123456789");
+        let mut m = MultiCursor::new();
+        m.set_cursors(primary, cursors);
+        m.normalize(&r);
+        let primary = m.primary().pos();
+        let index = m.iter().position(|cursor| cursor.pos() == primary).unwrap();
+        (m.iter().map(|cursor| cursor.selection().or(Some(cursor.offset..cursor.offset))).collect(), index)
+    }
+
+    #[rstest]
+    #[case::sorted(vec![cursor(5, None), cursor(1, None)], 0, vec![1..1, 5..5], 1)]
+    #[case::same_position(vec![cursor(3, None), cursor(3, None)], 1, vec![3..3], 0)]
+    #[case::adjacent_selections(vec![cursor(3, Some(6)), cursor(0, Some(3))], 0, vec![0..3, 3..6], 1)]
+    #[case::overlapping_selections(vec![cursor(0, Some(4)), cursor(6, Some(2))], 1, vec![0..6], 0)]
+    #[case::nested_selections(vec![cursor(3, Some(4)), cursor(8, Some(1))], 0, vec![1..8], 0)]
+    #[case::caret_inside_selection(vec![cursor(4, None), cursor(2, Some(7))], 0, vec![2..7], 0)]
+    #[case::caret_at_end_of_selection(vec![cursor(2, Some(7)), cursor(7, None)], 1, vec![2..7], 0)]
+    #[case::empty_selection(vec![cursor(2, Some(7)), cursor(5, Some(5))], 0, vec![2..7], 0)]
+    #[case::past_end_of_content(vec![cursor(12, None), cursor(10, None)], 0, vec![10..10], 0)]
+    fn normalize_cursors(
+        #[case] cursors: Vec<Cursor>,
+        #[case] primary: usize,
+        #[case] expected: Vec<Range<usize>>,
+        #[case] expected_primary: usize,
+    ) {
+        let expected = expected.into_iter().map(|r| Some(ByteOffset(r.start)..ByteOffset(r.end))).collect();
+        assert_eq!(normalized(cursors, primary), (expected, expected_primary));
+    }
+
+    #[rstest]
+    // "0\n234\n67\n9" has line ends at 1, 5, 8 and 10
+    #[case::three_lines(vec![cursor(1, Some(7))], 0, vec![1, 5, 8], 2)]
+    #[case::backward(vec![cursor(7, Some(1))], 0, vec![1, 5, 8], 2)]
+    #[case::ends_at_line_start(vec![cursor(0, Some(6))], 0, vec![1, 5], 1)]
+    #[case::within_a_line(vec![cursor(2, Some(4))], 0, vec![5], 0)]
+    #[case::caret_kept(vec![cursor(3, None), cursor(6, Some(10))], 0, vec![3, 8, 10], 0)]
+    #[case::duplicates_merged(vec![cursor(0, Some(3)), cursor(4, Some(6))], 1, vec![1, 5], 1)]
+    fn split_to_line_ends(
+        #[case] cursors: Vec<Cursor>,
+        #[case] primary: usize,
+        #[case] expected: Vec<usize>,
+        #[case] expected_primary: usize,
+    ) {
+        let r = RopeBuffer::from_str("0\n234\n67\n9");
+        let mut m = MultiCursor::new();
+        m.set_cursors(primary, cursors);
+        m.split_to_line_ends(&r);
+        assert!(m.iter().all(|cursor| !cursor.has_selection()));
+        assert_eq!(m.iter().map(|cursor| cursor.offset.0).collect::<Vec<_>>(), expected);
+        assert_eq!(m.primary().offset.0, expected[expected_primary]);
+    }
+
+    #[test]
+    fn merged_selection_keeps_direction() {
+        let r = RopeBuffer::from_str("0123456789");
+        let mut m = MultiCursor::new();
+        m.set_cursors(0, vec![cursor(1, Some(5)), cursor(3, Some(8))]);
+        m.normalize(&r);
         assert_eq!(m.cursor_count(), 1);
+        assert_eq!(m.primary().offset, ByteOffset(1));
+        assert_eq!(m.primary().selection_from, Some(ByteOffset(8)));
     }
 }
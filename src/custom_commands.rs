@@ -0,0 +1,176 @@
+//! Commands defined by the user in the `commands` file of the config directory.
+//!
+//! Each definition is on a line of its own:
+//!
+//! ```text
+//! # runs a shell command the same way as `exec TEMPLATE`
+//! cmd build = !cargo build
+//! # runs an editor command
+//! cmd w = save
+//! ```
+//!
+//! Any arguments given to a custom command are appended to its definition.
+
+use crate::App;
+
+pub(crate) const COMMANDS_FILE_TEMPLATE: &str = include_str!("../default_config/commands");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CustomCommand {
+    /// Shell command template that is run like `exec TEMPLATE`
+    Shell(String),
+    /// Built-in editor command (with arguments)
+    Editor(String),
+}
+
+impl CustomCommand {
+    fn with_args(&self, args: &str) -> String {
+        let definition = match self {
+            CustomCommand::Shell(template) => template,
+            CustomCommand::Editor(command) => command,
+        };
+        if args.is_empty() {
+            definition.to_string()
+        } else {
+            format!("{definition} {args}")
+        }
+    }
+
+    fn help(&self) -> String {
+        match self {
+            CustomCommand::Shell(template) => format!("!{template}"),
+            CustomCommand::Editor(command) => command.to_string(),
+        }
+    }
+}
+
+/// Parses the `cmd NAME = DEFINITION` lines of a commands file, returns the
+/// commands and an error message for each line that could not be parsed
+fn parse_commands(config: &str) -> (Vec<(String, CustomCommand)>, Vec<String>) {
+    let mut commands = vec![];
+    let mut errors = vec![];
+    for (lineno, line) in config.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+        let parsed = line
+            .strip_prefix("cmd ")
+            .and_then(|rest| rest.split_once('='))
+            .map(|(name, definition)| (name.trim(), definition.trim()))
+            .filter(|(name, definition)| !name.is_empty() && !name.contains(char::is_whitespace) && !definition.is_empty());
+        match parsed {
+            Some((name, definition)) => {
+                let command = match definition.strip_prefix('!') {
+                    Some(template) => CustomCommand::Shell(template.trim().to_string()),
+                    None => CustomCommand::Editor(definition.to_string()),
+                };
+                commands.push((name.to_string(), command));
+            }
+            None => errors.push(format!("line {}: expected 'cmd NAME = DEFINITION'", lineno + 1)),
+        }
+    }
+    (commands, errors)
+}
+
+impl App {
+    pub fn custom_commands_file(&self) -> Option<std::path::PathBuf> {
+        self.dirs.as_ref().map(|dirs| dirs.config_dir().join("commands"))
+    }
+
+    /// Registers the commands from the commands file in the config directory
+    pub fn load_custom_commands(&mut self) {
+        let Some(path) = self.custom_commands_file() else { return };
+        let config = match std::fs::read_to_string(&path) {
+            Ok(config) => config,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return,
+            Err(err) => {
+                self.inform(format!("commands error: {err}"));
+                return
+            }
+        };
+        let (commands, errors) = parse_commands(&config);
+        for (name, command) in commands {
+            self.register_command(&name, command);
+        }
+        if let Some(error) = errors.first() {
+            self.inform(format!("commands error: {error}"));
+        }
+    }
+
+    /// Adds a command that can be run from the command prompt. Built-in
+    /// commands can not be overridden.
+    pub fn register_command(&mut self, name: &str, command: CustomCommand) {
+        if self.prompt_completer.has_command(name) && !self.custom_commands.contains_key(name) {
+            self.inform(format!("commands error: '{name}' is a built-in command"));
+            return
+        }
+        self.custom_commands.insert(name.to_string(), command);
+        self.rebuild_prompt_completer();
+    }
+
+    pub(crate) fn run_custom_command(&mut self, command: &CustomCommand, args: &str) {
+        match command {
+            CustomCommand::Shell(_) => self.exec_template(&command.with_args(args)),
+            CustomCommand::Editor(_) => {
+                let full_command = command.with_args(args);
+                let name = full_command.split(' ').next().unwrap_or_default();
+                // only built-in commands are allowed to avoid infinite recursion
+                if self.custom_commands.contains_key(name) {
+                    self.inform(format!("commands error: '{name}' is not a built-in command"));
+                } else {
+                    self.handle_command(&full_command);
+                }
+            }
+        }
+    }
+
+    /// Creates the command prompt completer for the built-in and custom commands
+    pub(crate) fn rebuild_prompt_completer(&mut self) {
+        let mut completer = crate::prompt_completer::CmdCompleter::make_completer(self.highlighting.filetypes().as_slice(), &self.highlighting.theme_names());
+        let mut custom_commands: Vec<_> = self.custom_commands.iter().collect();
+        custom_commands.sort_unstable_by_key(|(name, _)| name.as_str());
+        for (name, command) in custom_commands {
+            completer.add_custom_command(name, command.help());
+        }
+        self.prompt_completer = completer;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_command_definitions() {
+        let (commands, errors) = parse_commands(COMMANDS_FILE_TEMPLATE);
+        assert!(commands.is_empty() && errors.is_empty());
+
+        let config = "# comment\n\ncmd build = !cargo build\ncmd w=save\nbad line\ncmd = nothing\ncmd x =\n";
+        let (commands, errors) = parse_commands(config);
+        assert_eq!(commands, [
+            ("build".to_string(), CustomCommand::Shell("cargo build".into())),
+            ("w".to_string(), CustomCommand::Editor("save".into())),
+        ]);
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].starts_with("line 5:"));
+    }
+
+    #[test]
+    fn custom_commands_run_built_in_commands() {
+        let mut app = App::new();
+        app.switch_to_new_pane(crate::Pane::empty());
+        app.register_command("foo", CustomCommand::Editor("insertchar U+66".into()));
+        app.register_command("loop", CustomCommand::Editor("loop".into()));
+        app.register_command("save", CustomCommand::Editor("close".into()));
+        assert!(app.status_msg().is_some_and(|msg| msg.contains("built-in")));
+
+        app.handle_command("foo ,U+6F");
+        app.handle_command("loop");
+        while let Some(action) = app.action_queue.pop_front() {
+            app.handle_action(action);
+        }
+        assert_eq!(app.current_pane().content.to_string(), "fo");
+        assert!(app.status_msg().is_some_and(|msg| msg.contains("'loop' is not a built-in command")));
+    }
+}
@@ -0,0 +1,268 @@
+/// Number of unchanged lines shown around each change
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineOp {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Computes the shortest edit script that turns `old` into `new` using the
+/// linear space variant of Myers' algorithm, so that very different inputs
+/// don't need memory proportional to the square of the number of changes
+fn shortest_edit_script<T: PartialEq>(old: &[T], new: &[T]) -> Vec<LineOp> {
+    let mut ops = Vec::with_capacity(old.len().max(new.len()));
+    push_edit_script(old, new, &mut ops);
+    ops
+}
+
+fn push_edit_script<T: PartialEq>(old: &[T], new: &[T], ops: &mut Vec<LineOp>) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old.iter().rev().zip(new.iter().rev()).take_while(|(a, b)| a == b).count();
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+
+    ops.extend(std::iter::repeat_n(LineOp::Equal, prefix));
+    if old.is_empty() {
+        ops.extend(std::iter::repeat_n(LineOp::Insert, new.len()));
+    } else if new.is_empty() {
+        ops.extend(std::iter::repeat_n(LineOp::Delete, old.len()));
+    } else {
+        // with the common ends removed there are at least two changes so
+        // both halves have fewer changes than the whole
+        let (start, end) = middle_snake(old, new);
+        push_edit_script(&old[..start.0], &new[..start.1], ops);
+        ops.extend(std::iter::repeat_n(LineOp::Equal, end.0 - start.0));
+        push_edit_script(&old[end.0..], &new[end.1..], ops);
+    }
+    ops.extend(std::iter::repeat_n(LineOp::Equal, suffix));
+}
+
+/// Finds the start and end of a run of equal lines (possibly empty) that is
+/// in the middle of a shortest edit script by searching forward from the start
+/// and backward from the end at the same time until the searches overlap
+fn middle_snake<T: PartialEq>(old: &[T], new: &[T]) -> ((usize, usize), (usize, usize)) {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let delta = n - m;
+    let max = (n + m + 1) / 2;
+    // forward[k] is the furthest x reached on diagonal k = x - y, backward[k]
+    // is the same from the end (x and y counted from the end of the inputs)
+    let mut forward = vec![0isize; 2 * max as usize + 3];
+    let mut backward = vec![0isize; 2 * max as usize + 3];
+    let idx = |k: isize| (k + max + 1) as usize;
+
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let x_start = if k == -d || (k != d && forward[idx(k - 1)] < forward[idx(k + 1)]) {
+                forward[idx(k + 1)]
+            } else {
+                forward[idx(k - 1)] + 1
+            };
+            let (mut x, mut y) = (x_start, x_start - k);
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[idx(k)] = x;
+            // the backward diagonal with the same x and y is delta - k
+            let reverse_k = delta - k;
+            if delta % 2 != 0 && reverse_k.abs() < d && x + backward[idx(reverse_k)] >= n {
+                let start = (x_start as usize, (x_start - k) as usize);
+                return (start, (x as usize, y as usize))
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let x_start = if k == -d || (k != d && backward[idx(k - 1)] < backward[idx(k + 1)]) {
+                backward[idx(k + 1)]
+            } else {
+                backward[idx(k - 1)] + 1
+            };
+            let (mut x, mut y) = (x_start, x_start - k);
+            while x < n && y < m && old[(n - x - 1) as usize] == new[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[idx(k)] = x;
+            let forward_k = delta - k;
+            if delta % 2 == 0 && forward_k.abs() <= d && forward[idx(forward_k)] + x >= n {
+                let start = ((n - x) as usize, (m - y) as usize);
+                return (start, ((n - x_start) as usize, (m - x_start + k) as usize))
+            }
+        }
+    }
+    unreachable!("the searches always meet within (n + m) / 2 rounds")
+}
+
+fn push_line(out: &mut String, prefix: char, line: &str) {
+    out.push(prefix);
+    match line.strip_suffix('\n') {
+        Some(line) => {
+            out.push_str(line);
+            out.push('\n');
+        }
+        None => {
+            out.push_str(line);
+            out.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+/// Returns a unified diff that turns `old` into `new`, or `None` if they are identical
+pub fn unified_diff(old_name: &str, old: &str, new_name: &str, new: &str) -> Option<String> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = shortest_edit_script(&old_lines, &new_lines);
+
+    let changes: Vec<usize> = ops.iter().enumerate().filter(|(_, op)| **op != LineOp::Equal).map(|(i, _)| i).collect();
+    if changes.is_empty() {
+        return None
+    }
+
+    // line numbers in old and new at the start of each op
+    let mut positions = Vec::with_capacity(ops.len());
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for op in ops.iter() {
+        positions.push((old_pos, new_pos));
+        match op {
+            LineOp::Equal => { old_pos += 1; new_pos += 1; }
+            LineOp::Delete => old_pos += 1,
+            LineOp::Insert => new_pos += 1,
+        }
+    }
+
+    let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+    let mut i = 0;
+    while i < changes.len() {
+        let start = changes[i].saturating_sub(CONTEXT_LINES);
+        while i + 1 < changes.len() && changes[i + 1] - changes[i] <= 2 * CONTEXT_LINES + 1 {
+            i += 1;
+        }
+        let end = (changes[i] + 1 + CONTEXT_LINES).min(ops.len());
+        i += 1;
+
+        let hunk = &ops[start..end];
+        let old_count = hunk.iter().filter(|op| **op != LineOp::Insert).count();
+        let new_count = hunk.iter().filter(|op| **op != LineOp::Delete).count();
+        let (old_start, new_start) = positions[start];
+        // an empty range refers to the line *before* it, hence no +1
+        let old_start = if old_count == 0 { old_start } else { old_start + 1 };
+        let new_start = if new_count == 0 { new_start } else { new_start + 1 };
+        out.push_str(&format!("@@ -{old_start},{old_count} +{new_start},{new_count} @@\n"));
+
+        for (op, &(old_pos, new_pos)) in hunk.iter().zip(&positions[start..end]) {
+            match op {
+                LineOp::Equal => push_line(&mut out, ' ', old_lines[old_pos]),
+                LineOp::Delete => push_line(&mut out, '-', old_lines[old_pos]),
+                LineOp::Insert => push_line(&mut out, '+', new_lines[new_pos]),
+            }
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Length of the shortest edit script computed with the quadratic dynamic
+    /// programming solution to the longest common subsequence problem
+    fn edit_distance(old: &[char], new: &[char]) -> usize {
+        let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for (i, a) in old.iter().enumerate() {
+            for (j, b) in new.iter().enumerate() {
+                lcs[i + 1][j + 1] = if a == b { lcs[i][j] + 1 } else { lcs[i][j + 1].max(lcs[i + 1][j]) };
+            }
+        }
+        old.len() + new.len() - 2 * lcs[old.len()][new.len()]
+    }
+
+    #[test]
+    fn identical_inputs_have_no_diff() {
+        assert_eq!(unified_diff("a", "x\ny\n", "b", "x\ny\n"), None);
+        assert_eq!(unified_diff("a", "", "b", ""), None);
+    }
+
+    #[test]
+    fn edit_script_is_minimal() {
+        let old: Vec<char> = "ABCABBA".chars().collect();
+        let new: Vec<char> = "CBABAC".chars().collect();
+        let ops = shortest_edit_script(&old, &new);
+        assert_eq!(ops.iter().filter(|op| **op != LineOp::Equal).count(), 5);
+    }
+
+    proptest! {
+        #[test]
+        fn edit_script_turns_old_into_new(
+            old in proptest::collection::vec(prop::sample::select(vec!['a', 'b', 'c']), 0..40),
+            new in proptest::collection::vec(prop::sample::select(vec!['a', 'b', 'c']), 0..40),
+        ) {
+            let ops = shortest_edit_script(&old, &new);
+            let (mut old_pos, mut new_pos) = (0, 0);
+            for op in ops.iter() {
+                match op {
+                    LineOp::Equal => {
+                        prop_assert_eq!(old[old_pos], new[new_pos]);
+                        old_pos += 1;
+                        new_pos += 1;
+                    }
+                    LineOp::Delete => old_pos += 1,
+                    LineOp::Insert => new_pos += 1,
+                }
+            }
+            prop_assert_eq!((old_pos, new_pos), (old.len(), new.len()));
+            let changes = ops.iter().filter(|op| **op != LineOp::Equal).count();
+            prop_assert_eq!(changes, edit_distance(&old, &new));
+        }
+    }
+
+    #[test]
+    fn unified_diff_with_context() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n10\neleven\n";
+        let expected = concat!(
+            "--- old\n",
+            "+++ new\n",
+            "@@ -2,9 +2,10 @@\n",
+            " 2\n",
+            " 3\n",
+            " 4\n",
+            "-5\n",
+            "+five\n",
+            " 6\n",
+            " 7\n",
+            " 8\n",
+            " 9\n",
+            " 10\n",
+            "+eleven\n",
+        );
+        assert_eq!(unified_diff("old", old, "new", new).unwrap(), expected);
+    }
+
+    #[test]
+    fn separate_hunks_and_missing_newline() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj";
+        let new = "A\nb\nc\nd\ne\nf\ng\nh\ni\nJ";
+        let expected = concat!(
+            "--- old\n",
+            "+++ new\n",
+            "@@ -1,4 +1,4 @@\n",
+            "-a\n",
+            "+A\n",
+            " b\n",
+            " c\n",
+            " d\n",
+            "@@ -7,4 +7,4 @@\n",
+            " g\n",
+            " h\n",
+            " i\n",
+            "-j\n",
+            "\\ No newline at end of file\n",
+            "+J\n",
+            "\\ No newline at end of file\n",
+        );
+        assert_eq!(unified_diff("old", old, "new", new).unwrap(), expected);
+    }
+}
@@ -2,9 +2,224 @@ use std::cmp::Ordering;
 use std::ops::Range;
 
 use ropey::Rope;
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::ropebuffer::RopeBuffer;
-use crate::{ByteOffset, MultiCursor};
+use crate::{ByteOffset, Cursor, MultiCursor};
+
+/// What typing a character does when brackets and quotes are closed automatically
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum AutoClose {
+    /// Insert the typed character followed by this closing character
+    Pair(char),
+    /// Move over the identical character that is already to the right of the cursors
+    Skip,
+}
+
+/// Decides what typing `c` does with auto-closing enabled, `None` means it is
+/// inserted as usual. Quotes are not closed right after a word character so
+/// that typing apostrophes in words works.
+pub(crate) fn auto_close(cursors: &MultiCursor, content: &RopeBuffer, c: char) -> Option<AutoClose> {
+    if !c.is_ascii() || cursors.iter().any(|cursor| cursor.has_selection()) {
+        return None
+    }
+    let byte_at = |offset: usize| content.get_byte(ByteOffset(offset));
+    let is_closer = matches!(c, ')' | ']' | '}' | '"' | '\'');
+    if is_closer && cursors.iter().all(|cursor| byte_at(cursor.offset.0) == Some(c as u8)) {
+        return Some(AutoClose::Skip)
+    }
+    let after_word = |offset: ByteOffset| {
+        offset.0.checked_sub(1)
+            .and_then(byte_at)
+            .is_some_and(|b| !b.is_ascii() || b.is_ascii_alphanumeric() || b == b'_')
+    };
+    match c {
+        '(' => Some(AutoClose::Pair(')')),
+        '[' => Some(AutoClose::Pair(']')),
+        '{' => Some(AutoClose::Pair('}')),
+        '"' | '\'' if !cursors.iter().any(|cursor| after_word(cursor.offset)) => Some(AutoClose::Pair(c)),
+        _ => None,
+    }
+}
+
+/// HTML elements that never have a closing tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+/// How far before the cursor an unclosed tag is looked for
+const TAG_SCAN_LIMIT: usize = 64 * 1024;
+
+/// Returns the offset of the `<` and the name of the innermost tag that is
+/// opened but not closed in `text`. Comments, doctypes, processing
+/// instructions, self-closing tags and HTML void elements are skipped. A
+/// closing tag closes the nearest open tag with the same name (ignoring case
+/// like HTML does) and any unclosed tags within it.
+fn unclosed_tag(text: &str) -> Option<(usize, &str)> {
+    let mut open_tags: Vec<(usize, &str)> = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        let tag_start = text.len() - rest.len() + start;
+        rest = &rest[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            let Some(end) = comment.find("-->") else { break };
+            rest = &comment[end + 3..];
+            continue
+        }
+        let Some(end) = tag_end(rest) else { break };
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+        let is_name_char = |c: char| c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.');
+        if let Some(closing) = tag.strip_prefix('/') {
+            let name = closing.trim_end();
+            if let Some(i) = open_tags.iter().rposition(|(_, open)| open.eq_ignore_ascii_case(name)) {
+                open_tags.truncate(i);
+            }
+        } else if !tag.ends_with('/') {
+            let name_len = tag.find(|c: char| !is_name_char(c)).unwrap_or(tag.len());
+            let name = &tag[..name_len];
+            if !name.is_empty() && !VOID_ELEMENTS.contains(&name.to_ascii_lowercase().as_str()) {
+                open_tags.push((tag_start, name));
+            }
+        }
+    }
+    open_tags.pop()
+}
+
+/// Returns the index of the `>` that ends the tag at the start of `tag`,
+/// skipping the ones within quoted attribute values
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut after_equals = false;
+    for (i, c) in tag.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => continue,
+            None if c == '>' => return Some(i),
+            None if after_equals && matches!(c, '"' | '\'') => {
+                quote = Some(c);
+                continue
+            }
+            None => {}
+        }
+        after_equals = c == '=' || (after_equals && c.is_whitespace());
+    }
+    None
+}
+
+/// Returns the offset of the `<` and the name of the innermost tag that is
+/// still open at `end`, only the last [`TAG_SCAN_LIMIT`] bytes are searched
+pub(crate) fn unclosed_tag_before(content: &RopeBuffer, end: ByteOffset) -> Option<(ByteOffset, String)> {
+    let start = content.floor_char_boundary(ByteOffset(end.0.saturating_sub(TAG_SCAN_LIMIT)));
+    let text = content.slice(&(start..end)).to_string();
+    unclosed_tag(&text).map(|(offset, name)| (ByteOffset(start.0 + offset), name.to_string()))
+}
+
+/// A transformation of the selected text done with the `to` command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Transform {
+    /// `*N` repeats the text N times
+    Repeat(usize),
+    /// `align DELIMITER`
+    Align(String),
+    Upper,
+    Lower,
+    /// Whitespace-separated words into `[a, b, c]`
+    List,
+    /// Every whitespace-separated word in double quotes
+    Quoted,
+}
+
+impl Transform {
+    pub(crate) fn parse(arg: &str) -> Option<Self> {
+        if let Some(reps) = arg.strip_prefix('*') {
+            return reps.parse().ok().map(Transform::Repeat)
+        }
+        if let Some(delimiter) = arg.strip_prefix("align").filter(|rest| rest.starts_with(char::is_whitespace)) {
+            let delimiter = delimiter.trim();
+            return (!delimiter.is_empty()).then(|| Transform::Align(delimiter.to_string()))
+        }
+        match arg {
+            "upper" => Some(Transform::Upper),
+            "lower" => Some(Transform::Lower),
+            "list" => Some(Transform::List),
+            "quoted" => Some(Transform::Quoted),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn apply(&self, s: &str, tab_width: usize) -> String {
+        match self {
+            Transform::Repeat(reps) => s.repeat(*reps),
+            Transform::Align(delimiter) => align_on_delimiter(s, delimiter, tab_width),
+            Transform::Upper => s.to_uppercase(),
+            Transform::Lower => s.to_lowercase(),
+            Transform::List => {
+                let v = s.split_ascii_whitespace().collect::<Vec<_>>();
+                format!("[{}]", v.join(", "))
+            }
+            Transform::Quoted => {
+                let mut transformed = String::new();
+                let mut in_word = false;
+                for c in s.chars() {
+                    if c.is_ascii_whitespace() {
+                        if in_word {
+                            transformed.push('"');
+                        }
+                        transformed.push(c);
+                        in_word = false;
+                    } else {
+                        if !in_word {
+                            transformed.push('"');
+                        }
+                        if c == '"' || c == '\\' {
+                            transformed.push('\\');
+                        }
+                        transformed.push(c);
+                        in_word = true;
+                    }
+                }
+                if in_word {
+                    transformed.push('"');
+                }
+                transformed
+            }
+        }
+    }
+}
+
+/// Pads the lines of `text` with spaces so that the first occurrences of
+/// `delimiter` line up. Columns are counted in grapheme clusters with tabs
+/// extending to the next multiple of `tab_width` (like when rendering, tabs
+/// take no space if `tab_width` is 0).
+pub(crate) fn align_on_delimiter(text: &str, delimiter: &str, tab_width: usize) -> String {
+    let column_of = |prefix: &str| {
+        prefix.graphemes(true).fold(0, |col, g| match g {
+            "\t" if tab_width > 0 => col + tab_width - col % tab_width,
+            "\t" => col,
+            _ => col + 1,
+        })
+    };
+    let lines: Vec<(&str, Option<usize>)> = text
+        .split_inclusive('\n')
+        .map(|line| (line, line.find(delimiter).filter(|_| !delimiter.is_empty())))
+        .collect();
+    let Some(max_col) = lines.iter().filter_map(|(line, idx)| idx.map(|idx| column_of(&line[..idx]))).max() else {
+        return text.to_string()
+    };
+    let mut aligned = String::with_capacity(text.len());
+    for (line, idx) in lines {
+        match idx {
+            Some(idx) => {
+                aligned.push_str(&line[..idx]);
+                aligned.push_str(&" ".repeat(max_col - column_of(&line[..idx])));
+                aligned.push_str(&line[idx..]);
+            }
+            None => aligned.push_str(line),
+        }
+    }
+    aligned
+}
 
 #[derive(Debug)]
 pub struct EditBatch {
@@ -28,12 +243,35 @@ impl EditBatch {
         self.edits.first().map(|e| e.pos())
     }
 
+    /// Range of the buffer that was changed by the edits, in the coordinates
+    /// of the buffer *after* the edits have been applied
+    pub fn affected_range(&self) -> Option<Range<ByteOffset>> {
+        let start = self.first_edit_offset()?;
+        let mut end = start.0;
+        let mut inserted = 0;
+        let mut deleted = 0;
+        for edit in self.edits.iter() {
+            match edit {
+                Edit::Insert(offset, rope) => {
+                    end = end.max(offset.0);
+                    inserted += rope.len_bytes();
+                }
+                Edit::Delete(range) => {
+                    end = end.max(range.end.0);
+                    deleted += range.end.0 - range.start.0;
+                }
+            }
+        }
+        Some(start..ByteOffset(end + inserted - deleted))
+    }
+
     pub fn from_edits(mut edits: Vec<Edit>) -> Self {
         edits.sort();
         let mut next_start_offset = ByteOffset::MAX;
         for edit in edits.iter_mut().rev() {
             match edit {
                 Edit::Delete(range) => {
+                    debug_assert!(range.start <= range.end, "backwards delete {range:?}");
                     range.end = range.end.min(next_start_offset);
                     next_start_offset = range.start;
                 }
@@ -59,29 +297,115 @@ impl EditBatch {
     pub fn insert_newline_keep_indent(cursors: &MultiCursor, content: &RopeBuffer, eol: &str) -> EditBatch {
         let mut edits = vec![];
         for cursor in cursors.iter() {
-            let indent = cursor.current_line_indentation(content);
-            let ins = format!("{eol}{indent}");
-            edits.push(Edit::insert_str(cursor.offset, &ins));
-            if let Some(selection) = cursor.selection() {
-                edits.push(Edit::Delete(selection));
+            push_newline_keep_indent(&mut edits, cursor, content, eol);
+        }
+        Self::from_edits(edits)
+    }
+
+    /// Like `insert_newline_keep_indent` but a cursor right between an opening and
+    /// a closing bracket splits them on three lines: the cursor ends up on an
+    /// empty line indented one level deeper and the closing bracket goes on the
+    /// next line at the original indentation level
+    pub fn insert_newline_smart_indent(cursors: &MultiCursor, content: &RopeBuffer, eol: &str, indent_unit: &str) -> EditBatch {
+        let mut edits = vec![];
+        for cursor in cursors.iter() {
+            let before = cursor.offset.0.checked_sub(1).and_then(|offset| content.get_byte(ByteOffset(offset)));
+            let after = content.get_byte(cursor.offset);
+            let closing = match (before, after) {
+                (Some(b'{'), Some(b'}')) | (Some(b'('), Some(b')')) | (Some(b'['), Some(b']')) if !cursor.has_selection() => after,
+                _ => None,
+            };
+            if let Some(closing) = closing {
+                let indent = cursor.current_line_indentation(content);
+                edits.push(Edit::insert_str(cursor.offset, &format!("{eol}{indent}{indent_unit}")));
+                // the closing bracket is moved rather than inserted before so
+                // that the insert doesn't push the cursor past it
+                let closing_end = ByteOffset(cursor.offset.0 + 1);
+                edits.push(Edit::Delete(cursor.offset..closing_end));
+                edits.push(Edit::insert_str(closing_end, &format!("{eol}{indent}{}", closing as char)));
+            } else {
+                push_newline_keep_indent(&mut edits, cursor, content, eol);
             }
         }
         Self::from_edits(edits)
     }
 
-    pub fn insert_from_clipboard(cursors: &MultiCursor, clips: &[String]) -> Self {
-        if clips.len() == cursors.cursor_count() {
-            let mut edits = vec![];
-            for (cursor, s) in cursors.iter().zip(clips) {
-                edits.push(Edit::insert_str(cursor.offset, s));
-                if let Some(selection) = cursor.selection() {
-                    edits.push(Edit::Delete(selection));
+    /// Re-indents the lines where a cursor is right after a closing bracket or
+    /// a closing tag that is the first non-whitespace on the line so that it
+    /// lines up with the line of its opening bracket or tag
+    pub fn dedent_closing_brackets(cursors: &MultiCursor, content: &RopeBuffer) -> EditBatch {
+        let mut edits = vec![];
+        for cursor in cursors.iter().filter(|cursor| !cursor.has_selection()) {
+            let Some(last) = cursor.offset.0.checked_sub(1).map(ByteOffset) else { continue };
+            let line_start = cursor.line_start(content);
+            let closer = match content.get_byte(last) {
+                Some(b')' | b']' | b'}') => last,
+                Some(b'>') => {
+                    let line = content.slice(&(line_start..last)).to_string();
+                    let Some(lt) = line.rfind("</") else { continue };
+                    ByteOffset(line_start.0 + lt)
                 }
+                _ => continue,
+            };
+            let indent_before = content.slice(&(line_start..closer)).to_string();
+            if !indent_before.bytes().all(|b| b == b' ' || b == b'\t') {
+                continue
+            }
+            let opening = if closer == last {
+                Cursor::new_with_offset(closer).matching_pair(content)
+            } else {
+                let name = content.slice(&(ByteOffset(closer.0 + 2)..last)).to_string();
+                unclosed_tag_before(content, closer)
+                    .filter(|(_, open)| open.eq_ignore_ascii_case(&name))
+                    .map(|(offset, _)| offset)
+            };
+            let Some(opening) = opening else { continue };
+            let indent = Cursor::new_with_offset(opening).current_line_indentation(content);
+            if indent != indent_before {
+                edits.push(Edit::Delete(line_start..closer));
+                edits.push(Edit::insert_str(line_start, &indent));
             }
-            Self::from_edits(edits)
-        } else {
-            Self::insert_with_cursors(cursors, &clips.join(""))
         }
+        Self::from_edits(edits)
+    }
+
+    /// Pastes the clips at the cursors. With `distribute` each cursor gets
+    /// its own clip when there are as many clips as cursors, otherwise every
+    /// cursor gets all of the clips joined together. `linewise` clips are
+    /// whole lines that go above the cursor's line (leaving any selections
+    /// alone). When a cursor is preceded by nothing but indentation the
+    /// indentation of the clip's first line is left out so that it doesn't
+    /// get indented twice.
+    pub fn insert_from_clipboard(
+        cursors: &MultiCursor,
+        content: &RopeBuffer,
+        clips: &[String],
+        distribute: bool,
+        linewise: bool,
+    ) -> Self {
+        let joined = clips.join("");
+        let distribute = distribute && clips.len() == cursors.cursor_count();
+        let mut edits = vec![];
+        for (i, cursor) in cursors.iter().enumerate() {
+            let clip = if distribute { &clips[i] } else { &joined };
+            if linewise {
+                edits.push(Edit::insert_str(cursor.line_start(content), clip));
+                continue
+            }
+            let start = cursor.selection().map_or(cursor.offset, |selection| selection.start);
+            let line_start = content.line_to_byte(content.byte_to_line(start));
+            let before = content.slice(&(line_start..start)).to_string();
+            let clip = if !before.is_empty() && before.chars().all(|c| c == ' ' || c == '\t') {
+                clip.trim_start_matches([' ', '\t'])
+            } else {
+                clip
+            };
+            edits.push(Edit::insert_str(cursor.offset, clip));
+            if let Some(selection) = cursor.selection() {
+                edits.push(Edit::Delete(selection));
+            }
+        }
+        Self::from_edits(edits)
     }
 
     pub fn transform_selections<F>(cursors: &MultiCursor, content: &RopeBuffer, transform: F) -> (Self, Vec<usize>)
@@ -103,24 +427,50 @@ impl EditBatch {
         (Self::from_edits(edits), selection_sizes_after)
     }
 
+    /// Exchanges the texts of the selections when there are exactly two cursors
+    /// and both have a selection. Also returns the sizes of the selections after
+    /// the swap in the same order as the cursors.
+    pub fn swap_selections(cursors: &MultiCursor, content: &RopeBuffer) -> Option<(Self, Vec<usize>)> {
+        let selections = cursors
+            .iter()
+            .map(|cursor| cursor.selection().filter(|selection| !selection.is_empty()))
+            .collect::<Option<Vec<_>>>()?;
+        let [first, second] = selections.as_slice() else { return None };
+        let first_text = content.slice(first).to_string();
+        let second_text = content.slice(second).to_string();
+        let edits = vec![
+            Edit::insert_str(first.start, &second_text),
+            Edit::Delete(first.clone()),
+            Edit::insert_str(second.start, &first_text),
+            Edit::Delete(second.clone()),
+        ];
+        Some((Self::from_edits(edits), vec![second_text.len(), first_text.len()]))
+    }
+
+    /// Deletes the selections, or the whole lines with the cursors (including
+    /// their line endings) if nothing is selected
     pub fn cut(cursors: &MultiCursor, content: &RopeBuffer) -> Self {
-        let mut edits = vec![];
-        for cursor in cursors.iter() {
-            if let Some(selection) = cursor.selection() {
-                edits.push(Edit::Delete(selection));
-            } else {
-                let a = cursor.line_start(content);
-                let b = cursor.line_end(content);
-                edits.push(Edit::Delete(a..b));
-            }
+        if cursors.iter().any(Cursor::has_selection) {
+            return Self::from_edits(cursors.iter().filter_map(Cursor::selection).map(Edit::Delete).collect())
         }
-        Self::from_edits(edits)
+        let mut spans: Vec<_> = cursors.iter().map(|cursor| cursor.line_span(content)).collect();
+        spans.dedup();
+        let edits = spans.into_iter().map(|span| {
+            Edit::Delete(content.line_to_byte(span.start)..content.line_to_byte(span.end))
+        });
+        Self::from_edits(edits.collect())
     }
 
-    /// Typically deletes one grapheme cluster backward. If the current line up
-    /// to the cursor only contains spaces then delete backwards to the nearest
-    /// tab stop.
-    pub fn delete_backward_with_cursors(cursors: &MultiCursor, content: &RopeBuffer, indent_width: usize) -> Self {
+    /// Deletes the selections or the grapheme before each cursor. With
+    /// `dedent_width` the spaces at the end of the indentation are deleted up to
+    /// the previous tab stop. Without `join_lines` cursors at the start of a line
+    /// don't delete the line break before them.
+    pub fn delete_backward_with_cursors(
+        cursors: &MultiCursor,
+        content: &RopeBuffer,
+        dedent_width: Option<usize>,
+        join_lines: bool,
+    ) -> Self {
         let mut edits = vec![];
         for cursor in cursors.iter() {
             match cursor.selection() {
@@ -128,11 +478,16 @@ impl EditBatch {
                     edits.push(Edit::Delete(selection));
                 }
                 None => {
+                    if !join_lines && cursor.offset == cursor.line_start(content) {
+                        continue
+                    }
                     let mut deleted_count = 1;
-                    if cursor.is_at_start_of_line(content) {
+                    if let Some(indent_width) = dedent_width.filter(|_| cursor.is_at_start_of_line(content)) {
+                        // the spaces after the last tab of the indentation
                         let indent_str = cursor.current_line_indentation(content);
-                        if !indent_str.is_empty() && indent_str.bytes().all(|b| b == b' ') {
-                            deleted_count = match indent_str.len() % indent_width {
+                        let spaces = indent_str.len() - indent_str.trim_end_matches(' ').len();
+                        if spaces > 0 {
+                            deleted_count = match spaces % indent_width {
                                 0 => indent_width,
                                 n => n,
                             }
@@ -156,6 +511,13 @@ impl EditBatch {
                 Some(selection) => {
                     edits.push(Edit::Delete(selection));
                 }
+                None if content.line_to_byte(content.byte_to_line(cursor.offset)) == cursor.offset => {
+                    // at the start of a line only the line break is deleted
+                    let a = cursor.left(content, 1);
+                    if a != cursor.offset {
+                        edits.push(Edit::Delete(a..cursor.offset));
+                    }
+                }
                 None => {
                     let a = cursor.word_boundary_left(content);
                     let b = cursor.offset;
@@ -234,9 +596,41 @@ impl EditBatch {
         Self::from_edits(edits)
     }
 
-    pub fn move_lines_up(cursors: &MultiCursor, content: &RopeBuffer) -> Self {
-        // FIXME: moving line without a trailing newline
-        // eg. "A\nB" should become "B\nA\n" instead of "BA\n"
+    /// Comments out the lines touched by the cursors by inserting `token` after
+    /// the indentation of each line, or uncomments them if every line already
+    /// starts with `token`. Blank lines are left alone.
+    pub fn toggle_line_comments(cursors: &MultiCursor, content: &RopeBuffer, token: &str) -> Self {
+        let mut lines = vec![];
+        for lineno in cursors.line_ranges(content).into_iter().flatten() {
+            let Some(line) = content.lines_at(lineno).next() else { continue };
+            let line = line.to_string();
+            let text = line.trim_start_matches([' ', '\t']);
+            if !text.trim_end().is_empty() {
+                let after_indent = ByteOffset(content.line_to_byte(lineno).0 + line.len() - text.len());
+                lines.push((after_indent, text.to_string()));
+            }
+        }
+
+        let mut edits = vec![];
+        if lines.iter().all(|(_, text)| text.starts_with(token)) {
+            for (offset, text) in lines {
+                let len = if text[token.len()..].starts_with(' ') { token.len() + 1 } else { token.len() };
+                edits.push(Edit::delete(offset, len));
+            }
+        } else {
+            let comment_start = format!("{token} ");
+            for (offset, _) in lines {
+                edits.push(Edit::insert_str(offset, &comment_start));
+            }
+        }
+
+        Self::from_edits(edits)
+    }
+
+    /// Moves the lines with cursors up by one line. A last line without a
+    /// line ending gets `eol` when it is moved up so that the lines stay
+    /// separated (and the line moved below it loses its line ending instead).
+    pub fn move_lines_up(cursors: &MultiCursor, content: &RopeBuffer, eol: &str) -> Self {
         let mut edits = vec![];
 
         for span in cursors.line_ranges(content).iter().rev() {
@@ -245,7 +639,14 @@ impl EditBatch {
             if span.start > 0 {
                 let prev_line = content.line_to_byte(span.start - 1) .. content.line_to_byte(span.start);
                 let end = content.line_to_byte(span.end);
-                edits.push(Edit::Insert(end, content.slice(&prev_line).into()));
+                if ends_without_eol(content, span) {
+                    let without_eol = prev_line.start..ByteOffset(prev_line.end.0 - eol_len_before(content, prev_line.end));
+                    let mut moved = Rope::from(eol);
+                    moved.append(content.slice(&without_eol).into());
+                    edits.push(Edit::Insert(end, moved));
+                } else {
+                    edits.push(Edit::Insert(end, content.slice(&prev_line).into()));
+                }
                 edits.push(Edit::Delete(prev_line));
             }
         }
@@ -253,9 +654,10 @@ impl EditBatch {
         Self::from_edits(edits)
     }
 
-    pub fn move_lines_down(cursors: &MultiCursor, content: &RopeBuffer) -> Self {
-        // FIXME: moving line without a trailing newline
-        // eg. "A\nB" should become "B\nA\n" instead of "BA\n"
+    /// Moves the lines with cursors down by one line. When the line moved
+    /// above them is the last line without a line ending it gets `eol` and
+    /// the moved lines lose their last line ending instead.
+    pub fn move_lines_down(cursors: &MultiCursor, content: &RopeBuffer, eol: &str) -> Self {
         let mut edits = vec![];
 
         for span in cursors.line_ranges(content).iter().rev() {
@@ -265,13 +667,20 @@ impl EditBatch {
                 let next_line_start = content.line_to_byte(span.end);
                 let next_line_end = content.line_to_byte(span.end + 1);
                 if next_line_start < next_line_end {
-                    edits.push(Edit::Delete(next_line_start..next_line_end));
-                    content.slice(&(next_line_start..next_line_end)).into()
+                    let mut next_line: Rope = content.slice(&(next_line_start..next_line_end)).into();
+                    if ends_without_eol(content, &(span.end..span.end + 1)) {
+                        let span_eol_start = ByteOffset(next_line_start.0 - eol_len_before(content, next_line_start));
+                        edits.push(Edit::Delete(span_eol_start..next_line_end));
+                        next_line.append(Rope::from(eol));
+                    } else {
+                        edits.push(Edit::Delete(next_line_start..next_line_end));
+                    }
+                    next_line
                 } else {
-                    Rope::from("\n")
+                    Rope::from(eol)
                 }
             } else {
-                Rope::from("\n")
+                Rope::from(eol)
             };
             let start = content.line_to_byte(span.start);
             edits.push(Edit::Insert(start, next_line));
@@ -279,6 +688,123 @@ impl EditBatch {
 
         Self::from_edits(edits)
     }
+
+    /// Appends `suffix` after the last non-whitespace character of the lines of
+    /// the spans, skipping blank lines and lines that already end with it
+    pub fn append_to_lines(spans: &[Range<usize>], content: &RopeBuffer, suffix: &str) -> Self {
+        let edits = spans
+            .iter()
+            .flat_map(|span| span.clone())
+            .filter_map(|line| {
+                let text = line_without_eol(content, line);
+                let text = text.trim_end();
+                (!text.trim_start().is_empty() && !text.ends_with(suffix))
+                    .then(|| Edit::insert_str(ByteOffset(content.line_to_byte(line).0 + text.len()), suffix))
+            })
+            .collect();
+        Self::from_edits(edits)
+    }
+
+    /// Puts `prefix` before the first and `suffix` after the last non-whitespace
+    /// character of the non-blank lines of the spans
+    pub fn enclose_lines(spans: &[Range<usize>], content: &RopeBuffer, prefix: &str, suffix: &str) -> Self {
+        let mut edits = vec![];
+        for line in spans.iter().flat_map(|span| span.clone()) {
+            let text = line_without_eol(content, line);
+            let trimmed = text.trim();
+            if trimmed.is_empty() {
+                continue
+            }
+            let start = content.line_to_byte(line).0 + (text.len() - text.trim_start().len());
+            edits.push(Edit::insert_str(ByteOffset(start), prefix));
+            edits.push(Edit::insert_str(ByteOffset(start + trimmed.len()), suffix));
+        }
+        Self::from_edits(edits)
+    }
+
+    /// Deletes the lines of the spans that are empty or contain only whitespace
+    pub fn remove_blank_lines(spans: &[Range<usize>], content: &RopeBuffer) -> Self {
+        let lines = spans
+            .iter()
+            .flat_map(|span| span.clone())
+            .filter(|&line| line_without_eol(content, line).trim().is_empty());
+        Self::delete_lines(content, lines)
+    }
+
+    /// Deletes the lines of the spans that are the same as the previous line,
+    /// or the same as any earlier line of the span when `global` is set
+    pub fn remove_duplicate_lines(spans: &[Range<usize>], content: &RopeBuffer, global: bool) -> Self {
+        let mut lines = vec![];
+        for span in spans {
+            let mut seen = std::collections::HashSet::new();
+            let mut prev = None;
+            for line in span.clone() {
+                let text = line_without_eol(content, line);
+                let is_duplicate = if global { !seen.insert(text.clone()) } else { prev.as_ref() == Some(&text) };
+                if is_duplicate {
+                    lines.push(line);
+                }
+                prev = Some(text);
+            }
+        }
+        Self::delete_lines(content, lines.into_iter())
+    }
+
+    /// Deletes whole lines (in ascending order) including their line endings.
+    /// When the last line has no line ending the line ending before it is
+    /// deleted instead so that the buffer doesn't end up with a new one.
+    fn delete_lines(content: &RopeBuffer, lines: impl Iterator<Item = usize>) -> Self {
+        let mut ranges: Vec<Range<ByteOffset>> = vec![];
+        for line in lines {
+            let range = content.line_to_byte(line)..content.line_to_byte(line + 1);
+            match ranges.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => ranges.push(range),
+            }
+        }
+        ranges.retain(|range| !range.is_empty());
+        if let Some(last) = ranges.last_mut() {
+            let eof = ByteOffset(content.len_bytes());
+            if last.end == eof && eol_len_before(content, eof) == 0 {
+                last.start.0 -= eol_len_before(content, last.start);
+            }
+        }
+        Self::from_edits(ranges.into_iter().map(Edit::Delete).collect())
+    }
+}
+
+/// Replaces the selection of the cursor (if any) with a line ending followed
+/// by the indentation of the current line
+fn push_newline_keep_indent(edits: &mut Vec<Edit>, cursor: &Cursor, content: &RopeBuffer, eol: &str) {
+    let indent = cursor.current_line_indentation(content);
+    edits.push(Edit::insert_str(cursor.offset, &format!("{eol}{indent}")));
+    if let Some(selection) = cursor.selection() {
+        edits.push(Edit::Delete(selection));
+    }
+}
+
+/// Contents of the line without the line ending
+fn line_without_eol(content: &RopeBuffer, line: usize) -> String {
+    let start = content.line_to_byte(line);
+    let end = content.line_to_byte(line + 1);
+    let end = ByteOffset(start.0.max(end.0 - eol_len_before(content, end)));
+    content.slice(&(start..end)).to_string()
+}
+
+/// Whether the last line of the span is a non-empty last line of the buffer
+/// that has no line ending
+fn ends_without_eol(content: &RopeBuffer, span: &Range<usize>) -> bool {
+    span.end == content.len_lines() && content.line_to_byte(span.end - 1).0 < content.len_bytes()
+}
+
+/// Length of the line ending that ends right before `offset`
+fn eol_len_before(content: &RopeBuffer, offset: ByteOffset) -> usize {
+    let before = |n: usize| offset.0.checked_sub(n).and_then(|i| content.get_byte(ByteOffset(i)));
+    match (before(2), before(1)) {
+        (Some(b'\r'), Some(b'\n')) => 2,
+        (_, Some(b'\n' | b'\r')) => 1,
+        _ => 0,
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -360,6 +886,16 @@ mod tests {
         assert_eq!(batch.edits[1], Edit::Delete(ByteOffset(10)..ByteOffset(20)));
     }
 
+    #[test]
+    fn affected_range_after_edits() {
+        let batch = EditBatch::from_edits(vec![
+            Edit::Delete(ByteOffset(5)..ByteOffset(10)),
+            Edit::insert_str(ByteOffset(20), "hello"),
+        ]);
+        assert_eq!(batch.affected_range(), Some(ByteOffset(5)..ByteOffset(20)));
+        assert_eq!(EditBatch::from_edits(vec![]).affected_range(), None);
+    }
+
     #[test]
     fn insert_and_delete_cmp() {
         assert!(
@@ -408,13 +944,36 @@ mod tests {
         let mut r = RopeBuffer::from_str("abab");
         let mut cursors = MultiCursor::new();
         cursors.select_to(&r, crate::MoveTarget::Right(2));
-        cursors.spawn_new_primary(crate::cursor::Cursor::new_with_selection(ByteOffset(2), Some(ByteOffset(4))));
+        cursors.spawn_new_primary(&r, crate::cursor::Cursor::new_with_selection(ByteOffset(2), Some(ByteOffset(4))));
         assert_eq!(cursors.cursor_count(), 2);
         let edits = EditBatch::insert_with_cursors(&cursors, "x");
         r.do_edits(&mut cursors, edits);
         assert_eq!(r.to_string(), "xx");
     }
 
+    #[rstest]
+    #[case::repeat("*3", "ab", "ababab")]
+    #[case::upper("upper", "straße", "STRASSE")]
+    #[case::lower("lower", "ÄbC", "äbc")]
+    #[case::list("list", "a b\nc", "[a, b, c]")]
+    #[case::quoted("quoted", "a \"b\" c\\", "\"a\" \"\\\"b\\\"\" \"c\\\\\"")]
+    #[case::align("align =", "a = 1\nbcd = 2", "a   = 1\nbcd = 2")]
+    #[case::align_trims_delimiter("align  =  ", "a= 1\nbcd = 2", "a   = 1\nbcd = 2")]
+    fn transform(#[case] arg: &str, #[case] text: &str, #[case] expected: &str) {
+        assert_eq!(Transform::parse(arg).unwrap().apply(text, 4), expected);
+    }
+
+    #[rstest]
+    #[case::unknown("reverse")]
+    #[case::not_a_number("*x")]
+    #[case::again("again")]
+    #[case::align_without_delimiter("align")]
+    #[case::align_blank_delimiter("align  ")]
+    #[case::not_align("alignment")]
+    fn invalid_transform(#[case] arg: &str) {
+        assert_eq!(Transform::parse(arg), None);
+    }
+
     #[test]
     fn delete_word() {
         let mut r = RopeBuffer::from_str("hello xxxxxworld");
@@ -435,6 +994,256 @@ mod tests {
         assert_eq!(r.to_string(), "hello world")
     }
 
+    #[rstest]
+    #[case::lf("hello\nworld", "helloworld")]
+    #[case::crlf("hello\r\nworld", "helloworld")]
+    #[case::after_space("hello \nworld", "hello world")]
+    fn delete_word_at_line_start_joins_lines(#[case] text: &str, #[case] expected: &str) {
+        let mut r = RopeBuffer::from_str(text);
+        let mut cursors = MultiCursor::new();
+        cursors.move_to(&r, crate::MoveTarget::Down(1));
+        let edits = EditBatch::delete_word_with_cursors(&cursors, &r);
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), expected);
+        assert_eq!(cursors.primary().offset, ByteOffset(expected.find('w').unwrap()));
+    }
+
+    #[rstest]
+    #[case::comment("//", "fn a() {\n\n    b();\n}", "// fn a() {\n\n    // b();\n// }")]
+    #[case::uncomment("//", "//fn a() {\n\n    // b();\n//}", "fn a() {\n\n    b();\n}")]
+    #[case::comment_mixed("#", "# a\n\tb\n", "# # a\n\t# b\n")]
+    #[case::uncomment_hash("#", "# a\n\t# b\n", "a\n\tb\n")]
+    #[case::only_blank_lines("#", "\n  \n", "\n  \n")]
+    fn toggle_line_comments(#[case] token: &str, #[case] text: &str, #[case] expected: &str) {
+        let mut r = RopeBuffer::from_str(text);
+        let mut cursors = MultiCursor::new();
+        cursors.select_to(&r, crate::MoveTarget::EndOfFile);
+        let edits = EditBatch::toggle_line_comments(&cursors, &r, token);
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), expected);
+    }
+
+    #[test]
+    fn toggle_line_comments_of_cursor_lines() {
+        let mut r = RopeBuffer::from_str("a\nb\nc\n");
+        let mut cursors = MultiCursor::new();
+        cursors.spawn_new(&r, crate::cursor::Cursor::new_with_offset(ByteOffset(5)));
+        let edits = EditBatch::toggle_line_comments(&cursors, &r, "//");
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), "// a\nb\n// c\n");
+        let edits = EditBatch::toggle_line_comments(&cursors, &r, "//");
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), "a\nb\nc\n");
+    }
+
+    #[rstest]
+    #[case::last_line_up(true, 3, "A\nB", "B\nA")]
+    #[case::penultimate_line_down(false, 0, "A\nB", "B\nA")]
+    #[case::crlf_up(true, 4, "A\r\nB", "B\r\nA")]
+    #[case::middle_line_up(true, 2, "A\nB\nC", "B\nA\nC")]
+    #[case::with_final_newline_up(true, 2, "A\nB\n", "B\nA\n")]
+    #[case::with_final_newline_down(false, 0, "A\nB\n", "B\nA\n")]
+    fn move_lines(#[case] up: bool, #[case] offset: usize, #[case] text: &str, #[case] expected: &str) {
+        let eol = if text.contains('\r') { "\r\n" } else { "\n" };
+        let mut r = RopeBuffer::from_str(text);
+        let mut cursors = MultiCursor::new();
+        cursors.move_to(&r, crate::MoveTarget::ByteOffset(offset));
+        let edits = if up {
+            EditBatch::move_lines_up(&cursors, &r, eol)
+        } else {
+            EditBatch::move_lines_down(&cursors, &r, eol)
+        };
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), expected);
+    }
+
+    #[rstest]
+    #[case::blank_lines("a\n\n  \nb\n\t\n", "a\nb\n")]
+    #[case::trailing_blank_lines_without_eol("a\nb\n\n  ", "a\nb")]
+    #[case::crlf("a\r\n\r\nb", "a\r\nb")]
+    #[case::only_blank("\n \n", "")]
+    fn remove_blank_lines(#[case] text: &str, #[case] expected: &str) {
+        let mut r = RopeBuffer::from_str(text);
+        let mut cursors = MultiCursor::new();
+        let edits = EditBatch::remove_blank_lines(std::slice::from_ref(&(0..r.len_lines())), &r);
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), expected);
+    }
+
+    #[rstest]
+    #[case::consecutive(false, "a\na\nb\na\n", "a\nb\na\n")]
+    #[case::global(true, "a\na\nb\na\n", "a\nb\n")]
+    #[case::last_line_without_eol(false, "a\nb\nb", "a\nb")]
+    #[case::trailing_blank_lines(false, "a\n\n\n", "a\n\n")]
+    fn remove_duplicate_lines(#[case] global: bool, #[case] text: &str, #[case] expected: &str) {
+        let mut r = RopeBuffer::from_str(text);
+        let mut cursors = MultiCursor::new();
+        let edits = EditBatch::remove_duplicate_lines(std::slice::from_ref(&(0..r.len_lines())), &r, global);
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), expected);
+    }
+
+    #[rstest]
+    #[case::indented_empty_line("    ", "    a\n    b\n    c\n", "    a\n    b\n    c\n")]
+    #[case::start_of_line("", "    a\n    b\n", "    a\n    b\n")]
+    #[case::after_text("x ", "  a\n", "x   a\n")]
+    fn paste_after_indentation(#[case] line: &str, #[case] clip: &str, #[case] expected: &str) {
+        let mut r = RopeBuffer::from_str(line);
+        let mut cursors = MultiCursor::new();
+        cursors.move_to(&r, crate::MoveTarget::EndOfFile);
+        let edits = EditBatch::insert_from_clipboard(&cursors, &r, &[clip.to_string()], true, false);
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), expected);
+    }
+
+    #[rstest]
+    #[case::distribute(true, &["1", "2"], "1a\n2b")]
+    #[case::distribute_count_mismatch(true, &["1", "2", "3"], "123a\n123b")]
+    #[case::block(false, &["1", "2"], "12a\n12b")]
+    fn paste_at_multiple_cursors(#[case] distribute: bool, #[case] clips: &[&str], #[case] expected: &str) {
+        let mut r = RopeBuffer::from_str("a\nb");
+        let mut cursors = MultiCursor::new();
+        cursors.spawn_new(&r, crate::cursor::Cursor::new_with_offset(ByteOffset(2)));
+        let clips: Vec<String> = clips.iter().map(|clip| clip.to_string()).collect();
+        let edits = EditBatch::insert_from_clipboard(&cursors, &r, &clips, distribute, false);
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), expected);
+    }
+
+    #[rstest]
+    #[case::equals("a = 1\nlong_name = 2\nno delimiter\n", "=", "a         = 1\nlong_name = 2\nno delimiter\n")]
+    #[case::colon("x: 1\nyy: 2\nzzz:3", ":", "x  : 1\nyy : 2\nzzz:3")]
+    #[case::first_occurrence("a = b = c\nccc = d", "=", "a   = b = c\nccc = d")]
+    #[case::tab("\ta: 1\nbbbbbb: 2", ":", "\ta : 1\nbbbbbb: 2")]
+    #[case::graphemes("äö = 1\n👩‍👩‍👧 = 2", "=", "äö = 1\n👩‍👩‍👧  = 2")]
+    #[case::no_delimiter("a\nb", "=", "a\nb")]
+    fn align_lines(#[case] text: &str, #[case] delimiter: &str, #[case] expected: &str) {
+        assert_eq!(align_on_delimiter(text, delimiter, 4), expected);
+    }
+
+    #[test]
+    fn align_with_zero_tab_width() {
+        assert_eq!(align_on_delimiter("\ta: 1\nbb: 2", ":", 0), "\ta : 1\nbb: 2");
+    }
+
+    #[rstest]
+    #[case::semicolons("a\nb;\n\n  c  \n", ";", "a;\nb;\n\n  c;  \n")]
+    #[case::commas("x,\r\ny\r\n", ",", "x,\r\ny,\r\n")]
+    #[case::no_final_newline("x\n\ty", ";", "x;\n\ty;")]
+    fn append_to_lines(#[case] text: &str, #[case] suffix: &str, #[case] expected: &str) {
+        let mut r = RopeBuffer::from_str(text);
+        let mut cursors = MultiCursor::new();
+        let edits = EditBatch::append_to_lines(std::slice::from_ref(&(0..r.len_lines())), &r, suffix);
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), expected);
+    }
+
+    #[rstest]
+    #[case::quoted("apple\n  banana \n\ncherry", "\"", "\",", "\"apple\",\n  \"banana\", \n\n\"cherry\",")]
+    #[case::empty_suffix("\tx\r\n", "- ", "", "\t- x\r\n")]
+    fn enclose_lines(#[case] text: &str, #[case] prefix: &str, #[case] suffix: &str, #[case] expected: &str) {
+        let mut r = RopeBuffer::from_str(text);
+        let mut cursors = MultiCursor::new();
+        let edits = EditBatch::enclose_lines(std::slice::from_ref(&(0..r.len_lines())), &r, prefix, suffix);
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), expected);
+    }
+
+    #[rstest]
+    #[case::braces("fn f() {}", 8, "fn f() {\n    \n}", 13)]
+    #[case::parens("  f()", 4, "  f(\n      \n  )", 11)]
+    #[case::brackets("[]", 1, "[\n    \n]", 6)]
+    #[case::mismatched("(]", 1, "(\n]", 2)]
+    #[case::not_between("{ }", 1, "{\n }", 2)]
+    fn insert_newline_between_brackets(
+        #[case] text: &str,
+        #[case] offset: usize,
+        #[case] expected: &str,
+        #[case] expected_offset: usize,
+    ) {
+        let mut r = RopeBuffer::from_str(text);
+        let mut cursors = MultiCursor::new();
+        cursors.primary_mut().offset = ByteOffset(offset);
+        let edits = EditBatch::insert_newline_smart_indent(&cursors, &r, "\n", "    ");
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), expected);
+        assert_eq!(cursors.primary().offset, ByteOffset(expected_offset));
+    }
+
+    #[rstest]
+    #[case::block("fn f() {\n    x\n    }", "fn f() {\n    x\n}")]
+    #[case::nested("  a [\n    (\n  b\n)", "  a [\n    (\n  b\n    )")]
+    #[case::tabs("\tif {\n\t\t}", "\tif {\n\t}")]
+    #[case::already_aligned("{\n}", "{\n}")]
+    #[case::not_at_line_start("{\n    x }", "{\n    x }")]
+    #[case::unmatched("\n    }", "\n    }")]
+    #[case::closing_tag("<ul>\n  <li>a</li>\n  </ul>", "<ul>\n  <li>a</li>\n</ul>")]
+    #[case::nested_closing_tag("<div>\n\t<p>\n\t\t\t</p>", "<div>\n\t<p>\n\t</p>")]
+    #[case::closing_tag_after_text("<p>\n  text</p>", "<p>\n  text</p>")]
+    #[case::mismatched_closing_tag("<p>\n  </b>", "<p>\n  </b>")]
+    fn dedent_closing_bracket(#[case] text: &str, #[case] expected: &str) {
+        let mut r = RopeBuffer::from_str(text);
+        let mut cursors = MultiCursor::new();
+        cursors.primary_mut().offset = ByteOffset(text.len());
+        let edits = EditBatch::dedent_closing_brackets(&cursors, &r);
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), expected);
+        assert_eq!(cursors.primary().offset, ByteOffset(expected.len()));
+    }
+
+    #[rstest]
+    #[case::simple("<div><p>text", Some("p"))]
+    #[case::closed("<div><p>text</p>", Some("div"))]
+    #[case::all_closed("<div><p>text</p></div>", None)]
+    #[case::attributes("<a href=\"x\" class='y'>link", Some("a"))]
+    #[case::self_closing("<ul><widget />", Some("ul"))]
+    #[case::void_element("<p>line<br>line<IMG src=x>", Some("p"))]
+    #[case::comment("<p><!-- <b> --></p><i>", Some("i"))]
+    #[case::doctype("<!DOCTYPE html><html>", Some("html"))]
+    #[case::processing_instruction("<?xml version=\"1.0\"?><root><ns:item>", Some("ns:item"))]
+    #[case::implicitly_closed("<ul><li>a<li>b</ul><p>", Some("p"))]
+    #[case::unterminated("<div><span", Some("div"))]
+    #[case::no_tags("plain text", None)]
+    #[case::quoted_gt("<div><a title=\"a > b\" href='>'>link", Some("a"))]
+    #[case::quote_in_text("<p>it's <b>bold</b>", Some("p"))]
+    #[case::different_case("<DIV><p>text</P>", Some("DIV"))]
+    fn find_unclosed_tag(#[case] text: &str, #[case] expected: Option<&str>) {
+        assert_eq!(unclosed_tag(text).map(|(_, name)| name), expected);
+    }
+
+    #[test]
+    fn unclosed_tag_is_only_searched_near_the_end() {
+        let filler = "ä".repeat(TAG_SCAN_LIMIT / 2);
+        let r = RopeBuffer::from_str(&format!("<div>{filler}<p>xy"));
+        assert_eq!(unclosed_tag_before(&r, ByteOffset(r.len_bytes())), Some((ByteOffset(5 + filler.len()), "p".into())));
+        let r = RopeBuffer::from_str(&format!("<div>{filler}x"));
+        assert_eq!(unclosed_tag_before(&r, ByteOffset(r.len_bytes())), None);
+    }
+
+    #[rstest]
+    #[case::opener("ab", 1, '(', Some(AutoClose::Pair(')')))]
+    #[case::quote("a ", 2, '"', Some(AutoClose::Pair('"')))]
+    #[case::apostrophe_in_word("don", 3, '\'', None)]
+    #[case::skip_closer("f()", 2, ')', Some(AutoClose::Skip))]
+    #[case::skip_quote("''", 1, '\'', Some(AutoClose::Skip))]
+    #[case::different_closer("f()", 2, ']', None)]
+    #[case::closer_at_eof("f(", 2, ')', None)]
+    #[case::other_char("ab", 1, 'x', None)]
+    fn auto_close_pairs(#[case] text: &str, #[case] offset: usize, #[case] c: char, #[case] expected: Option<AutoClose>) {
+        let r = RopeBuffer::from_str(text);
+        let mut cursors = MultiCursor::new();
+        cursors.move_to(&r, crate::MoveTarget::ByteOffset(offset));
+        assert_eq!(auto_close(&cursors, &r, c), expected);
+    }
+
+    #[test]
+    fn no_auto_close_with_selection() {
+        let r = RopeBuffer::from_str("ab");
+        let mut cursors = MultiCursor::new();
+        cursors.select_to(&r, crate::MoveTarget::Right(1));
+        assert_eq!(auto_close(&cursors, &r, '('), None);
+    }
+
     #[test]
     fn insert_newline_keep_indent_mid_indent() {
         let mut r = RopeBuffer::from_str("    abc");
@@ -463,7 +1272,7 @@ mod tests {
         let mut r = RopeBuffer::from_str(&" ".repeat(n_spaces));
         let mut cursors = MultiCursor::new();
         cursors.move_to(&r, crate::MoveTarget::EndOfFile);
-        let edits = EditBatch::delete_backward_with_cursors(&cursors, &r, indent_width);
+        let edits = EditBatch::delete_backward_with_cursors(&cursors, &r, Some(indent_width), true);
         r.do_edits(&mut cursors, edits);
         assert_eq!(r.len_bytes(), expected_length_after);
     }
@@ -473,13 +1282,33 @@ mod tests {
     #[case("\t", "")]
     #[case("\t\t", "\t")]
     #[case("\t\t ", "\t\t")]
-    #[case("\t\t  ", "\t\t ")]
+    #[case("\t\t  ", "\t\t")]
+    #[case("\t      ", "\t    ")]
+    #[case("\t    ", "\t")]
+    #[case("  \t", "  ")]
     fn test_delete_to_tabstop(#[case] before: &str, #[case] after: &str) {
         let mut r = RopeBuffer::from_str(before);
         let mut cursors = MultiCursor::new();
         cursors.move_to(&r, crate::MoveTarget::EndOfFile);
-        let edits = EditBatch::delete_backward_with_cursors(&cursors, &r, 4);
+        let edits = EditBatch::delete_backward_with_cursors(&cursors, &r, Some(4), true);
         r.do_edits(&mut cursors, edits);
         assert_eq!(&r.to_string(), after);
     }
+
+    #[rstest]
+    #[case::join(Some(4), true, "ab  cd")]
+    #[case::no_dedent(None, true, "ab   cd")]
+    #[case::no_join(Some(4), false, "ab\n  cd")]
+    fn backspace_settings(#[case] dedent_width: Option<usize>, #[case] join_lines: bool, #[case] expected: &str) {
+        // cursors in the middle of the indentation, at the start of the second
+        // line and at the very start of the buffer
+        let mut r = RopeBuffer::from_str("ab\n    cd");
+        let mut cursors = MultiCursor::new();
+        cursors.primary_mut().offset = ByteOffset(5);
+        cursors.spawn_new(&r, Cursor::new_with_offset(ByteOffset(3)));
+        cursors.spawn_new(&r, Cursor::new_with_offset(ByteOffset(0)));
+        let edits = EditBatch::delete_backward_with_cursors(&cursors, &r, dedent_width, join_lines);
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), expected);
+    }
 }
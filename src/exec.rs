@@ -115,3 +115,24 @@ pub fn execute_interactive_command_from_template(template: &str, path: &Path) ->
     execute_interactive_command(command)?;
     Ok(())
 }
+
+/// Opens `target` (a file or URL) with the desktop's default application
+/// without waiting for it to exit
+pub fn open_with_default_application(target: &str) -> Result<(), ExecError> {
+    let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    let mut child = Command::new(opener)
+        .arg(target)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .process_group(0)
+        .spawn()
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => ExecError::NotFound { executable: opener.to_string() },
+            std::io::ErrorKind::PermissionDenied => ExecError::PermissionDenied { executable: opener.to_string() },
+            _ => ExecError::Unknown(err)
+        })?;
+    // reap the child in the background so it does not linger as a zombie
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
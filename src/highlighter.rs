@@ -1,4 +1,5 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -12,75 +13,152 @@ use syntect::highlighting::{
     StyleModifier,
     Theme,
     ThemeItem,
+    ThemeSet,
     ThemeSettings,
 };
-use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::parsing::{ParseState, ScopeStack, ScopeStackOp, SyntaxReference, SyntaxSet};
 
+use crate::ByteOffset;
 use crate::ropebuffer::RopeBuffer;
 
+macro_rules! theme_scopes {
+    ( $( $scope:literal = $fg:literal )* ) => {
+        vec![
+            $(
+                ThemeItem {
+                    scope: ScopeSelectors::from_str($scope).unwrap(),
+                    style: StyleModifier {
+                        foreground: Color::from_str($fg).ok(),
+                        background: None,
+                        font_style: None,
+                    }
+                }
+            ),*
+        ]
+    }
+}
+
+fn dark_theme() -> Theme {
+    Theme {
+        name: Some("default".into()),
+        author: Some("Andriamanitra".into()),
+        settings: ThemeSettings {
+            foreground: Color::from_str("#F8F8F2").ok(),
+            background: Color::from_str("#1A1A1A").ok(),
+            ..ThemeSettings::default()
+        },
+        scopes: theme_scopes![
+            "string.quoted,punctuation.definition.string" = "#E6DB74"
+            "comment,punctuation.definition.comment" = "#75715E"
+            "keyword,storage,punctuation.separator,punctuation.terminator,punctuation.accessor,punctuation.definition.block" = "#D6006B"
+            "constant" = "#AE81FF"
+            "support.function,entity.name,meta.mapping.key.yaml" = "#66D9EF"
+            "storage.type,support.class,entity.name.type,support.type,meta.type" =  "#569CD6"
+            "storage.modifier.lifetime" = "#2AACAB"
+            "diff.inserted" = "#30CF50"
+            "diff.changed" = "#FFAF00"
+            "diff.deleted" = "#DB0000"
+            "string.regexp punctuation.definition.string.begin,string.regexp punctuation.definition.string.end" = "#D92682"
+            "string.regexp" = "#FB7FA8"
+            "support.macro,support.function.macro,variable.macro,entity.name.macro,punctuation.definition.macro" = "#A6E22E"
+            "punctuation.definition.annotation,variable.annotation" = "#A6E22E"
+            "meta.interpolation" = "#FFFFFF"
+            "punctuation.section" = "#D8D8D2"
+        ],
+    }
+}
+
+fn light_theme() -> Theme {
+    Theme {
+        name: Some("default-light".into()),
+        author: Some("Andriamanitra".into()),
+        settings: ThemeSettings {
+            foreground: Color::from_str("#2A2A2A").ok(),
+            background: Color::from_str("#FAFAF8").ok(),
+            ..ThemeSettings::default()
+        },
+        scopes: theme_scopes![
+            "string.quoted,punctuation.definition.string" = "#8A6D00"
+            "comment,punctuation.definition.comment" = "#8E8A7A"
+            "keyword,storage,punctuation.separator,punctuation.terminator,punctuation.accessor,punctuation.definition.block" = "#C0005C"
+            "constant" = "#7A3FD6"
+            "support.function,entity.name,meta.mapping.key.yaml" = "#0077A8"
+            "storage.type,support.class,entity.name.type,support.type,meta.type" =  "#2B62B0"
+            "storage.modifier.lifetime" = "#1A8584"
+            "diff.inserted" = "#1E8A34"
+            "diff.changed" = "#B36B00"
+            "diff.deleted" = "#C00000"
+            "string.regexp punctuation.definition.string.begin,string.regexp punctuation.definition.string.end" = "#B01E68"
+            "string.regexp" = "#C8467A"
+            "support.macro,support.function.macro,variable.macro,entity.name.macro,punctuation.definition.macro" = "#5C8A00"
+            "punctuation.definition.annotation,variable.annotation" = "#5C8A00"
+            "meta.interpolation" = "#000000"
+            "punctuation.section" = "#505050"
+        ],
+    }
+}
+
+/// Whether the theme in use has a dark or a light background, toggling
+/// switches between the `dark` and `light` themes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThemeVariant {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl ThemeVariant {
+    pub fn toggled(self) -> Self {
+        match self {
+            ThemeVariant::Dark => ThemeVariant::Light,
+            ThemeVariant::Light => ThemeVariant::Dark,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct BadHighlighterManager {
-    theme: Theme,
-    syntax_set: SyntaxSet,
+    /// The built-in themes and the ones loaded from `.tmTheme` files by their
+    /// file name (without the extension)
+    themes: BTreeMap<String, Theme>,
+    /// Name of the theme in use
+    theme_name: String,
+    theme_variant: ThemeVariant,
+    /// Shared so that changing the theme or the other settings, which clones
+    /// the manager through [`Arc::make_mut`], doesn't copy all the syntaxes
+    syntax_set: Arc<SyntaxSet>,
+    /// Lines longer than this (in bytes) are not highlighted, 0 disables highlighting
+    max_line_length: usize,
 }
 
 impl BadHighlighterManager {
+    const DEFAULT_MAX_LINE_LENGTH: usize = 1024;
+
     pub fn new() -> Self {
-        let syntax_set: SyntaxSet = syntect::dumps::from_uncompressed_data(
+        let mut syntax_set: SyntaxSet = syntect::dumps::from_uncompressed_data(
             include_bytes!(concat!(env!("OUT_DIR"), "/syntaxes.packdump"))
         ).expect("syntaxes.packdump should be valid");
-
-        macro_rules! theme_scopes {
-            ( $( $scope:literal = $fg:literal )* ) => {
-                vec![
-                    $(
-                        ThemeItem {
-                            scope: ScopeSelectors::from_str($scope).unwrap(),
-                            style: StyleModifier {
-                                foreground: Color::from_str($fg).ok(),
-                                background: None,
-                                font_style: None,
-                            }
-                        }
-                    ),*
-                ]
-            }
+        syntax_set.set_metadata(syntect::dumps::from_uncompressed_data(
+            include_bytes!(concat!(env!("OUT_DIR"), "/metadata.packdump"))
+        ).expect("metadata.packdump should be valid"));
+        let themes = BTreeMap::from([
+            ("dark".to_string(), dark_theme()),
+            ("light".to_string(), light_theme()),
+        ]);
+        Self {
+            themes,
+            theme_name: "dark".into(),
+            theme_variant: ThemeVariant::default(),
+            syntax_set: Arc::new(syntax_set),
+            max_line_length: Self::DEFAULT_MAX_LINE_LENGTH,
         }
-
-        let theme = Theme {
-            name: Some("default".into()),
-            author: Some("Andriamanitra".into()),
-            settings: ThemeSettings {
-                foreground: Color::from_str("#F8F8F2").ok(),
-                background: Color::from_str("#1A1A1A").ok(),
-                ..ThemeSettings::default()
-            },
-            scopes: theme_scopes![
-                "string.quoted,punctuation.definition.string" = "#E6DB74"
-                "comment,punctuation.definition.comment" = "#75715E"
-                "keyword,storage,punctuation.separator,punctuation.terminator,punctuation.accessor,punctuation.definition.block" = "#D6006B"
-                "constant" = "#AE81FF"
-                "support.function,entity.name,meta.mapping.key.yaml" = "#66D9EF"
-                "storage.type,support.class,entity.name.type,support.type,meta.type" =  "#569CD6"
-                "storage.modifier.lifetime" = "#2AACAB"
-                "diff.inserted" = "#30CF50"
-                "diff.changed" = "#FFAF00"
-                "diff.deleted" = "#DB0000"
-                "string.regexp punctuation.definition.string.begin,string.regexp punctuation.definition.string.end" = "#D92682"
-                "string.regexp" = "#FB7FA8"
-                "support.macro,support.function.macro,variable.macro,entity.name.macro,punctuation.definition.macro" = "#A6E22E"
-                "punctuation.definition.annotation,variable.annotation" = "#A6E22E"
-                "meta.interpolation" = "#FFFFFF"
-                "punctuation.section" = "#D8D8D2"
-            ],
-        };
-        Self { theme, syntax_set }
     }
 
     pub fn new_with_syntaxes_from_dir<P: AsRef<std::path::Path>>(syntax_dir: P) -> (Self, Result<(), syntect::LoadingError>) {
         let mut new = Self::new();
-        let mut builder = new.syntax_set.into_builder();
+        let mut builder = Arc::unwrap_or_clone(new.syntax_set).into_builder();
         let result = builder.add_from_folder(syntax_dir, true);
-        new.syntax_set = builder.build();
+        new.syntax_set = Arc::new(builder.build());
         (new, result)
     }
 
@@ -88,8 +166,95 @@ impl BadHighlighterManager {
         self.syntax_set.syntaxes().iter().filter(|syn| syn.name != "Plain Text").map(|syn| syn.name.as_str()).collect()
     }
 
+    pub fn theme_variant(&self) -> ThemeVariant {
+        self.theme_variant
+    }
+
+    /// Switches to the `dark` or `light` theme, highlighters created before the switch keep
+    /// using the old theme so they need to be recreated with [`BadHighlighter::with_manager`]
+    pub fn set_theme_variant(&mut self, variant: ThemeVariant) {
+        self.theme_variant = variant;
+        self.theme_name = match variant {
+            ThemeVariant::Dark => "dark".into(),
+            ThemeVariant::Light => "light".into(),
+        };
+    }
+
+    /// Names of the built-in themes and the ones loaded with [`Self::load_themes_from_folder`]
+    pub fn theme_names(&self) -> Vec<&str> {
+        self.themes.keys().map(String::as_str).collect()
+    }
+
+    pub fn theme_name(&self) -> &str {
+        &self.theme_name
+    }
+
+    /// Switches to the theme called `name` like [`Self::set_theme_variant`], fails
+    /// if there is no such theme. The theme variant follows the brightness of the
+    /// background of the theme.
+    pub fn set_theme(&mut self, name: &str) -> Result<(), ()> {
+        let theme = self.themes.get(name).ok_or(())?;
+        self.theme_variant = match (name, theme.settings.background) {
+            ("dark", _) => ThemeVariant::Dark,
+            ("light", _) => ThemeVariant::Light,
+            (_, Some(Color { r, g, b, .. })) if u32::from(r) + u32::from(g) + u32::from(b) > 3 * 128 => ThemeVariant::Light,
+            _ => ThemeVariant::Dark,
+        };
+        self.theme_name = name.to_string();
+        Ok(())
+    }
+
+    /// Takes the themes and other settings that don't depend on the syntaxes
+    /// from another manager
+    pub fn copy_settings_from(&mut self, other: &BadHighlighterManager) {
+        self.themes = other.themes.clone();
+        self.theme_name = other.theme_name.clone();
+        self.theme_variant = other.theme_variant;
+        self.max_line_length = other.max_line_length;
+    }
+
+    pub fn max_line_length(&self) -> usize {
+        self.max_line_length
+    }
+
+    /// Changes the length of the longest line that gets highlighted (0 disables
+    /// highlighting), existing highlighters need to be recreated like when
+    /// switching the theme
+    pub fn set_max_line_length(&mut self, max_line_length: usize) {
+        self.max_line_length = max_line_length;
+    }
+
+    /// Loads the `.tmTheme` files in `dir` to be selected by their names, eg.
+    /// `dark.tmTheme` replaces the built-in `dark` theme. Returns the files that
+    /// failed to load.
+    pub fn load_themes_from_folder<P: AsRef<std::path::Path>>(
+        &mut self,
+        dir: P,
+    ) -> Result<Vec<(std::path::PathBuf, syntect::LoadingError)>, syntect::LoadingError> {
+        let mut errors = vec![];
+        for path in ThemeSet::discover_theme_paths(dir)? {
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string) else { continue };
+            match ThemeSet::get_theme(&path) {
+                Ok(theme) => {
+                    self.themes.insert(name, theme);
+                }
+                Err(err) => errors.push((path, err)),
+            }
+        }
+        Ok(errors)
+    }
+
+    /// The colors that the theme defines for the UI (selection, gutter, etc.)
+    pub fn theme_settings(&self) -> &ThemeSettings {
+        &self.theme().settings
+    }
+
+    fn theme(&self) -> &Theme {
+        &self.themes[&self.theme_name]
+    }
+
     fn highlighter<'a>(&'a self) -> Highlighter<'a> {
-        Highlighter::new(&self.theme)
+        Highlighter::new(self.theme())
     }
 }
 
@@ -106,6 +271,15 @@ pub struct CachedState {
     line_number: usize,
 }
 
+/// What is needed to figure out the scopes at any point of the most recently
+/// highlighted line without parsing it again
+#[derive(Clone)]
+struct LastLine {
+    line_number: usize,
+    scopes_before: ScopeStack,
+    ops: Vec<(usize, ScopeStackOp)>,
+}
+
 #[derive(Clone)]
 pub struct BadHighlighter {
     filetype: String,
@@ -115,11 +289,12 @@ pub struct BadHighlighter {
     parse_state: ParseState,
     highlight_state: HighlightState,
     current_line: usize,
+    /// Line whose scopes are kept around when it is highlighted
+    scopes_line: Option<usize>,
+    last_line: Option<LastLine>,
 }
 
 impl BadHighlighter {
-    const MAX_LINE_LENGTH_FOR_HIGHLIGHTING: usize = 1024;
-
     pub fn for_file<P: AsRef<std::path::Path>>(file_path: P, manager: Arc<BadHighlighterManager>) -> Self {
         let syntax = match manager.syntax_set.find_syntax_for_file(file_path) {
             Ok(Some(s)) => s,
@@ -145,6 +320,41 @@ impl BadHighlighter {
             parse_state,
             highlight_state,
             current_line: 0,
+            scopes_line: None,
+            last_line: None,
+        }
+    }
+
+    /// Returns a highlighter for the same filetype that uses `manager`
+    pub fn with_manager(&self, manager: Arc<BadHighlighterManager>) -> Self {
+        let syntax = manager.syntax_set
+            .find_syntax_by_name(&self.filetype)
+            .unwrap_or_else(|| manager.syntax_set.find_syntax_plain_text());
+        BadHighlighter::for_syntax(syntax, manager.clone())
+    }
+
+    /// The token that starts a line comment in this filetype. It comes from the
+    /// `TM_COMMENT_START` shell variable of the syntax metadata (.tmPreferences)
+    /// when there is one, otherwise it's looked up by the filetype name.
+    pub fn line_comment_token(&self) -> Option<&str> {
+        let from_metadata = self.manager.syntax_set
+            .find_syntax_by_name(&self.filetype)
+            .and_then(|syntax| {
+                // the best matching metadata comes first
+                let metadata = self.manager.syntax_set.metadata().metadata_for_scope(&[syntax.scope]);
+                metadata.items.into_iter().find_map(|(_, set)| set.items.line_comment.as_deref())
+            })
+            .map(str::trim_end)
+            .filter(|token| !token.is_empty());
+        if from_metadata.is_some() {
+            return from_metadata
+        }
+        match self.filetype.as_str() {
+            "c" | "c++" | "c#" | "js" | "rust" | "typst" => Some("//"),
+            "bash" | "janet" | "julia" | "perl" | "python" | "ruby" | "toml" | "yaml" => Some("#"),
+            "haskell" | "lua" | "sql" => Some("--"),
+            "lisp" => Some(";"),
+            _ => None,
         }
     }
 
@@ -157,6 +367,13 @@ impl BadHighlighter {
         &self.filetype
     }
 
+    /// Whether a line of `len` bytes is too long to be highlighted (see
+    /// [`BadHighlighterManager::set_max_line_length`])
+    pub fn is_too_long_to_highlight(&self, len: usize) -> bool {
+        let max = self.manager.max_line_length;
+        max == 0 || len > max
+    }
+
     fn reset_state(&mut self) {
         self.current_line = 0;
         self.parse_state.clone_from(&self.initial_parse_state);
@@ -193,9 +410,28 @@ impl BadHighlighter {
         }
     }
 
+    /// Keeps the scopes of `line` when it gets highlighted so that `scope_stack_at`
+    /// can answer queries about it without parsing it again, `None` avoids the
+    /// extra work when nothing is going to ask
+    pub fn keep_scopes_of_line(&mut self, line: Option<usize>) {
+        self.scopes_line = line;
+        self.last_line = None;
+    }
+
+    /// Returns the scopes at `col_offset` (in bytes) of line `target_line`. This is
+    /// cheap for the line given to `keep_scopes_of_line` once it has been
+    /// highlighted, other lines need to be parsed again.
     pub fn scope_stack_at(&self, target_line: usize, col_offset: usize, text: &RopeBuffer) -> ScopeStack {
-        // TODO: make this less stupid, currently it doubles the render times
-        // (but this is only called when debug scopes is active)
+        if let Some(last) = self.last_line.as_ref().filter(|last| last.line_number == target_line) {
+            let mut scopes = last.scopes_before.clone();
+            let pp = last.ops.partition_point(|(i, _)| *i <= col_offset);
+            for (_, op) in &last.ops[..pp] {
+                if scopes.apply(op).is_err() {
+                    break
+                }
+            }
+            return scopes
+        }
         let mut clone = self.clone();
         clone.skip_to_line(target_line, text);
         let line = text.lines_at(clone.current_line).next().unwrap().to_string();
@@ -205,13 +441,15 @@ impl BadHighlighter {
         clone.highlight_state.path
     }
 
-    fn parse_line(&mut self, line: &str) {
-        if line.len() <= Self::MAX_LINE_LENGTH_FOR_HIGHLIGHTING {
-            let ops = self.parse_state.parse_line(line, &self.manager.syntax_set).unwrap_or_default();
+    fn parse_line(&mut self, line: &str) -> Vec<(usize, ScopeStackOp)> {
+        let mut ops = vec![];
+        if !self.is_too_long_to_highlight(line.len()) {
+            ops = self.parse_state.parse_line(line, &self.manager.syntax_set).unwrap_or_default();
             for _ in HighlightIterator::new(&mut self.highlight_state, &ops, line, &self.manager.highlighter()) {}
         }
         self.current_line += 1;
         self.memorize_current_state();
+        ops
     }
 
     fn memorize_current_state(&mut self) {
@@ -225,15 +463,239 @@ impl BadHighlighter {
     }
 
     pub fn highlight_line<'t>(&mut self, line: &'t str) -> impl Iterator<Item = (Style, &'t str)> {
-        let highlights: Vec<(Style, &'t str)> = if line.len() <= Self::MAX_LINE_LENGTH_FOR_HIGHLIGHTING {
-            let ops = self.parse_state.parse_line(line, &self.manager.syntax_set).unwrap_or_default();
-            HighlightIterator::new(&mut self.highlight_state, &ops, line, &self.manager.highlighter()).collect()
-        } else {
-            let style = self.manager.highlighter().style_for_stack(self.highlight_state.path.as_slice());
-            vec![(style, line)]
-        };
+        if self.is_too_long_to_highlight(line.len()) {
+            return vec![(self.highlight_long_line(), line)].into_iter()
+        }
+        let ops = self.parse_state.parse_line(line, &self.manager.syntax_set).unwrap_or_default();
+        let keep_scopes = self.scopes_line == Some(self.current_line);
+        let scopes_before = keep_scopes.then(|| self.highlight_state.path.clone());
+        let highlights: Vec<(Style, &'t str)> =
+            HighlightIterator::new(&mut self.highlight_state, &ops, line, &self.manager.highlighter()).collect();
+        if let Some(scopes_before) = scopes_before {
+            self.last_line = Some(LastLine { line_number: self.current_line, scopes_before, ops });
+        }
         self.current_line += 1;
         self.memorize_current_state();
         highlights.into_iter()
     }
+
+    /// Lines that are too long to highlight are not parsed at all,
+    /// returns the style for the whole line without needing its content
+    pub fn highlight_long_line(&mut self) -> Style {
+        let style = self.manager.highlighter().style_for_stack(self.highlight_state.path.as_slice());
+        if self.scopes_line == Some(self.current_line) {
+            self.last_line = Some(LastLine {
+                line_number: self.current_line,
+                scopes_before: self.highlight_state.path.clone(),
+                ops: vec![],
+            });
+        }
+        self.current_line += 1;
+        self.memorize_current_state();
+        style
+    }
+}
+
+/// Answers whether positions in the text are inside strings or comments.
+/// Lines are parsed (with a copy of the highlighter) as they are needed.
+pub struct StringsAndComments<'a> {
+    hl: BadHighlighter,
+    text: &'a RopeBuffer,
+    selector: ScopeSelectors,
+    spans_by_line: HashMap<usize, Vec<Range<usize>>>,
+}
+
+impl<'a> StringsAndComments<'a> {
+    pub fn new(hl: &BadHighlighter, text: &'a RopeBuffer) -> Self {
+        Self {
+            hl: hl.clone(),
+            text,
+            selector: ScopeSelectors::from_str("string, comment").expect("selector should be valid"),
+            spans_by_line: HashMap::new(),
+        }
+    }
+
+    pub fn contains(&mut self, offset: ByteOffset) -> bool {
+        let lineno = self.text.byte_to_line(offset);
+        let offset_in_line = offset.0 - self.text.line_to_byte(lineno).0;
+        if !self.spans_by_line.contains_key(&lineno) {
+            let spans = self.spans_on_line(lineno);
+            self.spans_by_line.insert(lineno, spans);
+        }
+        self.spans_by_line[&lineno].iter().any(|span| span.contains(&offset_in_line))
+    }
+
+    /// Byte ranges (relative to the start of the line) that are inside strings or comments
+    fn spans_on_line(&mut self, lineno: usize) -> Vec<Range<usize>> {
+        self.hl.skip_to_line(lineno, self.text);
+        let line = self.text.lines_at(lineno).next().map(|line| line.to_string()).unwrap_or_default();
+        let mut scopes = self.hl.highlight_state.path.clone();
+        let ops = self.hl.parse_line(&line);
+        let mut spans: Vec<Range<usize>> = vec![];
+        let mut span_start = 0;
+        for (i, op) in ops.iter().map(|(i, op)| (*i, Some(op))).chain([(line.len(), None)]) {
+            if i > span_start && self.selector.does_match(scopes.as_slice()).is_some() {
+                match spans.last_mut() {
+                    Some(last) if last.end == span_start => last.end = i,
+                    _ => spans.push(span_start..i),
+                }
+            }
+            span_start = i;
+            if let Some(op) = op {
+                let _ = scopes.apply(op);
+            }
+        }
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scope_stack_of_last_line_matches_highlighting() {
+        let manager = Arc::new(BadHighlighterManager::new());
+        let text = RopeBuffer::from_str("fn main() {\n    let s = \"hello\"; // hi\n}\n");
+        let mut hl = BadHighlighter::for_filetype("rust", manager.clone()).unwrap();
+        hl.keep_scopes_of_line(Some(1));
+        let first_line = text.lines_at(0).next().unwrap().to_string();
+        let _ = hl.highlight_line(&first_line);
+        let line = text.lines_at(1).next().unwrap().to_string();
+        let highlights: Vec<_> = hl.highlight_line(&line).collect();
+
+        let mut token_start = 0;
+        for (style, token) in highlights {
+            let scopes = hl.scope_stack_at(1, token_start, &text);
+            assert_eq!(manager.highlighter().style_for_stack(scopes.as_slice()), style, "style of {token:?}");
+            // the slow path that parses the line again should agree
+            let fresh = BadHighlighter::for_filetype("rust", manager.clone()).unwrap();
+            assert_eq!(fresh.scope_stack_at(1, token_start, &text), scopes, "scopes of {token:?}");
+            token_start += token.len();
+        }
+    }
+
+    #[test]
+    fn switching_theme_changes_styles() {
+        let mut manager = BadHighlighterManager::new();
+        let scopes = ScopeStack::from_str("source.rust string.quoted.double.rust").unwrap();
+        let dark = manager.highlighter().style_for_stack(scopes.as_slice());
+        manager.set_theme_variant(ThemeVariant::Light);
+        let light = manager.highlighter().style_for_stack(scopes.as_slice());
+        assert_ne!(light.foreground, dark.foreground);
+        assert_ne!(light.background, dark.background);
+        manager.set_theme_variant(ThemeVariant::Dark);
+        assert_eq!(manager.highlighter().style_for_stack(scopes.as_slice()), dark);
+        manager.set_theme("light").unwrap();
+        assert_eq!(manager.theme_variant(), ThemeVariant::Light);
+        assert_eq!(manager.highlighter().style_for_stack(scopes.as_slice()), light);
+        assert!(manager.set_theme("sepia").is_err());
+        assert_eq!(manager.theme_name(), "light");
+    }
+
+    #[test]
+    fn load_themes_from_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("sepia.tmTheme"), r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>name</key><string>Sepia</string>
+    <key>settings</key>
+    <array>
+        <dict>
+            <key>settings</key>
+            <dict>
+                <key>background</key><string>#F4ECD8</string>
+                <key>foreground</key><string>#5B4636</string>
+            </dict>
+        </dict>
+    </array>
+</dict>
+</plist>
+"#).unwrap();
+        std::fs::write(dir.path().join("broken.tmTheme"), "not a theme").unwrap();
+        let mut manager = BadHighlighterManager::new();
+        let errors = manager.load_themes_from_folder(dir.path()).unwrap();
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].0.ends_with("broken.tmTheme"));
+        assert_eq!(manager.theme_names(), ["dark", "light", "sepia"]);
+        assert!(manager.set_theme("broken").is_err());
+        assert!(manager.set_theme("sepia").is_ok());
+        assert_eq!(manager.theme_variant(), ThemeVariant::Light);
+        let style = manager.highlighter().style_for_stack(&[]);
+        assert_eq!(style.background, Color { r: 0xf4, g: 0xec, b: 0xd8, a: 0xff });
+        manager.set_theme_variant(ThemeVariant::Dark);
+        assert_eq!(manager.theme_name(), "dark");
+        assert_ne!(manager.highlighter().style_for_stack(&[]).background, style.background);
+    }
+
+    #[test]
+    fn line_comment_token_from_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("tex.sublime-syntax"), "%YAML 1.2
+---
+name: tex
+file_extensions: [tex]
+scope: text.tex
+contexts:
+  main: []
+").unwrap();
+        std::fs::write(dir.path().join("Comments.tmPreferences"), r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>scope</key><string>text.tex</string>
+    <key>settings</key>
+    <dict>
+        <key>shellVariables</key>
+        <array>
+            <dict>
+                <key>name</key><string>TM_COMMENT_START</string>
+                <key>value</key><string>% </string>
+            </dict>
+        </array>
+    </dict>
+</dict>
+</plist>
+"#).unwrap();
+        let (manager, result) = BadHighlighterManager::new_with_syntaxes_from_dir(dir.path());
+        result.unwrap();
+        let manager = Arc::new(manager);
+        let tex = BadHighlighter::for_filetype("tex", manager.clone()).unwrap();
+        assert_eq!(tex.line_comment_token(), Some("%"));
+        // the built-in syntaxes without metadata fall back to the table
+        let rust = BadHighlighter::for_filetype("rust", manager.clone()).unwrap();
+        assert_eq!(rust.line_comment_token(), Some("//"));
+        let json = BadHighlighter::for_filetype("json", manager).unwrap();
+        assert_eq!(json.line_comment_token(), None);
+    }
+
+    #[test]
+    fn changing_settings_shares_the_syntaxes() {
+        let mut manager = Arc::new(BadHighlighterManager::new());
+        let before = manager.clone();
+        Arc::make_mut(&mut manager).set_theme("light").unwrap();
+        Arc::make_mut(&mut manager).set_max_line_length(10);
+        assert!(!Arc::ptr_eq(&manager, &before));
+        assert!(Arc::ptr_eq(&manager.syntax_set, &before.syntax_set));
+    }
+
+    #[test]
+    fn lines_longer_than_the_limit_are_not_highlighted() {
+        let mut manager = BadHighlighterManager::new();
+        manager.set_max_line_length(20);
+        let manager = Arc::new(manager);
+        let mut hl = BadHighlighter::for_filetype("rust", manager.clone()).unwrap();
+        let short = "let x = \"s\";\n";
+        let long = "let long_name = \"long string\";\n";
+        assert!(hl.highlight_line(short).count() > 1);
+        let highlights: Vec<_> = hl.highlight_line(long).collect();
+        assert_eq!(highlights.len(), 1);
+        assert_eq!(highlights[0].1, long);
+
+        let mut manager = (*manager).clone();
+        manager.set_max_line_length(0);
+        let mut hl = hl.with_manager(Arc::new(manager));
+        assert_eq!(hl.highlight_line(short).count(), 1);
+    }
 }
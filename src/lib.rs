@@ -1,28 +1,42 @@
 mod app;
 pub mod cli;
 mod clipboard;
+mod conflict;
 mod cursor;
+mod custom_commands;
+mod diff;
 mod editing;
 mod exec;
 mod highlighter;
 mod linter;
+#[cfg(feature = "lsp")]
+mod lsp;
 mod pane;
 mod pane_settings;
 mod prompt;
 mod prompt_completer;
+mod remote;
 mod render;
 mod rope_ext;
 mod ropebuffer;
 mod run;
+mod search;
+mod swap;
+mod word_boundaries;
 mod completer;
 
 use std::num::NonZeroUsize;
 use std::path::PathBuf;
 
 pub use app::App;
-pub use cursor::MultiCursor;
+pub use cursor::{Cursor, MultiCursor};
+pub use custom_commands::CustomCommand;
+pub use editing::{Edit, EditBatch};
+pub use highlighter::ThemeVariant;
 pub use pane::{Pane, PaneAction};
 pub use rope_ext::RopeExt;
+pub use ropebuffer::RopeBuffer;
+pub use search::SearchQuery;
 
 use crate::cli::FilePathWithOptionalLocation;
 
@@ -32,7 +46,7 @@ impl ByteOffset {
     pub const MAX: ByteOffset = ByteOffset(usize::MAX);
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IndentKind {
     Spaces,
     Tabs,
@@ -51,15 +65,42 @@ pub enum Action {
     HandledByPane(PaneAction),
     Save,
     SaveAs(PathBuf),
-    Open(FilePathWithOptionalLocation),
+    /// Opens a file in the current pane, `force` skips asking about large files
+    Open { path: FilePathWithOptionalLocation, force: bool },
     Cut,
     Copy,
     Paste,
+    CopyToRegister(char),
+    PasteFromRegister(char),
+    /// Paste an earlier copy, 0 is the latest one
+    PasteFromHistory(usize),
+    /// Paste from the primary selection (middle click on Linux)
+    PastePrimary,
     NewPane,
     ClosePane,
     GoToPane(usize),
     NextPane,
     PreviousPane,
+    /// The next key press is a character that is passed to the function to get the action
+    AwaitChar(fn(char) -> Action),
+    CharSearch(CharSearch),
+    /// Repeats the last character search, in the opposite direction if `reverse` is set
+    RepeatCharSearch { reverse: bool },
+}
+
+/// Vim-style `f`/`t` search for a character on the current line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharSearch {
+    pub target: char,
+    /// Stop right before the character (`t`) instead of on it (`f`)
+    pub till: bool,
+    pub backward: bool,
+}
+
+impl CharSearch {
+    pub fn reversed(self) -> Self {
+        Self { backward: !self.backward, ..self }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -77,6 +118,7 @@ pub enum MoveTarget {
     NextWordBoundaryLeft,
     NextWordBoundaryRight,
     MatchingPair,
+    Char(CharSearch),
 }
 
 /// Quotes strings with spaces, quotes, or control characters in them
@@ -128,10 +170,45 @@ pub fn expand_path(path: &str) -> std::path::PathBuf {
     }
 }
 
+/// Expands `$VAR` and `${VAR}` environment variables, undefined variables are left as-is
+pub fn expand_env_vars(s: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = s;
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, len) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            }
+        } else {
+            let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+            (&after[..end], end)
+        };
+        match std::env::var(name) {
+            Ok(value) if !name.is_empty() => expanded.push_str(&value),
+            _ => expanded.push_str(&rest[dollar..dollar + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn env_var_expansion() {
+        let home = std::env::var("HOME").unwrap_or_default();
+        assert_eq!(expand_env_vars("$HOME/x"), format!("{home}/x"));
+        assert_eq!(expand_env_vars("${HOME}x"), format!("{home}x"));
+        assert_eq!(expand_env_vars("$BAD_SURELY_UNDEFINED_VAR/x"), "$BAD_SURELY_UNDEFINED_VAR/x");
+        assert_eq!(expand_env_vars("a$ ${b"), "a$ ${b");
+    }
+
     #[test]
     fn empty_string() {
         assert_eq!(quote_path(""), "''");
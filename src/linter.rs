@@ -14,13 +14,23 @@ type ColNo = NonZero<usize>;
 
 pub(crate) const DEFAULT_LINTER_SCRIPT: &str = include_str!("../default_config/linters.janet");
 
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
-enum Severity {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
     Info,
     Warning,
     Error,
 }
 
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum LinterError {
     FilenameRequired,
@@ -65,15 +75,41 @@ impl Lint {
         self.line.get()
     }
 
+    /// One-based column number where this Lint is located (1 if the linter did not report a column)
+    pub fn column(&self) -> usize {
+        self.column.map_or(1, NonZero::get)
+    }
+
     pub fn location(&self) -> Option<MoveTarget> {
         let col = self.column.unwrap_or(std::num::NonZero::<usize>::MIN);
         Some(MoveTarget::Location(self.line, col))
     }
 
+    pub fn severity(&self) -> Severity {
+        self.level
+    }
+
     pub fn is_error(&self) -> bool {
         matches!(self.level, Severity::Error)
     }
 
+    /// Creates a lint from a language server diagnostic (1-based `line` and `column`)
+    #[cfg(feature = "lsp")]
+    pub(crate) fn from_lsp_diagnostic(filename: String, line: usize, column: usize, severity: u64, message: String) -> Self {
+        let level = match severity {
+            1 => Severity::Error,
+            2 => Severity::Warning,
+            _ => Severity::Info,
+        };
+        Self {
+            message,
+            filename,
+            line: LineNo::new(line).unwrap_or(LineNo::MIN),
+            column: ColNo::new(column),
+            level,
+        }
+    }
+
     pub fn parse(line: &str) -> Option<Self> {
         static LINT_PATTERN: std::sync::OnceLock<grok::Pattern> = std::sync::OnceLock::new();
         let patt: &grok::Pattern = LINT_PATTERN.get_or_init(|| {
@@ -102,6 +138,165 @@ impl Lint {
     }
 }
 
+/// Severity from a word like `warning`, `note` or `fatal error` printed by a linter
+fn severity_from(word: &str) -> Severity {
+    let word = word.trim().to_ascii_lowercase();
+    if word.starts_with("info") || word.starts_with("note") || word.starts_with("help") {
+        Severity::Info
+    } else if word.starts_with('e') || word.starts_with("fatal") {
+        Severity::Error
+    } else {
+        Severity::Warning
+    }
+}
+
+/// How the lints are read from the output of a linter
+enum LintFormat {
+    /// A grok pattern that matches one lint per line
+    Pattern(grok::Pattern),
+    /// The JSON messages printed by `cargo --message-format=json`
+    CargoJson,
+}
+
+/// A linter that can be run without the linter script: a command and a grok
+/// pattern that picks the lints out of its output.
+///
+/// The command is split like a shell command and `{file}` in it is replaced with
+/// the name of the linted file. The pattern is a regular expression that can use
+/// the captures `%{file}`, `%{line}`, `%{col}`, `%{severity}` and `%{message}`.
+/// Only `line` is required: a missing filename means the linted file and a missing
+/// severity means a warning.
+pub struct LinterSpec {
+    filetype: String,
+    command: Vec<String>,
+    format: LintFormat,
+}
+
+impl LinterSpec {
+    pub fn new(filetype: &str, command: &str, pattern: &str) -> Result<Self, LinterError> {
+        let command = shlex::split(command)
+            .filter(|args| !args.is_empty())
+            .ok_or_else(|| LinterError::BadLinterScript(format!("invalid linter command `{command}`")))?;
+        let mut grok = grok::Grok::default();
+        grok.add_pattern("file", r"[^:\s][^:]*");
+        grok.add_pattern("line", r"[0-9]+");
+        grok.add_pattern("col", r"[0-9]*");
+        grok.add_pattern("severity", r"[^:]+");
+        grok.add_pattern("message", r".*");
+        let pattern = grok
+            .compile(pattern, false)
+            .map_err(|_| LinterError::BadLinterScript(format!("invalid lint pattern `{pattern}`")))?;
+        Ok(Self { filetype: filetype.to_string(), command, format: LintFormat::Pattern(pattern) })
+    }
+
+    /// Parses a `#spec FILETYPE | COMMAND | PATTERN` line of the linter script
+    fn from_directive(line: &str) -> Option<Result<Self, LinterError>> {
+        let rest = line.strip_prefix("#spec ")?;
+        let mut parts = rest.splitn(3, '|').map(str::trim);
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(filetype), Some(command), Some(pattern)) if !filetype.is_empty() => {
+                Some(Self::new(filetype, command, pattern))
+            }
+            _ => Some(Err(LinterError::BadLinterScript(format!("invalid linter spec `{line}`")))),
+        }
+    }
+
+    /// Runs the linter on `filename` and collects the lints from its stdout and stderr
+    pub fn run(&self, filename: &str) -> Result<HashMap<Filename, Vec<Lint>>, LinterError> {
+        let args: Vec<String> = self.command.iter().map(|arg| arg.replace("{file}", filename)).collect();
+        let output = std::process::Command::new(&args[0])
+            .args(&args[1..])
+            .output()
+            .map_err(|err| match err.kind() {
+                ErrorKind::NotFound => LinterError::Other(format!("{} not found in $PATH", args[0])),
+                _ => LinterError::Other(err.to_string()),
+            })?;
+        let mut combined = String::from_utf8_lossy(&output.stdout).into_owned();
+        combined.push('\n');
+        combined.push_str(&String::from_utf8_lossy(&output.stderr));
+        Ok(self.parse_output(&combined, filename))
+    }
+
+    fn parse_output(&self, output: &str, filename: &str) -> HashMap<Filename, Vec<Lint>> {
+        match &self.format {
+            LintFormat::Pattern(pattern) => {
+                group_lints(output.lines().filter_map(|line| parse_with_pattern(pattern, line, filename)))
+            }
+            LintFormat::CargoJson => group_lints(output.lines().filter_map(parse_cargo_message)),
+        }
+    }
+}
+
+fn parse_with_pattern(pattern: &grok::Pattern, line: &str, filename: &str) -> Option<Lint> {
+    let matches = pattern.match_against(line)?;
+    let line = matches.get("line")?.parse::<LineNo>().ok()?;
+    Some(Lint {
+        message: matches.get("message").unwrap_or_default().trim().to_string(),
+        filename: matches.get("file").unwrap_or(filename).to_string(),
+        line,
+        column: matches.get("col").and_then(|col| col.parse::<ColNo>().ok()),
+        level: matches.get("severity").map_or(Severity::Warning, severity_from),
+    })
+}
+
+/// Reads a lint from a `compiler-message` line of `cargo --message-format=json`,
+/// the lint is placed at the primary span of the diagnostic
+fn parse_cargo_message(line: &str) -> Option<Lint> {
+    let json: serde_json::Value = serde_json::from_str(line).ok()?;
+    if json["reason"] != "compiler-message" {
+        return None
+    }
+    let diagnostic = &json["message"];
+    let span = diagnostic["spans"].as_array()?.iter().find(|span| span["is_primary"] == true)?;
+    let level = match diagnostic["level"].as_str()? {
+        "note" | "help" => Severity::Info,
+        "warning" => Severity::Warning,
+        _ => Severity::Error,
+    };
+    Some(Lint {
+        message: diagnostic["message"].as_str()?.to_string(),
+        filename: span["file_name"].as_str()?.to_string(),
+        line: LineNo::new(span["line_start"].as_u64()? as usize)?,
+        column: span["column_start"].as_u64().and_then(|col| ColNo::new(col as usize)),
+        level,
+    })
+}
+
+/// Linters used when `janet` is not available to run the linter script
+fn builtin_specs() -> Vec<LinterSpec> {
+    [
+        ("c", "gcc -fsyntax-only -fdiagnostics-plain-output -Wall -Wextra {file}", r"^%{file}:%{line}:%{col}: %{severity}: %{message}$"),
+        ("python", "mypy --show-column-numbers {file}", r"^%{file}:%{line}:(%{col}:)? %{severity}: %{message}$"),
+        ("bash", "shellcheck --format=gcc {file}", r"^%{file}:%{line}:%{col}: %{severity}: %{message}$"),
+    ]
+    .into_iter()
+    .map(|(filetype, command, pattern)| LinterSpec::new(filetype, command, pattern).expect("built-in linter specs are valid"))
+    .chain([LinterSpec {
+        filetype: "rust".to_string(),
+        command: ["cargo", "clippy", "--message-format=json"].map(String::from).to_vec(),
+        format: LintFormat::CargoJson,
+    }])
+    .collect()
+}
+
+/// Collects the `#spec` lines of the linter script
+fn specs_from_script(script: &str) -> Result<Vec<LinterSpec>, LinterError> {
+    script.lines().filter_map(LinterSpec::from_directive).collect()
+}
+
+/// Lints the file with the first linter for `filetype` found in the following order:
+///
+/// 1. a `#spec FILETYPE | COMMAND | PATTERN` line in the linter script (see [`LinterSpec`]),
+/// 2. the `lint` function of the linter script,
+/// 3. a built-in [`LinterSpec`] if `janet` is not installed.
+///
+/// The linter script is `linters.janet` in the config directory or the default
+/// script. Its `lint` function is run with `janet` and is expected to print one
+/// lint per line in the form `FILENAME:LINE:COLUMN:SEVERITY:MESSAGE`, where the
+/// column may be empty and the severity is one of `info`, `warning` or `error`.
+/// The filename can be left out (`LINE:COLUMN:SEVERITY:MESSAGE`) for lints in the
+/// linted file. Lines that don't match are ignored and anything written to stderr
+/// is treated as an error.
 pub fn run_linter_command(script_path: Option<PathBuf>, filename: Option<&str>, filetype: &str) -> Result<HashMap<Filename, Vec<Lint>>, LinterError> {
     let Some(filename) = filename else {
         return Err(LinterError::FilenameRequired)
@@ -121,15 +316,14 @@ pub fn run_linter_command(script_path: Option<PathBuf>, filename: Option<&str>,
     } else {
         None
     };
+    let script = script.as_deref().unwrap_or(DEFAULT_LINTER_SCRIPT);
+
+    if let Some(spec) = specs_from_script(script)?.into_iter().find(|spec| spec.filetype == filetype) {
+        return spec.run(filename)
+    }
 
     let mut janet = std::process::Command::new("janet");
-    if let Some(script) = script {
-        janet.arg("-e");
-        janet.arg(script);
-    } else {
-        janet.arg("-e");
-        janet.arg(DEFAULT_LINTER_SCRIPT);
-    };
+    janet.arg("-e").arg(script);
     janet.arg("-E").arg("(lint $0 $1)").arg(filetype).arg(filename);
 
     match janet.output().map_err(|e| e.kind()) {
@@ -138,21 +332,121 @@ pub fn run_linter_command(script_path: Option<PathBuf>, filename: Option<&str>,
             if !stderr.is_empty() {
                 return Err(LinterError::BadLinterScript(stderr.into()));
             }
-            let mut lints = HashMap::new();
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            for line in stdout.lines() {
-                if let Some(lint) = Lint::parse(line) {
-                    let k = PathBuf::from(&lint.filename);
-                    let entry: &mut Vec<Lint> = lints.entry(k).or_default();
-                    entry.push(lint);
-                }
-            }
-            for lints_for_file in lints.values_mut() {
-                lints_for_file.sort_by(|a, b| b.level.cmp(&a.level));
-            }
-            Ok(lints)
+            Ok(parse_linter_output(&String::from_utf8_lossy(&output.stdout), filename))
         }
-        Err(ErrorKind::NotFound) => Err(LinterError::JanetNotInstalled),
+        Err(ErrorKind::NotFound) => match builtin_specs().into_iter().find(|spec| spec.filetype == filetype) {
+            Some(spec) => spec.run(filename),
+            None => Err(LinterError::JanetNotInstalled),
+        },
         Err(err) =>  Err(LinterError::Other(err.to_string())),
     }
 }
+
+fn parse_linter_output(stdout: &str, filename: &str) -> HashMap<Filename, Vec<Lint>> {
+    group_lints(
+        stdout
+            .lines()
+            .filter_map(|line| Lint::parse(line).or_else(|| Lint::parse(&format!("{filename}:{line}")))),
+    )
+}
+
+/// Groups lints by filename with the most severe lints first
+fn group_lints(lints: impl Iterator<Item = Lint>) -> HashMap<Filename, Vec<Lint>> {
+    let mut grouped = HashMap::new();
+    for lint in lints {
+        let k = PathBuf::from(&lint.filename);
+        let entry: &mut Vec<Lint> = grouped.entry(k).or_default();
+        entry.push(lint);
+    }
+    for lints_for_file in grouped.values_mut() {
+        lints_for_file.sort_by_key(|lint| std::cmp::Reverse(lint.level));
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_output_with_and_without_filenames() {
+        let output = "src/main.rs:3:7:warning:unused variable\n12::error:expected `;`\nnot a lint\nother.rs:1:1:info:note";
+        let lints = parse_linter_output(output, "src/main.rs");
+        let main: Vec<_> = lints[&PathBuf::from("src/main.rs")]
+            .iter()
+            .map(|lint| (lint.lineno(), lint.column(), lint.severity(), lint.message.as_str()))
+            .collect();
+        assert_eq!(main, [(12, 1, Severity::Error, "expected `;`"), (3, 7, Severity::Warning, "unused variable")]);
+        assert_eq!(lints[&PathBuf::from("other.rs")].len(), 1);
+        assert_eq!(lints.len(), 2);
+    }
+
+    fn summary(lints: &[Lint]) -> Vec<(usize, usize, Severity, &str)> {
+        lints.iter().map(|lint| (lint.lineno(), lint.column(), lint.severity(), lint.message.as_str())).collect()
+    }
+
+    #[test]
+    fn gcc_style_output() {
+        let spec = &builtin_specs()[0];
+        let output = "\
+main.c: In function 'main':
+main.c:4:9: warning: unused variable 'x' [-Wunused-variable]
+main.c:7:5: error: expected ';' before '}' token
+util.h:2:1: note: declared here
+";
+        let lints = spec.parse_output(output, "main.c");
+        assert_eq!(
+            summary(&lints[&PathBuf::from("main.c")]),
+            [(7, 5, Severity::Error, "expected ';' before '}' token"), (4, 9, Severity::Warning, "unused variable 'x' [-Wunused-variable]")]
+        );
+        assert_eq!(summary(&lints[&PathBuf::from("util.h")]), [(2, 1, Severity::Info, "declared here")]);
+        assert_eq!(lints.len(), 2);
+    }
+
+    #[test]
+    fn mypy_style_output() {
+        let spec = &builtin_specs()[1];
+        let output = "\
+app.py:12:5: error: Incompatible types in assignment (expression has type \"str\", variable has type \"int\")  [assignment]
+app.py:30: note: See https://mypy.rtfd.io/en/stable/_refs.html#code-assignment
+Found 1 error in 1 file (checked 1 source file)
+";
+        let lints = spec.parse_output(output, "app.py");
+        assert_eq!(
+            summary(&lints[&PathBuf::from("app.py")]),
+            [
+                (12, 5, Severity::Error, "Incompatible types in assignment (expression has type \"str\", variable has type \"int\")  [assignment]"),
+                (30, 1, Severity::Info, "See https://mypy.rtfd.io/en/stable/_refs.html#code-assignment"),
+            ]
+        );
+        assert_eq!(lints.len(), 1);
+    }
+
+    #[test]
+    fn specs_from_script_directives() {
+        let script = "(defn lint [LANGUAGE FILENAME] nil)\n#spec lua | luacheck --no-color {file} | ^\\s*%{line}:%{col}: %{message}$\n";
+        let specs = specs_from_script(script).unwrap();
+        assert_eq!(specs.len(), 1);
+        assert_eq!(specs[0].filetype, "lua");
+        assert_eq!(specs[0].command, ["luacheck", "--no-color", "{file}"]);
+        let lints = specs[0].parse_output("    3:7: unused variable x", "init.lua");
+        assert_eq!(summary(&lints[&PathBuf::from("init.lua")]), [(3, 7, Severity::Warning, "unused variable x")]);
+        assert!(specs_from_script("#spec lua | luacheck").is_err());
+    }
+
+    #[test]
+    fn clippy_json_output() {
+        let spec = builtin_specs().into_iter().find(|spec| spec.filetype == "rust").unwrap();
+        // recorded from `cargo clippy --message-format=json` (the rendered messages shortened)
+        let output = r#"{"reason":"compiler-artifact","package_id":"path+file:///tmp/demo#0.1.0","manifest_path":"/tmp/demo/Cargo.toml","target":{"kind":["bin"],"crate_types":["bin"],"name":"demo","src_path":"/tmp/demo/src/main.rs","edition":"2024","doc":true,"doctest":false,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false},"features":[],"filenames":[],"executable":null,"fresh":false}
+{"reason":"compiler-message","package_id":"path+file:///tmp/demo#0.1.0","manifest_path":"/tmp/demo/Cargo.toml","target":{"kind":["bin"],"crate_types":["bin"],"name":"demo","src_path":"/tmp/demo/src/main.rs","edition":"2024","doc":true,"doctest":false,"test":true},"message":{"rendered":"warning: unused variable: `x`\n","$message_type":"diagnostic","children":[{"children":[],"code":null,"level":"note","message":"`#[warn(unused_variables)]` on by default","rendered":null,"spans":[]},{"children":[],"code":null,"level":"help","message":"if this is intentional, prefix it with an underscore","rendered":null,"spans":[{"byte_end":21,"byte_start":20,"column_end":10,"column_start":9,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":2,"line_start":2,"suggested_replacement":"_x","suggestion_applicability":"MaybeIncorrect","text":[{"highlight_end":10,"highlight_start":9,"text":"    let x = 1;"}]}]}],"code":{"code":"unused_variables","explanation":null},"level":"warning","message":"unused variable: `x`","spans":[{"byte_end":21,"byte_start":20,"column_end":10,"column_start":9,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":2,"line_start":2,"suggested_replacement":null,"suggestion_applicability":null,"text":[{"highlight_end":10,"highlight_start":9,"text":"    let x = 1;"}]}]}}
+{"reason":"compiler-message","package_id":"path+file:///tmp/demo#0.1.0","manifest_path":"/tmp/demo/Cargo.toml","target":{"kind":["bin"],"crate_types":["bin"],"name":"demo","src_path":"/tmp/demo/src/main.rs","edition":"2024","doc":true,"doctest":false,"test":true},"message":{"rendered":"error[E0308]: mismatched types\n","$message_type":"diagnostic","children":[],"code":{"code":"E0308","explanation":"Expected type did not match the received type.\n"},"level":"error","message":"mismatched types","spans":[{"byte_end":52,"byte_start":49,"column_end":22,"column_start":19,"expansion":null,"file_name":"src/util.rs","is_primary":false,"label":"expected due to this","line_end":4,"line_start":4,"suggested_replacement":null,"suggestion_applicability":null,"text":[{"highlight_end":22,"highlight_start":19,"text":"    let n: u32 = \"1\";"}]},{"byte_end":58,"byte_start":55,"column_end":25,"column_start":22,"expansion":null,"file_name":"src/util.rs","is_primary":true,"label":"expected `u32`, found `&str`","line_end":4,"line_start":4,"suggested_replacement":null,"suggestion_applicability":null,"text":[{"highlight_end":25,"highlight_start":22,"text":"    let n: u32 = \"1\";"}]}]}}
+{"reason":"compiler-message","package_id":"path+file:///tmp/demo#0.1.0","manifest_path":"/tmp/demo/Cargo.toml","target":{"kind":["bin"],"crate_types":["bin"],"name":"demo","src_path":"/tmp/demo/src/main.rs","edition":"2024","doc":true,"doctest":false,"test":true},"message":{"rendered":"warning: 1 warning emitted\n\n","$message_type":"diagnostic","children":[],"code":null,"level":"warning","message":"1 warning emitted","spans":[]}}
+{"reason":"build-finished","success":false}
+"#;
+        let lints = spec.parse_output(output, "src/main.rs");
+        assert_eq!(summary(&lints[&PathBuf::from("src/main.rs")]), [(2, 9, Severity::Warning, "unused variable: `x`")]);
+        assert_eq!(summary(&lints[&PathBuf::from("src/util.rs")]), [(4, 22, Severity::Error, "mismatched types")]);
+        assert_eq!(lints.len(), 2);
+    }
+}
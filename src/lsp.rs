@@ -0,0 +1,503 @@
+//! Minimal language server client: the whole document is sent to the server
+//! on every change and the diagnostics it publishes are shown as lints.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::linter::Lint;
+use crate::{App, Pane};
+
+const INITIALIZE_ID: u64 = 1;
+const SHUTDOWN_ID: u64 = 2;
+
+/// Returns the language server command used for `filetype` when none is given explicitly
+pub fn default_server_command(filetype: &str) -> Option<&'static str> {
+    // TODO: these should come from a config file
+    match filetype {
+        "c" | "c++" => Some("clangd"),
+        "haskell" => Some("haskell-language-server-wrapper --lsp"),
+        "js" => Some("typescript-language-server --stdio"),
+        "lua" => Some("lua-language-server"),
+        "python" => Some("pylsp"),
+        "rust" => Some("rust-analyzer"),
+        _ => None,
+    }
+}
+
+fn language_id(filetype: &str) -> &str {
+    match filetype {
+        "c++" => "cpp",
+        "c#" => "csharp",
+        "js" => "javascript",
+        ft => ft,
+    }
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let absolute = std::fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    let mut uri = String::from("file://");
+    for &b in absolute.as_os_str().as_encoded_bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{b:02X}"));
+        }
+    }
+    uri
+}
+
+/// What the `character` offsets of positions count, the server picks one of
+/// the encodings offered by the client when initializing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PositionEncoding {
+    Utf8,
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// The encoding chosen in the response to initialize, servers that don't
+    /// know about the negotiation use UTF-16
+    fn from_initialize_result(result: Option<&Value>) -> Self {
+        let chosen = result
+            .and_then(|result| result.get("capabilities"))
+            .and_then(|capabilities| capabilities.get("positionEncoding"))
+            .and_then(Value::as_str);
+        match chosen {
+            Some("utf-8") => PositionEncoding::Utf8,
+            Some("utf-32") => PositionEncoding::Utf32,
+            _ => PositionEncoding::Utf16,
+        }
+    }
+
+    /// Converts a `character` offset in `line` to a column counted in grapheme
+    /// clusters like the columns of lints
+    fn column_in(self, line: &str, character: usize) -> usize {
+        let mut units = 0;
+        for (column, grapheme) in line.graphemes(true).enumerate() {
+            if units >= character {
+                return column
+            }
+            units += match self {
+                PositionEncoding::Utf8 => grapheme.len(),
+                PositionEncoding::Utf16 => grapheme.encode_utf16().count(),
+                PositionEncoding::Utf32 => grapheme.chars().count(),
+            };
+        }
+        line.graphemes(true).count()
+    }
+}
+
+/// Diagnostic with 0-based line and character offsets, as sent by the server
+struct Diagnostic {
+    line: usize,
+    character: usize,
+    severity: u64,
+    message: String,
+}
+
+struct PublishedDiagnostics {
+    uri: String,
+    encoding: PositionEncoding,
+    diagnostics: Vec<Diagnostic>,
+}
+
+fn parse_diagnostics(params: &Value, encoding: PositionEncoding) -> Option<PublishedDiagnostics> {
+    let uri = params.get("uri")?.as_str()?.to_string();
+    let diagnostics = params.get("diagnostics")?.as_array()?.iter().filter_map(|diagnostic| {
+        let start = diagnostic.get("range")?.get("start")?;
+        Some(Diagnostic {
+            line: start.get("line")?.as_u64()? as usize,
+            character: start.get("character")?.as_u64()? as usize,
+            // severity is optional, the client gets to decide what a missing one means
+            severity: diagnostic.get("severity").and_then(Value::as_u64).unwrap_or(1),
+            message: diagnostic.get("message")?.as_str()?.to_string(),
+        })
+    }).collect();
+    Some(PublishedDiagnostics { uri, encoding, diagnostics })
+}
+
+enum Outgoing {
+    Notification(&'static str, Value),
+    MethodNotFound(Value),
+    Shutdown,
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> std::io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
+
+/// Reads one message, returns `None` when the server has closed its output
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            return None
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok();
+            }
+        }
+    }
+    let mut body = vec![0; content_length?];
+    reader.read_exact(&mut body).ok()?;
+    Some(serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn write_loop(mut stdin: ChildStdin, initialize: Value, initialized: Receiver<()>, outgoing: Receiver<Outgoing>) {
+    // nothing else may be sent before the server has responded to initialize
+    if write_message(&mut stdin, &initialize).is_err() || initialized.recv().is_err() {
+        return
+    }
+    let initialized = json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} });
+    if write_message(&mut stdin, &initialized).is_err() {
+        return
+    }
+    for message in outgoing {
+        let result = match message {
+            Outgoing::Notification(method, params) => {
+                write_message(&mut stdin, &json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+            }
+            Outgoing::MethodNotFound(id) => {
+                let error = json!({ "code": -32601, "message": "method not supported by bad" });
+                write_message(&mut stdin, &json!({ "jsonrpc": "2.0", "id": id, "error": error }))
+            }
+            Outgoing::Shutdown => {
+                let _ = write_message(&mut stdin, &json!({ "jsonrpc": "2.0", "id": SHUTDOWN_ID, "method": "shutdown" }));
+                let _ = write_message(&mut stdin, &json!({ "jsonrpc": "2.0", "method": "exit" }));
+                return
+            }
+        };
+        if result.is_err() {
+            return
+        }
+    }
+}
+
+fn read_loop(stdout: ChildStdout, initialized: Sender<()>, replies: Sender<Outgoing>, diagnostics: Sender<PublishedDiagnostics>) {
+    let mut reader = BufReader::new(stdout);
+    let mut encoding = PositionEncoding::Utf16;
+    while let Some(message) = read_message(&mut reader) {
+        let method = message.get("method").and_then(Value::as_str);
+        match (message.get("id"), method) {
+            (Some(id), None) if id == INITIALIZE_ID => {
+                encoding = PositionEncoding::from_initialize_result(message.get("result"));
+                let _ = initialized.send(());
+            }
+            (Some(id), Some(_)) => {
+                let _ = replies.send(Outgoing::MethodNotFound(id.clone()));
+            }
+            (None, Some("textDocument/publishDiagnostics")) => {
+                if let Some(published) = message.get("params").and_then(|params| parse_diagnostics(params, encoding)) {
+                    if diagnostics.send(published).is_err() {
+                        return
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Document {
+    version: u64,
+    /// Set by the change hook of the pane which only holds a weak reference to
+    /// it, the pane has been closed when there are no weak references left
+    changed: Arc<AtomicBool>,
+}
+
+pub struct LspClient {
+    child: Child,
+    outgoing: Sender<Outgoing>,
+    diagnostics: Receiver<PublishedDiagnostics>,
+    documents: HashMap<String, Document>,
+    /// URIs of the paths of the panes, resolving them touches the file system
+    /// so it's not done again on every sync
+    uris: HashMap<PathBuf, String>,
+}
+
+impl LspClient {
+    pub fn start(command: &str, root: &Path) -> std::io::Result<LspClient> {
+        let args = shlex::split(command)
+            .filter(|args| !args.is_empty())
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid command"))?;
+        let mut child = Command::new(&args[0])
+            .args(&args[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let (outgoing_tx, outgoing_rx) = mpsc::channel();
+        let (initialized_tx, initialized_rx) = mpsc::channel();
+        let (diagnostics_tx, diagnostics_rx) = mpsc::channel();
+        let initialize = json!({
+            "jsonrpc": "2.0",
+            "id": INITIALIZE_ID,
+            "method": "initialize",
+            "params": {
+                "processId": std::process::id(),
+                "rootUri": path_to_uri(root),
+                "capabilities": {
+                    "general": { "positionEncodings": ["utf-8", "utf-32", "utf-16"] },
+                    "textDocument": { "publishDiagnostics": {} },
+                },
+            },
+        });
+        std::thread::spawn(move || write_loop(stdin, initialize, initialized_rx, outgoing_rx));
+        let replies = outgoing_tx.clone();
+        std::thread::spawn(move || read_loop(stdout, initialized_tx, replies, diagnostics_tx));
+
+        Ok(LspClient {
+            child,
+            outgoing: outgoing_tx,
+            diagnostics: diagnostics_rx,
+            documents: HashMap::new(),
+            uris: HashMap::new(),
+        })
+    }
+
+    fn uri_for(&mut self, path: &Path) -> &str {
+        self.uris.entry(path.to_path_buf()).or_insert_with(|| path_to_uri(path))
+    }
+
+    /// Opens the document of `pane` on the server if it is not open yet, or
+    /// sends its full text if it has changed since the last sync
+    fn sync(&mut self, pane: &mut Pane) {
+        let Some(path) = pane.path.as_deref() else { return };
+        let uri = self.uri_for(path).to_string();
+        if let Some(doc) = self.documents.get_mut(&uri) {
+            if Arc::weak_count(&doc.changed) > 0 {
+                if doc.changed.swap(false, Ordering::Relaxed) {
+                    doc.version += 1;
+                    let params = json!({
+                        "textDocument": { "uri": uri, "version": doc.version },
+                        "contentChanges": [{ "text": pane.text() }],
+                    });
+                    let _ = self.outgoing.send(Outgoing::Notification("textDocument/didChange", params));
+                }
+                return
+            }
+            // the pane that had the document open was replaced, start over
+            self.close(&uri);
+        }
+        let changed = Arc::new(AtomicBool::new(false));
+        let flag = Arc::downgrade(&changed);
+        // the hook of the user (or of an earlier language server) stays in place,
+        // this one does nothing once the document has been closed
+        pane.chain_on_change(move |_| {
+            if let Some(flag) = flag.upgrade() {
+                flag.store(true, Ordering::Relaxed);
+            }
+        });
+        let params = json!({
+            "textDocument": {
+                "uri": uri,
+                "languageId": language_id(pane.filetype()),
+                "version": 1,
+                "text": pane.text(),
+            },
+        });
+        let _ = self.outgoing.send(Outgoing::Notification("textDocument/didOpen", params));
+        self.documents.insert(uri, Document { version: 1, changed });
+    }
+
+    fn close(&mut self, uri: &str) {
+        self.documents.remove(uri);
+        let params = json!({ "textDocument": { "uri": uri } });
+        let _ = self.outgoing.send(Outgoing::Notification("textDocument/didClose", params));
+    }
+
+    fn close_documents_of_closed_panes(&mut self) {
+        let closed: Vec<String> = self.documents
+            .iter()
+            .filter(|(_, doc)| Arc::weak_count(&doc.changed) == 0)
+            .map(|(uri, _)| uri.clone())
+            .collect();
+        for uri in closed {
+            self.close(&uri);
+        }
+    }
+}
+
+impl Drop for LspClient {
+    fn drop(&mut self) {
+        let _ = self.outgoing.send(Outgoing::Shutdown);
+        // give the server a moment to exit on its own before killing it
+        let deadline = Instant::now() + Duration::from_millis(200);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl App {
+    pub(crate) fn lsp_command(&mut self, arg: &str) {
+        let ft = self.current_pane().filetype().to_string();
+        let (subcommand, server_command) = arg.split_once(' ').unwrap_or((arg, ""));
+        match subcommand {
+            "start" => {
+                let server_command = match server_command.trim() {
+                    "" => match default_server_command(&ft) {
+                        Some(cmd) => cmd.to_string(),
+                        None => {
+                            self.inform(format!("lsp error: no language server configured for ft:{ft}"));
+                            return
+                        }
+                    },
+                    cmd => cmd.to_string(),
+                };
+                let root = std::env::current_dir().unwrap_or_default();
+                match LspClient::start(&server_command, &root) {
+                    Ok(client) => {
+                        self.language_servers.insert(ft.clone(), client);
+                        self.inform(format!("Started language server '{server_command}' for ft:{ft}"));
+                    }
+                    Err(err) => self.inform(format!("lsp error: {err}: {server_command}")),
+                }
+            }
+            "stop" => {
+                if self.language_servers.remove(&ft).is_some() {
+                    for pane in self.panes.iter_mut().filter(|pane| pane.filetype() == ft) {
+                        pane.lints.clear();
+                    }
+                    self.inform(format!("Stopped language server for ft:{ft}"));
+                } else {
+                    self.inform(format!("lsp error: no language server running for ft:{ft}"));
+                }
+            }
+            _ => self.inform("Usage: lsp start [COMMAND] | lsp stop".into()),
+        }
+    }
+
+    /// Sends changed documents to the language servers and turns the diagnostics
+    /// they have published into lints, returns true if any lints were updated
+    pub(crate) fn sync_language_servers(&mut self) -> bool {
+        if self.language_servers.is_empty() {
+            return false
+        }
+        for pane in self.panes.iter_mut() {
+            let ft = pane.filetype().to_string();
+            if let Some(client) = self.language_servers.get_mut(&ft) {
+                client.sync(pane);
+            }
+        }
+        let mut updated = false;
+        for client in self.language_servers.values_mut() {
+            client.close_documents_of_closed_panes();
+            let all_published: Vec<_> = client.diagnostics.try_iter().collect();
+            for published in all_published {
+                for pane in self.panes.iter_mut() {
+                    let Some(path) = pane.path.as_deref() else { continue };
+                    if client.uri_for(path) != published.uri {
+                        continue
+                    }
+                    let filename = path.to_string_lossy().to_string();
+                    pane.lints = published.diagnostics.iter().map(|diagnostic| {
+                        let line = pane.line(diagnostic.line).unwrap_or_default();
+                        Lint::from_lsp_diagnostic(
+                            filename.clone(),
+                            diagnostic.line + 1,
+                            published.encoding.column_in(&line, diagnostic.character) + 1,
+                            diagnostic.severity,
+                            diagnostic.message.clone(),
+                        )
+                    }).collect();
+                    updated = true;
+                }
+            }
+        }
+        if updated {
+            self.update_lint_lists();
+        }
+        updated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_framing_roundtrip() {
+        let mut buf = vec![];
+        let message = json!({ "jsonrpc": "2.0", "method": "exit" });
+        write_message(&mut buf, &message).unwrap();
+        write_message(&mut buf, &message).unwrap();
+        assert!(buf.starts_with(b"Content-Length: 33\r\n\r\n{"));
+        let mut reader = std::io::Cursor::new(buf);
+        assert_eq!(read_message(&mut reader), Some(message.clone()));
+        assert_eq!(read_message(&mut reader), Some(message));
+        assert_eq!(read_message(&mut reader), None);
+    }
+
+    #[test]
+    fn uri_is_percent_encoded() {
+        assert_eq!(path_to_uri(Path::new("/nonexistent/my file#1.rs")), "file:///nonexistent/my%20file%231.rs");
+    }
+
+    #[test]
+    fn parse_published_diagnostics() {
+        let params = json!({
+            "uri": "file:///tmp/x.rs",
+            "diagnostics": [
+                { "range": { "start": { "line": 2, "character": 4 }, "end": { "line": 2, "character": 5 } }, "severity": 2, "message": "unused" },
+                { "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 1 } }, "message": "no severity" },
+            ],
+        });
+        let published = parse_diagnostics(&params, PositionEncoding::Utf16).unwrap();
+        assert_eq!(published.uri, "file:///tmp/x.rs");
+        assert_eq!(published.diagnostics.len(), 2);
+        assert_eq!(published.diagnostics[0].line, 2);
+        assert_eq!(published.diagnostics[0].character, 4);
+        assert_eq!(published.diagnostics[0].severity, 2);
+        assert_eq!(published.diagnostics[1].severity, 1);
+        assert_eq!(published.diagnostics[1].message, "no severity");
+    }
+
+    #[test]
+    fn negotiated_position_encoding() {
+        let result = json!({ "capabilities": { "positionEncoding": "utf-8" } });
+        assert_eq!(PositionEncoding::from_initialize_result(Some(&result)), PositionEncoding::Utf8);
+        let result = json!({ "capabilities": {} });
+        assert_eq!(PositionEncoding::from_initialize_result(Some(&result)), PositionEncoding::Utf16);
+        assert_eq!(PositionEncoding::from_initialize_result(None), PositionEncoding::Utf16);
+    }
+
+    #[test]
+    fn character_offsets_to_columns() {
+        // "ä" is 2 bytes in UTF-8 and the emoji takes 2 UTF-16 code units
+        let line = "ä\u{1f600}x";
+        assert_eq!(PositionEncoding::Utf8.column_in(line, 6), 2);
+        assert_eq!(PositionEncoding::Utf16.column_in(line, 3), 2);
+        assert_eq!(PositionEncoding::Utf32.column_in(line, 2), 2);
+        assert_eq!(PositionEncoding::Utf16.column_in(line, 100), 3);
+        assert_eq!(PositionEncoding::Utf16.column_in("", 0), 0);
+    }
+}
@@ -1,8 +1,9 @@
-use std::io::stdout;
+use std::io::{IsTerminal, stdin, stdout};
+use std::path::Path;
 
 use bad_editor::{App, cli};
 use crossterm::ExecutableCommand;
-use crossterm::cursor::{Hide as HideCursor, Show as ShowCursor};
+use crossterm::cursor::{Hide as HideCursor, SetCursorStyle, Show as ShowCursor};
 use crossterm::event::{
     DisableBracketedPaste,
     EnableBracketedPaste,
@@ -25,12 +26,31 @@ impl Drop for TerminalGuard {
     fn drop(&mut self) {
         let _ = crossterm::terminal::disable_raw_mode();
         let _ = stdout().execute(ShowCursor);
+        let _ = stdout().execute(SetCursorStyle::DefaultUserShape);
         let _ = stdout().execute(PopKeyboardEnhancementFlags);
         let _ = stdout().execute(DisableMouseCapture);
         let _ = stdout().execute(DisableBracketedPaste);
     }
 }
 
+/// Restores the terminal *before* the panic message gets printed so that the
+/// message (and backtrace) ends up in the main screen where it stays readable
+/// after the editor has exited.
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = crossterm::terminal::disable_raw_mode();
+        let _ = stdout().execute(PopKeyboardEnhancementFlags);
+        let _ = stdout().execute(DisableMouseCapture);
+        let _ = stdout().execute(DisableBracketedPaste);
+        let _ = stdout().execute(LeaveAlternateScreen);
+        let _ = stdout().execute(ShowCursor);
+        let _ = stdout().execute(SetCursorStyle::DefaultUserShape);
+        eprintln!("bad crashed, please report this at https://github.com/Andriamanitra/bad-editor/issues\n");
+        default_hook(info);
+    }));
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut app = App::new();
     let args = cli::parse_cli_args();
@@ -38,17 +58,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     if !args.get_flag("clean") {
         app.set_project_dirs();
         app.load_runtime_syntaxes();
+        app.load_themes();
+        app.load_custom_commands();
+        // the config has to be loaded before the files are opened to apply max_file_size to them
+        app.load_config();
     }
 
+    let stdin_filetype = args.get_one::<String>("ft").map(String::as_str);
     if let Some(file_locs) = args.get_many::<cli::FilePathWithOptionalLocation>("file") {
         for file_loc in file_locs {
-            app.open_file_in_new_pane(file_loc);
+            if file_loc.path == Path::new("-") {
+                app.open_stdin_in_new_pane(stdin_filetype);
+            } else {
+                app.open_file_in_new_pane(file_loc);
+            }
         }
+    } else if !stdin().is_terminal() {
+        app.open_stdin_in_new_pane(stdin_filetype);
     }
+    // crossterm reads the events from /dev/tty instead of stdin when stdin is
+    // not a terminal so the piped input being used up doesn't matter
 
     // TerminalGuard ensures raw mode gets disabled if the app crashes.
     // Drop runs when variable leaves the scope, even on panic.
     let terminal_guard = TerminalGuard::acquire()?;
+    install_panic_hook();
     stdout().execute(HideCursor)?;
     stdout().execute(EnterAlternateScreen)?;
     stdout().execute(EnableMouseCapture)?;
@@ -59,7 +93,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     drop(terminal_guard);
 
-    // the backtrace from panicking is in the alternate screen
+    // on panic the hook has already left the alternate screen
     // so we only want to execute this when exiting normally
     stdout().execute(LeaveAlternateScreen)?;
 
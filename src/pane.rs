@@ -1,17 +1,27 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io::{BufReader, ErrorKind, Read, Write};
+use std::io::{ErrorKind, Read, Write};
 use std::num::NonZeroUsize;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ropey::RopeSlice;
 
 use crate::cli::FilePathWithOptionalLocation;
+use crate::clipboard::Clips;
 use crate::completer::{Completer, CompletionResult, SuggestionMenu};
+use crate::conflict::{Conflict, ConflictResolution};
 use crate::cursor::Cursor;
-use crate::editing::{Edit, EditBatch};
-use crate::highlighter::{BadHighlighter, BadHighlighterManager};
+use crate::editing::{AutoClose, Edit, EditBatch, auto_close, unclosed_tag_before};
+use crate::highlighter::{BadHighlighter, BadHighlighterManager, StringsAndComments};
 use crate::linter::Lint;
-use crate::pane_settings::{AutoIndent, PaneSettings};
+use crate::pane_settings::{AutoIndent, PaneSettings, TitleStyle, default_extra_word_chars};
+use crate::remote::{LocalCopy, RemotePath};
 use crate::ropebuffer::RopeBuffer;
+use crate::search::SearchQuery;
+use crate::swap::{SwapFile, SwapState};
 use crate::{ByteOffset, MoveTarget, MultiCursor};
 
 #[derive(Debug, Clone)]
@@ -29,6 +39,13 @@ pub enum PaneAction {
     Dedent,
     MoveLinesUp,
     MoveLinesDown,
+    ToggleComment,
+    /// Surround the selections with the character and its pair
+    Surround(char),
+    /// Exchange the texts of two selections
+    SwapSelections,
+    /// Replace each selection with a cursor at the end of every line it covers
+    CursorAtLineEnds,
     Undo,
     Redo,
     Find(String),
@@ -37,30 +54,102 @@ pub enum PaneAction {
     QuickAddNext,
     ScrollDown(usize),
     ScrollUp(usize),
+    CursorToViewportTop,
+    CursorToViewportMiddle,
+    CursorToViewportBottom,
     Tab,
     BackTab,
     Autocomplete,
     AutocompleteCyclePrevious,
     AutocompleteCycleNext,
     AutocompleteAcceptSuggestion,
+    ResolveConflict(ConflictResolution),
+    NextConflict,
+}
+
+/// Callback that gets called with the changed range of the buffer after every edit
+pub type ChangeHook = Box<dyn FnMut(Range<ByteOffset>) + Send>;
+
+/// How long the pane has to be left alone before the occurrences of the word
+/// under the cursor get highlighted
+const WORD_HIGHLIGHT_DELAY: Duration = Duration::from_millis(150);
+
+/// Counting the occurrences of the highlighted word stops at this point so that
+/// moving around in huge files stays responsive
+const WORD_COUNT_SCAN_LIMIT: usize = 16 * 1024 * 1024;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Characters that ropey treats as line breaks (in addition to CRLF)
+/// https://docs.rs/ropey/1.6.1/ropey/index.html#a-note-about-line-breaks
+const UNICODE_LINE_END_CHARS: [char; 7] = [
+    '\u{000A}', '\u{000D}', '\u{000B}', '\u{000C}', '\u{0085}', '\u{2028}', '\u{2029}'
+];
+
+/// The contents of a file as they were read from disk
+struct LoadedFile {
+    content: RopeBuffer,
+    had_bom: bool,
+    /// The file was not valid UTF-8 so some bytes were replaced
+    lossy: bool,
+}
+
+/// Number of occurrences of the highlighted word in the buffer
+#[derive(Debug)]
+pub(crate) struct WordCount {
+    pub(crate) word: String,
+    pub(crate) count: usize,
+    /// `false` if the buffer was too large to be scanned completely
+    pub(crate) complete: bool,
+    /// The buffer is counted again when this no longer matches the last edit
+    last_edit: Option<Instant>,
 }
 
 pub struct Pane {
     pub(crate) title: String,
     pub(crate) path: Option<PathBuf>,
+    /// Remote file that `path` is a local copy of
+    pub(crate) remote: Option<RemotePath>,
+    /// The temporary copy of the remote file, deleted when the pane is dropped
+    local_copy: Option<LocalCopy>,
     pub(crate) content: RopeBuffer,
     pub(crate) viewport_position_row: usize,
     pub(crate) viewport_width: u16,
     pub(crate) viewport_height: u16,
     pub(crate) modified: bool,
+    /// The file started with a UTF-8 byte order mark which is not part of the
+    /// content but is written back when saving
+    pub(crate) had_bom: bool,
+    /// The file was not valid UTF-8, saving would replace the invalid bytes
+    /// for good so it is refused until confirmed with `save -f`
+    pub(crate) lossy: bool,
+    pub(crate) last_edit: Option<Instant>,
+    last_save_attempt: Option<Instant>,
     pub(crate) cursors: MultiCursor,
     pub(crate) settings: PaneSettings,
     pub(crate) highlighter: Option<BadHighlighter>,
-    pub(crate) last_search: Option<String>,
+    /// Set when the filetype was chosen explicitly (eg. `set ft`) so that
+    /// saving to a new path doesn't replace it with one guessed from the path
+    filetype_chosen: bool,
+    pub(crate) last_search: Option<SearchQuery>,
     pub(crate) lints: Vec<Lint>,
+    /// Locations of the lints listed in this pane (one per line) if the pane is a lint list
+    pub(crate) lint_list: Option<Vec<(PathBuf, MoveTarget)>>,
+    /// Directory whose entries are listed in this pane (one per line)
+    pub(crate) dir_listing: Option<PathBuf>,
+    pub(crate) swap: Option<SwapFile>,
+    file_lock: Option<fd_lock::RwLock<std::fs::File>>,
     info: Option<String>,
     completer: Completer,
     pub(crate) suggestions: Option<SuggestionMenu>,
+    on_change: Option<ChangeHook>,
+    last_event: Instant,
+    /// Whether the word highlight may need to be rendered after the last event
+    word_highlight_pending: bool,
+    pub(crate) word_count: Option<WordCount>,
+    /// Merge conflicts in the buffer, `None` until they have been looked for
+    /// after the last change to the buffer
+    pub(crate) conflicts: Option<Vec<Conflict>>,
 }
 
 impl Pane {
@@ -68,6 +157,8 @@ impl Pane {
         Self {
             title: "untitled".to_string(),
             path: None,
+            remote: None,
+            local_copy: None,
             content: RopeBuffer::new(),
             cursors: MultiCursor::new(),
             viewport_position_row: 0,
@@ -77,26 +168,50 @@ impl Pane {
 
             settings: PaneSettings::default(),
             highlighter: None,
+            filetype_chosen: false,
             completer: Completer::new(),
             suggestions: None,
             last_search: None,
             lints: vec![],
+            lint_list: None,
+            dir_listing: None,
+            swap: None,
+            file_lock: None,
             info: None,
             modified: false,
+            had_bom: false,
+            lossy: false,
+            last_edit: None,
+            last_save_attempt: None,
+            on_change: None,
+            last_event: Instant::now(),
+            word_highlight_pending: true,
+            word_count: None,
+            conflicts: None,
         }
     }
 
+    /// Opens a local copy of a remote file that gets copied back on save
+    pub fn new_from_remote(remote: RemotePath, hl: Arc<BadHighlighterManager>) -> std::io::Result<Self> {
+        let local = remote.fetch()?;
+        let mut pane = Pane::new_from_file(&FilePathWithOptionalLocation::from(local.path().to_path_buf()), hl);
+        pane.title = crate::quote_path(&remote.to_string());
+        pane.remote = Some(remote);
+        pane.local_copy = Some(local);
+        Ok(pane)
+    }
+
     pub fn new_from_file(fileloc: &FilePathWithOptionalLocation, hl: Arc<BadHighlighterManager>) -> Self {
         let mut pane = Pane::empty();
-        match std::fs::File::open(&fileloc.path) {
-            Ok(file) => {
-                // TODO: do something more efficient than this
-                let mut s = String::new();
-                if BufReader::new(file).read_to_string(&mut s).is_ok() {
-                    pane.content = RopeBuffer::from_str(&s);
-                    pane.path = Some(PathBuf::from(&fileloc.path));
-                } else {
-                    pane.inform("Error reading file".into());
+        match Self::load_file(&fileloc.path) {
+            Ok(loaded) => {
+                pane.content = loaded.content;
+                pane.had_bom = loaded.had_bom;
+                pane.lossy = loaded.lossy;
+                pane.path = Some(PathBuf::from(&fileloc.path));
+                if loaded.lossy {
+                    let fpath = crate::quote_path(fileloc.path.to_string_lossy().as_ref());
+                    pane.inform(format!("Warning: {fpath} is not valid UTF-8, invalid bytes were replaced with U+FFFD"));
                 }
             }
             Err(err) => {
@@ -106,26 +221,117 @@ impl Pane {
                         pane.path = Some(PathBuf::from(&fileloc.path));
                     },
                     ErrorKind::PermissionDenied => pane.inform(format!("Permission denied: {fpath}")),
-                    ErrorKind::IsADirectory => pane.inform(format!("Can not open a directory: {fpath}")),
+                    ErrorKind::IsADirectory => {
+                        if let Err(err) = pane.list_directory(&fileloc.path) {
+                            pane.inform(format!("Can not open a directory: {fpath} ({err})"));
+                        }
+                    }
                     _ => pane.inform(format!("{err}: {fpath}")),
                 }
             }
         };
 
-        if let Some(path) = pane.path.as_ref() {
-            pane.title = crate::quote_path(&path.to_string_lossy());
-            pane.highlighter = Some(BadHighlighter::for_file(path, hl));
-            pane.settings = PaneSettings::from_editorconfig(path);
+        if let Some(path) = pane.path.clone() {
+            pane.highlighter = Some(BadHighlighter::for_file(&path, hl));
+            pane.settings = PaneSettings::from_editorconfig(&path, &pane.content);
+            pane.update_title();
+            let (swap, swap_state) = SwapFile::open_for(&path);
+            pane.swap = swap;
+            match swap_state {
+                SwapState::Clean => {}
+                SwapState::Recoverable => {
+                    pane.inform(format!("Found unsaved changes for {}, use 'recover' to restore them", pane.title));
+                }
+                SwapState::InUse { pid } => {
+                    pane.inform(format!("Warning: {} is being edited in another instance (pid {pid})", pane.title));
+                }
+                SwapState::OpenInThisSession => {
+                    pane.inform(format!("Warning: {} is already open in another pane", pane.title));
+                }
+            }
+            pane.acquire_file_lock();
         }
         if let Some(line_no) = fileloc.line {
             let column_no = fileloc.column.unwrap_or(NonZeroUsize::new(1).unwrap());
             pane.cursors.primary_mut().move_to(&pane.content, MoveTarget::Location(line_no, column_no));
             let cursor_line_no = pane.cursors.primary().current_line_number(&pane.content);
             pane.viewport_position_row = cursor_line_no.saturating_sub(3);
+        } else if let Some(pattern) = fileloc.search.as_ref() {
+            let query = SearchQuery::new(pattern.clone(), pane.settings.smartcase);
+            if let Some(offset) = pane.content.find_next_with_case(ByteOffset(0), pattern, query.ignore_case) {
+                pane.cursors.primary_mut().move_to(&pane.content, MoveTarget::ByteOffset(offset.0));
+                let cursor_line_no = pane.cursors.primary().current_line_number(&pane.content);
+                pane.viewport_position_row = cursor_line_no.saturating_sub(3);
+            } else {
+                pane.inform(format!("Pattern not found: {pattern}"));
+            }
+            pane.last_search = Some(query);
         }
         pane
     }
 
+    /// Creates an unsaved buffer titled `title` from everything that can be read
+    /// from `reader` (eg. the piped standard input)
+    pub fn new_from_reader(mut reader: impl Read, title: &str) -> std::io::Result<Self> {
+        let mut s = String::new();
+        reader.read_to_string(&mut s)?;
+        let mut pane = Pane::empty();
+        pane.content = RopeBuffer::from_str(&s);
+        pane.title = title.to_string();
+        pane.modified = !s.is_empty();
+        Ok(pane)
+    }
+
+    fn load_file(path: &Path) -> std::io::Result<LoadedFile> {
+        // TODO: do something more efficient than this
+        let bytes = std::fs::read(path)?;
+        let (bytes, had_bom) = match bytes.strip_prefix(UTF8_BOM) {
+            Some(rest) => (rest, true),
+            None => (bytes.as_slice(), false),
+        };
+        let text = String::from_utf8_lossy(bytes);
+        let lossy = matches!(text, Cow::Owned(_));
+        Ok(LoadedFile { content: RopeBuffer::from_str(&text), had_bom, lossy })
+    }
+
+    /// Reloads the file from disk discarding all unsaved changes and the undo
+    /// history. The primary cursor stays on the same line if possible.
+    pub(crate) fn revert(&mut self) {
+        let Some(path) = self.path.as_ref() else {
+            self.inform("revert error: buffer is not associated with a file".into());
+            return
+        };
+        match Self::load_file(path) {
+            Ok(loaded) => {
+                let lineno = self.cursors.primary().current_line_number(&self.content);
+                self.content = loaded.content;
+                self.had_bom = loaded.had_bom;
+                self.lossy = loaded.lossy;
+                self.cursors = MultiCursor::new();
+                if let Some(line_no) = NonZeroUsize::new(lineno + 1) {
+                    self.cursors.primary_mut().move_to(&self.content, MoveTarget::Location(line_no, NonZeroUsize::MIN));
+                }
+                for hl in self.highlighter.iter_mut() {
+                    hl.invalidate_cache_starting_from_line(0);
+                }
+                self.suggestions.take();
+                self.notify_whole_buffer_changed();
+                self.modified = false;
+                self.last_edit = None;
+                self.word_count = None;
+                self.conflicts = None;
+                self.remove_swap_file();
+                self.adjust_viewport();
+                if loaded.lossy {
+                    self.inform(format!("Reverted {} (not valid UTF-8, invalid bytes were replaced with U+FFFD)", self.title));
+                } else {
+                    self.inform(format!("Reverted {}", self.title));
+                }
+            }
+            Err(err) => self.inform(format!("revert error: {err}")),
+        }
+    }
+
     pub fn esc(&mut self) {
         if self.cursors.cursor_count() > 1 || self.cursors.primary().has_selection() {
             self.cursors.esc();
@@ -148,6 +354,47 @@ impl Pane {
         self.info.replace(msg);
     }
 
+    /// Moves (or selects) each cursor to the bracket matching the one under it.
+    /// Brackets inside strings and comments are skipped unless the cursor is
+    /// inside one too.
+    fn go_to_matching_pairs(&mut self, select: bool) {
+        let targets: Vec<Option<ByteOffset>> = match self.highlighter.as_ref() {
+            Some(hl) => {
+                let mut strings_and_comments = StringsAndComments::new(hl, &self.content);
+                self.cursors.iter().map(|cursor| {
+                    if strings_and_comments.contains(cursor.offset) {
+                        cursor.matching_pair(&self.content)
+                    } else {
+                        cursor.matching_pair_ignoring(&self.content, |offset| strings_and_comments.contains(offset))
+                    }
+                }).collect()
+            }
+            None => self.cursors.iter().map(|cursor| cursor.matching_pair(&self.content)).collect(),
+        };
+        for (cursor, target) in self.cursors.iter_mut().zip(targets) {
+            if let Some(target) = target {
+                let target = MoveTarget::ByteOffset(target.0);
+                if select {
+                    cursor.select_to(&self.content, target);
+                } else {
+                    cursor.move_to(&self.content, target);
+                }
+            }
+        }
+    }
+
+    /// Lints that are severe enough to be displayed according to the `lint_level` setting
+    pub(crate) fn displayed_lints(&self) -> impl Iterator<Item = &Lint> {
+        self.lints.iter().filter(|lint| lint.severity() >= self.settings.lint_level)
+    }
+
+    pub fn extra_word_chars(&self) -> &str {
+        match &self.settings.extra_word_chars {
+            Some(chars) => chars,
+            None => default_extra_word_chars(self.filetype()),
+        }
+    }
+
     /// Returns the current filetype as a string, eg. "plain" or "c++"
     pub fn filetype(&self) -> &str {
         // Note that the render function temporarily takes ownership of the highlighter
@@ -158,81 +405,158 @@ impl Pane {
         }
     }
 
+    /// Takes an advisory lock on the file to detect other editors (or other
+    /// panes) editing the same file. Failing to get the lock is not fatal, the
+    /// user is only warned about it.
+    fn acquire_file_lock(&mut self) {
+        self.file_lock.take();
+        let Some(path) = self.path.as_ref() else { return };
+        // files that don't exist yet get locked when they are saved for the first time
+        let Ok(file) = std::fs::File::open(path) else { return };
+        let mut lock = fd_lock::RwLock::new(file);
+        let locked = match lock.try_write() {
+            Ok(guard) => {
+                // Dropping the guard would unlock the file. The lock is released
+                // when the file gets closed, ie. when the pane is closed.
+                std::mem::forget(guard);
+                true
+            }
+            Err(_) => false,
+        };
+        if locked {
+            self.file_lock = Some(lock);
+        } else {
+            self.inform(format!("Warning: {} is already open in another editor", self.title));
+        }
+    }
+
+    /// Sets the title to the path of the file in the style chosen in the
+    /// settings, remote files keep their remote path as the title
+    pub(crate) fn update_title(&mut self) {
+        let Some(path) = self.path.as_ref().filter(|_| self.remote.is_none()) else { return };
+        let shown = match self.settings.title_style {
+            TitleStyle::Path => path.clone(),
+            TitleStyle::Absolute => std::path::absolute(path).unwrap_or_else(|_| path.clone()),
+            TitleStyle::Relative => match (std::path::absolute(path), std::env::current_dir()) {
+                (Ok(abs), Ok(cwd)) => abs.strip_prefix(&cwd).map(Path::to_path_buf).unwrap_or(abs),
+                _ => path.clone(),
+            },
+            TitleStyle::Name => path.file_name().map(PathBuf::from).unwrap_or_else(|| path.clone()),
+        };
+        self.title = crate::quote_path(&shown.to_string_lossy());
+    }
+
     fn set_path(&mut self, path: impl AsRef<Path>, hl: Arc<BadHighlighterManager>) -> std::io::Result<()> {
         if let Err(err) = std::fs::OpenOptions::new().read(false).write(true).create(true).truncate(false).open(&path) {
             self.inform(format!("Unable to save: {err}"));
             return Err(err)
         }
         if self.path.as_ref().is_none_or(|old_path| old_path != path.as_ref()) {
+            self.remove_swap_file();
+            self.swap = SwapFile::open_for(path.as_ref()).0;
             self.path.replace(path.as_ref().into());
-            self.highlighter.replace(BadHighlighter::for_file(&path, hl));
-            self.title = crate::quote_path(&path.as_ref().to_string_lossy());
+            self.remote = None;
+            self.local_copy = None;
+            if !self.filetype_chosen {
+                self.highlighter.replace(BadHighlighter::for_file(&path, hl));
+            }
+            self.update_title();
+            self.acquire_file_lock();
         }
         Ok(())
     }
 
-    fn write_to_file(&self, mut file: std::fs::File, rope: &RopeBuffer) -> std::io::Result<()> {
-        // TODO: atomic file write
+    fn write_to_file(&self, mut file: impl Write, rope: &RopeBuffer) -> std::io::Result<()> {
+        if self.had_bom {
+            file.write_all(UTF8_BOM)?;
+        }
+        if self.settings.transforms_lines_on_save() {
+            self.write_lines_to_file(&mut file, rope)?;
+        } else {
+            // fast path that doesn't need to look for line breaks in the whole buffer
+            rope.write_to(&mut file)?;
+            let last_line_is_empty = rope.lines_at(rope.len_lines() - 1).next().is_none_or(|line| line.len_bytes() == 0);
+            if self.settings.insert_final_newline && !last_line_is_empty {
+                file.write_all(self.settings.end_of_line.as_bytes())?;
+            }
+        }
+        file.flush()
+    }
 
-        // https://docs.rs/ropey/1.6.1/ropey/index.html#a-note-about-line-breaks
-        const UNICODE_LINE_END_CHARS: [char; 7] = [
-            '\u{000A}', '\u{000D}', '\u{000B}', '\u{000C}', '\u{0085}', '\u{2028}', '\u{2029}'
-        ];
+    fn write_lines_to_file(&self, mut file: impl Write, rope: &RopeBuffer) -> std::io::Result<()> {
+        fn write_slice(file: &mut impl Write, slice: RopeSlice) -> std::io::Result<()> {
+            for chunk in slice.chunks() {
+                file.write_all(chunk.as_bytes())?;
+            }
+            Ok(())
+        }
 
-        for line in rope.lines() {
-            // TODO: iterate over line.chunks() instead to avoid building temporary strings
-            let full_line = line.to_string();
+        for (lineno, line) in rope.lines().enumerate() {
+            let n_chars = line.len_chars();
+            let last_char = |n: usize| n_chars.checked_sub(n).map(|idx| line.char(idx));
+            let line_end_chars = match (last_char(2), last_char(1)) {
+                (Some('\r'), Some('\n')) => 2,
+                (_, Some(c)) if UNICODE_LINE_END_CHARS.contains(&c) => 1,
+                _ => 0,
+            };
+            let text = line.slice(..n_chars - line_end_chars);
+            let line_end = line.slice(n_chars - line_end_chars..);
 
-            if let Some(line) = full_line.strip_suffix("\r\n") {
-                if self.settings.trim_trailing_whitespace {
-                    file.write_all(line.trim_end().as_bytes())?;
-                } else {
-                    file.write_all(line.as_bytes())?;
-                }
-                if self.settings.normalize_end_of_line {
-                    file.write_all(self.settings.end_of_line.as_bytes())?;
-                } else {
-                    file.write_all(b"\r\n")?;
-                }
-            } else if let Some(line) = full_line.strip_suffix(UNICODE_LINE_END_CHARS) {
-                if self.settings.trim_trailing_whitespace {
-                    file.write_all(line.trim_end().as_bytes())?;
-                } else {
-                    file.write_all(line.as_bytes())?;
-                }
-                if self.settings.normalize_end_of_line {
-                    file.write_all(self.settings.end_of_line.as_bytes())?;
-                } else {
-                    let line_end = full_line.chars().last().unwrap();
-                    file.write_all(line_end.to_string().as_bytes())?;
-                }
-            } else if !full_line.is_empty() {
-                file.write_all(full_line.as_bytes())?;
-                if self.settings.insert_final_newline {
-                    file.write_all(self.settings.end_of_line.as_bytes())?;
+            if line_end_chars == 0 {
+                if text.len_bytes() > 0 {
+                    write_slice(&mut file, text)?;
+                    if self.settings.insert_final_newline {
+                        file.write_all(self.settings.end_of_line.as_bytes())?;
+                    }
                 }
+                continue
+            }
+
+            if self.settings.trim_trailing_whitespace && (!self.settings.trim_modified_only || rope.is_line_modified(lineno)) {
+                let n_trailing = text.chars_at(text.len_chars()).reversed().take_while(|c| c.is_whitespace()).count();
+                write_slice(&mut file, text.slice(..text.len_chars() - n_trailing))?;
+            } else {
+                write_slice(&mut file, text)?;
+            }
+            if self.settings.normalize_end_of_line {
+                file.write_all(self.settings.end_of_line.as_bytes())?;
+            } else {
+                write_slice(&mut file, line_end)?;
             }
         }
-        file.flush()?;
         Ok(())
     }
 
     pub(crate) fn save(&mut self) {
+        self.last_save_attempt = Some(Instant::now());
+        if self.lossy {
+            self.inform(format!(
+                "Unable to save: {} was not valid UTF-8 and the invalid bytes would be lost, use 'save -f' to save anyway",
+                self.title
+            ));
+            return
+        }
         if let Some(path) = self.path.as_ref() {
-            let file = match std::fs::OpenOptions::new().read(false).write(true).create(true).truncate(true).open(path) {
-                Ok(file) => file,
-                Err(err) => {
-                    self.inform(format!("Unable to save: {err}"));
-                    return
-                }
-            };
             // FIXME: saving can modify the contents (eg. modifying line endings)
             // and the editor should react to that
-            match self.write_to_file(file, &self.content) {
+            match write_atomically(path, |file| self.write_to_file(file, &self.content)) {
                 Ok(()) => {
                     self.modified = false;
                     let quoted_path = crate::quote_path(path.to_string_lossy().as_ref());
-                    self.inform(format!("Saved {quoted_path}"));
+                    match self.remote.as_ref().map(|remote| remote.upload(path)) {
+                        None => self.inform(format!("Saved {quoted_path}")),
+                        Some(Ok(())) => self.inform(format!("Saved {}", self.title)),
+                        Some(Err(err)) => {
+                            self.modified = true;
+                            self.inform(format!("Saved local copy {quoted_path} but upload failed: {err}"));
+                        }
+                    }
+                    if !self.modified {
+                        self.content.mark_saved();
+                    }
+                    self.discard_swap_file();
+                    // the file that was locked has been replaced with a new one
+                    self.acquire_file_lock();
                 }
                 Err(err) => {
                     self.inform(format!("Failed to save: {err}"));
@@ -249,6 +573,259 @@ impl Pane {
         }
     }
 
+    /// Returns `true` once per event when the pane has been idle long enough
+    /// for the word under the cursor to get highlighted
+    pub(crate) fn word_highlight_became_due(&mut self) -> bool {
+        if !self.word_highlight_pending || self.last_event.elapsed() < WORD_HIGHLIGHT_DELAY {
+            return false
+        }
+        self.word_highlight_pending = false;
+        self.settings.highlight_word
+    }
+
+    /// The word under the primary cursor if it should be highlighted. Nothing is
+    /// highlighted while the cursor is on the move or when text is selected.
+    fn highlighted_word(&self) -> Option<Range<ByteOffset>> {
+        let cursor = self.cursors.primary();
+        if !self.settings.highlight_word || cursor.has_selection() || self.last_event.elapsed() < WORD_HIGHLIGHT_DELAY {
+            return None
+        }
+        self.content.word_at(cursor.offset)
+    }
+
+    /// Occurrences of the highlighted word within `visible`
+    pub(crate) fn word_occurrences_to_highlight(&self, visible: Range<ByteOffset>) -> Vec<Range<ByteOffset>> {
+        let Some(word) = self.highlighted_word() else { return vec![] };
+        let word = self.content.slice(&word).to_string();
+        self.content.word_occurrences(&word, visible)
+    }
+
+    /// Occurrences of the selected text within `visible` when there is a single
+    /// cursor with a selection of exactly one word
+    pub(crate) fn selection_occurrences_to_highlight(&self, visible: Range<ByteOffset>) -> Vec<Range<ByteOffset>> {
+        if !self.settings.highlight_occurrences || self.cursors.cursor_count() != 1 {
+            return vec![]
+        }
+        let Some(selection) = self.cursors.primary().selection() else { return vec![] };
+        if self.content.word_at(selection.start).as_ref() != Some(&selection) {
+            return vec![]
+        }
+        let selected = self.content.slice(&selection).to_string();
+        self.content.occurrences(&selected, visible)
+    }
+
+    /// Counts the occurrences of the highlighted word for the status line, the
+    /// buffer is only scanned again when the word or the buffer has changed
+    pub(crate) fn update_word_count(&mut self) {
+        let Some(range) = self.highlighted_word() else {
+            self.word_count = None;
+            return
+        };
+        let word = self.content.slice(&range).to_string();
+        if self.word_count.as_ref().is_some_and(|wc| wc.word == word && wc.last_edit == self.last_edit) {
+            return
+        }
+        let scan_end = self.content.len_bytes().min(WORD_COUNT_SCAN_LIMIT);
+        let count = self.content.word_occurrences(&word, ByteOffset(0)..ByteOffset(scan_end)).len();
+        self.word_count = Some(WordCount {
+            word,
+            count,
+            complete: scan_end == self.content.len_bytes(),
+            last_edit: self.last_edit,
+        });
+    }
+
+    /// Finds the merge conflicts in the buffer unless they are already known
+    pub(crate) fn update_conflicts(&mut self) -> &[Conflict] {
+        self.conflicts.get_or_insert_with(|| crate::conflict::find_conflicts(&self.content))
+    }
+
+    /// Saves the pane if autosave is enabled and the buffer has not been edited
+    /// for the configured time. Returns `true` if saving was attempted.
+    pub(crate) fn autosave_if_idle(&mut self) -> bool {
+        let Some(idle_time) = self.settings.autosave else { return false };
+        let Some(last_edit) = self.last_edit else { return false };
+        // a failed save is not retried until the buffer is edited again
+        let saved_since_last_edit = self.last_save_attempt.is_some_and(|t| t > last_edit);
+        if !self.modified || self.path.is_none() || self.lossy || saved_since_last_edit || last_edit.elapsed() < idle_time {
+            return false
+        }
+        self.save();
+        if !self.modified {
+            self.inform(format!("Autosaved {}", self.title));
+        }
+        true
+    }
+
+    /// Writes the unsaved changes to the swap file if the pane has been idle
+    /// for long enough since the last edit.
+    pub(crate) fn update_swap_file(&mut self) {
+        if !self.modified {
+            return
+        }
+        if let Some(swap) = self.swap.as_mut() {
+            if swap.needs_update(self.last_edit) && swap.write(&self.content).is_err() {
+                // most likely the directory is not writable, no point in retrying
+                self.swap = None;
+            }
+        }
+    }
+
+    pub(crate) fn remove_swap_file(&mut self) {
+        if let Some(swap) = self.swap.as_mut() {
+            swap.remove();
+        }
+    }
+
+    fn discard_swap_file(&mut self) {
+        if let Some(swap) = self.swap.as_mut() {
+            swap.discard();
+        }
+    }
+
+    /// Replaces the content with the one from a swap file left behind by an
+    /// earlier session. The replacement can be undone.
+    pub(crate) fn recover_from_swap_file(&mut self) {
+        let Some(swap) = self.swap.as_mut().filter(|swap| swap.pending_recovery) else {
+            self.inform("recover error: no swap file to recover from".into());
+            return
+        };
+        match swap.read_content() {
+            Ok(recovered) => {
+                let edits = EditBatch::from_edits(vec![
+                    Edit::delete(ByteOffset(0), self.content.len_bytes()),
+                    Edit::insert_str(ByteOffset(0), &recovered),
+                ]);
+                self.apply_editbatch(edits);
+                // the swap file already holds the recovered content
+                if let Some(swap) = self.swap.as_mut() {
+                    swap.mark_recovered();
+                }
+                self.inform("Recovered unsaved changes from swap file".into());
+            }
+            Err(err) => self.inform(format!("recover error: {err}")),
+        }
+    }
+
+    /// Returns the whole content of the buffer
+    pub fn text(&self) -> String {
+        self.content.to_string()
+    }
+
+    /// Returns the content of line `n` (0-based) without the line break
+    pub fn line(&self, n: usize) -> Option<String> {
+        if n >= self.content.len_lines() {
+            return None
+        }
+        let line = self.content.lines_at(n).next()?.to_string();
+        let without_eol = line.strip_suffix("\r\n").or_else(|| line.strip_suffix(UNICODE_LINE_END_CHARS));
+        Some(without_eol.unwrap_or(&line).to_string())
+    }
+
+    pub fn byte_len(&self) -> usize {
+        self.content.len_bytes()
+    }
+
+    /// Replaces the whole content of the buffer, the change can be undone like any other edit
+    pub fn set_text(&mut self, text: &str) {
+        self.cursors.esc();
+        let edits = EditBatch::from_edits(vec![
+            Edit::delete(ByteOffset(0), self.content.len_bytes()),
+            Edit::insert_str(ByteOffset(0), text),
+        ]);
+        self.apply_editbatch(edits);
+        self.cursors.primary_mut().offset = ByteOffset(0);
+        self.adjust_viewport();
+    }
+
+    /// Replaces the contents of a pane that shows generated text (like the lint
+    /// list) without recording undo history or marking the pane as modified.
+    /// The cursor stays on the same line if possible.
+    pub(crate) fn replace_generated_content(&mut self, text: &str) {
+        let line = self.cursors.primary().current_line_number(&self.content);
+        self.content = RopeBuffer::from_str(text);
+        self.word_count = None;
+        self.conflicts = None;
+        self.cursors = MultiCursor::new();
+        self.cursors.primary_mut().offset = self.content.line_to_byte(line.min(self.content.len_lines() - 1));
+        for hl in self.highlighter.iter_mut() {
+            hl.invalidate_cache_starting_from_line(0);
+        }
+        self.adjust_viewport();
+    }
+
+    /// Lists the entries of `dir` in this pane with the directories (marked with
+    /// a trailing `/`) first. Pressing Enter on an entry opens it.
+    pub(crate) fn list_directory(&mut self, dir: &Path) -> std::io::Result<()> {
+        let mut entries: Vec<(bool, String)> = std::fs::read_dir(dir)?
+            .filter_map(Result::ok)
+            .map(|entry| (!entry.path().is_dir(), entry.file_name().to_string_lossy().into_owned()))
+            .collect();
+        entries.sort();
+        let mut text = String::from("../\n");
+        for (is_file, name) in entries {
+            text.push_str(&name);
+            text.push_str(if is_file { "\n" } else { "/\n" });
+        }
+        self.title = crate::quote_path(&dir.to_string_lossy());
+        self.replace_generated_content(&text);
+        self.dir_listing = Some(dir.to_path_buf());
+        Ok(())
+    }
+
+    /// Generated listings can be navigated but not edited
+    pub(crate) fn is_read_only(&self) -> bool {
+        self.dir_listing.is_some() || self.lint_list.is_some()
+    }
+
+    /// Returns the path of the entry on the line with the primary cursor if
+    /// this pane is a directory listing
+    pub(crate) fn listed_path_under_cursor(&self) -> Option<PathBuf> {
+        let dir = self.dir_listing.as_ref()?;
+        let cursor = self.cursors.primary();
+        let line = self.content.slice(&(cursor.line_start(&self.content)..cursor.line_end(&self.content))).to_string();
+        match line.trim_end_matches('/') {
+            "" => None,
+            ".." => self.listed_parent(),
+            name => Some(dir.join(name)),
+        }
+    }
+
+    /// Returns the parent of the listed directory if this pane is a directory listing
+    pub(crate) fn listed_parent(&self) -> Option<PathBuf> {
+        let dir = self.dir_listing.as_ref()?;
+        Some(dir.parent().filter(|parent| !parent.as_os_str().is_empty()).map_or_else(|| dir.join(".."), Path::to_path_buf))
+    }
+
+    /// Name of the innermost unclosed tag when a lone cursor is right after a `<`
+    fn tag_to_close(&self) -> Option<String> {
+        let cursor = self.cursors.primary();
+        if self.cursors.cursor_count() != 1 || cursor.has_selection() {
+            return None
+        }
+        let lt_offset = ByteOffset(cursor.offset.0.checked_sub(1)?);
+        if self.content.get_byte(lt_offset) != Some(b'<') {
+            return None
+        }
+        unclosed_tag_before(&self.content, lt_offset).map(|(_, name)| name)
+    }
+
+    /// Returns the http(s) URL that the primary cursor is on, if any
+    pub(crate) fn url_under_cursor(&self) -> Option<String> {
+        let cursor = self.cursors.primary();
+        let line_start = cursor.line_start(&self.content);
+        let line = self.content.slice(&(line_start..cursor.line_end(&self.content))).to_string();
+        find_url_at(&line, cursor.offset.0 - line_start.0).map(str::to_string)
+    }
+
+    /// Returns the file path (possibly with a `:line:col` suffix) that the primary cursor is on
+    pub(crate) fn path_under_cursor(&self) -> Option<String> {
+        let cursor = self.cursors.primary();
+        let line_start = cursor.line_start(&self.content);
+        let line = self.content.slice(&(line_start..cursor.line_end(&self.content))).to_string();
+        find_path_at(&line, cursor.offset.0 - line_start.0).map(str::to_string)
+    }
+
     pub fn selections(&self) -> Vec<String> {
         self.cursors
             .iter()
@@ -260,6 +837,7 @@ impl Pane {
     pub(crate) fn set_filetype(&mut self, ftype: &str, manager: Arc<BadHighlighterManager>) -> Result<(), ()> {
         if let Some(hl) = BadHighlighter::for_filetype(ftype, manager) {
             self.highlighter.replace(hl);
+            self.filetype_chosen = true;
             Ok(())
         } else {
             Err(())
@@ -276,6 +854,24 @@ impl Pane {
         self.adjust_viewport_to_show_line(line_number);
     }
 
+    fn last_visible_line(&self) -> usize {
+        let last_line = self.content.len_lines().saturating_sub(1);
+        (self.viewport_position_row + (self.viewport_height as usize).saturating_sub(1)).min(last_line)
+    }
+
+    /// Moves the primary cursor to another line on the screen without scrolling,
+    /// the column is kept the same like when moving up or down
+    fn move_cursor_to_visible_line(&mut self, line_number: usize) {
+        self.cursors.esc();
+        let current = self.cursors.primary().current_line_number(&self.content);
+        let target = if line_number < current {
+            MoveTarget::Up(current - line_number)
+        } else {
+            MoveTarget::Down(line_number - current)
+        };
+        self.cursors.primary_mut().move_to(&self.content, target);
+    }
+
     fn adjust_viewport_to_show_line(&mut self, line_number: usize) {
         let pad = 2;
         let vh = self.viewport_height as usize;
@@ -289,7 +885,7 @@ impl Pane {
     }
 
     fn apply_editbatch(&mut self, edits: EditBatch) {
-        if edits.is_empty() {
+        if edits.is_empty() || self.is_read_only() {
             return
         }
         if let Some(offset) = edits.first_edit_offset() {
@@ -298,25 +894,87 @@ impl Pane {
                 hl.invalidate_cache_starting_from_line(lineno);
             }
         }
+        let changed_range = self.on_change.as_ref().and_then(|_| edits.affected_range());
         self.content.do_edits(&mut self.cursors, edits);
+        self.content.limit_undo_history(self.settings.undo_levels);
+        self.conflicts = None;
+        if let Some(range) = changed_range {
+            self.notify_change(range);
+        }
         self.modified = true;
+        self.last_edit = Some(Instant::now());
         self.adjust_viewport();
     }
 
-    pub fn insert_from_clipboard(&mut self, clips: &[String]) {
-        let edits = EditBatch::insert_from_clipboard(&self.cursors, clips);
+    /// Registers a callback that gets called whenever the buffer is modified.
+    /// Undo, redo and reverting report the whole buffer as changed.
+    pub fn set_on_change<F: FnMut(Range<ByteOffset>) + Send + 'static>(&mut self, hook: F) {
+        self.on_change = Some(Box::new(hook));
+    }
+
+    /// Like [`Pane::set_on_change`] but the hook registered before (if any)
+    /// keeps getting called too, before `hook`
+    pub fn chain_on_change<F: FnMut(Range<ByteOffset>) + Send + 'static>(&mut self, mut hook: F) {
+        match self.on_change.take() {
+            Some(mut previous) => self.set_on_change(move |range: Range<ByteOffset>| {
+                previous(range.clone());
+                hook(range);
+            }),
+            None => self.set_on_change(hook),
+        }
+    }
+
+    pub fn clear_on_change(&mut self) {
+        self.on_change = None;
+    }
+
+    fn notify_change(&mut self, range: Range<ByteOffset>) {
+        if let Some(hook) = self.on_change.as_mut() {
+            hook(range);
+        }
+    }
+
+    fn notify_whole_buffer_changed(&mut self) {
+        if self.on_change.is_some() {
+            self.notify_change(ByteOffset(0)..ByteOffset(self.content.len_bytes()));
+        }
+    }
+
+    pub fn insert_from_clipboard(&mut self, clips: &Clips) {
+        let edits = EditBatch::insert_from_clipboard(
+            &self.cursors,
+            &self.content,
+            &clips.texts,
+            self.settings.paste_distribute,
+            clips.linewise,
+        );
         self.apply_editbatch(edits);
     }
 
-    pub fn cut(&mut self) -> Vec<String> {
-        let edits = EditBatch::cut(&self.cursors, &self.content);
-        let clips = edits.iter().filter_map(|edit| {
-            if let crate::editing::Edit::Delete(range) = edit {
-                Some(self.content.slice(range).to_string())
-            } else {
-                None
+    /// The selections, or the whole lines with the cursors (including their
+    /// line endings) if nothing is selected
+    pub fn copy(&self) -> Clips {
+        if self.cursors.iter().any(Cursor::has_selection) {
+            return Clips::new(self.selections())
+        }
+        // not `line_ranges` because that merges the lines of adjacent cursors
+        let mut spans: Vec<_> = self.cursors.iter().map(|cursor| cursor.line_span(&self.content)).collect();
+        spans.dedup();
+        let lines = spans.into_iter().map(|span| {
+            let range = self.content.line_to_byte(span.start)..self.content.line_to_byte(span.end);
+            let mut line = self.content.slice(&range).to_string();
+            if !line.ends_with(['\n', '\r']) {
+                line.push_str(self.settings.end_of_line);
             }
-        }).collect();
+            line
+        });
+        Clips::lines(lines.collect())
+    }
+
+    /// Deletes what [`Pane::copy`] would copy and returns it
+    pub fn cut(&mut self) -> Clips {
+        let clips = self.copy();
+        let edits = EditBatch::cut(&self.cursors, &self.content);
         self.apply_editbatch(edits);
         for cursor in self.cursors.iter_mut() {
             cursor.deselect();
@@ -329,7 +987,29 @@ impl Pane {
     {
         let (edits, new_sizes) = EditBatch::transform_selections(&self.cursors, &self.content, transform);
         self.apply_editbatch(edits);
-        for (cursor, sel_size) in self.cursors.iter_mut().zip(new_sizes) {
+        self.reselect_before_cursors(new_sizes);
+    }
+
+    /// Puts `open` before and `close` after every selection. The selections
+    /// cover the delimiters too if `select_delimiters` is set, otherwise they
+    /// stay on the original text.
+    fn surround_selections(&mut self, open: &str, close: &str, select_delimiters: bool) {
+        self.transform_selections(|s| Some(format!("{open}{s}{close}")));
+        if select_delimiters {
+            return
+        }
+        for cursor in self.cursors.iter_mut() {
+            if let Some(from) = cursor.selection_from {
+                cursor.selection_from = Some(ByteOffset(from.0 + open.len()));
+                cursor.offset = ByteOffset(cursor.offset.0 - close.len());
+            }
+        }
+    }
+
+    /// Selects the given number of bytes before each cursor after their
+    /// selections have been replaced
+    fn reselect_before_cursors(&mut self, selection_sizes: Vec<usize>) {
+        for (cursor, sel_size) in self.cursors.iter_mut().zip(selection_sizes) {
             if sel_size > 0 {
                 cursor.selection_from = Some(ByteOffset(cursor.offset.0 - sel_size));
             } else {
@@ -338,6 +1018,79 @@ impl Pane {
         }
     }
 
+    /// Replaces the occurrences of `old` with `new` within the selections, or in
+    /// the whole buffer if nothing is selected. Returns the number of replacements.
+    pub(crate) fn replace(&mut self, old: &str, new: &str) -> usize {
+        if old.is_empty() {
+            return 0
+        }
+        if self.cursors.iter().any(Cursor::has_selection) {
+            let count = self.selections().iter().map(|sel| sel.matches(old).count()).sum();
+            if count > 0 {
+                self.transform_selections(|sel| Some(sel.replace(old, new)));
+            }
+            count
+        } else {
+            let (edits, count) = self.content.replace_all_edits(old, new);
+            self.apply_editbatch(edits);
+            count
+        }
+    }
+
+    /// Line spans of the selections, or the whole buffer if nothing is selected
+    fn selected_lines_or_all(&self) -> Vec<std::ops::Range<usize>> {
+        if self.cursors.iter().any(Cursor::has_selection) {
+            self.cursors.line_ranges(&self.content)
+        } else {
+            std::iter::once(0..self.content.len_lines()).collect()
+        }
+    }
+
+    /// Deletes the lines with only whitespace within the selections (or in the
+    /// whole buffer). Returns the number of deleted lines.
+    pub(crate) fn remove_blank_lines(&mut self) -> usize {
+        let lines_before = self.content.len_lines();
+        let edits = EditBatch::remove_blank_lines(&self.selected_lines_or_all(), &self.content);
+        if !edits.is_empty() {
+            self.apply_editbatch(edits);
+        }
+        lines_before - self.content.len_lines()
+    }
+
+    /// Deletes repeated lines within the selections (or in the whole buffer),
+    /// only consecutive ones unless `global` is set. Returns the number of
+    /// deleted lines.
+    pub(crate) fn remove_duplicate_lines(&mut self, global: bool) -> usize {
+        let lines_before = self.content.len_lines();
+        let edits = EditBatch::remove_duplicate_lines(&self.selected_lines_or_all(), &self.content, global);
+        if !edits.is_empty() {
+            self.apply_editbatch(edits);
+        }
+        lines_before - self.content.len_lines()
+    }
+
+    /// Appends `suffix` to the non-blank lines with cursors that don't already
+    /// end with it. Returns the number of changed lines.
+    pub(crate) fn append_to_lines(&mut self, suffix: &str) -> usize {
+        let edits = EditBatch::append_to_lines(&self.cursors.line_ranges(&self.content), &self.content, suffix);
+        let count = edits.iter().count();
+        if !edits.is_empty() {
+            self.apply_editbatch(edits);
+        }
+        count
+    }
+
+    /// Puts `prefix` and `suffix` around the non-blank lines with cursors (not
+    /// counting the indentation). Returns the number of changed lines.
+    pub(crate) fn enclose_lines(&mut self, prefix: &str, suffix: &str) -> usize {
+        let edits = EditBatch::enclose_lines(&self.cursors.line_ranges(&self.content), &self.content, prefix, suffix);
+        let count = edits.iter().count() / 2;
+        if !edits.is_empty() {
+            self.apply_editbatch(edits);
+        }
+        count
+    }
+
     pub(crate) fn pipe_through_shell_command(&mut self, command_str: &str) {
         fn run_shell(cmd: &str, input: &str) -> Option<String> {
             let mut child_process = std::process::Command::new("sh");
@@ -364,6 +1117,13 @@ impl Pane {
     }
 
     pub(crate) fn handle_event(&mut self, event: PaneAction) {
+        self.last_event = Instant::now();
+        self.word_highlight_pending = true;
+        // the filetype or the setting could have changed since the last event
+        if self.content.extra_word_chars() != self.extra_word_chars() {
+            let extra_word_chars = self.extra_word_chars().to_string();
+            self.content.set_extra_word_chars(&extra_word_chars);
+        }
         let quotes = {
             static PAIRS: std::sync::OnceLock<HashMap<&str, &str>> = std::sync::OnceLock::new();
             PAIRS.get_or_init(||
@@ -390,6 +1150,14 @@ impl Pane {
         }
 
         match event {
+            PaneAction::MoveTo(MoveTarget::MatchingPair) => {
+                self.go_to_matching_pairs(false);
+                self.adjust_viewport();
+            }
+            PaneAction::SelectTo(MoveTarget::MatchingPair) => {
+                self.go_to_matching_pairs(true);
+                self.adjust_viewport();
+            }
             PaneAction::MoveTo(target) => {
                 self.cursors.move_to(&self.content, target);
                 self.adjust_viewport();
@@ -404,7 +1172,7 @@ impl Pane {
                     new
                 }).collect();
                 for cursor in new_cursors {
-                    if self.cursors.spawn_new(cursor) {
+                    if self.cursors.spawn_new(&self.content, cursor) {
                         self.adjust_viewport_to_show_line(cursor.current_line_number(&self.content));
                     }
                 }
@@ -424,21 +1192,80 @@ impl Pane {
                     && quotes.contains_key(l_quote.as_str()) =>
             {
                 if let Some(r_quote) = quotes.get(l_quote.as_str()) {
-                    self.transform_selections(|s| Some(format!("{l_quote}{s}{r_quote}")));
+                    self.surround_selections(&l_quote, r_quote, true);
                 }
             }
-            PaneAction::Insert(s) => {
-                let edits = EditBatch::insert_with_cursors(&self.cursors, &s);
+            PaneAction::Surround(c) => {
+                let (open, close) = match c {
+                    '(' | ')' => ('(', ')'),
+                    '[' | ']' => ('[', ']'),
+                    '{' | '}' => ('{', '}'),
+                    '<' | '>' => ('<', '>'),
+                    c => (c, c),
+                };
+                self.surround_selections(&open.to_string(), &close.to_string(), false);
+            }
+            PaneAction::CursorAtLineEnds => self.cursors.split_to_line_ends(&self.content),
+            PaneAction::SwapSelections => match EditBatch::swap_selections(&self.cursors, &self.content) {
+                Some((edits, new_sizes)) => {
+                    self.apply_editbatch(edits);
+                    self.reselect_before_cursors(new_sizes);
+                }
+                None => self.inform("swap error: needs exactly two selections".into()),
+            },
+            PaneAction::Insert(s) if s == "/" && matches!(self.filetype(), "html" | "xml") => {
+                let tag = self.tag_to_close();
+                let text = match &tag {
+                    Some(name) => format!("/{name}>"),
+                    None => s,
+                };
+                let edits = EditBatch::insert_with_cursors(&self.cursors, &text);
                 self.apply_editbatch(edits);
                 for cursor in self.cursors.iter_mut() {
                     cursor.deselect();
                 }
+                if tag.is_some() && matches!(self.settings.autoindent, AutoIndent::Smart) {
+                    let edits = EditBatch::dedent_closing_brackets(&self.cursors, &self.content);
+                    self.apply_editbatch(edits);
+                }
+            }
+            PaneAction::Insert(s) => {
+                let mut chars = s.chars();
+                let auto_close = match (chars.next(), chars.next()) {
+                    (Some(c), None) if self.settings.auto_close_pairs => {
+                        auto_close(&self.cursors, &self.content, c).map(|action| (c, action))
+                    }
+                    _ => None,
+                };
+                match auto_close {
+                    Some((_, AutoClose::Skip)) => self.cursors.move_to(&self.content, MoveTarget::Right(1)),
+                    Some((c, AutoClose::Pair(close))) => {
+                        let edits = EditBatch::insert_with_cursors(&self.cursors, &format!("{c}{close}"));
+                        self.apply_editbatch(edits);
+                        self.cursors.move_to(&self.content, MoveTarget::Left(1));
+                    }
+                    None => {
+                        let edits = EditBatch::insert_with_cursors(&self.cursors, &s);
+                        self.apply_editbatch(edits);
+                        for cursor in self.cursors.iter_mut() {
+                            cursor.deselect();
+                        }
+                        if matches!(self.settings.autoindent, AutoIndent::Smart) && matches!(s.as_str(), ")" | "]" | "}") {
+                            let edits = EditBatch::dedent_closing_brackets(&self.cursors, &self.content);
+                            self.apply_editbatch(edits);
+                        }
+                    }
+                }
             }
             PaneAction::InsertNewline => {
                 let eol = self.settings.end_of_line;
                 let edits = match self.settings.autoindent {
                     AutoIndent::None => EditBatch::insert_with_cursors(&self.cursors, eol),
                     AutoIndent::Keep => EditBatch::insert_newline_keep_indent(&self.cursors, &self.content, eol),
+                    AutoIndent::Smart => {
+                        let indent = self.settings.indent_as_string();
+                        EditBatch::insert_newline_smart_indent(&self.cursors, &self.content, eol, &indent)
+                    }
                 };
                 self.apply_editbatch(edits);
                 for cursor in self.cursors.iter_mut() {
@@ -446,7 +1273,12 @@ impl Pane {
                 }
             }
             PaneAction::DeleteBackward => {
-                let edits = EditBatch::delete_backward_with_cursors(&self.cursors, &self.content, self.settings.indent_size);
+                let edits = EditBatch::delete_backward_with_cursors(
+                    &self.cursors,
+                    &self.content,
+                    Some(self.settings.indent_size).filter(|_| self.settings.backspace_dedent),
+                    self.settings.backspace_join,
+                );
                 self.apply_editbatch(edits);
                 for cursor in self.cursors.iter_mut() {
                     cursor.deselect();
@@ -476,26 +1308,62 @@ impl Pane {
                 self.apply_editbatch(edits);
             }
             PaneAction::MoveLinesUp => {
-                let edits = EditBatch::move_lines_up(&self.cursors, &self.content);
+                let last_line = self.content.len_lines() - 1;
+                let last_line_start = self.content.line_to_byte(last_line);
+                let eof = ByteOffset(self.content.len_bytes());
+                // the line above a last line without a newline gets inserted at
+                // the end of the buffer which would drag the cursors at the end along
+                let moves_last_line = last_line_start < eof && self.cursors.line_ranges(&self.content)
+                    .last()
+                    .is_some_and(|span| span.start > 0 && span.end > last_line);
+                let edits = EditBatch::move_lines_up(&self.cursors, &self.content, self.settings.end_of_line);
                 self.apply_editbatch(edits);
+                if moves_last_line {
+                    let moved_to = ByteOffset(self.content.line_to_byte(last_line - 1).0 + eof.0 - last_line_start.0);
+                    for cursor in self.cursors.iter_mut() {
+                        if cursor.offset == eof {
+                            cursor.offset = moved_to;
+                        }
+                        if cursor.selection_from == Some(eof) {
+                            cursor.selection_from = Some(moved_to);
+                        }
+                    }
+                }
             }
             PaneAction::MoveLinesDown => {
-                let edits = EditBatch::move_lines_down(&self.cursors, &self.content);
+                let edits = EditBatch::move_lines_down(&self.cursors, &self.content, self.settings.end_of_line);
                 self.apply_editbatch(edits);
             }
+            PaneAction::ToggleComment => {
+                match self.highlighter.as_ref().and_then(BadHighlighter::line_comment_token) {
+                    Some(token) => {
+                        let edits = EditBatch::toggle_line_comments(&self.cursors, &self.content, token);
+                        self.apply_editbatch(edits);
+                    }
+                    None => self.inform("this filetype has no line comments".into()),
+                }
+            }
             PaneAction::Undo => {
                 self.cursors = self.content.undo(self.cursors.clone());
-                self.modified = true;
+                self.conflicts = None;
+                self.notify_whole_buffer_changed();
+                self.modified = !self.content.is_at_saved_state();
+                self.last_edit = Some(Instant::now());
                 self.adjust_viewport();
             }
             PaneAction::Redo => {
                 self.cursors = self.content.redo(self.cursors.clone());
-                self.modified = true;
+                self.conflicts = None;
+                self.content.limit_undo_history(self.settings.undo_levels);
+                self.notify_whole_buffer_changed();
+                self.modified = !self.content.is_at_saved_state();
+                self.last_edit = Some(Instant::now());
                 self.adjust_viewport();
             }
             PaneAction::Find(needle) => {
-                self.content.search_with_cursors(&mut self.cursors, &needle);
-                self.last_search = Some(needle);
+                let query = SearchQuery::new(needle, self.settings.smartcase);
+                self.content.search_with_cursors(&mut self.cursors, &query);
+                self.last_search = Some(query);
                 self.adjust_viewport();
             }
             PaneAction::RepeatFind => {
@@ -517,12 +1385,54 @@ impl Pane {
                         if offset != selection.start {
                             let sel_end = ByteOffset(offset.0 + selection.end.0 - selection.start.0);
                             let new_cursor = Cursor::new_with_selection(offset, Some(sel_end));
-                            self.cursors.spawn_new_primary(new_cursor);
+                            self.cursors.spawn_new_primary(&self.content, new_cursor);
                         }
                     }
                     self.adjust_viewport();
                 }
             }
+            PaneAction::ResolveConflict(resolution) => {
+                let lineno = self.cursors.primary().current_line_number(&self.content);
+                let conflict = self.update_conflicts()
+                    .iter()
+                    .find(|conflict| conflict.contains_line(lineno))
+                    .copied();
+                if let Some(conflict) = conflict {
+                    let range = conflict.byte_range(&self.content);
+                    let replacement = conflict.resolved(&self.content, resolution);
+                    self.cursors.esc();
+                    let edits = EditBatch::from_edits(vec![
+                        Edit::delete(range.start, range.end.0 - range.start.0),
+                        Edit::insert_str(range.start, &replacement),
+                    ]);
+                    self.apply_editbatch(edits);
+                    self.cursors.primary_mut().offset = range.start;
+                    self.adjust_viewport();
+                } else {
+                    self.inform("resolve error: cursor is not inside a merge conflict".into());
+                }
+            }
+            PaneAction::NextConflict => {
+                let lineno = self.cursors.primary().current_line_number(&self.content);
+                let conflicts = self.update_conflicts();
+                let next = match conflicts.iter().find(|conflict| conflict.start > lineno) {
+                    Some(conflict) => Some(*conflict),
+                    None => {
+                        let first = conflicts.first().copied();
+                        if first.is_some() {
+                            self.inform("Reached end of file, continuing from the top".into());
+                        }
+                        first
+                    }
+                };
+                if let Some(conflict) = next {
+                    self.cursors.esc();
+                    self.cursors.primary_mut().offset = self.content.line_to_byte(conflict.start);
+                    self.adjust_viewport();
+                } else {
+                    self.inform("No merge conflicts".into());
+                }
+            }
             PaneAction::ScrollDown(n) => {
                 let new_pos = self.viewport_position_row + n;
                 self.viewport_position_row = new_pos.min(self.content.len_lines().saturating_sub(1));
@@ -530,6 +1440,16 @@ impl Pane {
             PaneAction::ScrollUp(n) => {
                 self.viewport_position_row = self.viewport_position_row.saturating_sub(n);
             }
+            PaneAction::CursorToViewportTop => {
+                self.move_cursor_to_visible_line(self.viewport_position_row);
+            }
+            PaneAction::CursorToViewportMiddle => {
+                let middle = (self.viewport_position_row + self.last_visible_line()) / 2;
+                self.move_cursor_to_visible_line(middle);
+            }
+            PaneAction::CursorToViewportBottom => {
+                self.move_cursor_to_visible_line(self.last_visible_line());
+            }
             PaneAction::Tab => {
                 if self.suggestions.is_some() {
                     self.handle_event(PaneAction::AutocompleteCycleNext);
@@ -611,10 +1531,283 @@ impl Pane {
     }
 }
 
+/// Finds an http(s) URL in `line` that contains the byte offset `offset`
+fn find_url_at(line: &str, offset: usize) -> Option<&str> {
+    let mut search_from = 0;
+    while let Some(found) = line[search_from..].find("http") {
+        let start = search_from + found;
+        search_from = start + 4;
+        let rest = &line[start..];
+        if !(rest.starts_with("http://") || rest.starts_with("https://")) {
+            continue
+        }
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"' | '\'' | '`'))
+            .unwrap_or(rest.len());
+        let mut url = &rest[..len];
+        // punctuation at the end is most likely part of the surrounding text
+        while let Some(trimmed) = url.strip_suffix(['.', ',', ';', ':', '!', '?']) {
+            url = trimmed;
+        }
+        // keep closing parens only when they are balanced, eg. wikipedia links
+        while url.ends_with([')', ']']) && url.matches(['(', '[']).count() < url.matches([')', ']']).count() {
+            url = &url[..url.len() - 1];
+        }
+        if (start..=start + url.len()).contains(&offset) {
+            return Some(url)
+        }
+    }
+    None
+}
+
+/// Finds a file path-looking token in `line` that contains the byte offset `offset`
+fn find_path_at(line: &str, offset: usize) -> Option<&str> {
+    let is_delimiter = |c: char| c.is_whitespace() || "\"'`<>()[]|;,=".contains(c);
+    let offset = offset.min(line.len());
+    let start = line[..offset].rfind(is_delimiter).map_or(0, |i| i + 1);
+    let end = line[offset..].find(is_delimiter).map_or(line.len(), |i| offset + i);
+    let token = line[start..end].trim_end_matches(['.', ':']);
+    if token.is_empty() { None } else { Some(token) }
+}
+
+/// Saves by writing to a temporary file next to `path` and renaming it over
+/// the original so that a failed or interrupted save doesn't leave a partially
+/// written file behind. Falls back to writing in place if the temporary file
+/// can not be created or renamed (eg. the directory is not writable) or if
+/// replacing the file would change its owner or break its hard links.
+fn write_atomically(path: &Path, write: impl Fn(&mut dyn Write) -> std::io::Result<()>) -> std::io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    // write through symlinks instead of replacing them
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let metadata = std::fs::metadata(&path).ok();
+    if let Some(metadata) = metadata.as_ref() {
+        // renaming over a read-only file would succeed, so check that the
+        // file is writable the same way as set_path does
+        std::fs::OpenOptions::new().write(true).truncate(false).open(&path)?;
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        if metadata.uid() != uid || metadata.gid() != gid || metadata.nlink() > 1 {
+            return write_in_place(&path, write)
+        }
+    }
+    let Some(file_name) = path.file_name() else { return write_in_place(&path, write) };
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(".bad-save-{}", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+    let Ok(tmp_file) = std::fs::OpenOptions::new().write(true).create(true).truncate(true).open(&tmp_path) else {
+        return write_in_place(&path, write)
+    };
+    let written = (|| {
+        let mut writer = std::io::BufWriter::new(tmp_file);
+        write(&mut writer)?;
+        let file = writer.into_inner().map_err(|err| err.into_error())?;
+        file.sync_all()?;
+        if let Some(metadata) = metadata.as_ref() {
+            std::fs::set_permissions(&tmp_path, metadata.permissions())?;
+        }
+        Ok(())
+    })();
+    if let Err(err) = written {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(err)
+    }
+    if std::fs::rename(&tmp_path, &path).is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+        return write_in_place(&path, write)
+    }
+    Ok(())
+}
+
+fn write_in_place(path: &Path, write: impl Fn(&mut dyn Write) -> std::io::Result<()>) -> std::io::Result<()> {
+    let file = std::fs::OpenOptions::new().write(true).create(true).truncate(true).open(path)?;
+    let mut writer = std::io::BufWriter::new(file);
+    write(&mut writer)?;
+    writer.flush()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn written(pane: &Pane, text: &str) -> String {
+        let mut out = vec![];
+        pane.write_to_file(&mut out, &RopeBuffer::from_str(text)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn write_keeps_line_endings() {
+        let mut pane = Pane::empty();
+        pane.settings.trim_trailing_whitespace = false;
+        pane.settings.normalize_end_of_line = false;
+        pane.settings.insert_final_newline = false;
+        let text = "a \r\nb\t\u{2028}c\u{85}ö  \rd\u{2029}\r\n\ne ";
+        assert_eq!(written(&pane, text), text);
+    }
+
+    #[test]
+    fn write_trims_and_normalizes_line_endings() {
+        let mut pane = Pane::empty();
+        pane.settings.trim_trailing_whitespace = true;
+        pane.settings.normalize_end_of_line = true;
+        pane.settings.insert_final_newline = true;
+        pane.settings.end_of_line = "\n";
+        let text = "a \r\nb\t\u{2028}c\u{85}ö \u{3000}\rd\u{2029}\r\n\ne ";
+        assert_eq!(written(&pane, text), "a\nb\nc\nö\nd\n\n\ne \n");
+        pane.settings.end_of_line = "\r\n";
+        pane.settings.normalize_end_of_line = false;
+        assert_eq!(written(&pane, "x \u{2028}y\r\n"), "x\u{2028}y\r\n");
+    }
+
+    #[test]
+    fn write_trims_only_modified_lines() {
+        let mut pane = Pane::empty();
+        pane.content = RopeBuffer::from_str("a \nb \nc \n");
+        pane.settings.trim_trailing_whitespace = true;
+        pane.settings.trim_modified_only = true;
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::Down(1)));
+        pane.handle_event(PaneAction::Insert("x".into()));
+        let mut out = vec![];
+        pane.write_to_file(&mut out, &pane.content).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a \nxb\nc \n");
+    }
+
+    #[test]
+    fn fast_and_line_by_line_write_agree() {
+        let mut pane = Pane::empty();
+        pane.settings.trim_trailing_whitespace = false;
+        pane.settings.normalize_end_of_line = false;
+        pane.settings.end_of_line = "\r\n";
+        for insert_final_newline in [false, true] {
+            pane.settings.insert_final_newline = insert_final_newline;
+            for text in ["", "\n", "a", "a \r\nb\t\u{2028}c\u{85}ö  \rd\u{2029}\r\n\ne ", &"line \n".repeat(10_000)] {
+                let mut line_by_line = vec![];
+                pane.write_lines_to_file(&mut line_by_line, &RopeBuffer::from_str(text)).unwrap();
+                assert_eq!(written(&pane, text).as_bytes(), line_by_line, "{text:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn matching_pair_skips_strings_and_comments() {
+        let mut pane = Pane::empty();
+        pane.set_filetype("rust", Arc::new(BadHighlighterManager::new())).unwrap();
+        pane.handle_event(PaneAction::Insert("fn f() {\n    let s = \"}\"; // }\n    g('}');\n}\n".into()));
+        let closing = ByteOffset(pane.content.len_bytes() - 2);
+
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::ByteOffset(7)));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::MatchingPair));
+        assert_eq!(pane.cursors.primary().offset, closing);
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::MatchingPair));
+        assert_eq!(pane.cursors.primary().offset, ByteOffset(7));
+        pane.handle_event(PaneAction::SelectTo(MoveTarget::MatchingPair));
+        assert_eq!(pane.cursors.primary().selection(), Some(ByteOffset(7)..closing));
+    }
+
+    #[test]
+    fn counts_occurrences_of_highlighted_word() {
+        let mut pane = Pane::empty();
+        pane.handle_event(PaneAction::Insert("foo(foo, foobar);\nfoo".into()));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::StartOfFile));
+        pane.update_word_count();
+        assert!(pane.word_count.is_none(), "the word should not be highlighted right after moving");
+        pane.last_event -= WORD_HIGHLIGHT_DELAY;
+        pane.update_word_count();
+        let wc = pane.word_count.as_ref().unwrap();
+        assert_eq!((wc.word.as_str(), wc.count, wc.complete), ("foo", 3, true));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::EndOfLine));
+        pane.last_event -= WORD_HIGHLIGHT_DELAY;
+        pane.update_word_count();
+        assert!(pane.word_count.is_none());
+    }
+
+    #[test]
+    fn word_count_is_updated_when_generated_content_is_replaced() {
+        let mut pane = Pane::empty();
+        pane.replace_generated_content("foo bar\n");
+        pane.last_event -= WORD_HIGHLIGHT_DELAY;
+        pane.update_word_count();
+        assert_eq!(pane.word_count.as_ref().map(|wc| wc.count), Some(1));
+        pane.replace_generated_content("foo foo\nfoo\n");
+        pane.update_word_count();
+        assert_eq!(pane.word_count.as_ref().map(|wc| wc.count), Some(3));
+    }
+
+    #[test]
+    fn highlights_occurrences_of_selection() {
+        let mut pane = Pane::empty();
+        pane.handle_event(PaneAction::Insert("foo(foo, foobar);\nfoo".into()));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::StartOfFile));
+        pane.handle_event(PaneAction::SelectTo(MoveTarget::Right(3)));
+        let all = ByteOffset(0)..ByteOffset(pane.content.len_bytes());
+        let starts = |pane: &Pane| pane.selection_occurrences_to_highlight(all.clone()).iter().map(|occ| occ.start.0).collect::<Vec<usize>>();
+        assert_eq!(starts(&pane), [0, 4, 9, 18]);
+        pane.settings.highlight_occurrences = false;
+        assert!(starts(&pane).is_empty());
+        pane.settings.highlight_occurrences = true;
+        pane.handle_event(PaneAction::SelectTo(MoveTarget::Right(1)));
+        assert!(starts(&pane).is_empty(), "the selection is more than a word");
+        pane.handle_event(PaneAction::SelectTo(MoveTarget::Left(2)));
+        assert!(starts(&pane).is_empty(), "the selection is part of a word");
+        pane.handle_event(PaneAction::SelectTo(MoveTarget::Down(1)));
+        assert!(starts(&pane).is_empty());
+    }
+
+    #[test]
+    fn cursor_to_visible_lines() {
+        let mut pane = Pane::empty();
+        pane.set_text(&"abcd\n".repeat(100));
+        pane.update_viewport_size(80, 10);
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::Location(NonZeroUsize::new(50).unwrap(), NonZeroUsize::new(3).unwrap())));
+        pane.viewport_position_row = 20;
+        let line_and_column = |pane: &Pane| {
+            let cursor = pane.cursors.primary();
+            (cursor.current_line_number(&pane.content), cursor.column(&pane.content))
+        };
+        pane.handle_event(PaneAction::CursorToViewportTop);
+        assert_eq!(line_and_column(&pane), (20, 2));
+        pane.handle_event(PaneAction::CursorToViewportBottom);
+        assert_eq!(line_and_column(&pane), (29, 2));
+        pane.handle_event(PaneAction::CursorToViewportMiddle);
+        assert_eq!(line_and_column(&pane), (24, 2));
+        assert_eq!(pane.viewport_position_row, 20);
+
+        pane.viewport_position_row = 95;
+        pane.handle_event(PaneAction::CursorToViewportBottom);
+        assert_eq!(line_and_column(&pane), (100, 0));
+    }
+
+    #[test]
+    fn extra_word_chars() {
+        let mut pane = Pane::empty();
+        pane.settings.extra_word_chars = Some("-".into());
+        pane.handle_event(PaneAction::Insert(".foo-bar { margin-top: 0 }".into()));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::StartOfFile));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::Right(1)));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::NextWordBoundaryRight));
+        assert_eq!(pane.cursors.primary().offset, ByteOffset(8));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::EndOfLine));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::Left(5)));
+        pane.handle_event(PaneAction::DeleteWord);
+        assert_eq!(pane.content.to_string(), ".foo-bar { : 0 }");
+
+        let mut pane = Pane::empty();
+        pane.settings.extra_word_chars = Some("$".into());
+        pane.handle_event(PaneAction::Insert("let $el = $(sel-ector);".into()));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::ByteOffset(4)));
+        pane.handle_event(PaneAction::SelectTo(MoveTarget::NextWordBoundaryRight));
+        assert_eq!(pane.cursors.primary().selection(), Some(ByteOffset(4)..ByteOffset(7)));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::ByteOffset(16)));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::NextWordBoundaryLeft));
+        // '-' is not a word character in this case
+        assert_eq!(pane.cursors.primary().offset, ByteOffset(15));
+
+        assert_eq!(default_extra_word_chars("css"), "-");
+        assert_eq!(default_extra_word_chars("js"), "$");
+        assert_eq!(default_extra_word_chars("rust"), "");
+    }
+
     #[test]
     fn surround_selection() {
         let mut pane = Pane::empty();
@@ -630,8 +1823,241 @@ mod tests {
     }
 
     #[test]
-    // FIXME
-    #[ignore = "known bug: the two cursors end up in the same position during editing"]
+    fn surround_keeps_selection() {
+        let mut pane = Pane::empty();
+        pane.handle_event(PaneAction::Insert("hello".into()));
+        pane.handle_event(PaneAction::SelectAll);
+        pane.handle_event(PaneAction::Surround('('));
+        pane.handle_event(PaneAction::Surround('"'));
+        pane.handle_event(PaneAction::Surround(']'));
+        assert_eq!(pane.content.to_string(), "(\"[hello]\")");
+        assert_eq!(pane.selections(), ["hello"]);
+    }
+
+    #[test]
+    fn surround_adjacent_selections() {
+        let mut pane = Pane::empty();
+        pane.handle_event(PaneAction::Insert("abc".into()));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::StartOfFile));
+        pane.handle_event(PaneAction::SelectTo(MoveTarget::Right(1)));
+        for offset in [1, 2] {
+            pane.cursors.spawn_new(&pane.content, Cursor::new_with_selection(ByteOffset(offset + 1), Some(ByteOffset(offset))));
+        }
+        assert_eq!(pane.cursors.cursor_count(), 3);
+        pane.handle_event(PaneAction::Surround('<'));
+        assert_eq!(pane.content.to_string(), "<a><b><c>");
+        assert_eq!(pane.selections(), ["a", "b", "c"]);
+        pane.handle_event(PaneAction::Surround('\''));
+        assert_eq!(pane.content.to_string(), "<'a'><'b'><'c'>");
+        assert_eq!(pane.selections(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn move_last_line_without_newline() {
+        let mut pane = Pane::empty();
+        pane.handle_event(PaneAction::Insert("A\nB".into()));
+        pane.handle_event(PaneAction::MoveLinesUp);
+        assert_eq!(pane.content.to_string(), "B\nA");
+        assert_eq!(pane.cursors.primary().offset, ByteOffset(1));
+        pane.handle_event(PaneAction::MoveLinesDown);
+        assert_eq!(pane.content.to_string(), "A\nB");
+        assert_eq!(pane.cursors.primary().offset, ByteOffset(3));
+    }
+
+    #[test]
+    fn typing_auto_closes_pairs() {
+        let mut pane = Pane::empty();
+        pane.settings.auto_close_pairs = true;
+        for c in "f(x, \"a\")".chars() {
+            pane.handle_event(PaneAction::Insert(c.to_string()));
+        }
+        assert_eq!(pane.content.to_string(), "f(x, \"a\")");
+        pane.settings.auto_close_pairs = false;
+        pane.handle_event(PaneAction::Insert("[".into()));
+        assert_eq!(pane.content.to_string(), "f(x, \"a\")[");
+    }
+
+    #[test]
+    fn smart_autoindent_expands_brackets() {
+        let mut pane = Pane::empty();
+        pane.settings.autoindent = AutoIndent::Smart;
+        pane.settings.indent_kind = crate::IndentKind::Tabs;
+        pane.settings.indent_size = pane.settings.tab_width;
+        pane.content = RopeBuffer::from_str("\tif (x) {}\n\tf()");
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::ByteOffset(9)));
+        pane.cursors.spawn_new(&pane.content, Cursor::new_with_offset(ByteOffset(14)));
+        pane.handle_event(PaneAction::InsertNewline);
+        assert_eq!(pane.content.to_string(), "\tif (x) {\n\t\t\n\t}\n\tf(\n\t\t\n\t)");
+        pane.handle_event(PaneAction::Insert("y".into()));
+        assert_eq!(pane.content.to_string(), "\tif (x) {\n\t\ty\n\t}\n\tf(\n\t\ty\n\t)");
+    }
+
+    #[test]
+    fn smart_autoindent_dedents_closing_brace() {
+        let mut pane = Pane::empty();
+        pane.settings.autoindent = AutoIndent::Smart;
+        pane.handle_event(PaneAction::Insert("fn f() {".into()));
+        pane.handle_event(PaneAction::InsertNewline);
+        pane.handle_event(PaneAction::Insert("    x;".into()));
+        pane.handle_event(PaneAction::InsertNewline);
+        pane.handle_event(PaneAction::Insert("}".into()));
+        assert_eq!(pane.content.to_string(), "fn f() {\n    x;\n}");
+        assert_eq!(pane.cursors.primary().offset, ByteOffset(pane.content.len_bytes()));
+
+        pane.settings.autoindent = AutoIndent::Keep;
+        pane.handle_event(PaneAction::InsertNewline);
+        pane.handle_event(PaneAction::Insert("    }".into()));
+        pane.handle_event(PaneAction::InsertNewline);
+        pane.handle_event(PaneAction::Insert("}".into()));
+        assert_eq!(pane.content.to_string(), "fn f() {\n    x;\n}\n    }\n    }");
+    }
+
+    #[test]
+    fn typing_closes_html_tags() {
+        let mut pane = Pane::empty();
+        pane.set_filetype("html", Arc::new(BadHighlighterManager::new())).unwrap();
+        pane.handle_event(PaneAction::Insert("<ul>\n<li>item<".into()));
+        pane.handle_event(PaneAction::Insert("/".into()));
+        assert_eq!(pane.content.to_string(), "<ul>\n<li>item</li>");
+        pane.handle_event(PaneAction::Insert("<".into()));
+        pane.handle_event(PaneAction::Insert("/".into()));
+        pane.handle_event(PaneAction::Insert(" 1 </".into()));
+        assert_eq!(pane.content.to_string(), "<ul>\n<li>item</li></ul> 1 </");
+        pane.handle_event(PaneAction::Insert("/".into()));
+        assert_eq!(pane.content.to_string(), "<ul>\n<li>item</li></ul> 1 <//");
+    }
+
+    #[test]
+    fn closing_tag_is_dedented() {
+        let mut pane = Pane::empty();
+        pane.set_filetype("html", Arc::new(BadHighlighterManager::new())).unwrap();
+        pane.settings.autoindent = AutoIndent::Smart;
+        pane.handle_event(PaneAction::Insert("<ul>\n    <li>item</li>\n    <".into()));
+        pane.handle_event(PaneAction::Insert("/".into()));
+        assert_eq!(pane.content.to_string(), "<ul>\n    <li>item</li>\n</ul>");
+    }
+
+    #[test]
+    fn replace_within_selections() {
+        let mut pane = Pane::empty();
+        pane.handle_event(PaneAction::Insert("a a\na a".into()));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::StartOfFile));
+        pane.handle_event(PaneAction::SelectTo(MoveTarget::EndOfLine));
+        assert_eq!(pane.replace("a", "bb"), 2);
+        assert_eq!(pane.content.to_string(), "bb bb\na a");
+        assert_eq!(pane.selections(), ["bb bb"]);
+        pane.esc();
+        assert_eq!(pane.replace("a", "c"), 2);
+        assert_eq!(pane.content.to_string(), "bb bb\nc c");
+    }
+
+    #[test]
+    fn remove_lines_within_selection() {
+        let mut pane = Pane::empty();
+        pane.handle_event(PaneAction::Insert("x\nx\n\ny\ny\n\n".into()));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::StartOfFile));
+        pane.handle_event(PaneAction::SelectTo(MoveTarget::Down(2)));
+        assert_eq!(pane.remove_duplicate_lines(false), 1);
+        assert_eq!(pane.remove_blank_lines(), 1);
+        assert_eq!(pane.content.to_string(), "x\ny\ny\n\n");
+        pane.esc();
+        assert_eq!(pane.remove_duplicate_lines(false), 1);
+        assert_eq!(pane.remove_blank_lines(), 1);
+        assert_eq!(pane.content.to_string(), "x\ny\n");
+    }
+
+    #[test]
+    fn append_to_lines_with_cursors() {
+        let mut pane = Pane::empty();
+        pane.handle_event(PaneAction::Insert("a\nb\n\nc;\nd\n".into()));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::StartOfFile));
+        pane.handle_event(PaneAction::SelectTo(MoveTarget::Down(3)));
+        assert_eq!(pane.append_to_lines(";"), 2);
+        assert_eq!(pane.content.to_string(), "a;\nb;\n\nc;\nd\n");
+        assert_eq!(pane.append_to_lines(";"), 0);
+    }
+
+    #[test]
+    fn pane_from_reader() {
+        let pane = Pane::new_from_reader("piped\ncontent\n".as_bytes(), "stdin").unwrap();
+        assert_eq!(pane.title, "stdin");
+        assert_eq!(pane.path, None);
+        assert_eq!(pane.content.to_string(), "piped\ncontent\n");
+        assert!(pane.modified);
+        assert_eq!(pane.cursors.primary().offset, ByteOffset(0));
+        assert!(!Pane::new_from_reader([].as_slice(), "stdin").unwrap().modified);
+        assert!(Pane::new_from_reader([0xff, 0xfe].as_slice(), "stdin").is_err());
+    }
+
+    #[test]
+    fn title_styles() {
+        let cwd = std::env::current_dir().unwrap();
+        let mut pane = Pane::empty();
+        pane.path = Some(cwd.join("dir").join("file.txt"));
+        let title_with = |pane: &mut Pane, style| {
+            pane.settings.title_style = style;
+            pane.update_title();
+            pane.title.clone()
+        };
+        assert_eq!(title_with(&mut pane, TitleStyle::Name), "file.txt");
+        assert_eq!(PathBuf::from(title_with(&mut pane, TitleStyle::Relative)), Path::new("dir").join("file.txt"));
+        assert_eq!(PathBuf::from(title_with(&mut pane, TitleStyle::Absolute)), cwd.join("dir").join("file.txt"));
+        pane.path = Some(PathBuf::from("file.txt"));
+        assert_eq!(title_with(&mut pane, TitleStyle::Path), "file.txt");
+        assert_eq!(PathBuf::from(title_with(&mut pane, TitleStyle::Absolute)), cwd.join("file.txt"));
+    }
+
+    #[test]
+    fn enclose_selected_lines() {
+        let mut pane = Pane::empty();
+        pane.handle_event(PaneAction::Insert("a\n  b\nc\n".into()));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::StartOfFile));
+        pane.handle_event(PaneAction::SelectTo(MoveTarget::Down(1)));
+        assert_eq!(pane.enclose_lines("\"", "\","), 2);
+        assert_eq!(pane.content.to_string(), "\"a\",\n  \"b\",\nc\n");
+    }
+
+    #[rstest::rstest]
+    #[case::smartcase_lowercase("foo", true, vec![0, 4, 8, 12])]
+    #[case::smartcase_uppercase("Foo", true, vec![0, 12])]
+    #[case::case_sensitive("foo", false, vec![4])]
+    fn smartcase_search(#[case] needle: &str, #[case] smartcase: bool, #[case] expected: Vec<usize>) {
+        let mut pane = Pane::empty();
+        pane.settings.smartcase = smartcase;
+        pane.handle_event(PaneAction::Insert("Foo foo FOO Foo".into()));
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::StartOfFile));
+        pane.handle_event(PaneAction::Find(needle.into()));
+        let mut found = vec![];
+        while let Some(selection) = pane.cursors.primary().selection() {
+            if found.contains(&selection.start.0) {
+                break
+            }
+            found.push(selection.start.0);
+            pane.handle_event(PaneAction::RepeatFind);
+        }
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn swap_two_words() {
+        let mut pane = Pane::empty();
+        pane.handle_event(PaneAction::Insert("f(first, second)".into()));
+        pane.handle_event(PaneAction::SwapSelections);
+        assert!(pane.info.is_some());
+        assert_eq!(pane.content.to_string(), "f(first, second)");
+        pane.cursors.primary_mut().selection_from = Some(ByteOffset(2));
+        pane.cursors.primary_mut().offset = ByteOffset(7);
+        pane.cursors.spawn_new(&pane.content, Cursor::new_with_selection(ByteOffset(15), Some(ByteOffset(9))));
+        pane.handle_event(PaneAction::SwapSelections);
+        assert_eq!(pane.content.to_string(), "f(second, first)");
+        let mut selections = pane.selections();
+        selections.sort();
+        assert_eq!(selections, ["first", "second"]);
+        pane.handle_event(PaneAction::SwapSelections);
+        assert_eq!(pane.content.to_string(), "f(first, second)");
+    }
+
+    #[test]
     fn surround_two_adjacent_selections() {
         let mut pane = Pane::empty();
         pane.handle_event(PaneAction::Insert("murmur".into()));
@@ -643,4 +2069,249 @@ mod tests {
         pane.handle_event(PaneAction::Insert("(".into()));
         assert_eq!(pane.content.to_string(), "([mur])([mur])");
     }
+
+    #[test]
+    fn change_hook_receives_changed_range() {
+        let changes = Arc::new(std::sync::Mutex::new(vec![]));
+        let mut pane = Pane::empty();
+        let changes_clone = changes.clone();
+        pane.set_on_change(move |range| changes_clone.lock().unwrap().push(range));
+        pane.handle_event(PaneAction::Insert("hello".into()));
+        pane.handle_event(PaneAction::DeleteBackward);
+        pane.handle_event(PaneAction::Undo);
+        assert_eq!(*changes.lock().unwrap(), vec![
+            ByteOffset(0)..ByteOffset(5),
+            ByteOffset(4)..ByteOffset(4),
+            ByteOffset(0)..ByteOffset(5),
+        ]);
+    }
+
+    #[test]
+    fn chained_change_hooks_are_all_called() {
+        let changes = Arc::new(std::sync::Mutex::new(vec![]));
+        let mut pane = Pane::empty();
+        let first = changes.clone();
+        pane.set_on_change(move |range| first.lock().unwrap().push(("first", range)));
+        let second = changes.clone();
+        pane.chain_on_change(move |range| second.lock().unwrap().push(("second", range)));
+        pane.handle_event(PaneAction::Insert("a".into()));
+        assert_eq!(*changes.lock().unwrap(), vec![
+            ("first", ByteOffset(0)..ByteOffset(1)),
+            ("second", ByteOffset(0)..ByteOffset(1)),
+        ]);
+    }
+
+    #[test]
+    fn url_at_offset() {
+        let line = "see (https://en.wikipedia.org/wiki/Rust_(language)) or http://example.com/a?b=1.";
+        assert_eq!(find_url_at(line, 0), None);
+        assert_eq!(find_url_at(line, 10), Some("https://en.wikipedia.org/wiki/Rust_(language)"));
+        assert_eq!(find_url_at(line, 60), Some("http://example.com/a?b=1"));
+        assert_eq!(find_url_at(line, line.len()), None);
+        assert_eq!(find_url_at("httpx://nope", 3), None);
+    }
+
+    #[test]
+    fn path_at_offset() {
+        assert_eq!(find_path_at("#include \"foo/bar.h\"", 12), Some("foo/bar.h"));
+        assert_eq!(find_path_at("error at src/main.rs:12:5: oops", 12), Some("src/main.rs:12:5"));
+        assert_eq!(find_path_at("see ~/notes.txt.", 15), Some("~/notes.txt"));
+        assert_eq!(find_path_at("a  b", 2), None);
+    }
+
+    #[test]
+    fn text_accessors() {
+        let mut pane = Pane::empty();
+        pane.set_text("first\r\nsecond\n");
+        assert_eq!(pane.text(), "first\r\nsecond\n");
+        assert_eq!(pane.byte_len(), 14);
+        assert_eq!(pane.line(0).as_deref(), Some("first"));
+        assert_eq!(pane.line(1).as_deref(), Some("second"));
+        assert_eq!(pane.line(2).as_deref(), Some(""));
+        assert_eq!(pane.line(3), None);
+        pane.handle_event(PaneAction::Undo);
+        assert_eq!(pane.text(), "");
+        pane.set_text("a\rb\u{0B}c\u{0C}d\u{85}e\u{2028}f\u{2029}g");
+        let lines: Vec<_> = (0..7).filter_map(|n| pane.line(n)).collect();
+        assert_eq!(lines, ["a", "b", "c", "d", "e", "f", "g"]);
+    }
+
+    #[test]
+    fn jump_to_next_conflict() {
+        let mut pane = Pane::empty();
+        pane.content = RopeBuffer::from_str("a\n<<<<<<< HEAD\nb\n=======\nc\n>>>>>>> x\n<<<<<<< HEAD\n=======\n>>>>>>> y\n");
+        pane.handle_event(PaneAction::NextConflict);
+        assert_eq!(pane.cursors.primary().offset, pane.content.line_to_byte(1));
+        pane.handle_event(PaneAction::NextConflict);
+        assert_eq!(pane.cursors.primary().offset, pane.content.line_to_byte(6));
+        assert!(pane.status_msg().is_none());
+        pane.handle_event(PaneAction::NextConflict);
+        assert_eq!(pane.cursors.primary().offset, pane.content.line_to_byte(1));
+        assert!(pane.status_msg().is_some());
+    }
+
+    #[test]
+    fn conflicts_are_found_again_after_edits() {
+        let mut pane = Pane::empty();
+        pane.set_text("<<<<<<< HEAD\nb\n=======\nc\n>>>>>>> x\n");
+        assert_eq!(pane.update_conflicts().len(), 1);
+        pane.handle_event(PaneAction::ResolveConflict(ConflictResolution::Ours));
+        assert_eq!(pane.text(), "b\n");
+        assert!(pane.update_conflicts().is_empty());
+        pane.handle_event(PaneAction::Undo);
+        assert_eq!(pane.update_conflicts().len(), 1);
+    }
+
+    #[test]
+    fn warn_when_file_is_already_locked() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "hello").unwrap();
+        let hl = Arc::new(BadHighlighterManager::new());
+        let fileloc = FilePathWithOptionalLocation::from(path.clone());
+        let first = Pane::new_from_file(&fileloc, hl.clone());
+        assert!(first.status_msg().is_none());
+        let second = Pane::new_from_file(&fileloc, hl.clone());
+        assert!(second.status_msg().is_some_and(|msg| msg.contains("already open")));
+        drop(first);
+        let third = Pane::new_from_file(&fileloc, hl);
+        assert!(third.status_msg().is_none());
+    }
+
+    #[test]
+    fn crlf_file_keeps_its_line_endings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "abc\r\ndef\r\n").unwrap();
+        let hl = Arc::new(BadHighlighterManager::new());
+        let mut pane = Pane::new_from_file(&FilePathWithOptionalLocation::from(path.clone()), hl);
+        assert_eq!(pane.settings.end_of_line, "\r\n");
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::EndOfLine));
+        pane.handle_event(PaneAction::InsertNewline);
+        pane.handle_event(PaneAction::Insert("xyz".into()));
+        pane.save();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "abc\r\nxyz\r\ndef\r\n");
+    }
+
+    #[test]
+    fn indentation_is_detected_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "a {\n\tb {\n\t\tc\n\t}\n}\n").unwrap();
+        let hl = Arc::new(BadHighlighterManager::new());
+        let pane = Pane::new_from_file(&FilePathWithOptionalLocation::from(path.clone()), hl);
+        assert_eq!(pane.settings.indent_kind, crate::IndentKind::Tabs);
+        assert_eq!(pane.settings.indent_as_string(), "\t");
+    }
+
+    #[test]
+    fn bom_is_stripped_and_written_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "\u{feff}abc\n").unwrap();
+        let hl = Arc::new(BadHighlighterManager::new());
+        let mut pane = Pane::new_from_file(&FilePathWithOptionalLocation::from(path.clone()), hl);
+        assert!(pane.had_bom);
+        assert_eq!(pane.content.to_string(), "abc\n");
+        pane.handle_event(PaneAction::Insert("x".into()));
+        pane.save();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "\u{feff}xabc\n");
+    }
+
+    #[test]
+    fn invalid_utf8_is_read_lossily() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, b"caf\xe9\n").unwrap();
+        let hl = Arc::new(BadHighlighterManager::new());
+        let pane = Pane::new_from_file(&FilePathWithOptionalLocation::from(path.clone()), hl);
+        assert!(!pane.had_bom);
+        assert_eq!(pane.content.to_string(), "caf\u{fffd}\n");
+        assert!(pane.status_msg().is_some_and(|msg| msg.contains("not valid UTF-8")));
+    }
+
+    #[test]
+    fn failed_write_leaves_the_original_intact() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "original\n").unwrap();
+        let result = write_atomically(&path, |file| {
+            file.write_all(b"partial")?;
+            Err(std::io::Error::other("disk full"))
+        });
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "original\n");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1, "the temporary file should be removed");
+
+        let mut permissions = std::fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&path, permissions).unwrap();
+        // root can write to read-only files
+        if unsafe { libc::geteuid() } != 0 {
+            assert!(write_atomically(&path, |file| file.write_all(b"new\n")).is_err());
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "original\n");
+        }
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn hard_linked_file_is_written_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        let link = dir.path().join("link.txt");
+        std::fs::write(&path, "original\n").unwrap();
+        std::fs::hard_link(&path, &link).unwrap();
+        write_atomically(&path, |file| file.write_all(b"new\n")).unwrap();
+        assert_eq!(std::fs::read_to_string(&link).unwrap(), "new\n");
+    }
+
+    #[test]
+    fn revert_discards_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "abc\ndef\n").unwrap();
+        let hl = Arc::new(BadHighlighterManager::new());
+        let mut pane = Pane::new_from_file(&FilePathWithOptionalLocation::from(path.clone()), hl);
+        pane.handle_event(PaneAction::MoveTo(MoveTarget::Down(1)));
+        pane.handle_event(PaneAction::Insert("xyz".into()));
+        assert!(pane.modified);
+        pane.revert();
+        assert_eq!(pane.content.to_string(), "abc\ndef\n");
+        assert!(!pane.modified);
+        assert_eq!(pane.cursors.primary().current_line_number(&pane.content), 1);
+    }
+
+    #[test]
+    fn undoing_back_to_saved_state_clears_modified() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "abc\n").unwrap();
+        let hl = Arc::new(BadHighlighterManager::new());
+        let mut pane = Pane::new_from_file(&FilePathWithOptionalLocation::from(path.clone()), hl);
+        pane.handle_event(PaneAction::Insert("x".into()));
+        assert!(pane.modified);
+        pane.handle_event(PaneAction::Undo);
+        assert!(!pane.modified);
+        pane.handle_event(PaneAction::Redo);
+        pane.save();
+        assert!(!pane.modified);
+        pane.handle_event(PaneAction::Undo);
+        assert!(pane.modified);
+        pane.handle_event(PaneAction::Redo);
+        assert!(!pane.modified);
+    }
+
+    #[test]
+    fn chosen_filetype_survives_save_as() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("file.py");
+        let second = dir.path().join("file.rb");
+        let hl = Arc::new(BadHighlighterManager::new());
+        let mut pane = Pane::empty();
+        pane.save_as(&first, hl.clone());
+        assert_eq!(pane.filetype(), "python");
+        pane.set_filetype("rust", hl.clone()).unwrap();
+        pane.save_as(&second, hl);
+        assert_eq!(pane.filetype(), "rust");
+    }
 }
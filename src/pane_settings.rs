@@ -1,8 +1,11 @@
 use std::path::Path;
+use std::time::Duration;
 
 use ec4rs::PropertiesSource;
 
 use crate::IndentKind;
+use crate::linter::Severity;
+use crate::ropebuffer::{RopeBuffer, detect_indentation, detect_line_ending};
 
 const DEFAULT_EDITOR_CONFIG: &str = include_str!("../default_config/editorconfig");
 
@@ -12,7 +15,32 @@ pub enum AutoIndent {
     None,
     /// Keep the current indentation level when a newline is inserted
     Keep,
-    // TODO: smart indent
+    /// Like `Keep` but a newline between brackets puts the closing bracket on
+    /// its own line, and a closing bracket typed at the start of a line moves
+    /// to the indentation level of the line with the opening bracket
+    Smart,
+}
+
+/// How the path of the file is shown in the title of the pane
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TitleStyle {
+    /// The path the file was opened with
+    Path,
+    /// The absolute path
+    Absolute,
+    /// The path relative to the working directory (if the file is under it)
+    Relative,
+    /// Only the file name
+    Name,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorShape {
+    /// Reverse the colors of the character under the cursor
+    Block,
+    /// Underline the character under the cursor so that it stays readable,
+    /// the terminal cursor is shown as a bar at the primary cursor
+    Bar,
 }
 
 #[derive(Debug)]
@@ -22,13 +50,68 @@ pub struct PaneSettings {
     pub tab_width: usize,
     pub end_of_line: &'static str,
     pub autoindent: AutoIndent,
+    /// Save automatically after the buffer has been left untouched for this long
+    pub autosave: Option<Duration>,
     pub trim_trailing_whitespace: bool,
+    /// Only trim the trailing whitespace of the lines that have been edited
+    pub trim_modified_only: bool,
     pub normalize_end_of_line: bool,
     pub insert_final_newline: bool,
+    /// Insert the closing bracket or quote when an opening one is typed (and
+    /// type over a closing one that is already there)
+    pub auto_close_pairs: bool,
+    /// Backspace in the indentation deletes spaces up to the previous tab stop
+    pub backspace_dedent: bool,
+    /// Backspace at the start of a line joins it with the previous line
+    pub backspace_join: bool,
+    /// Give each cursor its own clip when pasting if there are as many clips
+    /// as cursors, otherwise paste all of the clips at every cursor
+    pub paste_distribute: bool,
+    /// Maximum number of steps kept in the undo history
+    pub undo_levels: usize,
+    /// Characters that are part of words in addition to alphanumerics and `_`,
+    /// `None` means the default for the filetype
+    pub extra_word_chars: Option<String>,
+    /// Highlight other occurrences of the word under the cursor
+    pub highlight_word: bool,
+    /// Highlight other occurrences of the selected text (with a single cursor)
+    pub highlight_occurrences: bool,
+    /// Draw trailing whitespace with a distinct background
+    pub show_trailing_whitespace: bool,
+    /// Draw faint vertical guides at each indentation level
+    pub indent_guides: bool,
+    /// Columns (starting from 1) where a vertical ruler is drawn
+    pub rulers: Vec<usize>,
+    /// Searches ignore case unless the search term contains an uppercase letter
+    pub smartcase: bool,
+    /// Show the lints of the lines with the primary cursor below the lines
+    pub inline_lints: bool,
+    /// Lints less severe than this are not displayed
+    pub lint_level: Severity,
+    /// Show the innermost scope at the primary cursor in the status line
+    pub status_scope: bool,
+    pub title_style: TitleStyle,
+    pub cursor_shape: CursorShape,
+    /// Whether the terminal cursor blinks when it is shown as a bar
+    pub cursor_blink: bool,
     pub debug_scopes: bool,
 }
 
+/// Characters that commonly appear in identifiers of the language
+pub(crate) fn default_extra_word_chars(filetype: &str) -> &'static str {
+    match filetype {
+        "css" | "janet" | "lisp" => "-",
+        "js" | "perl" => "$",
+        _ => "",
+    }
+}
+
 impl PaneSettings {
+    /// Whether saving needs to modify the contents of lines (not counting the final newline)
+    pub(crate) fn transforms_lines_on_save(&self) -> bool {
+        self.trim_trailing_whitespace || self.normalize_end_of_line
+    }
+
     pub(crate) fn indent_as_string(&self) -> String {
         match self.indent_kind {
             IndentKind::Spaces => " ".repeat(self.indent_size),
@@ -49,7 +132,10 @@ impl PaneSettings {
         }
     }
 
-    pub(crate) fn from_editorconfig(path: impl AsRef<Path>) -> Self {
+    /// Settings for the file at `path` from the editorconfig files. The
+    /// indentation and line endings that are not set in a user's
+    /// .editorconfig are detected from `content` when possible.
+    pub(crate) fn from_editorconfig(path: impl AsRef<Path>, content: &RopeBuffer) -> Self {
         use ec4rs::property::*;
         let mut settings = Self::default();
 
@@ -59,7 +145,10 @@ impl PaneSettings {
             .apply_to(&mut props, &path)
             .expect("this should not fail because default editorconfig is checked in build.rs");
 
+        let mut indentation_configured = false;
         if let Ok(override_props) = ec4rs::properties_of(&path) {
+            indentation_configured =
+                override_props.get::<IndentStyle>().is_ok() || override_props.get::<IndentSize>().is_ok();
             let _ = override_props.apply_to(&mut props, &path);
         }
 
@@ -78,6 +167,19 @@ impl PaneSettings {
                 IndentSize::Value(n) => n,
             };
         }
+        if !indentation_configured {
+            match detect_indentation(content) {
+                Some((IndentKind::Tabs, n)) => {
+                    settings.indent_kind = IndentKind::Tabs;
+                    settings.indent_size = n * settings.tab_width;
+                }
+                Some((IndentKind::Spaces, n)) => {
+                    settings.indent_kind = IndentKind::Spaces;
+                    settings.indent_size = n;
+                }
+                None => {}
+            }
+        }
 
         if let Ok(eol) = props.get::<EndOfLine>() {
             settings.end_of_line = match eol {
@@ -86,6 +188,8 @@ impl PaneSettings {
                 EndOfLine::Cr => "\r",
             };
             settings.normalize_end_of_line = true;
+        } else {
+            settings.end_of_line = detect_line_ending(content);
         }
 
         if let Ok(FinalNewline::Value(val)) = props.get::<FinalNewline>() {
@@ -108,9 +212,29 @@ impl std::default::Default for PaneSettings {
             indent_size: 4,
             end_of_line: "\n",
             autoindent: AutoIndent::Keep,
+            autosave: None,
             trim_trailing_whitespace: true,
+            trim_modified_only: false,
             normalize_end_of_line: false,
             insert_final_newline: true,
+            auto_close_pairs: false,
+            backspace_dedent: true,
+            backspace_join: true,
+            paste_distribute: true,
+            undo_levels: 1000,
+            extra_word_chars: None,
+            highlight_word: true,
+            highlight_occurrences: true,
+            show_trailing_whitespace: false,
+            indent_guides: false,
+            rulers: vec![],
+            smartcase: false,
+            inline_lints: true,
+            lint_level: Severity::Info,
+            status_scope: false,
+            title_style: TitleStyle::Path,
+            cursor_shape: CursorShape::Block,
+            cursor_blink: true,
             debug_scopes: false,
         }
     }
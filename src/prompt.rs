@@ -15,9 +15,11 @@ use reedline::{
 
 use crate::app::AppState;
 use crate::cli::FilePathWithOptionalLocation;
+use crate::conflict::ConflictResolution;
 use crate::exec::execute_interactive_command_from_template;
 use crate::prompt_completer::CmdCompleter;
-use crate::{Action, App, MoveTarget, PaneAction};
+use crate::ropebuffer::RopeBuffer;
+use crate::{Action, App, MoveTarget, Pane, PaneAction};
 
 
 fn parse_insertchar(s: &str) -> Option<char> {
@@ -30,20 +32,52 @@ fn parse_insertchar(s: &str) -> Option<char> {
     }
 }
 
-fn parse_target(s: &str) -> Option<MoveTarget> {
+/// Registers are named by a single letter
+fn register_name(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_alphabetic() => Some(c),
+        _ => None,
+    }
+}
+
+/// Parses a `goto` target, negative line numbers count from the end of the
+/// `len_lines` lines (`-1` is the last line)
+fn parse_target(s: &str, len_lines: usize) -> Option<MoveTarget> {
     if let Some(s) = s.strip_prefix("B") {
         let offset = s.parse().ok()?;
         Some(MoveTarget::ByteOffset(offset))
     } else if let Some((line, col)) = s.split_once(":") {
-        let line = line.parse().ok()?;
+        let line = parse_line_number(line, len_lines)?;
         let col = col.parse().ok()?;
         Some(MoveTarget::Location(line, col))
     } else {
-        let line = s.parse().ok()?;
+        let line = parse_line_number(s, len_lines)?;
         Some(MoveTarget::Location(line, NonZero::<usize>::MIN))
     }
 }
 
+/// The number of lines that negative `goto` targets count from, a line break
+/// at the end of the buffer doesn't start another line
+fn goto_line_count(content: &RopeBuffer) -> usize {
+    let len_lines = content.len_lines();
+    if len_lines > 1 && content.line_to_byte(len_lines - 1).0 == content.len_bytes() {
+        len_lines - 1
+    } else {
+        len_lines
+    }
+}
+
+fn parse_line_number(s: &str, len_lines: usize) -> Option<NonZero<usize>> {
+    match s.strip_prefix('-') {
+        Some(from_end) => {
+            let from_end = from_end.parse::<NonZero<usize>>().ok()?;
+            NonZero::new(len_lines.saturating_sub(from_end.get()) + 1)
+        }
+        None => s.parse().ok(),
+    }
+}
+
 impl App {
     pub fn handle_command(&mut self, s: &str) {
         self.clear_status_msg();
@@ -54,54 +88,57 @@ impl App {
         let (command, arg) = s.split_once(' ').unwrap_or((s, ""));
         match command {
             "exit" | "quit" | "q" | ":q" => self.enqueue(Action::Quit),
+            "append" => match arg.trim() {
+                "" => self.inform("Usage: append CHAR".into()),
+                suffix => {
+                    let count = self.current_pane_mut().append_to_lines(suffix);
+                    self.inform(format!("Appended to {count} line{}", if count == 1 { "" } else { "s" }));
+                }
+            },
+            "browse" => {
+                let dir = match arg.trim() {
+                    "" => match self.current_pane().path.as_ref().and_then(|path| path.parent()) {
+                        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                        _ => ".".into(),
+                    },
+                    arg => crate::expand_path(arg),
+                };
+                self.browse_directory(&dir);
+            }
             "close" => self.enqueue(Action::ClosePane),
+            "copy" => match arg.trim() {
+                "" => self.enqueue(Action::Copy),
+                arg => match register_name(arg) {
+                    Some(register) => self.enqueue(Action::CopyToRegister(register)),
+                    None => self.inform("Usage: copy [REGISTER]".into()),
+                },
+            },
+            "copypath" => match arg.trim() {
+                "" => self.copy_current_path(false),
+                "abs" => self.copy_current_path(true),
+                _ => self.inform("Usage: copypath [abs]".into()),
+            },
+            "diff" => self.show_diff(arg.trim()),
             "find" => self.enqueue(Action::HandledByPane(PaneAction::Find(arg.to_string()))),
             "goto" => {
-                if let Some(target) = parse_target(arg) {
+                if let Some(action) = match arg.trim() {
+                    "top" => Some(PaneAction::CursorToViewportTop),
+                    "middle" => Some(PaneAction::CursorToViewportMiddle),
+                    "bottom" => Some(PaneAction::CursorToViewportBottom),
+                    _ => None,
+                } {
+                    self.enqueue(Action::HandledByPane(action));
+                } else if let Some(target) = parse_target(arg, goto_line_count(&self.current_pane().content)) {
                     self.enqueue(Action::HandledByPane(PaneAction::MoveTo(target)));
                 } else {
                     self.inform(format!("goto error: {arg:?} is not a valid target"));
                 }
             }
             "to" => {
-                if let Some(reps) = arg.strip_prefix('*').and_then(|n| n.parse::<usize>().ok()) {
-                    self.current_pane_mut().transform_selections(|s| Some(s.repeat(reps)));
-                } else if arg == "upper" {
-                    self.current_pane_mut().transform_selections(|s| Some(s.to_uppercase()));
-                } else if arg == "lower" {
-                    self.current_pane_mut().transform_selections(|s| Some(s.to_lowercase()));
-                } else if arg == "list" {
-                    self.current_pane_mut().transform_selections(|s| {
-                        let v = s.split_ascii_whitespace().collect::<Vec<_>>();
-                        Some(format!("[{}]", v.join(", ")))
-                    });
-                } else if arg == "quoted" {
-                    self.current_pane_mut().transform_selections(|s| {
-                        let mut transformed = String::new();
-                        let mut in_word = false;
-                        for c in s.chars() {
-                            if c.is_ascii_whitespace() {
-                                if in_word {
-                                    transformed.push('"');
-                                }
-                                transformed.push(c);
-                                in_word = false;
-                            } else {
-                                if !in_word {
-                                    transformed.push('"');
-                                }
-                                if c == '"' || c == '\\' {
-                                    transformed.push('\\');
-                                }
-                                transformed.push(c);
-                                in_word = true;
-                            }
-                        }
-                        if in_word {
-                            transformed.push('"');
-                        }
-                        Some(transformed)
-                    });
+                if arg == "again" {
+                    self.repeat_transform();
+                } else if let Some(transform) = crate::editing::Transform::parse(arg) {
+                    self.transform_selections(transform);
                 } else {
                     self.inform(format!("to error: {arg:?} is not a valid transformation"));
                 }
@@ -116,7 +153,7 @@ impl App {
                                 let fname = format!("{ftype}.sublime-syntax");
                                 let fpath = syntax_dir.join(fname);
                                 let pane = self.open_file_in_new_pane(&FilePathWithOptionalLocation::from(fpath.clone()));
-                                if pane.path.is_some() && pane.content.len_bytes() == 0 {
+                                if let Some(pane) = pane.filter(|pane| pane.path.is_some() && pane.content.len_bytes() == 0) {
                                     let template = include_str!("../default_config/template.sublime-syntax").replace("FTYPE", ftype);
                                     pane.content = crate::ropebuffer::RopeBuffer::from_str(&template);
                                 }
@@ -127,10 +164,20 @@ impl App {
                             self.inform("Usage: edit syntax FTYPE".into());
                         }
                     }
+                    Some("commands") => {
+                        if let Some(fpath) = self.custom_commands_file() {
+                            let pane = self.open_file_in_new_pane(&FilePathWithOptionalLocation::from(fpath));
+                            if let Some(pane) = pane.filter(|pane| pane.path.is_some() && pane.content.len_bytes() == 0) {
+                                pane.content = crate::ropebuffer::RopeBuffer::from_str(crate::custom_commands::COMMANDS_FILE_TEMPLATE);
+                            }
+                        } else {
+                            self.inform("edit error: no config directory".into());
+                        }
+                    }
                     Some("linters") => {
                         if let Some(fpath) = self.linter_script_file() {
                             let pane = self.open_file_in_new_pane(&FilePathWithOptionalLocation::from(fpath));
-                            if pane.path.is_some() && pane.content.len_bytes() == 0 {
+                            if pane.is_some_and(|pane| pane.path.is_some() && pane.content.len_bytes() == 0) {
                                 self.enqueue(Action::HandledByPane(PaneAction::Insert(crate::linter::DEFAULT_LINTER_SCRIPT.to_string())));
                                 let loc = MoveTarget::Location(NonZero::try_from(32).unwrap(), NonZero::try_from(5).unwrap());
                                 self.enqueue(Action::HandledByPane(PaneAction::MoveTo(loc)));
@@ -144,6 +191,13 @@ impl App {
                     }
                 }
             }
+            "enclose" => match shlex::split(arg).as_deref() {
+                Some([prefix, suffix]) => {
+                    let count = self.current_pane_mut().enclose_lines(prefix, suffix);
+                    self.inform(format!("Enclosed {count} line{}", if count == 1 { "" } else { "s" }));
+                }
+                _ => self.inform("Usage: enclose PREFIX SUFFIX".into()),
+            },
             "exec" | "x" => {
                 let arg = arg.trim();
 
@@ -173,19 +227,8 @@ impl App {
                     }
                 };
 
-                let fpath = match &self.current_pane().path {
-                    None if template.contains("%f") => {
-                        self.inform("exec error: file needs to be saved".into());
-                        return
-                    }
-                    Some(path) => path,
-                    None => std::path::Path::new(""),
-                };
-
-                match execute_interactive_command_from_template(template, fpath) {
-                    Ok(()) => {}
-                    Err(err) => self.inform(format!("{err}"))
-                }
+                let template = template.to_string();
+                self.exec_template(&template);
             }
             "lint" => {
                 if self.current_pane().modified {
@@ -214,6 +257,7 @@ impl App {
                                 }
                             }
                         }
+                        self.update_lint_lists();
                         self.inform("linted".into());
                     }
                     Err(err) => {
@@ -221,6 +265,8 @@ impl App {
                     }
                 }
             }
+            "lints" => self.show_lint_list(),
+            "gotofile" | "gf" => self.open_path_under_cursor(),
             "insertchar" | "c" => {
                 let mut out = String::new();
                 let mut success = true;
@@ -237,9 +283,26 @@ impl App {
                     self.enqueue(Action::HandledByPane(PaneAction::Insert(out)))
                 }
             }
+            #[cfg(feature = "lsp")]
+            "lsp" => self.lsp_command(arg.trim()),
+            "nextconflict" => self.enqueue(Action::HandledByPane(PaneAction::NextConflict)),
+            "swap" => self.enqueue(Action::HandledByPane(PaneAction::SwapSelections)),
             "open" => {
+                let (arg, force) = match arg.trim_start().strip_prefix("-f ") {
+                    Some(rest) => (rest, true),
+                    None => (arg, false),
+                };
                 let path = FilePathWithOptionalLocation::parse_from_str(arg, true);
-                self.enqueue(Action::Open(path));
+                self.enqueue(Action::Open { path, force });
+            }
+            "scratch" => {
+                let mut pane = Pane::empty();
+                let ft = arg.trim();
+                if !ft.is_empty() && pane.set_filetype(ft, self.highlighting.clone()).is_err() {
+                    self.inform(format!("scratch error: unknown filetype {ft}"));
+                    return
+                }
+                self.switch_to_new_pane(pane);
             }
             "set" => {
                 if let Some((key, value)) = arg.trim_start().split_once(' ') {
@@ -248,26 +311,116 @@ impl App {
                     self.inform("set error: correct usage is 'set KEY VALUE'".into());
                 }
             }
+            "surround" => {
+                let mut chars = arg.trim().chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => self.enqueue(Action::HandledByPane(PaneAction::Surround(c))),
+                    _ => self.inform("Usage: surround CHAR".into()),
+                }
+            }
+            "remote" => self.open_remote_file(arg.trim()),
+            "resolve" => {
+                let resolution = match arg.trim() {
+                    "ours" => ConflictResolution::Ours,
+                    "theirs" => ConflictResolution::Theirs,
+                    "both" => ConflictResolution::Both,
+                    _ => {
+                        self.inform("Usage: resolve (ours|theirs|both)".into());
+                        return
+                    }
+                };
+                self.enqueue(Action::HandledByPane(PaneAction::ResolveConflict(resolution)));
+            }
+            "replace" => match shlex::split(arg).as_deref() {
+                Some([old, new]) if !old.is_empty() => {
+                    let count = self.current_pane_mut().replace(old, new);
+                    self.inform(format!("Replaced {count} occurrence{}", if count == 1 { "" } else { "s" }));
+                }
+                _ => self.inform("Usage: replace OLD NEW".into()),
+            },
+            "recover" => self.current_pane_mut().recover_from_swap_file(),
+            "removeblank" => {
+                let count = self.current_pane_mut().remove_blank_lines();
+                self.inform(format!("Removed {count} line{}", if count == 1 { "" } else { "s" }));
+            }
+            "revert" | "e!" => self.revert_current_pane(),
             "save" => {
+                let (arg, force) = match arg.trim_start().strip_prefix("-f") {
+                    Some(rest) if rest.is_empty() || rest.starts_with(' ') => (rest.trim_start(), true),
+                    _ => (arg, false),
+                };
+                if force {
+                    self.current_pane_mut().lossy = false;
+                }
                 if arg.is_empty() {
                     self.enqueue(Action::Save);
                 } else {
                     self.enqueue(Action::SaveAs(crate::expand_path(arg)));
                 }
             }
+            "openurl" => {
+                match self.current_pane().url_under_cursor() {
+                    Some(url) => {
+                        if let Err(err) = crate::exec::open_with_default_application(&url) {
+                            self.inform(err.to_string());
+                        } else {
+                            self.inform(format!("Opened {url}"));
+                        }
+                    }
+                    None => self.inform("openurl error: no URL under cursor".into()),
+                }
+            }
             "pane" => {
                 self.enqueue(Action::NewPane);
                 if !arg.is_empty() {
                     let path = FilePathWithOptionalLocation::parse_from_str(arg, true);
-                    self.enqueue(Action::Open(path));
+                    self.enqueue(Action::Open { path, force: false });
                 }
             }
-            _ => self.inform(format!("Unknown command '{command}'")),
+            "paste" => match arg.trim() {
+                "" => self.list_clipboard_history(),
+                arg => match (arg.parse(), register_name(arg)) {
+                    (Ok(index), _) => self.enqueue(Action::PasteFromHistory(index)),
+                    (_, Some(register)) => self.enqueue(Action::PasteFromRegister(register)),
+                    _ => self.inform("Usage: paste [N|REGISTER]".into()),
+                },
+            },
+            "pasteprimary" => self.enqueue(Action::PastePrimary),
+            "uniq" => match arg.trim() {
+                flag @ ("" | "-g") => {
+                    let count = self.current_pane_mut().remove_duplicate_lines(flag == "-g");
+                    self.inform(format!("Removed {count} line{}", if count == 1 { "" } else { "s" }));
+                }
+                _ => self.inform("Usage: uniq [-g]".into()),
+            },
+            _ => match self.custom_commands.get(command).cloned() {
+                Some(custom) => self.run_custom_command(&custom, arg.trim()),
+                None => self.inform(format!("Unknown command '{command}'")),
+            },
+        }
+    }
+
+    /// Runs a shell command template interactively, `%f` is replaced with the
+    /// path of the current file
+    pub(crate) fn exec_template(&mut self, template: &str) {
+        let fpath = match &self.current_pane().path {
+            None if template.contains("%f") => {
+                self.inform("exec error: file needs to be saved".into());
+                return
+            }
+            Some(path) => path,
+            None => std::path::Path::new(""),
+        };
+        if let Err(err) = execute_interactive_command_from_template(template, fpath) {
+            self.inform(format!("{err}"));
         }
     }
 
     pub fn command_prompt_with(&mut self, stub: Option<String>, completer: CmdCompleter) {
         self.state = AppState::InPrompt;
+        if let Ok(wsize) = crossterm::terminal::window_size() {
+            let _ = self.render(&mut std::io::stdout(), &wsize);
+        }
         let history = self.prompt_history_file()
             .and_then(|hist_file| FileBackedHistory::with_file(100, hist_file).ok())
             .unwrap_or_else(|| FileBackedHistory::new(100).expect("creating in-memory history should never fail"));
@@ -387,3 +540,47 @@ fn get_command(stub: Option<String>, completer: CmdCompleter, history: FileBacke
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("3", Some((3, 1)))]
+    #[case("3:7", Some((3, 7)))]
+    #[case("-1", Some((10, 1)))]
+    #[case("-5", Some((6, 1)))]
+    #[case("-2:4", Some((9, 4)))]
+    #[case("-10", Some((1, 1)))]
+    #[case("-50", Some((1, 1)))]
+    #[case("0", None)]
+    #[case("-0", None)]
+    #[case("-", None)]
+    #[case("3:", None)]
+    #[case("abc", None)]
+    fn parse_goto_target(#[case] s: &str, #[case] expected: Option<(usize, usize)>) {
+        let location = parse_target(s, 10).map(|target| match target {
+            MoveTarget::Location(line, col) => (line.get(), col.get()),
+            other => panic!("unexpected target {other:?}"),
+        });
+        assert_eq!(location, expected);
+    }
+
+    #[rstest]
+    #[case("a\nb\n", 2)]
+    #[case("a\nb", 2)]
+    #[case("a\r\nb\r\n", 2)]
+    #[case("a\n\n", 2)]
+    #[case("\n", 1)]
+    #[case("", 1)]
+    fn trailing_line_break_is_not_a_goto_line(#[case] text: &str, #[case] expected: usize) {
+        assert_eq!(goto_line_count(&RopeBuffer::from_str(text)), expected);
+    }
+
+    #[test]
+    fn parse_byte_offset_target() {
+        assert!(matches!(parse_target("B42", 10), Some(MoveTarget::ByteOffset(42))));
+        assert!(parse_target("B-1", 10).is_none());
+    }
+}
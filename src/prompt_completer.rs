@@ -1,10 +1,12 @@
+use std::borrow::Cow;
+
 #[derive(Clone)]
 pub struct CmdCompleter {
     cmds: Vec<Cmd>
 }
 
 impl CmdCompleter {
-    pub fn make_completer(filetypes: &[&str]) -> CmdCompleter {
+    pub fn make_completer(filetypes: &[&str], themes: &[&str]) -> CmdCompleter {
         macro_rules! argchoice {
             ($($x:expr),* $(,)?) => {
                 Arg::OneOf(vec![$($x.into()),*])
@@ -18,20 +20,50 @@ impl CmdCompleter {
         }
 
         let filetypes: Vec<Arg> = filetypes.iter().map(|s| Arg::Literal(s.to_string())).collect();
+        let themes: Vec<Arg> = themes
+            .iter()
+            .chain(&["toggle"])
+            .map(|s| Arg::Literal(s.to_string()))
+            .collect();
 
         CmdCompleter {
             cmds: vec![
+                CmdBuilder::new("append")
+                    .args(argchoice![";", ","])
+                    .help("append CHAR")
+                    .build(),
+                CmdBuilder::new("browse")
+                    .args(Arg::File)
+                    .help("browse [DIR]")
+                    .build(),
                 CmdBuilder::new("close")
                     .help("close")
                     .build(),
+                CmdBuilder::new("copy")
+                    .args(Arg::String)
+                    .help("copy [REGISTER]")
+                    .build(),
+                CmdBuilder::new("copypath")
+                    .args(argchoice!["abs"])
+                    .help("copypath [abs]")
+                    .build(),
+                CmdBuilder::new("diff")
+                    .args(Arg::File)
+                    .help("diff PANE_NUMBER | diff FILE")
+                    .build(),
                 CmdBuilder::new("edit")
                     .args(
                         argchoice![
+                            "commands",
                             "linters",
                             argseq!["syntax", Arg::OneOf(filetypes.clone())]
                         ]
                     )
-                    .help("edit linters | edit syntax FILETYPE")
+                    .help("edit commands | edit linters | edit syntax FILETYPE")
+                    .build(),
+                CmdBuilder::new("enclose")
+                    .args(argchoice!["\"", "'"])
+                    .help("enclose PREFIX SUFFIX")
                     .build(),
                 CmdBuilder::new("exec").alias("x")
                     .args(Arg::String)
@@ -43,7 +75,10 @@ impl CmdCompleter {
                     .build(),
                 CmdBuilder::new("goto")
                     .args(Arg::String)
-                    .help("goto LINE[:COL]")
+                    .help("goto (LINE[:COL]|-N|top|middle|bottom)")
+                    .build(),
+                CmdBuilder::new("gotofile").alias("gf")
+                    .help("gotofile")
                     .build(),
                 CmdBuilder::new("insertchar").alias("c")
                     .args(Arg::String)
@@ -52,37 +87,120 @@ impl CmdCompleter {
                 CmdBuilder::new("lint")
                     .help("lint")
                     .build(),
+                CmdBuilder::new("lints")
+                    .help("lints")
+                    .build(),
+                #[cfg(feature = "lsp")]
+                CmdBuilder::new("lsp")
+                    .args(argchoice!["start", "stop"])
+                    .help("lsp start [COMMAND] | lsp stop")
+                    .build(),
+                CmdBuilder::new("nextconflict")
+                    .help("nextconflict")
+                    .build(),
                 CmdBuilder::new("open")
                     .args(Arg::File)
-                    .help("open FILE")
+                    .help("open [-f] FILE")
+                    .build(),
+                CmdBuilder::new("openurl")
+                    .help("openurl")
                     .build(),
                 CmdBuilder::new("pane")
                     .args(Arg::File)
                     .help("pane [FILE]")
                     .build(),
+                CmdBuilder::new("paste")
+                    .args(Arg::String)
+                    .help("paste [N|REGISTER]")
+                    .build(),
+                CmdBuilder::new("pasteprimary")
+                    .help("pasteprimary")
+                    .build(),
+                CmdBuilder::new("recover")
+                    .help("recover")
+                    .build(),
+                CmdBuilder::new("remote")
+                    .args(Arg::String)
+                    .help("remote [USER@]HOST:PATH")
+                    .build(),
+                CmdBuilder::new("removeblank")
+                    .help("removeblank")
+                    .build(),
+                CmdBuilder::new("replace")
+                    .args(Arg::String)
+                    .help("replace OLD NEW")
+                    .build(),
+                CmdBuilder::new("resolve")
+                    .args(argchoice!["ours", "theirs", "both"])
+                    .help("resolve (ours|theirs|both)")
+                    .build(),
+                CmdBuilder::new("revert").alias("e!")
+                    .help("revert")
+                    .build(),
                 CmdBuilder::new("save")
                     .args(Arg::File)
-                    .help("save [FILE]")
+                    .help("save [-f] [FILE]")
+                    .build(),
+                CmdBuilder::new("scratch")
+                    .args(Arg::OneOf(filetypes.clone()))
+                    .help("scratch [FILETYPE]")
                     .build(),
                 CmdBuilder::new("set")
                     .args(
                         argchoice![
-                            argseq!["autoindent", argchoice!["off", "keep"]],
+                            argseq!["auto_close", argchoice!["on", "off"]],
+                            argseq!["autoindent", argchoice!["off", "keep", "smart"]],
+                            argseq!["autosave", argchoice!["off", "10", "30", "60"]],
+                            argseq!["backspace_dedent", argchoice!["on", "off"]],
+                            argseq!["backspace_join", argchoice!["on", "off"]],
+                            argseq!["clipboard", argchoice!["osc52", "system", "both"]],
+                            argseq!["clipboard_history", argchoice!["10", "50", "100"]],
+                            argseq!["clipboard_joiner", argchoice!["\\n", ",\\s", "\\t"]],
+                            argseq!["cursor", argchoice!["block", "bar"]],
+                            argseq!["cursor_blink", argchoice!["on", "off"]],
                             argseq!["debug", argchoice!["off", "scopes"]],
                             argseq!["eol", argchoice!["lf", "crlf", "cr"]],
                             argseq!["ftype", Arg::OneOf(filetypes)],
+                            argseq!["highlight_max_line_length", argchoice!["0", "1024", "4096"]],
+                            argseq!["highlight_occurrences", argchoice!["on", "off"]],
+                            argseq!["highlight_word", argchoice!["on", "off"]],
+                            argseq!["indent_guides", argchoice!["on", "off"]],
                             argseq!["indent_size", argchoice!["2", "4", "8"]],
                             argseq!["indent_style", argchoice!["spaces", "tabs"]],
+                            argseq!["inline_lints", argchoice!["on", "off"]],
                             argseq!["insert_final_newline", argchoice!["on", "off"]],
+                            argseq!["lint_level", argchoice!["error", "warning", "info"]],
+                            argseq!["max_file_size", argchoice!["0", "256", "1024"]],
                             argseq!["normalize_end_of_line", argchoice!["on", "off"]],
+                            argseq!["paste_distribute", argchoice!["on", "off"]],
+                            argseq!["ruler", argchoice!["off", "80", "80,120"]],
+                            argseq!["smartcase", argchoice!["on", "off"]],
+                            argseq!["status_scope", argchoice!["on", "off"]],
+                            argseq!["theme", Arg::OneOf(themes)],
+                            argseq!["title", argchoice!["path", "absolute", "relative", "name"]],
+                            argseq!["trim_modified_only", argchoice!["on", "off"]],
                             argseq!["trim_trailing_whitespace", argchoice!["on", "off"]],
+                            argseq!["undo_levels", argchoice!["100", "1000", "10000"]],
+                            argseq!["whitespace", argchoice!["on", "off"]],
+                            argseq!["word_chars", argchoice!["default", "none", "-", "$", "-$"]],
                         ]
                     )
                     .help("set KEY VALUE")
                     .build(),
+                CmdBuilder::new("surround")
+                    .args(argchoice!["(", "[", "{", "<", "'", "\""])
+                    .help("surround CHAR")
+                    .build(),
+                CmdBuilder::new("swap")
+                    .help("swap")
+                    .build(),
                 CmdBuilder::new("to")
-                    .args(argchoice!["lower", "upper", "quoted", "list"])
-                    .help("to (lower|upper|quoted|list)")
+                    .args(argchoice!["lower", "upper", "quoted", "list", argseq!["align", argchoice!["=", ":", ","]], "again"])
+                    .help("to (lower|upper|quoted|list|align DELIMITER|*N|again)")
+                    .build(),
+                CmdBuilder::new("uniq")
+                    .args(argchoice!["-g"])
+                    .help("uniq [-g]")
                     .build(),
                 CmdBuilder::new("quit").alias(":q").alias("exit").alias("q")
                     .help("quit")
@@ -90,6 +208,16 @@ impl CmdCompleter {
             ]
         }
     }
+
+    /// Whether `name` is the name or an alias of a known command
+    pub fn has_command(&self, name: &str) -> bool {
+        self.cmds.iter().any(|cmd| cmd.has_alias(name))
+    }
+
+    /// Adds a user defined command that takes any arguments
+    pub fn add_custom_command(&mut self, name: &str, help: String) {
+        self.cmds.push(CmdBuilder::new(name.to_string()).args(Arg::String).help(help).build());
+    }
 }
 
 impl reedline::Completer for CmdCompleter {
@@ -262,14 +390,14 @@ impl Arg {
 
 #[derive(Default, Clone)]
 pub struct Cmd {
-    prefixes: Vec<&'static str>,
+    prefixes: Vec<Cow<'static, str>>,
     args: Arg,
-    help: &'static str,
+    help: Cow<'static, str>,
 }
 
 impl Cmd {
     fn has_alias(&self, alias: &str) -> bool {
-        self.prefixes.contains(&alias)
+        self.prefixes.iter().any(|prefix| prefix == alias)
     }
 
     fn takes_args(&self) -> bool {
@@ -279,8 +407,8 @@ impl Cmd {
         }
     }
 
-    fn primary_name(&self) -> &'static str {
-        self.prefixes[0]
+    fn primary_name(&self) -> &str {
+        &self.prefixes[0]
     }
 
     fn arg_complete(&self, s: &str, s_offset: usize) -> Vec<reedline::Suggestion> {
@@ -297,12 +425,12 @@ struct CmdBuilder {
 }
 
 impl CmdBuilder {
-    fn new(prefix: &'static str) -> Self {
-        Self { cmd: Cmd { prefixes: vec![prefix], ..Default::default() } }
+    fn new(prefix: impl Into<Cow<'static, str>>) -> Self {
+        Self { cmd: Cmd { prefixes: vec![prefix.into()], ..Default::default() } }
     }
 
     fn alias(mut self, prefix: &'static str) -> Self {
-        self.cmd.prefixes.push(prefix);
+        self.cmd.prefixes.push(prefix.into());
         self
     }
 
@@ -311,8 +439,8 @@ impl CmdBuilder {
         self
     }
 
-    fn help(mut self, help: &'static str) -> Self {
-        self.cmd.help = help;
+    fn help(mut self, help: impl Into<Cow<'static, str>>) -> Self {
+        self.cmd.help = help.into();
         self
     }
 
@@ -0,0 +1,108 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A file on another machine in the `[user@]host:path` form understood by scp.
+///
+/// The file is copied to a local temporary file for editing and copied back
+/// when saving. scp runs in batch mode so only non-interactive authentication
+/// (eg. keys loaded in ssh-agent) works, a password prompt would mess up the
+/// terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemotePath {
+    host: String,
+    path: String,
+}
+
+impl RemotePath {
+    pub fn parse(s: &str) -> Option<Self> {
+        let (host, path) = s.split_once(':')?;
+        if host.is_empty() || host.contains('/') || path.is_empty() {
+            return None
+        }
+        Some(RemotePath { host: host.to_string(), path: path.to_string() })
+    }
+
+    fn file_name(&self) -> &str {
+        self.path.rsplit('/').find(|part| !part.is_empty()).unwrap_or("remote")
+    }
+
+    /// Copies the remote file to a local temporary file
+    pub fn fetch(&self) -> std::io::Result<LocalCopy> {
+        let local = LocalCopy::new(self.file_name())?;
+        scp(&self.to_string(), &local.path.to_string_lossy())?;
+        Ok(local)
+    }
+
+    /// Copies `local` over the remote file
+    pub fn upload(&self, local: &Path) -> std::io::Result<()> {
+        scp(&local.to_string_lossy(), &self.to_string())
+    }
+}
+
+impl Display for RemotePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.host, self.path)
+    }
+}
+
+/// Path of the local copy of a remote file in a temporary directory of its
+/// own. The directory is removed when this is dropped (when the pane is
+/// closed or the editor exits).
+#[derive(Debug)]
+pub struct LocalCopy {
+    // only held to remove the directory on drop
+    _dir: tempfile::TempDir,
+    path: PathBuf,
+}
+
+impl LocalCopy {
+    /// The file name is kept the same so that filetype detection works as usual
+    fn new(file_name: &str) -> std::io::Result<Self> {
+        let dir = tempfile::Builder::new().prefix("bad-remote-").tempdir()?;
+        let path = dir.path().join(file_name);
+        Ok(LocalCopy { _dir: dir, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn scp(from: &str, to: &str) -> std::io::Result<()> {
+    let output = Command::new("scp")
+        .args(["-q", "-o", "BatchMode=yes", "--", from, to])
+        .stdin(std::process::Stdio::null())
+        .output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(std::io::Error::other(format!("scp failed: {}", stderr.trim())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_remote_paths() {
+        let remote = RemotePath::parse("user@example.com:/etc/hosts").unwrap();
+        assert_eq!(remote.to_string(), "user@example.com:/etc/hosts");
+        assert_eq!(remote.file_name(), "hosts");
+        assert_eq!(RemotePath::parse("host:notes.md").unwrap().file_name(), "notes.md");
+        assert_eq!(RemotePath::parse("./local:file"), None);
+        assert_eq!(RemotePath::parse("host:"), None);
+        assert_eq!(RemotePath::parse("no-colon"), None);
+    }
+
+    #[test]
+    fn local_copy_is_removed_when_dropped() {
+        let local = LocalCopy::new("hosts").unwrap();
+        std::fs::write(local.path(), "127.0.0.1 localhost\n").unwrap();
+        let dir = local.path().parent().unwrap().to_path_buf();
+        drop(local);
+        assert!(!dir.exists());
+    }
+}
@@ -1,7 +1,7 @@
 use std::time::Instant;
 
 use crossterm::QueueableCommand;
-use crossterm::cursor::{MoveTo, MoveToNextLine};
+use crossterm::cursor::{MoveTo, MoveToNextLine, SetCursorStyle};
 use crossterm::style::{Color, ContentStyle, Print, PrintStyledContent, StyledContent, Stylize};
 use crossterm::terminal::{
     BeginSynchronizedUpdate,
@@ -10,12 +10,20 @@ use crossterm::terminal::{
     EndSynchronizedUpdate,
     WindowSize,
 };
-use syntect::highlighting::{FontStyle as SyntectFontStyle, Style as SyntectStyle};
+use syntect::highlighting::{
+    Color as SyntectColor,
+    FontStyle as SyntectFontStyle,
+    Style as SyntectStyle,
+    ThemeSettings,
+};
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
 
 use crate::completer::SuggestionMenu;
-use crate::highlighter::BadHighlighter;
+use crate::conflict::ConflictRegion;
+use crate::highlighter::{BadHighlighter, ThemeVariant};
+use crate::app::AppState;
+use crate::pane_settings::CursorShape;
 use crate::{App, ByteOffset};
 
 fn to_crossterm_style(syntect_style: SyntectStyle) -> ContentStyle {
@@ -84,11 +92,25 @@ fn replacement_symbol(g: &str) -> Option<String> {
 struct RenderingContext {
     n_selections: usize,
     is_cursor: bool,
+    cursor_shape: CursorShape,
+    /// Column where the primary cursor is on the line being rendered (if it
+    /// is a caret without a selection)
+    primary_cursor_at: Option<usize>,
     current_column: usize,
     visible_from_column: usize,
     available_columns: usize,
     tab_width: usize,
     token_style: ContentStyle,
+    selection_style: ContentStyle,
+    /// Style of the placeholders shown for invisible characters
+    escaped_style: ContentStyle,
+    /// Indent size and style of the indent guides drawn on blank columns that
+    /// are a multiple of the indent size, set while in the leading whitespace
+    indent_guide: Option<(usize, ContentStyle)>,
+    /// Columns (starting from 1) of the rulers
+    rulers: Vec<usize>,
+    /// Background of the rulers, set while nothing else changes the background
+    ruler_bg: Option<Color>,
     queue: Vec<(usize, usize, StyledContent<String>)>,
 }
 impl RenderingContext {
@@ -96,16 +118,59 @@ impl RenderingContext {
         self.n_selections > 0
     }
 
-    fn push(&mut self, g: StyledContent<String>) {
+    fn cursor_style(&self, style: ContentStyle) -> ContentStyle {
+        match self.cursor_shape {
+            CursorShape::Block => style.reverse(),
+            CursorShape::Bar => style.underlined(),
+        }
+    }
+
+    fn push(&mut self, mut g: StyledContent<String>) {
         let width = UnicodeWidthStr::width(g.content().as_str());
+        if let Some(bg) = self.ruler_bg.filter(|_| !self.is_selection() && self.rulers.contains(&(self.current_column + 1))) {
+            g = g.on(bg);
+        }
         self.queue.push((self.current_column, width, g));
         self.current_column += width;
     }
+
+    /// Pushes a blank column, or an indent guide if one belongs on it
+    fn push_blank(&mut self) {
+        match self.indent_guide {
+            Some((indent_size, style)) if self.current_column.is_multiple_of(indent_size) => self.push(style.apply(INDENT_GUIDE.into())),
+            _ => self.push(self.token_style.apply(" ".into())),
+        }
+    }
+}
+
+/// Returns the screen columns (relative to the start of the text area) of the
+/// rulers that are visible when the line is scrolled to `visible_from_column`
+fn ruler_screen_columns(rulers: &[usize], visible_from_column: usize, available_columns: usize) -> impl Iterator<Item = usize> {
+    rulers
+        .iter()
+        .filter_map(move |ruler| ruler.checked_sub(1)?.checked_sub(visible_from_column))
+        .filter(move |&column| column < available_columns)
+}
+
+/// Returns how many bytes from the start of a line that is too long to be
+/// highlighted are rendered, the rest of the line can't end up on the screen
+fn long_line_bytes_needed(line_len: usize, cursor_offset_in_line: usize, available_columns: usize) -> usize {
+    (cursor_offset_in_line + MAX_BYTES_PER_COLUMN * (available_columns + 1)).min(line_len)
+}
+
+/// Returns the byte index where the leading whitespace of `line` ends and the
+/// byte index where its trailing whitespace starts (not counting the line break).
+/// A line with nothing but whitespace is all trailing whitespace.
+fn whitespace_bounds(line: &str) -> (usize, usize) {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let trailing_start = line.trim_end().len();
+    let leading_end = line.len() - line.trim_start().len();
+    (leading_end, trailing_start)
 }
 
 fn grapheme_representation(g: &str, ctx: &mut RenderingContext) {
-    let sel_style = ContentStyle::new().with(SELECTION_FG).on(SELECTION_BG);
-    let escaped_style = ContentStyle::new().with(DEFAULT_FG).on(BLUEISH);
+    let sel_style = ctx.selection_style;
+    let escaped_style = ctx.escaped_style;
 
     if g == "\t" {
         if ctx.tab_width > 0 {
@@ -117,13 +182,13 @@ fn grapheme_representation(g: &str, ctx: &mut RenderingContext) {
                     ctx.push(sel_style.apply(" ".into()));
                 }
             } else if ctx.is_cursor {
-                ctx.push(ctx.token_style.reverse().apply(" ".to_string()));
+                ctx.push(ctx.cursor_style(ctx.token_style).apply(" ".to_string()));
                 for _ in 1..w {
-                    ctx.push(ctx.token_style.apply(" ".into()));
+                    ctx.push_blank();
                 }
             } else {
                 for _ in 0..w {
-                    ctx.push(ctx.token_style.apply(" ".into()));
+                    ctx.push_blank();
                 }
             }
         }
@@ -131,33 +196,137 @@ fn grapheme_representation(g: &str, ctx: &mut RenderingContext) {
         if ctx.is_selection() {
             ctx.push(sel_style.with(BLUEISH).apply(glyph.into()));
         } else if ctx.is_cursor {
-            ctx.push(ctx.token_style.reverse().apply(" ".into()));
+            ctx.push(ctx.cursor_style(ctx.token_style).apply(" ".into()));
         }
     } else if let Some(disp) = replacement_symbol(g) {
         if ctx.is_selection() {
             ctx.push(sel_style.with(BLUEISH).apply(disp));
         } else if ctx.is_cursor {
-            ctx.push(escaped_style.reverse().apply(disp));
+            ctx.push(ctx.cursor_style(escaped_style).apply(disp));
         } else {
             ctx.push(escaped_style.apply(disp));
         }
     } else if ctx.is_selection() {
         ctx.push(sel_style.apply(g.into()));
     } else if ctx.is_cursor {
-        ctx.push(ctx.token_style.reverse().apply(g.into()));
+        ctx.push(ctx.cursor_style(ctx.token_style).apply(g.into()));
+    } else if g == " " {
+        ctx.push_blank();
     } else {
         ctx.push(ctx.token_style.apply(g.into()));
     }
 }
 
 const BLUEISH: Color = Color::Rgb { r: 0x4a, g: 0x54, b: 0x6e };
-const DEFAULT_FG: Color = Color::White;
-const DEFAULT_BG: Color = Color::Rgb { r: 0x1a, g: 0x1a, b: 0x1a };
-const SELECTION_FG: Color = Color::Black;
-const SELECTION_BG: Color = Color::Rgb { r: 0x88, g: 0xff, b: 0xc5 };
-const LIGHT_GREY: Color = Color::Rgb { r: 0xaa, g: 0xaa, b: 0xaa };
-const SLIGHTLY_LIGHTER_BG: Color = Color::Rgb { r: 0x1e, g: 0x1e, b: 0x1e };
-const LIGHTER_BG: Color = Color::Rgb { r: 0x24, g: 0x24, b: 0x24 };
+const INDENT_GUIDE: &str = "│";
+/// Heuristic for how many bytes of a line it takes to fill one column of the
+/// screen, used to avoid processing the invisible parts of very long lines.
+/// Lines full of combining characters can need more and get cut short.
+const MAX_BYTES_PER_COLUMN: usize = 16;
+/// How many lints are shown below the lines at most, the rest are summarized in one row
+const MAX_INLINE_LINTS: usize = 3;
+
+/// How much the text colors are blended toward the background when dimmed
+const DIM_FACTOR: f32 = 0.5;
+
+/// Reduces the contrast of `style` by blending its foreground color toward its
+/// background color (or `bg` if it doesn't have one)
+fn dimmed(style: ContentStyle, bg: Color) -> ContentStyle {
+    let blend = |fg: u8, bg: u8| (f32::from(fg) + (f32::from(bg) - f32::from(fg)) * DIM_FACTOR).round() as u8;
+    match (style.foreground_color, style.background_color.unwrap_or(bg)) {
+        (Some(Color::Rgb { r, g, b }), Color::Rgb { r: bg_r, g: bg_g, b: bg_b }) => {
+            style.with(Color::Rgb { r: blend(r, bg_r), g: blend(g, bg_g), b: blend(b, bg_b) })
+        }
+        _ => style,
+    }
+}
+
+/// Colors of the parts of the screen that are not colored by the syntax rules
+/// of the theme. The theme can override some of them in its settings.
+#[derive(Clone, Copy)]
+struct Palette {
+    fg: Color,
+    bg: Color,
+    selection_fg: Color,
+    selection_bg: Color,
+    /// Background of the line with the primary cursor
+    line_highlight_bg: Option<Color>,
+    /// Foreground of the placeholders shown for invisible characters
+    escaped_fg: Color,
+    dim_fg: Color,
+    completions_bg: Color,
+    gutter_bg: Color,
+    word_highlight_bg: Color,
+    /// Background of the bracket at the cursor and its matching pair
+    matching_bracket_bg: Color,
+    trailing_whitespace_bg: Color,
+    indent_guide_fg: Color,
+    ruler_bg: Color,
+    conflict_marker_bg: Color,
+    conflict_ours_bg: Color,
+    conflict_base_bg: Color,
+    conflict_theirs_bg: Color,
+}
+
+const DARK_PALETTE: Palette = Palette {
+    fg: Color::White,
+    bg: Color::Rgb { r: 0x1a, g: 0x1a, b: 0x1a },
+    selection_fg: Color::Black,
+    selection_bg: Color::Rgb { r: 0x88, g: 0xff, b: 0xc5 },
+    line_highlight_bg: None,
+    escaped_fg: Color::White,
+    dim_fg: Color::Rgb { r: 0xaa, g: 0xaa, b: 0xaa },
+    completions_bg: Color::Rgb { r: 0x1e, g: 0x1e, b: 0x1e },
+    gutter_bg: Color::Rgb { r: 0x24, g: 0x24, b: 0x24 },
+    word_highlight_bg: Color::Rgb { r: 0x33, g: 0x36, b: 0x3d },
+    matching_bracket_bg: Color::Rgb { r: 0x3f, g: 0x52, b: 0x5e },
+    trailing_whitespace_bg: Color::Rgb { r: 0x5a, g: 0x22, b: 0x22 },
+    indent_guide_fg: Color::Rgb { r: 0x3c, g: 0x3c, b: 0x3c },
+    ruler_bg: Color::Rgb { r: 0x26, g: 0x26, b: 0x26 },
+    conflict_marker_bg: Color::Rgb { r: 0x3a, g: 0x3a, b: 0x3a },
+    conflict_ours_bg: Color::Rgb { r: 0x1d, g: 0x33, b: 0x24 },
+    conflict_base_bg: Color::Rgb { r: 0x33, g: 0x2d, b: 0x1d },
+    conflict_theirs_bg: Color::Rgb { r: 0x1d, g: 0x27, b: 0x3d },
+};
+
+const LIGHT_PALETTE: Palette = Palette {
+    fg: Color::Rgb { r: 0x2a, g: 0x2a, b: 0x2a },
+    bg: Color::Rgb { r: 0xfa, g: 0xfa, b: 0xf8 },
+    selection_fg: Color::Black,
+    selection_bg: Color::Rgb { r: 0x9c, g: 0xe8, b: 0xc0 },
+    line_highlight_bg: None,
+    escaped_fg: Color::White,
+    dim_fg: Color::Rgb { r: 0x70, g: 0x70, b: 0x70 },
+    completions_bg: Color::Rgb { r: 0xf0, g: 0xf0, b: 0xee },
+    gutter_bg: Color::Rgb { r: 0xe8, g: 0xe8, b: 0xe6 },
+    word_highlight_bg: Color::Rgb { r: 0xdd, g: 0xe4, b: 0xf0 },
+    matching_bracket_bg: Color::Rgb { r: 0xc4, g: 0xd6, b: 0xe6 },
+    trailing_whitespace_bg: Color::Rgb { r: 0xf2, g: 0xc8, b: 0xc8 },
+    indent_guide_fg: Color::Rgb { r: 0xd6, g: 0xd6, b: 0xd6 },
+    ruler_bg: Color::Rgb { r: 0xee, g: 0xee, b: 0xea },
+    conflict_marker_bg: Color::Rgb { r: 0xd8, g: 0xd8, b: 0xd8 },
+    conflict_ours_bg: Color::Rgb { r: 0xdc, g: 0xf2, b: 0xe2 },
+    conflict_base_bg: Color::Rgb { r: 0xf4, g: 0xec, b: 0xd6 },
+    conflict_theirs_bg: Color::Rgb { r: 0xdc, g: 0xe6, b: 0xf6 },
+};
+
+impl Palette {
+    fn for_theme(variant: ThemeVariant, settings: &ThemeSettings) -> Palette {
+        let mut palette = match variant {
+            ThemeVariant::Dark => DARK_PALETTE,
+            ThemeVariant::Light => LIGHT_PALETTE,
+        };
+        let to_crossterm = |color: Option<SyntectColor>| color.map(|SyntectColor { r, g, b, .. }| Color::Rgb { r, g, b });
+        palette.fg = to_crossterm(settings.foreground).unwrap_or(palette.fg);
+        palette.bg = to_crossterm(settings.background).unwrap_or(palette.bg);
+        palette.selection_fg = to_crossterm(settings.selection_foreground).unwrap_or(palette.selection_fg);
+        palette.selection_bg = to_crossterm(settings.selection).unwrap_or(palette.selection_bg);
+        palette.line_highlight_bg = to_crossterm(settings.line_highlight).or(palette.line_highlight_bg);
+        palette.dim_fg = to_crossterm(settings.gutter_foreground).unwrap_or(palette.dim_fg);
+        palette.gutter_bg = to_crossterm(settings.gutter).unwrap_or(palette.gutter_bg);
+        palette
+    }
+}
 
 impl SuggestionMenu {
     // TODO: Renderable trait instead of this nonsense
@@ -222,16 +391,20 @@ impl SuggestionMenu {
 }
 
 impl App {
-    fn status_line_text_left(&self, ft: &str) -> String {
+    fn status_line_text_left(&self, ft: &str, scope: Option<&str>) -> String {
         let title = &self.current_pane().title;
         let modified = match self.current_pane().modified {
             true => "[+] ",
             false => "",
         };
-        format!("{title} {modified}| ft:{ft}")
+        match scope {
+            Some(scope) => format!("{title} {modified}| ft:{ft} | {scope}"),
+            None => format!("{title} {modified}| ft:{ft}"),
+        }
     }
 
-    fn status_line_text_right(&self) -> String {
+    /// `primary_cursor_line` and `primary_cursor_column` are 0-based, the column is in grapheme clusters
+    fn status_line_text_right(&self, primary_cursor_line: usize, primary_cursor_column: usize) -> String {
         let pane = self.current_pane();
         let content = &pane.content;
         let cursor = self.current_pane().cursors.primary();
@@ -279,29 +452,64 @@ impl App {
                 }
             }
         };
+        let word_count = match &pane.word_count {
+            Some(wc) => {
+                const MAX_WORD_CHARS: usize = 24;
+                let word: String = match wc.word.char_indices().nth(MAX_WORD_CHARS) {
+                    Some((i, _)) => format!("{}…", &wc.word[..i]),
+                    None => wc.word.clone(),
+                };
+                let more = if wc.complete { "" } else { "+" };
+                format!("{word} ×{}{more} ", wc.count)
+            }
+            None => String::new(),
+        };
         format!(
-            "{} {:>3}:{:<3} {}",
+            "{}{} {:>3}:{:<3} {}",
+            word_count,
             pane_indicator,
-            1 + content.byte_to_line(cursor.offset),
-            1 + cursor.column(content),
+            1 + primary_cursor_line,
+            1 + primary_cursor_column,
             fsize_indicator
         )
     }
 
+    /// The UI colors of the active theme
+    fn palette(&self) -> Palette {
+        Palette::for_theme(self.highlighting.theme_variant(), self.highlighting.theme_settings())
+    }
+
     pub fn render(&mut self, mut writer: &mut dyn std::io::Write, wsize: &WindowSize) -> std::io::Result<()> {
         crossterm::execute!(&mut writer, BeginSynchronizedUpdate)?;
         writer.queue(crossterm::cursor::Hide)?;
 
         if wsize.rows < 3 {
+            let palette = self.palette();
+            writer.queue(crossterm::style::SetStyle(ContentStyle::new().with(palette.fg).on(palette.bg)))?;
             writer.queue(Clear(ClearType::All))?;
             writer.queue(MoveTo(0, 0))?;
             writer.queue(Print("window too smol"))?;
         } else {
+            self.current_pane_mut().update_conflicts();
             let mut hl = self.current_pane_mut().highlighter.take().unwrap_or_else(|| {
                 BadHighlighter::for_file("", self.highlighting.clone())
             });
-            self.render_content(writer, wsize, &mut hl)?;
+            let cursor_position = self.render_content(writer, wsize, &mut hl)?;
             self.current_pane_mut().highlighter.replace(hl);
+            // the terminal cursor is only shown as a bar, the block cursor is drawn as part of the content
+            if let Some((column, row)) = cursor_position.filter(|_| !matches!(self.state, AppState::InPrompt)) {
+                let style = if self.current_pane().settings.cursor_blink {
+                    SetCursorStyle::BlinkingBar
+                } else {
+                    SetCursorStyle::SteadyBar
+                };
+                writer.queue(MoveTo(column, row))?;
+                writer.queue(style)?;
+                writer.queue(crossterm::cursor::Show)?;
+            } else {
+                // the prompt (and anything outside the editor) uses the shape the user has configured
+                writer.queue(SetCursorStyle::DefaultUserShape)?;
+            }
         }
         writer.flush()?;
 
@@ -309,16 +517,22 @@ impl App {
         Ok(())
     }
 
-    fn render_content(&self, writer: &mut dyn std::io::Write, wsize: &WindowSize, hl: &mut BadHighlighter) -> std::io::Result<()> {
+    /// Returns the screen position for the terminal cursor if it should be shown
+    fn render_content(&self, writer: &mut dyn std::io::Write, wsize: &WindowSize, hl: &mut BadHighlighter) -> std::io::Result<Option<(u16, u16)>> {
         let current_pane = &self.current_pane();
         let now = Instant::now();
         let content = &current_pane.content;
         let primary_cursor_offset = current_pane.cursors.primary().offset;
         let primary_cursor_span = current_pane.cursors.primary().line_span(content);
         let primary_cursor_line = current_pane.cursors.primary().current_line_number(content);
-        let default_style = ContentStyle::new().with(DEFAULT_FG).on(DEFAULT_BG);
-        let completions_style = ContentStyle::new().with(LIGHT_GREY).on(SLIGHTLY_LIGHTER_BG);
-        let lineno_style = ContentStyle::new().with(LIGHT_GREY).on(LIGHTER_BG);
+        let shows_scopes = current_pane.settings.debug_scopes || current_pane.settings.status_scope;
+        hl.keep_scopes_of_line(shows_scopes.then_some(primary_cursor_line));
+        let palette = self.palette();
+        // the content is dimmed while the command prompt has the focus
+        let dim = matches!(self.state, AppState::InPrompt);
+        let default_style = ContentStyle::new().with(palette.fg).on(palette.bg);
+        let completions_style = ContentStyle::new().with(palette.dim_fg).on(palette.completions_bg);
+        let lineno_style = ContentStyle::new().with(palette.dim_fg).on(palette.gutter_bg);
 
         macro_rules! peek {
             ($it:expr) => {
@@ -370,19 +584,67 @@ impl App {
 
         let mut ctx = RenderingContext {
             is_cursor: false,
+            cursor_shape: current_pane.settings.cursor_shape,
+            primary_cursor_at: None,
             n_selections: 0,
             current_column: 0,
             visible_from_column: 0,
             available_columns: (wsize.columns as usize).saturating_sub(max_lineno_width + 2),
             tab_width: current_pane.settings.tab_width,
             token_style: default_style,
+            selection_style: ContentStyle::new().with(palette.selection_fg).on(palette.selection_bg),
+            escaped_style: ContentStyle::new().with(palette.escaped_fg).on(BLUEISH),
+            indent_guide: None,
+            rulers: current_pane.settings.rulers.clone(),
+            ruler_bg: None,
             queue: vec![],
         };
 
+        // the column is figured out while rendering the line to avoid another pass over it
+        let mut primary_cursor_column = None;
+        let mut terminal_cursor_position = None;
         let mut console_row: u16 = 0;
         writer.queue(MoveTo(0, 0))?;
         let first_visible_lineno = current_pane.viewport_position_row;
+        let conflicts: Vec<_> = current_pane.conflicts.iter()
+            .flatten()
+            .filter(|conflict| conflict.end >= first_visible_lineno && conflict.start <= last_visible_lineno)
+            .collect();
+        // the lints of the lines with the cursor come first, errors before other lints
+        let (inline_lints, hidden_lints) = if current_pane.settings.inline_lints {
+            let mut lints: Vec<_> = current_pane.displayed_lints()
+                .filter(|lint| primary_cursor_span.contains(&(lint.lineno() - 1)))
+                .filter(|lint| (first_visible_lineno..=last_visible_lineno).contains(&(lint.lineno() - 1)))
+                .collect();
+            lints.sort_by_key(|lint| (lint.lineno() != 1 + primary_cursor_line, !lint.is_error(), lint.lineno()));
+            let hidden = lints.len().saturating_sub(MAX_INLINE_LINTS);
+            lints.truncate(MAX_INLINE_LINTS);
+            (lints, hidden)
+        } else {
+            (vec![], 0)
+        };
+        let mut inline_lints_left = inline_lints.len();
+        // offsets of the following lines are derived from the line lengths as the
+        // loop advances rather than looked up from the rope every time
         let mut byte_offset = content.line_to_byte(first_visible_lineno);
+        let mut word_occurrences = {
+            let visible_end = content.try_line_to_byte(last_visible_lineno + 1).unwrap_or(ByteOffset(content.len_bytes()));
+            // there is no word to highlight while text is selected so the
+            // occurrences of the selection keep the list in order
+            let mut occurrences = current_pane.word_occurrences_to_highlight(byte_offset..visible_end);
+            occurrences.extend(current_pane.selection_occurrences_to_highlight(byte_offset..visible_end));
+            occurrences.into_iter().peekable()
+        };
+        // brackets are only matched for a lone cursor without a selection, and
+        // `<>` only in markup where they aren't also used as operators
+        let matching_brackets = {
+            let is_markup = matches!(hl.ft(), "html" | "xml");
+            let visible_end = content.try_line_to_byte(last_visible_lineno + 1).unwrap_or(ByteOffset(content.len_bytes()));
+            let cursor = current_pane.cursors.primary();
+            Some(cursor)
+                .filter(|cursor| current_pane.cursors.cursor_count() == 1 && !cursor.has_selection())
+                .and_then(|cursor| cursor.bracket_pair(content, byte_offset..visible_end, is_markup))
+        };
 
         hl.skip_to_line(first_visible_lineno, content);
 
@@ -391,12 +653,41 @@ impl App {
                 break
             }
             let one_based_lineno = 1 + lineno;
-            let line = line.to_string();
+            let line_start_offset = byte_offset;
+            let next_line_offset = ByteOffset(byte_offset.0 + line.len_bytes());
+            let is_primary_cursor_line = lineno == primary_cursor_line;
+            let line_text;
+            let highlights: Vec<(SyntectStyle, &str)> = if hl.is_too_long_to_highlight(line.len_bytes()) {
+                let cursor_offset_in_line = if is_primary_cursor_line { primary_cursor_offset.0 - line_start_offset.0 } else { 0 };
+                let needed_bytes = long_line_bytes_needed(line.len_bytes(), cursor_offset_in_line, ctx.available_columns);
+                line_text = line.slice(..line.byte_to_char(needed_bytes)).to_string();
+                vec![(hl.highlight_long_line(), line_text.as_str())]
+            } else {
+                line_text = line.to_string();
+                hl.highlight_line(&line_text).collect()
+            };
             ctx.visible_from_column = 0;
             ctx.current_column = 0;
+            let mut grapheme_index = 0;
+            let conflict_bg = conflicts.iter().find_map(|conflict| conflict.region_of_line(lineno)).map(|region| match region {
+                ConflictRegion::Marker => palette.conflict_marker_bg,
+                ConflictRegion::Ours => palette.conflict_ours_bg,
+                ConflictRegion::Base => palette.conflict_base_bg,
+                ConflictRegion::Theirs => palette.conflict_theirs_bg,
+            });
+            let line_bg = conflict_bg.or(palette.line_highlight_bg.filter(|_| lineno == primary_cursor_line));
+            let (leading_whitespace_end, trailing_whitespace_start) = match line_text.len() < line.len_bytes() {
+                // the end of a line that was cut short is not known
+                true => (whitespace_bounds(&line_text).0, usize::MAX),
+                false => whitespace_bounds(&line_text),
+            };
+            let indent_size = current_pane.settings.indent_size;
 
-            for (style, s) in hl.highlight_line(&line) {
+            'graphemes: for (style, s) in highlights {
                 ctx.token_style = to_crossterm_style(style);
+                if let Some(bg) = line_bg {
+                    ctx.token_style = ctx.token_style.on(bg);
+                }
                 for g in s.graphemes(true) {
                     ctx.is_cursor = false;
                     while peek!(curs) <= byte_offset {
@@ -410,14 +701,56 @@ impl App {
                         }
                         curs.next();
                     }
+                    while word_occurrences.next_if(|occurrence| occurrence.end <= byte_offset).is_some() {}
+                    let token_style = ctx.token_style;
+                    if word_occurrences.peek().is_some_and(|occurrence| occurrence.start <= byte_offset) {
+                        ctx.token_style = token_style.on(palette.word_highlight_bg);
+                    }
+                    if matching_brackets.is_some_and(|(bracket, pair)| bracket == byte_offset || pair == byte_offset) {
+                        ctx.token_style = token_style.on(palette.matching_bracket_bg);
+                    }
+                    let offset_in_line = byte_offset.0 - line_start_offset.0;
+                    if current_pane.settings.show_trailing_whitespace && offset_in_line >= trailing_whitespace_start {
+                        ctx.token_style = token_style.on(palette.trailing_whitespace_bg);
+                    }
+                    ctx.ruler_bg = (line_bg.is_none() && ctx.token_style == token_style).then_some(palette.ruler_bg);
+                    ctx.indent_guide = (current_pane.settings.indent_guides && indent_size > 0 && offset_in_line < leading_whitespace_end)
+                        .then(|| (indent_size, ctx.token_style.with(palette.indent_guide_fg)));
+                    if ctx.is_cursor && byte_offset == primary_cursor_offset {
+                        ctx.primary_cursor_at = Some(ctx.current_column);
+                    }
                     grapheme_representation(g, &mut ctx);
+                    ctx.token_style = token_style;
                     if byte_offset == primary_cursor_offset {
+                        primary_cursor_column = Some(grapheme_index);
                         let required_columns = ctx.current_column;
                         ctx.visible_from_column = required_columns.saturating_sub(ctx.available_columns.saturating_sub(1));
                     }
                     byte_offset.0 += g.len();
+                    grapheme_index += 1;
+                    // the rest of the line is not visible (one extra grapheme is needed
+                    // to know whether to show the '>' indicator)
+                    if ctx.current_column > ctx.visible_from_column + ctx.available_columns
+                        && (!is_primary_cursor_line || byte_offset > primary_cursor_offset)
+                    {
+                        break 'graphemes
+                    }
                 }
             }
+            ctx.indent_guide = None;
+            ctx.ruler_bg = None;
+            if is_primary_cursor_line && primary_cursor_column.is_none() {
+                // the walk only stops early after passing the cursor so it must be at the end of file
+                primary_cursor_column = Some(grapheme_index);
+            }
+            while peek!(curs) < next_line_offset {
+                match curs.next() {
+                    Some(Cur::Start(_)) => ctx.n_selections += 1,
+                    Some(Cur::End(_)) => ctx.n_selections -= 1,
+                    _ => {}
+                }
+            }
+            byte_offset = next_line_offset;
 
             // render cursor at the end of the file
             if one_based_lineno >= content.len_lines() && {
@@ -425,6 +758,9 @@ impl App {
                 current_pane.cursors.iter().any(|cur| !cur.has_selection() && cur.offset == content_end_offset)
             } {
                 ctx.is_cursor = true;
+                if primary_cursor_offset.0 == content.len_bytes() {
+                    ctx.primary_cursor_at = Some(ctx.current_column);
+                }
                 let required_columns = ctx.current_column + 1;
                 ctx.visible_from_column = required_columns.saturating_sub(ctx.available_columns.saturating_sub(1));
                 grapheme_representation(" ", &mut ctx);
@@ -434,7 +770,7 @@ impl App {
                 let left_scroll_indicator = if ctx.visible_from_column > 0 { '<' } else { ' ' };
                 let sidebar = format!(" {one_based_lineno:max_lineno_width$}{left_scroll_indicator}");
                 let mut lineno_style = lineno_style;
-                if let Some(lint) = current_pane.lints.iter().find(|lint| lint.lineno() == one_based_lineno) {
+                if let Some(lint) = current_pane.displayed_lints().find(|lint| lint.lineno() == one_based_lineno) {
                     lineno_style = lineno_style.with(lint.color());
                 }
                 writer.queue(PrintStyledContent(lineno_style.apply(&sidebar)))?;
@@ -447,7 +783,14 @@ impl App {
                     continue
                 }
                 if current_column + width <= ctx.available_columns {
-                    writer.queue(PrintStyledContent(s))?;
+                    if ctx.primary_cursor_at == Some(s_start) && ctx.cursor_shape == CursorShape::Bar {
+                        terminal_cursor_position = Some(((max_lineno_width + 2 + current_column) as u16, console_row));
+                    }
+                    if dim {
+                        writer.queue(PrintStyledContent(dimmed(*s.style(), palette.bg).apply(s.content())))?;
+                    } else {
+                        writer.queue(PrintStyledContent(s))?;
+                    }
                     current_column += width;
                 } else {
                     writer.queue(MoveTo(wsize.columns.saturating_sub(1), console_row))?;
@@ -456,9 +799,23 @@ impl App {
                 }
             }
 
+            ctx.primary_cursor_at = None;
+
             // clear rest
-            writer.queue(crossterm::style::SetStyle(default_style))?;
+            writer.queue(crossterm::style::SetStyle(match line_bg {
+                Some(bg) => default_style.on(bg),
+                None => default_style,
+            }))?;
             writer.queue(Clear(ClearType::UntilNewLine))?;
+            if line_bg.is_none() {
+                let text_start = max_lineno_width + 2;
+                for column in ruler_screen_columns(&ctx.rulers, ctx.visible_from_column, ctx.available_columns) {
+                    if column >= current_column {
+                        writer.queue(MoveTo((text_start + column) as u16, console_row))?;
+                        writer.queue(PrintStyledContent(default_style.on(palette.ruler_bg).apply(" ")))?;
+                    }
+                }
+            }
             writer.queue(MoveToNextLine(1))?;
             console_row += 1;
 
@@ -473,8 +830,7 @@ impl App {
 
             // render debug scopes
             if current_pane.settings.debug_scopes && primary_cursor_line == lineno {
-                let line_start = current_pane.cursors.primary().line_start(content);
-                let primary_cursor_offset_within_line = primary_cursor_offset.0 - line_start.0;
+                let primary_cursor_offset_within_line = primary_cursor_offset.0 - line_start_offset.0;
                 let ss = hl.scope_stack_at(primary_cursor_line, primary_cursor_offset_within_line, content);
                 for scope in ss.as_slice().iter() {
                     writer.queue(crossterm::style::SetStyle(lineno_style))?;
@@ -486,11 +842,18 @@ impl App {
             }
 
             // render possible lints
-            if primary_cursor_span.contains(&lineno) {
-                for lint in current_pane.lints.iter().filter(|lint| lint.lineno() == one_based_lineno) {
-                    writer.queue(PrintStyledContent(ContentStyle::new().on(lint.color()).apply(" ".repeat(max_lineno_width + 2))))?;
-                    writer.queue(PrintStyledContent(default_style.on(LIGHTER_BG).apply(&lint.message)))?;
-                    writer.queue(crossterm::style::SetStyle(default_style.on(LIGHTER_BG)))?;
+            for lint in inline_lints.iter().filter(|lint| lint.lineno() == one_based_lineno) {
+                writer.queue(PrintStyledContent(ContentStyle::new().on(lint.color()).apply(" ".repeat(max_lineno_width + 2))))?;
+                writer.queue(PrintStyledContent(default_style.on(palette.gutter_bg).apply(&lint.message)))?;
+                writer.queue(crossterm::style::SetStyle(default_style.on(palette.gutter_bg)))?;
+                writer.queue(Clear(ClearType::UntilNewLine))?;
+                writer.queue(MoveToNextLine(1))?;
+                console_row += 1;
+                last_visible_lineno = last_visible_lineno.saturating_sub(1);
+                inline_lints_left -= 1;
+                if inline_lints_left == 0 && hidden_lints > 0 {
+                    writer.queue(PrintStyledContent(lineno_style.apply(" ".repeat(max_lineno_width + 2))))?;
+                    writer.queue(PrintStyledContent(lineno_style.apply(format!("+{hidden_lints} more"))))?;
                     writer.queue(Clear(ClearType::UntilNewLine))?;
                     writer.queue(MoveToNextLine(1))?;
                     console_row += 1;
@@ -505,33 +868,342 @@ impl App {
         writer.queue(MoveTo(0, wsize.rows - 2))?;
         writer.queue(crossterm::style::SetStyle(default_style.negative()))?;
         let width = wsize.columns as usize;
-        let status_line_left = format!("{:width$}", self.status_line_text_left(hl.ft()), width = width);
+        let scope = current_pane.settings.status_scope.then(|| {
+            let offset_within_line = primary_cursor_offset.0 - content.line_to_byte(primary_cursor_line).0;
+            let scopes: Vec<String> = hl.scope_stack_at(primary_cursor_line, offset_within_line, content)
+                .as_slice()
+                .iter()
+                .map(|scope| scope.build_string())
+                .collect();
+            // punctuation scopes don't tell much about where the cursor is
+            scopes.iter().rev().find(|scope| !scope.starts_with("punctuation.")).or(scopes.last()).cloned().unwrap_or_default()
+        });
+        let status_line_left = format!("{:width$}", self.status_line_text_left(hl.ft(), scope.as_deref()), width = width);
         writer.queue(PrintStyledContent(default_style.negative().apply(status_line_left)))?;
-        let status_line_right = self.status_line_text_right();
-        writer.queue(MoveTo(width.saturating_sub(status_line_right.len()) as u16, wsize.rows - 2))?;
+        // the line with the cursor is not necessarily visible if the view has been scrolled
+        let primary_cursor_column = primary_cursor_column.unwrap_or_else(|| current_pane.cursors.primary().column(content));
+        let status_line_right = self.status_line_text_right(primary_cursor_line, primary_cursor_column);
+        writer.queue(MoveTo(width.saturating_sub(status_line_right.width()) as u16, wsize.rows - 2))?;
         writer.queue(PrintStyledContent(default_style.negative().apply(status_line_right)))?;
 
+        // the lint of the cursor line is shown in the message line when inline lints are off
+        let cursor_line_lint = current_pane.displayed_lints()
+            .filter(|lint| !current_pane.settings.inline_lints && lint.lineno() == 1 + primary_cursor_line)
+            .min_by_key(|lint| !lint.is_error());
         writer.queue(MoveTo(0, wsize.rows - 1))?;
         writer.queue(crossterm::style::SetStyle(default_style))?;
         writer.queue(Print(
-            match self.status_msg() {
-                Some(info) => format!("{:.width$}", &info, width = wsize.columns as usize),
-                None => format!("render took {:.3?}", now.elapsed()),
+            match (self.status_msg(), cursor_line_lint) {
+                (Some(info), _) => format!("{:.width$}", &info, width = wsize.columns as usize),
+                (None, Some(lint)) => format!("{:.width$}", &lint.message, width = wsize.columns as usize),
+                (None, None) => format!("render took {:.3?}", now.elapsed()),
             }
         ))?;
         // this ensures prompt is printed in the right place!
         writer.queue(MoveTo(0, wsize.rows - 1))?;
-        Ok(())
+        Ok(terminal_cursor_position)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rstest::rstest;
+
+    /// An app showing `pane` in a window of the given size
+    fn app_with_pane(pane: crate::Pane, wsize: &WindowSize) -> App {
+        let mut app = App::new();
+        app.switch_to_new_pane(pane);
+        app.current_pane_mut().update_viewport_size(wsize.columns, wsize.rows - 2);
+        app
+    }
+
+    fn render_screen(app: &mut App, wsize: &WindowSize) -> String {
+        let mut out = vec![];
+        app.render(&mut out, wsize).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn only_the_visible_part_of_a_long_line_is_rendered() {
+        let line_len = 1 << 20;
+        assert_eq!(long_line_bytes_needed(line_len, 0, 120), MAX_BYTES_PER_COLUMN * 121);
+        assert_eq!(long_line_bytes_needed(line_len, 1000, 120), 1000 + MAX_BYTES_PER_COLUMN * 121);
+        assert_eq!(long_line_bytes_needed(line_len, line_len, 120), line_len);
+        assert_eq!(long_line_bytes_needed(10, 0, 120), 10);
+    }
+
+    #[test]
+    fn render_huge_single_line() {
+        let mut pane = crate::Pane::empty();
+        pane.content = crate::ropebuffer::RopeBuffer::from_str(&"x".repeat(1 << 20));
+        let wsize = WindowSize { rows: 40, columns: 120, width: 0, height: 0 };
+        let mut app = app_with_pane(pane, &wsize);
+        let screen = render_screen(&mut app, &wsize);
+        assert!(screen.len() < 1 << 16, "{} bytes were written for one screen", screen.len());
+        assert!(screen.matches('x').count() > 100, "the line should fill the screen");
+        // horizontally scrolled to the end of the line
+        app.current_pane_mut().handle_event(crate::PaneAction::MoveTo(crate::MoveTarget::EndOfFile));
+        let screen = render_screen(&mut app, &wsize);
+        assert!(screen.len() < 1 << 16, "{} bytes were written for one screen", screen.len());
+        assert!(screen.matches('x').count() > 100, "the line should fill the screen");
+    }
+
+    #[test]
+    fn status_line_shows_cursor_location() {
+        let mut pane = crate::Pane::empty();
+        pane.content = crate::ropebuffer::RopeBuffer::from_str(&"äö\n".repeat(100));
+        let wsize = WindowSize { rows: 20, columns: 80, width: 0, height: 0 };
+        let mut app = app_with_pane(pane, &wsize);
+        let mut render_status_line = |target| {
+            app.current_pane_mut().handle_event(crate::PaneAction::MoveTo(target));
+            render_screen(&mut app, &wsize)
+        };
+        assert!(render_status_line(crate::MoveTarget::Down(2)).contains("  3:1  "));
+        assert!(render_status_line(crate::MoveTarget::Right(2)).contains("  3:3  "));
+        assert!(render_status_line(crate::MoveTarget::EndOfFile).contains("101:1  "));
+    }
+
+    #[test]
+    fn status_line_shows_scope() {
+        let mut pane = crate::Pane::empty();
+        pane.content = crate::ropebuffer::RopeBuffer::from_str("fn main() {\n    let x = \"hi\";\n}\n");
+        let wsize = WindowSize { rows: 20, columns: 80, width: 0, height: 0 };
+        let mut app = app_with_pane(pane, &wsize);
+        let hl = app.highlighting.clone();
+        app.current_pane_mut().set_filetype("rust", hl).unwrap();
+        app.current_pane_mut().handle_event(crate::PaneAction::MoveTo(crate::MoveTarget::ByteOffset(26)));
+        assert!(!render_screen(&mut app, &wsize).contains("| string.quoted.double.rust"));
+        app.set("status_scope", "on");
+        assert!(render_screen(&mut app, &wsize).contains("| ft:rust | string.quoted.double.rust"));
+    }
+
+    #[test]
+    fn matching_bracket_is_highlighted() {
+        let mut pane = crate::Pane::empty();
+        pane.content = crate::ropebuffer::RopeBuffer::from_str("f(x)\n");
+        let wsize = WindowSize { rows: 20, columns: 80, width: 0, height: 0 };
+        let mut app = app_with_pane(pane, &wsize);
+        let Color::Rgb { r, g, b } = app.palette().matching_bracket_bg else { unreachable!() };
+        let bracket_bg = format!("48;2;{r};{g};{b}m");
+        let mut render_at = |offset| {
+            app.current_pane_mut().handle_event(crate::PaneAction::MoveTo(crate::MoveTarget::ByteOffset(offset)));
+            render_screen(&mut app, &wsize)
+        };
+        assert!(render_at(1).contains(&bracket_bg));
+        assert!(render_at(4).contains(&bracket_bg));
+        assert!(!render_at(2).contains(&bracket_bg));
+    }
+
+    #[test]
+    fn bar_cursor_shows_terminal_cursor() {
+        let mut pane = crate::Pane::empty();
+        pane.content = crate::ropebuffer::RopeBuffer::from_str("abc\n");
+        let wsize = WindowSize { rows: 20, columns: 80, width: 0, height: 0 };
+        let mut app = app_with_pane(pane, &wsize);
+        app.current_pane_mut().handle_event(crate::PaneAction::MoveTo(crate::MoveTarget::ByteOffset(1)));
+        // the text starts after the line number and two columns of padding
+        let show_bar_at_b = "\x1b[1;5H\x1b[5 q\x1b[?25h";
+        let default_shape = "\x1b[0 q";
+        let screen = render_screen(&mut app, &wsize);
+        assert!(!screen.contains(show_bar_at_b));
+        assert!(!screen.contains("\x1b[4m"));
+        assert!(screen.contains(default_shape));
+        app.set("cursor", "bar");
+        let screen = render_screen(&mut app, &wsize);
+        assert!(screen.contains(show_bar_at_b));
+        assert!(screen.contains("\x1b[4m"));
+        assert!(!screen.contains(default_shape));
+        app.set("cursor_blink", "off");
+        assert!(render_screen(&mut app, &wsize).contains("\x1b[1;5H\x1b[6 q\x1b[?25h"));
+        app.set("cursor", "block");
+        assert!(render_screen(&mut app, &wsize).contains(default_shape));
+    }
+
+    #[rstest]
+    #[case("foo\n", (0, 3))]
+    #[case("  foo  \n", (2, 5))]
+    #[case("\t x\t\r\n", (2, 3))]
+    #[case("    \n", (4, 0))]
+    #[case("", (0, 0))]
+    fn whitespace_bounds_of_line(#[case] line: &str, #[case] expected: (usize, usize)) {
+        assert_eq!(whitespace_bounds(line), expected);
+    }
+
+    #[test]
+    fn indent_guides_and_trailing_whitespace() {
+        let mut pane = crate::Pane::empty();
+        pane.content = crate::ropebuffer::RopeBuffer::from_str("x\n\tif y:  \n        z\n");
+        pane.settings.indent_size = 4;
+        let wsize = WindowSize { rows: 20, columns: 80, width: 0, height: 0 };
+        let mut app = app_with_pane(pane, &wsize);
+        let Color::Rgb { r, g, b } = app.palette().trailing_whitespace_bg else { unreachable!() };
+        let trailing_bg = format!("48;2;{r};{g};{b}m");
+        let screen = render_screen(&mut app, &wsize);
+        assert!(!screen.contains(INDENT_GUIDE));
+        assert!(!screen.contains(&trailing_bg));
+        app.set("indent_guides", "on");
+        app.set("whitespace", "on");
+        let screen = render_screen(&mut app, &wsize);
+        // one guide at column 0 of the tab (whose width is 4) and two for the eight spaces
+        assert_eq!(screen.matches(INDENT_GUIDE).count(), 3);
+        assert_eq!(screen.matches(&trailing_bg).count(), 2);
+    }
+
+    #[rstest]
+    #[case::unscrolled(&[80, 120], 0, 100, vec![79])]
+    #[case::scrolled(&[80, 120], 50, 100, vec![29, 69])]
+    #[case::scrolled_past(&[80], 80, 100, vec![])]
+    #[case::first_column(&[1], 0, 10, vec![0])]
+    #[case::zero_is_ignored(&[0], 0, 10, vec![])]
+    fn ruler_columns_on_screen(
+        #[case] rulers: &[usize],
+        #[case] visible_from_column: usize,
+        #[case] available_columns: usize,
+        #[case] expected: Vec<usize>,
+    ) {
+        assert_eq!(ruler_screen_columns(rulers, visible_from_column, available_columns).collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn ruler_is_drawn_on_the_right_column() {
+        let mut pane = crate::Pane::empty();
+        pane.content = crate::ropebuffer::RopeBuffer::from_str("\tabcdef\n");
+        pane.settings.tab_width = 4;
+        let wsize = WindowSize { rows: 20, columns: 80, width: 0, height: 0 };
+        let mut app = app_with_pane(pane, &wsize);
+        app.current_pane_mut().handle_event(crate::PaneAction::MoveTo(crate::MoveTarget::EndOfFile));
+        let Color::Rgb { r, g, b } = app.palette().ruler_bg else { unreachable!() };
+        let ruler_bg = format!("48;2;{r};{g};{b}m");
+        assert!(!render_screen(&mut app, &wsize).contains(&ruler_bg));
+        // the tab takes up columns 1-4 so column 6 is the 'b'
+        app.set("ruler", "6");
+        let screen = render_screen(&mut app, &wsize);
+        let ruler_cell = screen.find(&ruler_bg).unwrap();
+        assert!(screen[ruler_cell..].split('\x1b').nth(1).unwrap().ends_with('b'));
+    }
+
+    #[test]
+    fn inline_lints_are_capped() {
+        let mut pane = crate::Pane::empty();
+        pane.content = crate::ropebuffer::RopeBuffer::from_str(&"x\n".repeat(10));
+        pane.lints = (1..=6)
+            .map(|i| crate::linter::Lint::parse(&format!("f:1:1:warning:lint number {i}")).unwrap())
+            .chain(crate::linter::Lint::parse("f:1:1:error:the error"))
+            .collect();
+        let wsize = WindowSize { rows: 20, columns: 80, width: 0, height: 0 };
+        let mut app = app_with_pane(pane, &wsize);
+        let screen = render_screen(&mut app, &wsize);
+        assert!(screen.contains("the error"));
+        assert!(screen.contains("lint number 2"));
+        assert!(!screen.contains("lint number 3"));
+        assert!(screen.contains("+4 more"));
+
+        app.set("inline_lints", "off");
+        let screen = render_screen(&mut app, &wsize);
+        assert!(!screen.contains("lint number"));
+        assert!(screen.contains("the error"), "the lint should be shown in the message line instead");
+    }
+
+    #[test]
+    fn lints_below_lint_level_are_hidden() {
+        let mut pane = crate::Pane::empty();
+        pane.content = crate::ropebuffer::RopeBuffer::from_str("x\n");
+        pane.lints = ["f:1:1:info:some info", "f:1:1:warning:some warning"]
+            .into_iter()
+            .filter_map(crate::linter::Lint::parse)
+            .collect();
+        let wsize = WindowSize { rows: 20, columns: 80, width: 0, height: 0 };
+        let mut app = app_with_pane(pane, &wsize);
+        app.set("lint_level", "warning");
+        let screen = render_screen(&mut app, &wsize);
+        assert!(screen.contains("some warning"));
+        assert!(!screen.contains("some info"));
+    }
+
+    #[test]
+    fn dimming_blends_toward_background() {
+        let white = Color::Rgb { r: 0xff, g: 0xff, b: 0xff };
+        let black = Color::Rgb { r: 0, g: 0, b: 0 };
+        let grey = Color::Rgb { r: 0x80, g: 0x80, b: 0x80 };
+        assert_eq!(dimmed(ContentStyle::new().with(white), black).foreground_color, Some(grey));
+        assert_eq!(dimmed(ContentStyle::new().with(black).on(white), black).foreground_color, Some(grey));
+        assert_eq!(dimmed(ContentStyle::new().with(Color::Red), black).foreground_color, Some(Color::Red));
+    }
+
+    #[test]
+    fn content_is_dimmed_while_in_prompt() {
+        let mut pane = crate::Pane::empty();
+        pane.content = crate::ropebuffer::RopeBuffer::from_str("hello\n");
+        let wsize = WindowSize { rows: 20, columns: 80, width: 0, height: 0 };
+        let mut app = app_with_pane(pane, &wsize);
+        // plain text is #F8F8F2 on #1A1A1A in the default theme
+        let dimmed_fg = "\x1b[38;2;137;137;134m";
+        assert!(!render_screen(&mut app, &wsize).contains(dimmed_fg));
+        app.state = AppState::InPrompt;
+        assert!(render_screen(&mut app, &wsize).contains(dimmed_fg));
+    }
 
     #[test]
     fn test_replacement_symbols() {
         assert_eq!(replacement_symbol("\u{200C}"), Some("<U+200C>".into()));
         assert_eq!(replacement_symbol("\u{0}"), Some("<00>".into()));
     }
+
+    const TEST_THEME: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>name</key><string>test</string>
+    <key>settings</key>
+    <array>
+        <dict>
+            <key>settings</key>
+            <dict>
+                <key>background</key><string>#101010</string>
+                <key>foreground</key><string>#EEEEEE</string>
+                <key>selection</key><string>#123456</string>
+            </dict>
+        </dict>
+    </array>
+</dict>
+</plist>
+"#;
+
+    fn load_test_theme(app: &mut App) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("dark.tmTheme"), TEST_THEME).unwrap();
+        app.load_themes_from(dir.path());
+        assert!(app.status_msg().is_none());
+    }
+
+    #[test]
+    fn theme_selection_color_is_respected() {
+        let mut pane = crate::Pane::empty();
+        pane.content = crate::ropebuffer::RopeBuffer::from_str("hello world\n");
+        let wsize = WindowSize { rows: 20, columns: 80, width: 0, height: 0 };
+        let mut app = app_with_pane(pane, &wsize);
+        app.current_pane_mut().handle_event(crate::PaneAction::SelectTo(crate::MoveTarget::Right(5)));
+        let selection_bg = "\x1b[48;2;18;52;86m";
+        assert!(render_screen(&mut app, &wsize).contains("\x1b[48;2;136;255;197m"), "default selection color is used without a theme");
+
+        app.set_theme(ThemeVariant::Light);
+        load_test_theme(&mut app);
+        assert!(!render_screen(&mut app, &wsize).contains(selection_bg), "only the active theme is used");
+        app.set_theme(ThemeVariant::Dark);
+        assert!(render_screen(&mut app, &wsize).contains(selection_bg));
+    }
+
+    #[test]
+    fn theme_background_fills_empty_area() {
+        let mut app = App::new();
+        app.switch_to_new_pane(crate::Pane::empty());
+        load_test_theme(&mut app);
+        let theme_style = "\x1b[48;2;16;16;16m\x1b[38;2;238;238;238m";
+        for (rows, clear) in [(2, "\x1b[2J"), (20, "\x1b[J")] {
+            let wsize = WindowSize { rows, columns: 80, width: 0, height: 0 };
+            app.current_pane_mut().update_viewport_size(wsize.columns, wsize.rows.saturating_sub(2));
+            let screen = render_screen(&mut app, &wsize);
+            assert!(screen.contains(&format!("{theme_style}{clear}")), "{screen:?}");
+        }
+    }
 }
@@ -21,6 +21,11 @@ impl<'a> RopeExt<'a> for ropey::RopeSlice<'a> {
                 Err(GraphemeIncomplete::NextChunk) => {
                     (chunk, chunk_byte_idx, _, _) = self.chunk_at_byte(chunk_byte_idx + chunk.len());
                 }
+                Err(GraphemeIncomplete::PreContext(idx)) => {
+                    let (ctx_chunk, ctx_chunk_byte_idx, _, _) =
+                        self.chunk_at_byte(idx.saturating_sub(1));
+                    gr.provide_context(ctx_chunk, ctx_chunk_byte_idx);
+                }
                 Err(err) => unreachable!("{err:?} should never happen!"),
             }
         }
@@ -103,6 +108,11 @@ impl RopeExt<'_> for ropey::Rope {
                 Err(GraphemeIncomplete::NextChunk) => {
                     (chunk, chunk_byte_idx, _, _) = self.chunk_at_byte(chunk_byte_idx + chunk.len());
                 }
+                Err(GraphemeIncomplete::PreContext(idx)) => {
+                    let (ctx_chunk, ctx_chunk_byte_idx, _, _) =
+                        self.chunk_at_byte(idx.saturating_sub(1));
+                    gr.provide_context(ctx_chunk, ctx_chunk_byte_idx);
+                }
                 Err(err) => unreachable!("{err:?} should never happen!"),
             }
         }
@@ -172,3 +182,17 @@ impl RopeExt<'_> for ropey::Rope {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_grapheme_clusters_across_chunks() {
+        // long enough to be split into many chunks, some of them in the middle of a ZWJ sequence
+        let text = "a👩‍👩‍👧".repeat(1000);
+        let rope = ropey::Rope::from_str(&text);
+        assert_eq!(rope.count_grapheme_clusters(), 2000);
+        assert_eq!(rope.slice(..).count_grapheme_clusters(), 2000);
+    }
+}
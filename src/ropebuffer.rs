@@ -1,3 +1,4 @@
+use std::collections::{BTreeSet, VecDeque};
 use std::fmt::Display;
 use std::ops::Range;
 
@@ -5,13 +6,23 @@ use ropey::{Rope, RopeSlice};
 
 use crate::cursor::Cursor;
 use crate::editing::{Edit, EditBatch};
-use crate::{ByteOffset, MultiCursor, RopeExt};
+use crate::search::{Horspool, SearchQuery};
+use crate::word_boundaries::{WordBoundaries, is_word_boundary_between, is_word_char};
+use crate::{ByteOffset, IndentKind, MultiCursor, RopeExt};
 
 #[derive(Debug, Default)]
 pub struct RopeBuffer {
     rope: Rope,
-    undo: Vec<(EditBatch, MultiCursor)>,
+    undo: VecDeque<(EditBatch, MultiCursor)>,
     redo: Vec<(EditBatch, MultiCursor)>,
+    /// Characters considered part of words in addition to alphanumerics and `_`
+    extra_word_chars: String,
+    /// Numbers of the lines that have been edited (including by undo and redo)
+    modified_lines: BTreeSet<usize>,
+    /// Length of the undo stack when the content was last saved
+    saved_undo_len: usize,
+    /// Whether the saved state can no longer be reached with undo and redo
+    saved_state_lost: bool,
 }
 
 impl RopeBuffer {
@@ -19,6 +30,8 @@ impl RopeBuffer {
         Self::default()
     }
 
+    // infallible unlike FromStr, same as ropey::Rope::from_str
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(text: &str) -> Self {
         let rope = Rope::from_str(text);
         Self { rope, ..Default::default() }
@@ -54,6 +67,11 @@ impl RopeBuffer {
         self.rope.byte_to_char(offset.0)
     }
 
+    /// Moves `offset` back to the start of the character it is in
+    pub fn floor_char_boundary(&self, offset: ByteOffset) -> ByteOffset {
+        ByteOffset(self.rope.char_to_byte(self.byte_to_char(offset)))
+    }
+
     pub fn get_byte(&self, offset: ByteOffset) -> Option<u8> {
         self.rope.get_byte(offset.0)
     }
@@ -71,53 +89,77 @@ impl RopeBuffer {
     }
 
     pub fn is_word_boundary(&self, offset: ByteOffset) -> bool {
-        // The unicode segmentation crates don't currently (as of August 2025) provide
-        // an API for word boundaries that would be usable with Rope so we will use a
-        // simple implementation that should be reasonable for the simplest of cases.
-        // Unicode defines more thorough word boundary rules that might be worth
-        // implementing: https://www.unicode.org/reports/tr29/#Word_Boundaries
+        let char_offset = self.byte_to_char(offset);
+        let mut prevs = self.rope.chars_at(char_offset);
+        let mut nexts = self.rope.chars_at(char_offset);
+        let prev = prevs.prev();
+        let next = nexts.next();
+        is_word_boundary_between(prevs.prev(), prev, next, nexts.next(), &self.extra_word_chars)
+    }
 
-        fn is_midletter(c: char) -> bool {
-            matches!(c, '\u{003A}' | '\u{00B7}' | '\u{0387}' | '\u{055F}' | '\u{05F4}' | '\u{2027}' | '\u{FE13}' | '\u{FE55}' | '\u{FF1A}')
-        }
+    pub fn extra_word_chars(&self) -> &str {
+        &self.extra_word_chars
+    }
 
-        fn is_midnumletq(c: char) -> bool {
-            matches!(c, '\u{002E}' | '\u{2018}' | '\u{2019}' | '\u{2024}' | '\u{FE52}' | '\u{FF07}' | '\u{FF0E}' | '\u{0027}')
+    pub fn set_extra_word_chars(&mut self, chars: &str) {
+        if self.extra_word_chars != chars {
+            self.extra_word_chars = chars.to_string();
         }
+    }
 
-        fn is_midnum(c: char) -> bool {
-            matches!(c, '\u{066C}' | '\u{FE50}' | '\u{FE54}' | '\u{FF0C}' | '\u{FF1B}')
-        }
+    /// Word boundaries after `from`, see [`RopeBuffer::is_word_boundary`]
+    pub fn word_boundaries(&self, from: ByteOffset) -> WordBoundaries<'_> {
+        WordBoundaries::new(&self.rope, from, false, &self.extra_word_chars)
+    }
+
+    /// Word boundaries before `from` in reverse order
+    pub fn word_boundaries_backward(&self, from: ByteOffset) -> WordBoundaries<'_> {
+        WordBoundaries::new(&self.rope, from, true, &self.extra_word_chars)
+    }
 
+    /// Range of the word that `offset` is inside of or at either end of,
+    /// `None` if there are no word characters next to `offset`
+    pub fn word_at(&self, offset: ByteOffset) -> Option<Range<ByteOffset>> {
+        let is_word_char = |c: &char| is_word_char(*c, &self.extra_word_chars);
         let char_offset = self.byte_to_char(offset);
+        let mut start = offset.0;
         let mut prevs = self.rope.chars_at(char_offset);
-        let mut nexts = self.rope.chars_at(char_offset);
-        let Some(prev) = prevs.prev() else { return true };
-        let Some(next) = nexts.next() else { return true };
-        if prev.is_whitespace() && next.is_whitespace() {
-            return false
-        }
-        if (prev.is_alphanumeric() || prev == '_') && (next.is_alphanumeric() || next == '_') {
-            return false
-        }
-        if prev.is_ascii_punctuation() && next.is_ascii_punctuation() {
-            return false
+        while let Some(c) = prevs.prev().filter(is_word_char) {
+            start -= c.len_utf8();
         }
-        let prevprev = prevs.prev();
-        let nextnext = nexts.next();
-        if prev.is_alphabetic() && (is_midletter(next) || is_midnumletq(next)) && nextnext.is_some_and(|c| c.is_alphabetic()) {
-            return false
-        }
-        if next.is_alphabetic() && (is_midletter(prev) || is_midnumletq(prev)) && prevprev.is_some_and(|c| c.is_alphabetic()) {
-            return false
-        }
-        if prev.is_numeric() && (is_midnum(next) || is_midnumletq(next)) && nextnext.is_some_and(|c| c.is_numeric()) {
-            return false
+        let end = offset.0 + self.rope.chars_at(char_offset).take_while(is_word_char).map(char::len_utf8).sum::<usize>();
+        (start < end).then_some(ByteOffset(start)..ByteOffset(end))
+    }
+
+    /// Occurrences of `word` within `range` that are not part of a longer word
+    pub fn word_occurrences(&self, word: &str, range: Range<ByteOffset>) -> Vec<Range<ByteOffset>> {
+        let is_word_char_at = |c: Option<char>| c.is_some_and(|c| is_word_char(c, &self.extra_word_chars));
+        let searcher = Horspool::new(word.as_bytes());
+        let mut occurrences = vec![];
+        let mut start = range.start.0;
+        while let Some(pos) = searcher.find_before(&self.rope, start, range.end.0) {
+            let end = pos + word.len();
+            let before = self.rope.chars_at(self.rope.byte_to_char(pos)).prev();
+            let after = self.rope.chars_at(self.rope.byte_to_char(end)).next();
+            if !is_word_char_at(before) && !is_word_char_at(after) {
+                occurrences.push(ByteOffset(pos)..ByteOffset(end));
+            }
+            start = end;
         }
-        if next.is_numeric() && (is_midnum(prev) || is_midnumletq(prev)) && prevprev.is_some_and(|c| c.is_numeric()) {
-            return false
+        occurrences
+    }
+
+    /// Non-overlapping occurrences of `needle` within `range`
+    pub fn occurrences(&self, needle: &str, range: Range<ByteOffset>) -> Vec<Range<ByteOffset>> {
+        let searcher = Horspool::new(needle.as_bytes());
+        let mut occurrences = vec![];
+        let mut start = range.start.0;
+        while let Some(pos) = searcher.find_before(&self.rope, start, range.end.0) {
+            let end = pos + needle.len();
+            occurrences.push(ByteOffset(pos)..ByteOffset(end));
+            start = end;
         }
-        true
+        occurrences
     }
 
     fn insert_rope(&mut self, offset: ByteOffset, rope: Rope) {
@@ -140,12 +182,32 @@ impl RopeBuffer {
     fn edit_rope(&mut self, edits: &EditBatch) {
         for edit in edits.rev_iter() {
             match edit {
-                Edit::Insert(offset, s) => self.insert_rope(*offset, s.clone()),
-                Edit::Delete(range) => self.remove(range),
+                Edit::Insert(offset, s) => {
+                    let line = self.byte_to_line(*offset);
+                    self.mark_lines_modified(line, line, line + s.len_lines() - 1);
+                    self.insert_rope(*offset, s.clone());
+                }
+                Edit::Delete(range) => {
+                    let first = self.byte_to_line(range.start);
+                    self.mark_lines_modified(first, self.byte_to_line(range.end), first);
+                    self.remove(range);
+                }
             }
         }
     }
 
+    /// Records that the lines `first..=last_before` are being replaced by the
+    /// lines `first..=last_after`, the lines after them get renumbered
+    fn mark_lines_modified(&mut self, first: usize, last_before: usize, last_after: usize) {
+        let after = self.modified_lines.split_off(&first);
+        self.modified_lines.extend(first..=last_after);
+        self.modified_lines.extend(after.into_iter().filter(|&line| line > last_before).map(|line| line - last_before + last_after));
+    }
+
+    pub fn is_line_modified(&self, line: usize) -> bool {
+        self.modified_lines.contains(&line)
+    }
+
     fn inverse_of(&self, edits: &EditBatch) -> EditBatch {
         let mut inverted_edits = vec![];
         let mut n_deleted: usize = 0;
@@ -177,23 +239,37 @@ impl RopeBuffer {
     pub fn do_edits(&mut self, cursors: &mut MultiCursor, edits: EditBatch) {
         let cursors_before_edits = cursors.clone();
         let inverted = self.inverse_of(&edits);
-        self.undo.push((inverted, cursors_before_edits));
+        if self.undo.len() < self.saved_undo_len {
+            self.saved_state_lost = true;
+        }
+        self.undo.push_back((inverted, cursors_before_edits));
+        // the redo steps were made against the content before this edit
+        self.redo.clear();
         for cursor in cursors.iter_mut() {
             let original_offset = cursor.offset;
             let original_sel = cursor.selection_from;
+            // text inserted right at the end of a selection goes after it (eg. the
+            // closing bracket when surrounding) instead of extending the selection
+            let selection_end = cursor.selection().filter(|sel| !sel.is_empty()).map(|sel| sel.end);
+            let is_moved_by_insert_at = |pos: ByteOffset, offset: ByteOffset| {
+                offset < pos || (offset == pos && Some(pos) != selection_end)
+            };
             for edit in edits.iter() {
                 match edit {
                     Edit::Insert(offset, rope) => {
-                        if offset <= &original_offset {
+                        if is_moved_by_insert_at(original_offset, *offset) {
                             cursor.offset.0 += rope.len_bytes();
                         }
-                        if original_sel.is_some_and(|sel| offset <= &sel) {
+                        if original_sel.is_some_and(|sel| is_moved_by_insert_at(sel, *offset)) {
                             for sel_offset in cursor.selection_from.iter_mut() {
                                 sel_offset.0 += rope.len_bytes();
                             }
                         }
                     }
                     Edit::Delete(range) => {
+                        // the deletes of a batch are disjoint (see `EditBatch::from_edits`)
+                        // so the parts of them before an offset add up to at most the
+                        // offset itself and the subtractions can not underflow
                         if range.start <= original_offset {
                             cursor.offset.0 -= range.end.0.min(original_offset.0) - range.start.0;
                         }
@@ -209,13 +285,14 @@ impl RopeBuffer {
             }
         }
         self.edit_rope(&edits);
+        cursors.normalize(self);
     }
 
     /// Restores the last state from the undo stack (if any).
     /// Returns the updated positions of cursors.
     #[must_use]
     pub fn undo(&mut self, cursors: MultiCursor) -> MultiCursor {
-        if let Some((edits, old_cursors)) = self.undo.pop() {
+        if let Some((edits, old_cursors)) = self.undo.pop_back() {
             self.redo.push((self.inverse_of(&edits), cursors));
             self.edit_rope(&edits);
             old_cursors
@@ -229,7 +306,7 @@ impl RopeBuffer {
     #[must_use]
     pub fn redo(&mut self, cursors: MultiCursor) -> MultiCursor {
         if let Some((edits, old_cursors)) = self.redo.pop() {
-            self.undo.push((self.inverse_of(&edits), cursors));
+            self.undo.push_back((self.inverse_of(&edits), cursors));
             self.edit_rope(&edits);
             old_cursors
         } else {
@@ -237,7 +314,30 @@ impl RopeBuffer {
         }
     }
 
-    pub fn search_with_cursors_backward(&self, cursors: &mut MultiCursor, s: &str) {
+    /// Drops the oldest undo steps so that at most `max_steps` remain
+    pub fn limit_undo_history(&mut self, max_steps: usize) {
+        let excess = self.undo.len().saturating_sub(max_steps);
+        self.undo.drain(..excess);
+        if excess > self.saved_undo_len {
+            self.saved_state_lost = true;
+        }
+        self.saved_undo_len = self.saved_undo_len.saturating_sub(excess);
+    }
+
+    /// Remembers the current state as the one that was saved to disk
+    pub fn mark_saved(&mut self) {
+        self.saved_undo_len = self.undo.len();
+        self.saved_state_lost = false;
+    }
+
+    /// Whether the content is the same as when it was last saved (or loaded),
+    /// eg. after undoing all edits made since saving
+    pub fn is_at_saved_state(&self) -> bool {
+        !self.saved_state_lost && self.undo.len() == self.saved_undo_len
+    }
+
+    pub fn search_with_cursors_backward(&self, cursors: &mut MultiCursor, query: &SearchQuery) {
+        let s = &query.needle;
         let mut prev_found: Option<ByteOffset> = None;
         let mut new_cursors = vec![];
         for cursor in cursors.rev_iter() {
@@ -246,7 +346,7 @@ impl RopeBuffer {
                 None => cursor.offset,
             };
             if prev_found.is_none_or(|p| start < p) {
-                if let Some(offset) = self.find_prev(start, s) {
+                if let Some(offset) = self.find_prev_with_case(start, s, query.ignore_case) {
                     prev_found.replace(offset);
                     let match_end = ByteOffset(offset.0 + s.len());
                     new_cursors.push(Cursor::new_with_selection(offset, Some(match_end)))
@@ -266,7 +366,8 @@ impl RopeBuffer {
         cursors.set_cursors(new_primary, new_cursors);
     }
 
-    pub fn search_with_cursors(&self, cursors: &mut MultiCursor, s: &str) {
+    pub fn search_with_cursors(&self, cursors: &mut MultiCursor, query: &SearchQuery) {
+        let s = &query.needle;
         let mut prev_found: Option<ByteOffset> = None;
         let mut new_cursors = vec![];
         for cursor in cursors.iter() {
@@ -275,7 +376,7 @@ impl RopeBuffer {
                 None => cursor.offset,
             };
             if prev_found.is_none_or(|p| start > p) {
-                if let Some(offset) = self.find_next(start, s) {
+                if let Some(offset) = self.find_next_with_case(start, s, query.ignore_case) {
                     prev_found.replace(offset);
                     let match_end = ByteOffset(offset.0 + s.len());
                     new_cursors.push(Cursor::new_with_selection(offset, Some(match_end)))
@@ -296,22 +397,49 @@ impl RopeBuffer {
     }
 
     pub fn find_prev(&self, start: ByteOffset, s: &str) -> Option<ByteOffset> {
-        let c = s.bytes().next()?;
-        let first_possible_start = ByteOffset(start.0.checked_sub(s.len() - 1)?);
-        self.find_byte_positions_backwards_from(first_possible_start, c)
-            .find(|pos| s.bytes().eq(self.rope.bytes_at(pos.0).take(s.len())))
+        self.find_prev_with_case(start, s, false)
+    }
+
+    pub fn find_prev_with_case(&self, start: ByteOffset, s: &str, ignore_case: bool) -> Option<ByteOffset> {
+        match s.as_bytes() {
+            [] => None,
+            [c] if !ignore_case || !c.is_ascii_alphabetic() => self.find_byte_positions_backwards_from(start, *c).next(),
+            needle => Horspool::with_case(needle, ignore_case).rfind(&self.rope, start.0).map(ByteOffset),
+        }
     }
 
     pub fn find_next(&self, start: ByteOffset, s: &str) -> Option<ByteOffset> {
-        let c = s.bytes().next()?;
-        self.find_byte_positions_from(start, c)
-            .find(|pos| s.bytes().eq(self.rope.bytes_at(pos.0).take(s.len())))
+        self.find_next_with_case(start, s, false)
+    }
+
+    pub fn find_next_with_case(&self, start: ByteOffset, s: &str, ignore_case: bool) -> Option<ByteOffset> {
+        match s.as_bytes() {
+            [] => None,
+            [c] if !ignore_case || !c.is_ascii_alphabetic() => self.find_byte_positions_from(start, *c).next(),
+            needle => Horspool::with_case(needle, ignore_case).find(&self.rope, start.0).map(ByteOffset),
+        }
     }
 
     pub fn find_next_cycle(&self, start: ByteOffset, s: &str) -> Option<ByteOffset> {
         self.find_next(start, s).or_else(|| self.find_next(ByteOffset(0), s))
     }
 
+    /// Returns the edits that replace every occurrence of `old` with `new` and
+    /// the number of occurrences. Searching continues from the end of the
+    /// previous occurrence so the replaced ranges never overlap.
+    pub fn replace_all_edits(&self, old: &str, new: &str) -> (EditBatch, usize) {
+        let mut edits = vec![];
+        let mut count = 0;
+        let mut start = ByteOffset(0);
+        while let Some(offset) = self.find_next(start, old) {
+            edits.push(Edit::insert_str(offset, new));
+            edits.push(Edit::delete(offset, old.len()));
+            count += 1;
+            start = ByteOffset(offset.0 + old.len());
+        }
+        (EditBatch::from_edits(edits), count)
+    }
+
     fn find_byte_positions_backwards_from(&self, from: ByteOffset, c: u8) -> impl Iterator<Item = ByteOffset> {
         // note that .reversed() is different than .rev():
         // it iterates backwards from the *CURRENT* position of the iterator
@@ -363,9 +491,144 @@ impl Display for RopeBuffer {
     }
 }
 
+/// Returns the most common line ending in `content`, LF if there are none.
+/// Ties are resolved in favor of LF and then CRLF.
+pub(crate) fn detect_line_ending(content: &RopeBuffer) -> &'static str {
+    let (mut lf, mut crlf, mut cr) = (0, 0, 0);
+    let mut after_cr = false;
+    for byte in content.rope.bytes() {
+        match byte {
+            b'\n' if after_cr => crlf += 1,
+            b'\n' => lf += 1,
+            _ if after_cr => cr += 1,
+            _ => {}
+        }
+        after_cr = byte == b'\r';
+    }
+    if after_cr {
+        cr += 1;
+    }
+    if crlf > lf && crlf >= cr {
+        "\r\n"
+    } else if cr > lf && cr > crlf {
+        "\r"
+    } else {
+        "\n"
+    }
+}
+
+/// How many lines from the start of a file are looked at when detecting its
+/// indentation
+const INDENT_DETECTION_LINES: usize = 1000;
+
+/// Guesses the indentation of `content` from its first lines. Returns `None`
+/// if there is nothing to go by. The size is the number of tabs or spaces per
+/// indentation level, it is guessed from the most common change in the
+/// indentation between consecutive non-blank lines.
+pub(crate) fn detect_indentation(content: &RopeBuffer) -> Option<(IndentKind, usize)> {
+    let (mut tab_lines, mut space_lines) = (0, 0);
+    let mut changes = [0usize; 9];
+    let mut previous_spaces = 0;
+    for line in content.lines().take(INDENT_DETECTION_LINES) {
+        let mut chars = line.chars();
+        let (mut tabs, mut spaces) = (0, 0usize);
+        let blank = loop {
+            match chars.next() {
+                Some('\t') if spaces == 0 => tabs += 1,
+                Some(' ') => spaces += 1,
+                None | Some('\n' | '\r') => break true,
+                _ => break false,
+            }
+        };
+        if blank {
+            continue;
+        }
+        if tabs > 0 {
+            tab_lines += 1;
+            continue;
+        }
+        if spaces > 0 {
+            space_lines += 1;
+        }
+        let change = spaces.abs_diff(previous_spaces);
+        if (2..changes.len()).contains(&change) {
+            changes[change] += 1;
+        }
+        previous_spaces = spaces;
+    }
+    if tab_lines == 0 && space_lines == 0 {
+        None
+    } else if tab_lines > space_lines {
+        Some((IndentKind::Tabs, 1))
+    } else {
+        let (size, &count) = changes.iter().enumerate().rev().max_by_key(|&(_, count)| count)?;
+        (count > 0).then_some((IndentKind::Spaces, size))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+    use proptest::sample::Index;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::empty("", "\n")]
+    #[case::no_line_breaks("abc", "\n")]
+    #[case::lf("a\nb\n", "\n")]
+    #[case::crlf("a\r\nb\r\n", "\r\n")]
+    #[case::cr("a\rb\r", "\r")]
+    #[case::mostly_crlf("a\r\nb\nc\r\n", "\r\n")]
+    #[case::mostly_lf("a\r\nb\nc\n\r", "\n")]
+    #[case::tie("a\r\nb\n", "\n")]
+    #[case::consecutive_cr("\r\r\r\n", "\r")]
+    fn line_ending_is_detected(#[case] text: &str, #[case] expected: &str) {
+        assert_eq!(detect_line_ending(&RopeBuffer::from_str(text)), expected);
+    }
+
+    #[rstest]
+    #[case::empty("", None)]
+    #[case::no_indentation("a\nb\n", None)]
+    #[case::tabs("fn a() {\n\tif b {\n\t\tc();\n\t}\n}\n", Some((IndentKind::Tabs, 1)))]
+    #[case::two_spaces("a:\n  b:\n    c: 1\n  d: 2\n", Some((IndentKind::Spaces, 2)))]
+    #[case::four_spaces("def a():\n    if b:\n        c()\n\n    return d\n", Some((IndentKind::Spaces, 4)))]
+    #[case::mostly_tabs("\ta\n\tb\n    c\n", Some((IndentKind::Tabs, 1)))]
+    #[case::doc_comment_spaces("/**\n * a\n */\nb\n", None)]
+    #[case::blank_lines_are_skipped("a\n  b\n  \n\n  c\nd\n", Some((IndentKind::Spaces, 2)))]
+    fn indentation_is_detected(#[case] text: &str, #[case] expected: Option<(IndentKind, usize)>) {
+        assert_eq!(detect_indentation(&RopeBuffer::from_str(text)), expected);
+    }
+
+    #[test]
+    fn word_under_cursor_and_its_occurrences() {
+        let text = "let föö = föö_bar + föö;\nföö";
+        let r = RopeBuffer::from_str(text);
+        assert_eq!(r.word_at(ByteOffset(10)), None);
+        assert_eq!(r.word_at(ByteOffset(3)), Some(ByteOffset(0)..ByteOffset(3)));
+        assert_eq!(r.word_at(ByteOffset(0)), Some(ByteOffset(0)..ByteOffset(3)));
+        assert_eq!(r.word_at(ByteOffset(7)), Some(ByteOffset(4)..ByteOffset(9)));
+        assert_eq!(r.word_at(ByteOffset(9)), Some(ByteOffset(4)..ByteOffset(9)));
+        assert_eq!(r.word_at(ByteOffset(15)), Some(ByteOffset(12)..ByteOffset(21)));
+        let all = ByteOffset(0)..ByteOffset(text.len());
+        assert_eq!(
+            r.word_occurrences("föö", all),
+            vec![ByteOffset(4)..ByteOffset(9), ByteOffset(24)..ByteOffset(29), ByteOffset(31)..ByteOffset(36)],
+        );
+        assert_eq!(r.word_occurrences("föö", ByteOffset(5)..ByteOffset(29)), vec![ByteOffset(24)..ByteOffset(29)]);
+    }
+
+    #[test]
+    fn occurrences_within_range() {
+        let r = RopeBuffer::from_str("aaaa foo_bar(foo) foo\nfoo");
+        let first_line = ByteOffset(0)..ByteOffset(22);
+        assert_eq!(
+            r.occurrences("foo", first_line.clone()),
+            vec![ByteOffset(5)..ByteOffset(8), ByteOffset(13)..ByteOffset(16), ByteOffset(18)..ByteOffset(21)],
+        );
+        assert_eq!(r.occurrences("aa", first_line), vec![ByteOffset(0)..ByteOffset(2), ByteOffset(2)..ByteOffset(4)]);
+        assert_eq!(r.occurrences("foo", ByteOffset(14)..ByteOffset(20)), vec![]);
+    }
 
     #[test]
     fn find_bytes() {
@@ -399,6 +662,31 @@ mod tests {
         assert_eq!(r.find_next(ByteOffset(4), "abc"), None);
     }
 
+    #[test]
+    fn replace_all_occurrences() {
+        let mut r = RopeBuffer::from_str("aaaa");
+        let mut cursors = MultiCursor::new();
+        let (edits, count) = r.replace_all_edits("aa", "a");
+        assert_eq!(count, 2);
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), "aa");
+
+        let mut r = RopeBuffer::from_str("föö bar föö\nföö");
+        let (edits, count) = r.replace_all_edits("föö", "x");
+        assert_eq!(count, 3);
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), "x bar x\nx");
+
+        let (edits, count) = r.replace_all_edits("x", "xx");
+        assert_eq!(count, 3);
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), "xx bar xx\nxx");
+
+        let (edits, count) = r.replace_all_edits("", "y");
+        assert_eq!(count, 0);
+        assert!(edits.is_empty());
+    }
+
     #[test]
     fn delete_at_eof() {
         let mut r = RopeBuffer::from_str("abc");
@@ -412,6 +700,226 @@ mod tests {
         assert_eq!(r.to_string(), "ab");
     }
 
+    /// A cursor (and the start of its selection) at the char boundaries
+    /// picked by the indices from whatever content the test generated
+    type CursorSpec = (Index, Option<Index>);
+
+    #[derive(Debug, Clone)]
+    enum EditSpec {
+        Insert(Index, &'static str),
+        Delete(Index, Index),
+    }
+
+    fn char_boundary(content: &RopeBuffer, index: &Index) -> ByteOffset {
+        let text = content.to_string();
+        let boundaries: Vec<usize> = (0..=text.len()).filter(|&i| text.is_char_boundary(i)).collect();
+        ByteOffset(*index.get(&boundaries))
+    }
+
+    fn text_of(chars: &'static [char]) -> impl Strategy<Value = String> {
+        prop::collection::vec(prop::sample::select(chars), 0..16).prop_map(String::from_iter)
+    }
+
+    fn cursor_specs() -> impl Strategy<Value = Vec<CursorSpec>> {
+        prop::collection::vec((any::<Index>(), prop::option::of(any::<Index>())), 1..5)
+    }
+
+    /// Inserts (possibly multibyte) and deletes
+    fn edit_specs() -> impl Strategy<Value = Vec<EditSpec>> {
+        let edit = prop_oneof![
+            1 => (any::<Index>(), prop::sample::select(vec!["x", "yy\n", "ö"]))
+                .prop_map(|(at, s)| EditSpec::Insert(at, s)),
+            2 => (any::<Index>(), any::<Index>()).prop_map(|(a, b)| EditSpec::Delete(a, b)),
+        ];
+        prop::collection::vec(edit, 0..6)
+    }
+
+    fn cursors_at(content: &RopeBuffer, specs: &[CursorSpec]) -> MultiCursor {
+        let mut cursors = MultiCursor::new();
+        for (i, (offset, selection_from)) in specs.iter().enumerate() {
+            let offset = char_boundary(content, offset);
+            let selection_from = selection_from.as_ref().map(|from| char_boundary(content, from));
+            let cursor = Cursor::new_with_selection(offset, selection_from);
+            if i == 0 {
+                *cursors.primary_mut() = cursor;
+            } else {
+                cursors.spawn_new(content, cursor);
+            }
+        }
+        cursors
+    }
+
+    fn edits_at(content: &RopeBuffer, specs: &[EditSpec]) -> Vec<Edit> {
+        specs
+            .iter()
+            .map(|spec| match spec {
+                EditSpec::Insert(at, s) => Edit::insert_str(char_boundary(content, at), s),
+                EditSpec::Delete(a, b) => {
+                    let (a, b) = (char_boundary(content, a), char_boundary(content, b));
+                    Edit::Delete(a.min(b)..a.max(b))
+                }
+            })
+            .collect()
+    }
+
+    fn cursor_positions(cursors: &MultiCursor) -> Vec<(ByteOffset, Option<ByteOffset>)> {
+        cursors.iter().map(|cursor| (cursor.offset, cursor.selection_from)).collect()
+    }
+
+    proptest! {
+        #[test]
+        fn random_edit_batches_keep_cursors_in_bounds(
+            text in text_of(&['a', 'b', '\n']),
+            cursors in cursor_specs(),
+            edits in edit_specs(),
+        ) {
+            let mut r = RopeBuffer::from_str(&text);
+            let mut cursors = cursors_at(&r, &cursors);
+            let edits = edits_at(&r, &edits);
+            r.do_edits(&mut cursors, EditBatch::from_edits(edits));
+            for cursor in cursors.iter() {
+                prop_assert!(cursor.offset.0 <= r.len_bytes());
+                prop_assert!(cursor.selection_from.is_none_or(|sel| sel.0 <= r.len_bytes()));
+            }
+            let _ = r.undo(cursors);
+            prop_assert_eq!(r.to_string(), text);
+        }
+
+        #[test]
+        fn undoing_random_edit_batches_restores_everything(
+            text in text_of(&['a', 'ä', '\n']),
+            cursors in cursor_specs(),
+            batches in prop::collection::vec(edit_specs(), 1..6),
+        ) {
+            let mut r = RopeBuffer::from_str(&text);
+            let mut cursors = cursors_at(&r, &cursors);
+            let mut history = vec![];
+            for batch in &batches {
+                let edits = edits_at(&r, batch);
+                history.push((r.to_string(), cursor_positions(&cursors), format!("{edits:?}")));
+                r.do_edits(&mut cursors, EditBatch::from_edits(edits));
+            }
+            let final_content = r.to_string();
+            let final_cursors = cursor_positions(&cursors);
+            for (content, positions, edits) in history.iter().rev() {
+                cursors = r.undo(cursors);
+                prop_assert_eq!(&r.to_string(), content, "undoing {}", edits);
+                prop_assert_eq!(&cursor_positions(&cursors), positions, "undoing {}", edits);
+            }
+            prop_assert_eq!(r.to_string(), text);
+            for _ in 0..history.len() {
+                cursors = r.redo(cursors);
+            }
+            prop_assert_eq!(r.to_string(), final_content);
+            prop_assert_eq!(cursor_positions(&cursors), final_cursors);
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_restore_content_and_cursors() {
+        let mut r = RopeBuffer::new();
+        let mut cursors = MultiCursor::new();
+        let mut states = vec![(r.to_string(), cursors.primary().offset)];
+        for s in ["hello", " world"] {
+            let edits = EditBatch::insert_with_cursors(&cursors, s);
+            r.do_edits(&mut cursors, edits);
+            states.push((r.to_string(), cursors.primary().offset));
+        }
+        for (content, offset) in states.iter().rev().skip(1) {
+            cursors = r.undo(cursors);
+            assert_eq!((&r.to_string(), cursors.primary().offset), (content, *offset));
+        }
+        cursors = r.undo(cursors);
+        assert_eq!(r.to_string(), "");
+        for (content, offset) in states.iter().skip(1) {
+            cursors = r.redo(cursors);
+            assert_eq!((&r.to_string(), cursors.primary().offset), (content, *offset));
+        }
+        cursors = r.redo(cursors);
+        assert_eq!((r.to_string().as_str(), cursors.primary().offset), ("hello world", ByteOffset(11)));
+    }
+
+    #[test]
+    fn modified_lines_follow_edits() {
+        let mut r = RopeBuffer::from_str("a\nb\nc\nd\n");
+        let mut cursors = MultiCursor::new();
+        let modified = |r: &RopeBuffer| (0..r.len_lines()).filter(|&line| r.is_line_modified(line)).collect::<Vec<_>>();
+        cursors.move_to(&r, crate::MoveTarget::ByteOffset(6));
+        let edits = EditBatch::insert_with_cursors(&cursors, "x");
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(modified(&r), [3]);
+        cursors.move_to(&r, crate::MoveTarget::ByteOffset(1));
+        let edits = EditBatch::insert_with_cursors(&cursors, "\n\n");
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), "a\n\n\nb\nc\nxd\n");
+        assert_eq!(modified(&r), [0, 1, 2, 5]);
+        let edits = EditBatch::from_edits(vec![Edit::Delete(ByteOffset(1)..ByteOffset(5))]);
+        r.do_edits(&mut cursors, edits);
+        assert_eq!(r.to_string(), "a\nc\nxd\n");
+        assert_eq!(modified(&r), [0, 2]);
+        // undoing the deletion puts back the line "b" so it counts as modified too
+        cursors = r.undo(cursors);
+        let _ = r.undo(cursors);
+        assert_eq!(r.to_string(), "a\nb\nc\nxd\n");
+        assert_eq!(modified(&r), [0, 1, 3]);
+    }
+
+    #[test]
+    fn new_edit_discards_redo_steps() {
+        let mut r = RopeBuffer::new();
+        let mut cursors = MultiCursor::new();
+        let edits = EditBatch::insert_with_cursors(&cursors, "hello");
+        r.do_edits(&mut cursors, edits);
+        cursors = r.undo(cursors);
+        let edits = EditBatch::insert_with_cursors(&cursors, "hi");
+        r.do_edits(&mut cursors, edits);
+        cursors = r.redo(cursors);
+        assert_eq!(r.to_string(), "hi");
+        assert_eq!(cursors.primary().offset, ByteOffset(2));
+        cursors = r.undo(cursors);
+        assert_eq!(r.to_string(), "");
+        assert_eq!(cursors.primary().offset, ByteOffset(0));
+    }
+
+    #[test]
+    fn saved_state_is_tracked_through_undo_and_redo() {
+        let mut r = RopeBuffer::from_str("a");
+        let mut cursors = MultiCursor::new();
+        assert!(r.is_at_saved_state());
+        let edits = EditBatch::insert_with_cursors(&cursors, "b");
+        r.do_edits(&mut cursors, edits);
+        assert!(!r.is_at_saved_state());
+        cursors = r.undo(cursors);
+        assert!(r.is_at_saved_state());
+        cursors = r.redo(cursors);
+        r.mark_saved();
+        cursors = r.undo(cursors);
+        assert!(!r.is_at_saved_state());
+        // the saved state was on the redo stack that gets discarded by a new edit
+        let edits = EditBatch::insert_with_cursors(&cursors, "c");
+        r.do_edits(&mut cursors, edits);
+        cursors = r.undo(cursors);
+        assert_eq!(r.to_string(), "a");
+        let _ = r.redo(cursors);
+        assert!(!r.is_at_saved_state());
+    }
+
+    #[test]
+    fn undo_history_limit_drops_oldest_steps() {
+        let mut r = RopeBuffer::new();
+        let mut cursors = MultiCursor::new();
+        for s in ["a", "b", "c"] {
+            let edits = EditBatch::insert_with_cursors(&cursors, s);
+            r.do_edits(&mut cursors, edits);
+            r.limit_undo_history(2);
+        }
+        assert_eq!(r.undo.len(), 2);
+        for _ in 0..3 {
+            cursors = r.undo(cursors);
+        }
+        assert_eq!(r.to_string(), "a");
+    }
+
     #[test]
     fn word_boundary_hello_world() {
         let r = RopeBuffer::from_str("hello world");
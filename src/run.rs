@@ -1,9 +1,42 @@
 use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 
-use crossterm::event::{self, KeyCode, KeyEvent, KeyModifiers, MouseEventKind};
+use crossterm::event::{self, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEventKind};
 
-use crate::{Action, App, MoveTarget, PaneAction};
+use crate::{Action, App, CharSearch, MoveTarget, PaneAction};
+
+/// Set by the SIGCONT handler when the editor is resumed after being stopped
+static RESUMED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_sigcont(_signal: libc::c_int) {
+    // only async-signal-safe operations are allowed here
+    RESUMED.store(true, Ordering::Relaxed);
+}
+
+/// The shell restores the terminal to cooked mode when a job is stopped, so
+/// after resuming we need to set it up the same way main.rs does on startup.
+fn restore_terminal_after_resume(mut out: &mut dyn std::io::Write) -> std::io::Result<()> {
+    use crossterm::cursor::Hide as HideCursor;
+    use crossterm::event::{
+        EnableBracketedPaste,
+        EnableMouseCapture,
+        KeyboardEnhancementFlags,
+        PushKeyboardEnhancementFlags,
+    };
+    use crossterm::terminal::{Clear, ClearType, EnterAlternateScreen};
+
+    crossterm::terminal::enable_raw_mode()?;
+    crossterm::execute!(
+        &mut out,
+        EnterAlternateScreen,
+        HideCursor,
+        EnableMouseCapture,
+        EnableBracketedPaste,
+        PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES),
+        Clear(ClearType::All)
+    )
+}
 
 enum AfterActions {
     Render,
@@ -19,18 +52,48 @@ impl App {
 
         const POLL_TIMEOUT: Duration = Duration::from_millis(16);
 
+        unsafe {
+            libc::signal(libc::SIGCONT, handle_sigcont as *const () as libc::sighandler_t);
+        }
+
         let mut need_to_render = true;
         let mut wsize = crossterm::terminal::window_size()?;
 
         loop {
             let frame = Instant::now();
+            if RESUMED.swap(false, Ordering::Relaxed) {
+                restore_terminal_after_resume(&mut out)?;
+                need_to_render = true;
+            }
             if need_to_render {
+                // Resize events are only delivered while we are polling, so the size
+                // may have changed while the editor was stopped or blocked in a prompt.
+                if let Ok(new_wsize) = crossterm::terminal::window_size() {
+                    wsize = new_wsize;
+                }
                 self.current_pane_mut().update_viewport_size(wsize.columns, wsize.rows.saturating_sub(2));
+                // counting can scan megabytes of text so it is done before rendering starts
+                self.current_pane_mut().update_word_count();
                 self.render(&mut out, &wsize)?;
             }
             while crossterm::event::poll(POLL_TIMEOUT.saturating_sub(frame.elapsed()))? {
                 let event = crossterm::event::read()?;
-                let action = get_action(&event);
+                // the character is captured right away because the action that started
+                // waiting for it may not have been processed yet, any other key cancels
+                // the wait but mouse and resize events leave it pending
+                let awaiting_char = match event {
+                    event::Event::Key(_) => self.awaiting_char.take(),
+                    _ => None,
+                };
+                let action = match (awaiting_char, &event) {
+                    (Some(f), event::Event::Key(KeyEvent { code: KeyCode::Char(c), modifiers, .. }))
+                        if (*modifiers - KeyModifiers::SHIFT).is_empty() => f(*c),
+                    _ => get_action(&event),
+                };
+                if let Action::AwaitChar(f) = action {
+                    self.awaiting_char = Some(f);
+                    continue
+                }
                 if let Action::Resize(columns, rows) = action {
                     wsize.columns = columns;
                     wsize.rows = rows;
@@ -38,9 +101,20 @@ impl App {
                 self.enqueue(action);
             }
             match self.process_queued_actions() {
-                AfterActions::Quit => return Ok(()),
+                AfterActions::Quit => {
+                    self.remove_swap_files();
+                    return Ok(())
+                }
                 AfterActions::Render => need_to_render = true,
-                AfterActions::Noop => need_to_render = false,
+                AfterActions::Noop => {
+                    need_to_render = self.autosave_idle_panes();
+                    need_to_render |= self.current_pane_mut().word_highlight_became_due();
+                    #[cfg(feature = "lsp")]
+                    {
+                        need_to_render |= self.sync_language_servers();
+                    }
+                    self.update_swap_files();
+                }
             }
         }
     }
@@ -76,6 +150,7 @@ pub fn get_action(ev: &event::Event) -> Action {
         Mouse(ev) => match ev.kind {
             MouseEventKind::ScrollUp => Action::HandledByPane(PaneAction::ScrollUp(1)),
             MouseEventKind::ScrollDown => Action::HandledByPane(PaneAction::ScrollDown(1)),
+            MouseEventKind::Down(MouseButton::Middle) => Action::PastePrimary,
             MouseEventKind::Down(_) => Action::None,
             MouseEventKind::Up(_) => Action::None,
             MouseEventKind::Drag(_) => Action::None,
@@ -106,12 +181,30 @@ pub fn get_action(ev: &event::Event) -> Action {
                 KeyCode::Char('x') if ctrl => Action::Cut,
                 KeyCode::Char('v') if ctrl => Action::Paste,
                 KeyCode::Char('a') if ctrl => Action::HandledByPane(PaneAction::SelectAll),
+                // most terminals send Ctrl+/ as Ctrl+7 unless they support Kitty Keyboard Protocol
+                KeyCode::Char('/' | '7') if ctrl => Action::HandledByPane(PaneAction::ToggleComment),
                 KeyCode::Char('s') if ctrl => Action::Save,
                 KeyCode::Char(c @ '1'..='9') if alt => Action::GoToPane((c as u8 - b'1') as usize),
+                KeyCode::Char('o') if alt => Action::Command("gotofile".into()),
+                KeyCode::Char('c') if alt => Action::HandledByPane(PaneAction::NextConflict),
+                KeyCode::Char('s') if alt => Action::HandledByPane(PaneAction::SwapSelections),
+                // Ctrl+Shift+L can only be told apart from Ctrl+L with Kitty Keyboard Protocol
+                KeyCode::Char('l' | 'L') if ctrl && shift => Action::HandledByPane(PaneAction::CursorAtLineEnds),
+                KeyCode::Char('l') if alt => Action::HandledByPane(PaneAction::CursorAtLineEnds),
                 KeyCode::Char('M') if alt =>
                     Action::HandledByPane(PaneAction::SelectTo(MoveTarget::MatchingPair)),
                 KeyCode::Char('m') if alt =>
                     Action::HandledByPane(PaneAction::MoveTo(MoveTarget::MatchingPair)),
+                KeyCode::Char('f') if alt =>
+                    Action::AwaitChar(|target| Action::CharSearch(CharSearch { target, till: false, backward: false })),
+                KeyCode::Char('F') if alt =>
+                    Action::AwaitChar(|target| Action::CharSearch(CharSearch { target, till: false, backward: true })),
+                KeyCode::Char('t') if alt =>
+                    Action::AwaitChar(|target| Action::CharSearch(CharSearch { target, till: true, backward: false })),
+                KeyCode::Char('T') if alt =>
+                    Action::AwaitChar(|target| Action::CharSearch(CharSearch { target, till: true, backward: true })),
+                KeyCode::Char(';') if alt => Action::RepeatCharSearch { reverse: false },
+                KeyCode::Char(',') if alt => Action::RepeatCharSearch { reverse: true },
                 KeyCode::Char(c) if only_shift => Action::HandledByPane(PaneAction::Insert(c.to_string())),
                 KeyCode::Up =>
                     if alt && shift { Action::HandledByPane(PaneAction::SpawnMultiCursorTo(MoveTarget::Up(1))) }
@@ -147,6 +240,8 @@ pub fn get_action(ev: &event::Event) -> Action {
                 KeyCode::End =>
                     if shift { Action::HandledByPane(PaneAction::SelectTo(MoveTarget::EndOfLine)) }
                     else     { Action::HandledByPane(PaneAction::MoveTo(MoveTarget::EndOfLine)) },
+                KeyCode::PageUp if alt => Action::HandledByPane(PaneAction::CursorToViewportTop),
+                KeyCode::PageDown if alt => Action::HandledByPane(PaneAction::CursorToViewportBottom),
                 KeyCode::PageUp => Action::HandledByPane(PaneAction::MoveTo(MoveTarget::Up(25))),
                 KeyCode::PageDown => Action::HandledByPane(PaneAction::MoveTo(MoveTarget::Down(25))),
                 KeyCode::Enter =>
@@ -162,6 +257,7 @@ pub fn get_action(ev: &event::Event) -> Action {
                 KeyCode::Delete => Action::HandledByPane(PaneAction::DeleteForward),
                 KeyCode::F(5) => Action::Command("exec".into()),
                 KeyCode::F(6) => Action::Command("lint".into()),
+                KeyCode::F(7) => Action::Command("set theme toggle".into()),
                 KeyCode::Esc => Action::Esc,
                 _ => Action::SetInfo(format!("{kevent:?}")),
             }
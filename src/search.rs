@@ -0,0 +1,178 @@
+use ropey::Rope;
+
+/// What to search for, `ignore_case` makes ASCII letters match regardless of
+/// their case
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchQuery {
+    pub needle: String,
+    pub ignore_case: bool,
+}
+
+impl SearchQuery {
+    /// With `smartcase` the search ignores case unless the needle contains an
+    /// uppercase letter
+    pub fn new(needle: String, smartcase: bool) -> Self {
+        let ignore_case = smartcase && !needle.chars().any(char::is_uppercase);
+        Self { needle, ignore_case }
+    }
+}
+
+/// Random access to the bytes of a rope that remembers the last chunk it
+/// looked at, most lookups during a search hit the same chunk so they don't
+/// need to walk the tree.
+struct ChunkCursor<'a> {
+    rope: &'a Rope,
+    chunk: &'a [u8],
+    chunk_start: usize,
+}
+
+impl<'a> ChunkCursor<'a> {
+    fn new(rope: &'a Rope) -> Self {
+        Self { rope, chunk: &[], chunk_start: 0 }
+    }
+
+    fn byte(&mut self, idx: usize) -> u8 {
+        if idx < self.chunk_start || idx >= self.chunk_start + self.chunk.len() {
+            let (chunk, chunk_start, _, _) = self.rope.chunk_at_byte(idx);
+            self.chunk = chunk.as_bytes();
+            self.chunk_start = chunk_start;
+        }
+        self.chunk[idx - self.chunk_start]
+    }
+
+    fn matches_at(&mut self, idx: usize, needle: &[u8], ignore_case: bool) -> bool {
+        needle.iter().enumerate().all(|(i, b)| bytes_match(self.byte(idx + i), *b, ignore_case))
+    }
+}
+
+fn bytes_match(a: u8, b: u8, ignore_case: bool) -> bool {
+    if ignore_case { a.eq_ignore_ascii_case(&b) } else { a == b }
+}
+
+/// Substring search using the Boyer–Moore–Horspool algorithm. Unlike
+/// comparing the needle at every position where its first byte occurs, most
+/// of the haystack gets skipped over when the needle is long.
+pub(crate) struct Horspool<'n> {
+    needle: &'n [u8],
+    ignore_case: bool,
+    /// How far the window can move forward based on the byte under its last position
+    forward_shift: [usize; 256],
+    /// How far the window can move backward based on the byte under its first position
+    backward_shift: [usize; 256],
+}
+
+impl<'n> Horspool<'n> {
+    pub(crate) fn new(needle: &'n [u8]) -> Self {
+        Self::with_case(needle, false)
+    }
+
+    /// Like `new` but ASCII letters in the needle match both cases if
+    /// `ignore_case` is set
+    pub(crate) fn with_case(needle: &'n [u8], ignore_case: bool) -> Self {
+        let n = needle.len();
+        let mut forward_shift = [n; 256];
+        let mut backward_shift = [n; 256];
+        let variants = |b: u8| if ignore_case { [b.to_ascii_lowercase(), b.to_ascii_uppercase()] } else { [b, b] };
+        for (i, &b) in needle.iter().enumerate().take(n.saturating_sub(1)) {
+            for v in variants(b) {
+                forward_shift[v as usize] = n - 1 - i;
+            }
+        }
+        for (i, &b) in needle.iter().enumerate().skip(1).rev() {
+            for v in variants(b) {
+                backward_shift[v as usize] = i;
+            }
+        }
+        Self { needle, ignore_case, forward_shift, backward_shift }
+    }
+
+    /// Returns the first position `>= start` where the needle occurs
+    pub(crate) fn find(&self, rope: &Rope, start: usize) -> Option<usize> {
+        self.find_before(rope, start, rope.len_bytes())
+    }
+
+    /// Returns the first position `>= start` where the needle occurs such
+    /// that the match ends at or before `end`
+    pub(crate) fn find_before(&self, rope: &Rope, start: usize, end: usize) -> Option<usize> {
+        let n = self.needle.len();
+        let last = *self.needle.last()?;
+        let mut bytes = ChunkCursor::new(rope);
+        let mut pos = start;
+        while pos + n <= end.min(rope.len_bytes()) {
+            let b = bytes.byte(pos + n - 1);
+            if bytes_match(b, last, self.ignore_case) && bytes.matches_at(pos, &self.needle[..n - 1], self.ignore_case) {
+                return Some(pos)
+            }
+            pos += self.forward_shift[b as usize];
+        }
+        None
+    }
+
+    /// Returns the last position where the needle occurs such that the
+    /// match ends at or before `end`
+    pub(crate) fn rfind(&self, rope: &Rope, end: usize) -> Option<usize> {
+        let first = *self.needle.first()?;
+        let mut bytes = ChunkCursor::new(rope);
+        let mut pos = end.min(rope.len_bytes()).checked_sub(self.needle.len())?;
+        loop {
+            let b = bytes.byte(pos);
+            if bytes_match(b, first, self.ignore_case) && bytes.matches_at(pos + 1, &self.needle[1..], self.ignore_case) {
+                return Some(pos)
+            }
+            pos = pos.checked_sub(self.backward_shift[b as usize])?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naive_find(haystack: &str, needle: &str, start: usize) -> Option<usize> {
+        (start..=haystack.len().checked_sub(needle.len())?)
+            .find(|&i| haystack.as_bytes()[i..].starts_with(needle.as_bytes()))
+    }
+
+    fn naive_rfind(haystack: &str, needle: &str, end: usize) -> Option<usize> {
+        (0..=end.checked_sub(needle.len())?)
+            .rev()
+            .find(|&i| haystack.as_bytes()[i..].starts_with(needle.as_bytes()))
+    }
+
+    #[test]
+    fn agrees_with_naive_search() {
+        // large enough to span multiple chunks of the rope
+        let haystack = "abracadabra eee ab e abr ".repeat(500);
+        let rope = Rope::from_str(&haystack);
+        for needle in ["a", "ab", "abra", "e a", "eee", "r e", "cadabra eee ab", "x", "abracadabra eee ab e abr abracadabra"] {
+            let searcher = Horspool::new(needle.as_bytes());
+            for i in (0..=haystack.len()).step_by(37) {
+                assert_eq!(searcher.find(&rope, i), naive_find(&haystack, needle, i), "find {needle:?} from {i}");
+                assert_eq!(searcher.find_before(&rope, i, i + 100), naive_find(&haystack[..(i + 100).min(haystack.len())], needle, i), "find {needle:?} from {i} before {}", i + 100);
+                assert_eq!(searcher.rfind(&rope, i), naive_rfind(&haystack, needle, i), "rfind {needle:?} before {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn ignoring_case_agrees_with_naive_search_of_lowercase() {
+        let haystack = "AbraCadabra eEe aB e ABR ".repeat(500);
+        let lowercase = haystack.to_ascii_lowercase();
+        let rope = Rope::from_str(&haystack);
+        for needle in ["a", "Ab", "abra", "E A", "eee", "cadabra EEE ab", "x"] {
+            let searcher = Horspool::with_case(needle.as_bytes(), true);
+            let needle = needle.to_ascii_lowercase();
+            for i in (0..=haystack.len()).step_by(37) {
+                assert_eq!(searcher.find(&rope, i), naive_find(&lowercase, &needle, i), "find {needle:?} from {i}");
+                assert_eq!(searcher.rfind(&rope, i), naive_rfind(&lowercase, &needle, i), "rfind {needle:?} before {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn empty_needle_is_never_found() {
+        let rope = Rope::from_str("abc");
+        assert_eq!(Horspool::new(b"").find(&rope, 0), None);
+        assert_eq!(Horspool::new(b"").rfind(&rope, 3), None);
+    }
+}
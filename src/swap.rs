@@ -0,0 +1,208 @@
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::ropebuffer::RopeBuffer;
+
+/// Crash recovery file that holds the unsaved contents of a buffer.
+///
+/// The swap file lives next to the edited file as `.<name>.bad.swp`. The first
+/// line contains the PID of the editor that wrote it and the rest of the file is
+/// the buffer content as-is.
+#[derive(Debug)]
+pub struct SwapFile {
+    path: PathBuf,
+    last_written: Option<Instant>,
+    /// The swap file was left behind by an earlier session and has not been
+    /// recovered (or discarded by saving) yet, so it must not be overwritten.
+    pub(crate) pending_recovery: bool,
+}
+
+pub enum SwapState {
+    /// No swap file exists for the file
+    Clean,
+    /// A swap file left behind by an editor that is no longer running
+    Recoverable,
+    /// Another running instance of the editor is editing the file
+    InUse { pid: u32 },
+    /// The file is already open in another pane of this editor
+    OpenInThisSession,
+}
+
+impl SwapFile {
+    /// How long the buffer has to stay untouched before the swap file is updated
+    const IDLE_TIME_BEFORE_WRITE: Duration = Duration::from_secs(2);
+
+    pub fn path_for(file_path: &Path) -> Option<PathBuf> {
+        let name = file_path.file_name()?.to_string_lossy();
+        let dir = file_path.parent()?;
+        Some(dir.join(format!(".{name}.bad.swp")))
+    }
+
+    /// Checks if there is a swap file for `file_path` and returns a handle
+    /// for writing it if it is safe for this process to do so.
+    pub fn open_for(file_path: &Path) -> (Option<SwapFile>, SwapState) {
+        let Some(path) = Self::path_for(file_path) else {
+            return (None, SwapState::Clean)
+        };
+        match read_pid(&path) {
+            None => {
+                let swap = SwapFile { path, last_written: None, pending_recovery: false };
+                (Some(swap), SwapState::Clean)
+            }
+            Some(pid) if pid == std::process::id() => (None, SwapState::OpenInThisSession),
+            Some(pid) if is_process_alive(pid) => (None, SwapState::InUse { pid }),
+            Some(_) => {
+                let swap = SwapFile { path, last_written: None, pending_recovery: true };
+                (Some(swap), SwapState::Recoverable)
+            }
+        }
+    }
+
+    pub fn needs_update(&self, last_edit: Option<Instant>) -> bool {
+        if self.pending_recovery {
+            return false
+        }
+        match last_edit {
+            Some(edited) => {
+                edited.elapsed() >= Self::IDLE_TIME_BEFORE_WRITE
+                    && self.last_written.is_none_or(|written| written < edited)
+            }
+            None => false,
+        }
+    }
+
+    pub fn write(&mut self, content: &RopeBuffer) -> std::io::Result<()> {
+        let file = std::fs::File::create(&self.path)?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "{}", std::process::id())?;
+        content.write_to(&mut writer)?;
+        writer.flush()?;
+        self.last_written = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Returns the buffer content stored in the swap file
+    pub fn read_content(&self) -> std::io::Result<String> {
+        let mut s = String::new();
+        BufReader::new(std::fs::File::open(&self.path)?).read_to_string(&mut s)?;
+        match s.split_once('\n') {
+            Some((_pid, content)) => Ok(content.to_string()),
+            None => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "malformed swap file")),
+        }
+    }
+
+    /// The content of the swap file has been restored into the buffer, from
+    /// now on it is written and removed like one created by this session
+    pub fn mark_recovered(&mut self) {
+        self.pending_recovery = false;
+        self.last_written = Some(Instant::now());
+    }
+
+    /// Removes the swap file if this session has written it. A swap file that
+    /// is still waiting to be recovered is left alone.
+    pub fn remove(&mut self) {
+        if self.last_written.is_some() {
+            let _ = std::fs::remove_file(&self.path);
+        }
+        self.last_written = None;
+    }
+
+    /// Removes the swap file even if it has not been recovered, for when the
+    /// unsaved changes it holds are no longer needed (the file was saved)
+    pub fn discard(&mut self) {
+        if self.last_written.is_some() || self.pending_recovery {
+            let _ = std::fs::remove_file(&self.path);
+        }
+        self.last_written = None;
+        self.pending_recovery = false;
+    }
+}
+
+fn read_pid(swap_path: &Path) -> Option<u32> {
+    let file = std::fs::File::open(swap_path).ok()?;
+    let mut first_line = Vec::new();
+    for byte in BufReader::new(file).bytes() {
+        match byte.ok()? {
+            b'\n' => break,
+            b => first_line.push(b),
+        }
+    }
+    std::str::from_utf8(&first_line).ok()?.parse().ok()
+}
+
+fn is_process_alive(pid: u32) -> bool {
+    // signal 0 does not send anything, it only checks if the process exists.
+    // EPERM means that it exists but belongs to another user.
+    let signalled = unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+    signalled || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_path_is_hidden_file_next_to_original() {
+        assert_eq!(
+            SwapFile::path_for(Path::new("/tmp/dir/file.txt")),
+            Some(PathBuf::from("/tmp/dir/.file.txt.bad.swp"))
+        );
+    }
+
+    #[test]
+    fn processes_of_other_users_are_alive() {
+        assert!(is_process_alive(std::process::id()));
+        // init always exists and belongs to root, so unless the tests are run
+        // as root this checks that not being allowed to signal it is fine
+        assert!(is_process_alive(1));
+    }
+
+    #[test]
+    fn write_and_recover() {
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("file.txt");
+        let (swap, state) = SwapFile::open_for(&file_path);
+        assert!(matches!(state, SwapState::Clean));
+        let mut swap = swap.unwrap();
+        swap.write(&RopeBuffer::from_str("hello\nworld")).unwrap();
+        assert_eq!(read_pid(&swap.path), Some(std::process::id()));
+        assert_eq!(swap.read_content().unwrap(), "hello\nworld");
+        swap.remove();
+        assert!(!swap.path.exists());
+    }
+
+    #[test]
+    fn same_process_swap_is_not_recoverable() {
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("file.txt");
+        let mut swap = SwapFile::open_for(&file_path).0.unwrap();
+        swap.write(&RopeBuffer::from_str("hello")).unwrap();
+        let (other, state) = SwapFile::open_for(&file_path);
+        assert!(matches!(state, SwapState::OpenInThisSession));
+        assert!(other.is_none());
+    }
+
+    #[test]
+    fn unrecovered_swap_is_only_removed_when_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("file.txt");
+        let swap_path = SwapFile::path_for(&file_path).unwrap();
+        // larger than the maximum pid on Linux so the process can't be alive
+        std::fs::write(&swap_path, "4194305\nunsaved").unwrap();
+        let (swap, state) = SwapFile::open_for(&file_path);
+        assert!(matches!(state, SwapState::Recoverable));
+        let mut swap = swap.unwrap();
+        swap.remove();
+        assert!(swap_path.exists());
+        swap.discard();
+        assert!(!swap_path.exists());
+
+        std::fs::write(&swap_path, "4194305\nunsaved").unwrap();
+        let mut swap = SwapFile::open_for(&file_path).0.unwrap();
+        assert_eq!(swap.read_content().unwrap(), "unsaved");
+        swap.mark_recovered();
+        swap.remove();
+        assert!(!swap_path.exists());
+    }
+}
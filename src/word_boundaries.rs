@@ -0,0 +1,170 @@
+use ropey::Rope;
+use ropey::iter::Chars;
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete};
+
+use crate::ByteOffset;
+
+fn is_midletter(c: char) -> bool {
+    matches!(c, '\u{003A}' | '\u{00B7}' | '\u{0387}' | '\u{055F}' | '\u{05F4}' | '\u{2027}' | '\u{FE13}' | '\u{FE55}' | '\u{FF1A}')
+}
+
+fn is_midnumletq(c: char) -> bool {
+    matches!(c, '\u{002E}' | '\u{2018}' | '\u{2019}' | '\u{2024}' | '\u{FE52}' | '\u{FF07}' | '\u{FF0E}' | '\u{0027}')
+}
+
+fn is_midnum(c: char) -> bool {
+    matches!(c, '\u{066C}' | '\u{FE50}' | '\u{FE54}' | '\u{FF0C}' | '\u{FF1B}')
+}
+
+/// Alphanumerics, `_` and `extra_word_chars` make up words
+pub(crate) fn is_word_char(c: char, extra_word_chars: &str) -> bool {
+    c.is_alphanumeric() || c == '_' || extra_word_chars.contains(c)
+}
+
+/// Decides whether there is a word boundary between `prev` and `next` given
+/// the two characters on both sides of the position (`None` past either end
+/// of the text).
+///
+/// The unicode segmentation crates don't currently (as of August 2025) provide
+/// an API for word boundaries that would be usable with Rope so we will use a
+/// simple implementation that should be reasonable for the simplest of cases.
+/// Unicode defines more thorough word boundary rules that might be worth
+/// implementing: https://www.unicode.org/reports/tr29/#Word_Boundaries
+pub(crate) fn is_word_boundary_between(
+    prevprev: Option<char>,
+    prev: Option<char>,
+    next: Option<char>,
+    nextnext: Option<char>,
+    extra_word_chars: &str,
+) -> bool {
+    let (Some(prev), Some(next)) = (prev, next) else { return true };
+    if prev.is_whitespace() && next.is_whitespace() {
+        return false
+    }
+    if is_word_char(prev, extra_word_chars) && is_word_char(next, extra_word_chars) {
+        return false
+    }
+    if prev.is_ascii_punctuation() && next.is_ascii_punctuation() {
+        return false
+    }
+    if prev.is_alphabetic() && (is_midletter(next) || is_midnumletq(next)) && nextnext.is_some_and(|c| c.is_alphabetic()) {
+        return false
+    }
+    if next.is_alphabetic() && (is_midletter(prev) || is_midnumletq(prev)) && prevprev.is_some_and(|c| c.is_alphabetic()) {
+        return false
+    }
+    if prev.is_numeric() && (is_midnum(next) || is_midnumletq(next)) && nextnext.is_some_and(|c| c.is_numeric()) {
+        return false
+    }
+    if next.is_numeric() && (is_midnum(prev) || is_midnumletq(prev)) && prevprev.is_some_and(|c| c.is_numeric()) {
+        return false
+    }
+    true
+}
+
+/// Iterator over the word boundaries (that are also grapheme cluster
+/// boundaries) before or after a position in the text, not including the
+/// starting position itself. The grapheme cursor and the character iterator
+/// are advanced along the way instead of seeking to every candidate position
+/// from scratch.
+pub struct WordBoundaries<'a> {
+    rope: &'a Rope,
+    graphemes: GraphemeCursor,
+    chunk: &'a str,
+    chunk_start: usize,
+    /// Positioned at `offset`
+    chars: Chars<'a>,
+    offset: usize,
+    backward: bool,
+    extra_word_chars: &'a str,
+}
+
+impl<'a> WordBoundaries<'a> {
+    pub(crate) fn new(rope: &'a Rope, from: ByteOffset, backward: bool, extra_word_chars: &'a str) -> Self {
+        let (chunk, chunk_start, _, _) = rope.chunk_at_byte(from.0);
+        Self {
+            rope,
+            graphemes: GraphemeCursor::new(from.0, rope.len_bytes(), true),
+            chunk,
+            chunk_start,
+            chars: rope.chars_at(rope.byte_to_char(from.0)),
+            offset: from.0,
+            backward,
+            extra_word_chars,
+        }
+    }
+
+    fn next_grapheme_boundary(&mut self) -> Option<usize> {
+        loop {
+            let result = if self.backward {
+                self.graphemes.prev_boundary(self.chunk, self.chunk_start)
+            } else {
+                self.graphemes.next_boundary(self.chunk, self.chunk_start)
+            };
+            match result {
+                Ok(boundary) => return boundary,
+                Err(GraphemeIncomplete::NextChunk) => {
+                    (self.chunk, self.chunk_start, _, _) = self.rope.chunk_at_byte(self.chunk_start + self.chunk.len());
+                }
+                Err(GraphemeIncomplete::PrevChunk) => {
+                    (self.chunk, self.chunk_start, _, _) = self.rope.chunk_at_byte(self.chunk_start - 1);
+                }
+                Err(GraphemeIncomplete::PreContext(idx)) => {
+                    let (ctx_chunk, ctx_chunk_byte_idx, _, _) = self.rope.chunk_at_byte(idx.saturating_sub(1));
+                    self.graphemes.provide_context(ctx_chunk, ctx_chunk_byte_idx);
+                }
+                Err(err) => unreachable!("{err:?} should never happen!"),
+            }
+        }
+    }
+}
+
+impl Iterator for WordBoundaries<'_> {
+    type Item = ByteOffset;
+
+    fn next(&mut self) -> Option<ByteOffset> {
+        loop {
+            let boundary = self.next_grapheme_boundary()?;
+            while self.offset < boundary {
+                self.offset += self.chars.next().map_or(1, char::len_utf8);
+            }
+            while self.offset > boundary {
+                self.offset -= self.chars.prev().map_or(1, char::len_utf8);
+            }
+            let mut before = self.chars.clone();
+            let mut after = self.chars.clone();
+            let prev = before.prev();
+            let prevprev = before.prev();
+            let next = after.next();
+            let nextnext = after.next();
+            if is_word_boundary_between(prevprev, prev, next, nextnext, self.extra_word_chars) {
+                return Some(ByteOffset(boundary))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ropebuffer::RopeBuffer;
+
+    use super::*;
+
+    #[test]
+    fn iterators_agree_with_one_off_queries() {
+        let text = "hello, world! 3.14 can't  stop__me 👩‍👩‍👧x\r\n(foo::bar)  ";
+        let content = RopeBuffer::from_str(text);
+        let expected: Vec<_> = (1..=text.len())
+            .map(ByteOffset)
+            .filter(|&offset| content.is_grapheme_cluster_boundary(offset) && content.is_word_boundary(offset))
+            .collect();
+        assert_eq!(content.word_boundaries(ByteOffset(0)).collect::<Vec<_>>(), expected);
+
+        let mut expected_backward: Vec<_> = (0..text.len())
+            .map(ByteOffset)
+            .filter(|&offset| content.is_grapheme_cluster_boundary(offset) && content.is_word_boundary(offset))
+            .collect();
+        expected_backward.reverse();
+        assert_eq!(content.word_boundaries_backward(ByteOffset(text.len())).collect::<Vec<_>>(), expected_backward);
+    }
+}